@@ -5,6 +5,46 @@ extern crate cc;
 use std::env;
 
 fn main() {
+    if cfg!(feature = "system-vma") && cfg!(feature = "pure-rust") {
+        panic!("The system-vma and pure-rust features replace VMA in different ways, enable one of them");
+    }
+    if cfg!(feature = "system-vma") {
+        link_system_vma();
+        generate_bindings("src/ffi.rs");
+        return;
+    }
+
+    // VMA_ASSERT follows rust assertions, see Allocator::build_config
+    println!(
+        "cargo:rustc-env=VK_MEM_VMA_ASSERT={}",
+        cfg!(debug_assertions) as u8
+    );
+
+    // Sizes and alignments in bytes, VMA's defaults unless overridden with
+    // the environment variable of the same name. They are passed on to
+    // Allocator::build_config.
+    let mut sizes = Vec::new();
+    for var in [
+        "VMA_DEBUG_MIN_BUFFER_IMAGE_GRANULARITY",
+        "VMA_MIN_ALIGNMENT",
+        "VMA_SMALL_HEAP_MAX_SIZE",
+        "VMA_DEFAULT_LARGE_HEAP_BLOCK_SIZE",
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+        if let Ok(value) = env::var(var) {
+            if value.parse::<u64>().is_err() {
+                panic!("{} must be a number of bytes, not {:?}", var, value);
+            }
+            println!("cargo:rustc-env=VK_MEM_{}={}", var, value);
+            sizes.push((var, value));
+        }
+    }
+
+    if cfg!(feature = "pure-rust") {
+        configure_pure_rust();
+        return;
+    }
+
     let mut build = cc::Build::new();
 
     build.include("vendor/VulkanMemoryAllocator/include");
@@ -20,22 +60,64 @@ fn main() {
     // Linux (pkconfig) and Windows (VULKAN_SDK environment variable).
     build.define("VMA_STATIC_VULKAN_FUNCTIONS", "0");
 
-    // This prevents VMA from trying to fetch any remaining pointers
-    // that are still null after using the loader in ash, which can
-    // cause linker errors.
-    build.define("VMA_DYNAMIC_VULKAN_FUNCTIONS", "0");
+    // Lets VMA fetch the pointers that are still null through the
+    // vkGetInstanceProcAddr and vkGetDeviceProcAddr it is given, which
+    // only happens with AllocatorCreateInfo::from_entry. The other paths
+    // provide every function, so nothing is fetched nor linked.
+    build.define("VMA_DYNAMIC_VULKAN_FUNCTIONS", "1");
+
+    // Runs VMA's expensive internal validation along with VMA_ASSERT.
+    #[cfg(feature = "heavy-assert")]
+    build.define("VMA_HEAVY_ASSERT(expr)", "VMA_ASSERT(expr)");
 
-    // TODO: Add some configuration options under crate features
-    //#define VMA_HEAVY_ASSERT(expr) assert(expr)
-    //#define VMA_USE_STL_CONTAINERS 1
-    //#define VMA_DEDICATED_ALLOCATION 0
-    //#define VMA_DEBUG_MARGIN 16
-    //#define VMA_DEBUG_DETECT_CORRUPTION 1
-    //#define VMA_DEBUG_INITIALIZE_ALLOCATIONS 1
-    //#define VMA_DEBUG_MIN_BUFFER_IMAGE_GRANULARITY 256
+    // Gives every allocation its own VkDeviceMemory, to rule out
+    // suballocation issues.
+    #[cfg(feature = "debug-always-dedicated")]
+    build.define("VMA_DEBUG_ALWAYS_DEDICATED_MEMORY", "1");
 
-    #[cfg(feature = "recording")]
-    build.define("VMA_RECORDING_ENABLED", "1");
+    // Serializes all VMA calls with a global mutex, to rule out threading
+    // issues.
+    #[cfg(feature = "debug-global-mutex")]
+    build.define("VMA_DEBUG_GLOBAL_MUTEX", "1");
+
+    for (var, value) in &sizes {
+        build.define(var, value.as_str());
+    }
+
+    // Margin in bytes around every allocation, 16 unless overridden with the
+    // VMA_DEBUG_MARGIN environment variable.
+    #[cfg(feature = "debug-margin")]
+    {
+        println!("cargo:rerun-if-env-changed=VMA_DEBUG_MARGIN");
+        let margin = env::var("VMA_DEBUG_MARGIN").unwrap_or_else(|_| "16".to_owned());
+        build.define("VMA_DEBUG_MARGIN", margin.as_str());
+        println!("cargo:rustc-env=VK_MEM_VMA_DEBUG_MARGIN={}", margin);
+    }
+
+    // Fills the margins with a magic number, validated by vmaCheckCorruption
+    // and on every free.
+    #[cfg(feature = "detect-corruption")]
+    build.define("VMA_DEBUG_DETECT_CORRUPTION", "1");
+
+    // Fills new and freed host-visible allocations with 0xDCDCDCDC and
+    // 0xEFEFEFEF respectively.
+    #[cfg(feature = "debug-init-allocations")]
+    build.define("VMA_DEBUG_INITIALIZE_ALLOCATIONS", "1");
+
+    // Forwards VMA's debug and leak messages to the log crate, see
+    // src/logging.rs.
+    #[cfg(feature = "log")]
+    build.define("VMA_RUST_LOG", "1");
+
+    // Allocates VMA's CPU memory from Rust's global allocator, unless
+    // AllocatorCreateInfo::allocation_callbacks are given, see
+    // src/host_allocator.rs.
+    #[cfg(feature = "rust-allocator")]
+    build.define("VMA_RUST_ALLOCATOR", "1");
+
+    // Compiles out vmaBuildStatsString and its JSON writer.
+    #[cfg(not(feature = "stats-string"))]
+    build.define("VMA_STATS_STRING_ENABLED", "0");
 
     // Add the files we build
     build.file("wrapper.cpp");
@@ -95,6 +177,42 @@ fn main() {
             .flag("-Wno-type-limits")
             .cpp_link_stdlib("stdc++")
             .cpp(true);
+    } else if target.contains("windows") && target.contains("msvc") {
+        // Uses the same CRT as Rust, /MT with crt-static and /MD otherwise,
+        // since mixing them fails to link.
+        let static_crt = env::var("CARGO_CFG_TARGET_FEATURE")
+            .map(|features| features.split(',').any(|feature| feature == "crt-static"))
+            .unwrap_or(false);
+        build.flag("/std:c++17").static_crt(static_crt).cpp(true);
+        if !cfg!(feature = "no-exceptions") {
+            build.flag("/EHsc");
+        }
+        if build.get_compiler().is_like_clang_cl() {
+            // clang-cl takes MSVC style flags, but warns like clang
+            build
+                .flag("-Wno-missing-field-initializers")
+                .flag("-Wno-unused-variable")
+                .flag("-Wno-unused-parameter")
+                .flag("-Wno-unused-private-field")
+                .flag("-Wno-reorder")
+                .flag("-Wno-nullability-completeness");
+        } else {
+            // Otherwise __cplusplus stays 199711L whatever the standard is
+            build.flag("/Zc:__cplusplus");
+        }
+    }
+
+    // For targets without C++ exceptions or RTTI, which VMA doesn't use. A
+    // panic of the assert handler then can't unwind through VMA and aborts.
+    if cfg!(feature = "no-exceptions") {
+        if build.get_compiler().is_like_msvc() {
+            build
+                .flag("/EHs-c-")
+                .flag("/GR-")
+                .define("_HAS_EXCEPTIONS", "0");
+        } else {
+            build.flag("-fno-exceptions").flag("-fno-rtti");
+        }
     }
 
     build.compile("vma");
@@ -102,6 +220,103 @@ fn main() {
     generate_bindings("src/ffi.rs");
 }
 
+// Serves the VMA API from src/rust_vma.rs instead of compiling VMA, so that
+// no C++ toolchain is needed.
+fn configure_pure_rust() {
+    // These configure the vendored VMA, the Rust implementation only follows
+    // debug-always-dedicated, log and stats-string.
+    for (enabled, feature) in [
+        (cfg!(feature = "no-exceptions"), "no-exceptions"),
+        (cfg!(feature = "heavy-assert"), "heavy-assert"),
+        (cfg!(feature = "debug-global-mutex"), "debug-global-mutex"),
+        (cfg!(feature = "debug-margin"), "debug-margin"),
+        (cfg!(feature = "detect-corruption"), "detect-corruption"),
+        (
+            cfg!(feature = "debug-init-allocations"),
+            "debug-init-allocations",
+        ),
+    ] {
+        if enabled {
+            println!(
+                "cargo:warning=The {} feature has no effect with the pure-rust feature",
+                feature
+            );
+        }
+    }
+}
+
+// Links a prebuilt VMA instead of compiling the vendored one:
+// - VMA_LIB_DIR: directory of the library, if it isn't on the default link path.
+// - VMA_LIB_NAME: name of the library, VulkanMemoryAllocator unless overridden.
+// - VMA_LIB_KIND: static (the default) or dylib. A static library built from
+//   C++ also needs the C++ standard library linked in.
+// - VMA_INCLUDE_DIR: directory of the library's vk_mem_alloc.h, which has the
+//   version checked when creating an allocator.
+// - VMA_VERSION: version of the library, when its header isn't available.
+fn link_system_vma() {
+    for var in [
+        "VMA_LIB_DIR",
+        "VMA_LIB_NAME",
+        "VMA_LIB_KIND",
+        "VMA_INCLUDE_DIR",
+        "VMA_VERSION",
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    // These are compiled into the vendored VMA, the prebuilt one has its own
+    // configuration.
+    for (enabled, feature) in [
+        (cfg!(feature = "no-exceptions"), "no-exceptions"),
+        (cfg!(feature = "heavy-assert"), "heavy-assert"),
+        (
+            cfg!(feature = "debug-always-dedicated"),
+            "debug-always-dedicated",
+        ),
+        (cfg!(feature = "debug-global-mutex"), "debug-global-mutex"),
+        (cfg!(feature = "debug-margin"), "debug-margin"),
+        (cfg!(feature = "detect-corruption"), "detect-corruption"),
+        (
+            cfg!(feature = "debug-init-allocations"),
+            "debug-init-allocations",
+        ),
+        (cfg!(feature = "log"), "log"),
+    ] {
+        if enabled {
+            println!(
+                "cargo:warning=The {} feature has no effect on a system VMA",
+                feature
+            );
+        }
+    }
+
+    if let Ok(dir) = env::var("VMA_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={}", dir);
+    }
+    let name = env::var("VMA_LIB_NAME").unwrap_or_else(|_| "VulkanMemoryAllocator".to_owned());
+    let kind = env::var("VMA_LIB_KIND").unwrap_or_else(|_| "static".to_owned());
+    println!("cargo:rustc-link-lib={}={}", kind, name);
+
+    let version = match env::var("VMA_VERSION") {
+        Ok(version) => version,
+        Err(_) => env::var("VMA_INCLUDE_DIR")
+            .ok()
+            .and_then(|dir| {
+                std::fs::read_to_string(std::path::Path::new(&dir).join("vk_mem_alloc.h")).ok()
+            })
+            .and_then(|header| header_version(&header))
+            .unwrap_or_else(|| "unknown".to_owned()),
+    };
+    println!("cargo:rustc-env=VK_MEM_SYSTEM_VMA_VERSION={}", version);
+}
+
+// The version is only documented in the header, as `<b>Version 3.1.0</b>`.
+fn header_version(header: &str) -> Option<String> {
+    let start = header.find("<b>Version ")? + "<b>Version ".len();
+    let end = start + header[start..].find("</b>")?;
+    Some(header[start..end].trim().to_owned())
+}
+
 #[cfg(feature = "generate_bindings")]
 fn generate_bindings(output_file: &str) {
     let bindings = bindgen::Builder::default()
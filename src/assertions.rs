@@ -0,0 +1,91 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Failed `VMA_ASSERT` inside VMA, see `set_assert_handler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmaAssertion<'a> {
+    /// Source of the asserted expression.
+    pub expression: &'a str,
+    /// VMA source file of the assertion.
+    pub file: &'a str,
+    /// Line of the assertion in `file`.
+    pub line: u32,
+}
+
+/// `VMA assertion failed: <expression>, <file>:<line>`
+impl fmt::Display for VmaAssertion<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VMA assertion failed: {}, {}:{}",
+            self.expression, self.file, self.line
+        )
+    }
+}
+
+type AssertHandler = Arc<dyn Fn(&VmaAssertion<'_>) + Send + Sync>;
+
+static ASSERT_HANDLER: RwLock<Option<AssertHandler>> = RwLock::new(None);
+
+/// Sets the handler called when an assertion inside VMA fails, for all allocators of the
+/// process, replacing the default handler which panics with the assertion message.
+///
+/// A panic usually unwinds through VMA back to the Rust caller, so it can be caught, e.g. by a
+/// test with `#[should_panic]`. If the handler returns instead, VMA carries on as if the
+/// assertion passed, which is likely to fail in other ways later.
+///
+/// Unwinding has two limits:
+/// - Assertions inside C++ destructors, e.g. those of VMA's block vectors and of the allocator
+///   itself checking that no allocation is left, can't unwind: destructors are implicitly
+///   `noexcept`, so the panic calls `std::terminate` and aborts the process.
+/// - A caught panic leaves VMA in the middle of the failed operation, with locks possibly held
+///   and its state inconsistent. The allocator shouldn't be used anymore afterwards, catching is
+///   meant for reporting the failure.
+///
+/// VMA assertions are only compiled into debug builds, release builds define `NDEBUG`. With the
+/// `no-exceptions` feature, a panic may not unwind through VMA and abort instead. With the
+/// `pure-rust` feature, the Rust implementation of VMA checks the same assertions in debug
+/// builds.
+pub fn set_assert_handler(handler: impl Fn(&VmaAssertion<'_>) + Send + Sync + 'static) {
+    *ASSERT_HANDLER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(handler));
+}
+
+/// Restores the default handler of `set_assert_handler`, which panics.
+pub fn reset_assert_handler() {
+    *ASSERT_HANDLER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Called by `VMA_ASSERT` in wrapper.cpp when `expression` is false.
+#[no_mangle]
+unsafe extern "C-unwind" fn vma_rust_assert(
+    expression: *const c_char,
+    file: *const c_char,
+    line: c_int,
+) {
+    let expression = CStr::from_ptr(expression).to_string_lossy();
+    let file = CStr::from_ptr(file).to_string_lossy();
+    assertion_failed(&VmaAssertion {
+        expression: &expression,
+        file: &file,
+        line: line as u32,
+    });
+}
+
+/// Reports a failed assertion to the handler of `set_assert_handler`.
+pub(crate) fn assertion_failed(assertion: &VmaAssertion<'_>) {
+    // Cloned so that the handler can replace itself.
+    let handler = ASSERT_HANDLER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    match handler {
+        Some(handler) => handler(assertion),
+        None => panic!("{}", assertion),
+    }
+}
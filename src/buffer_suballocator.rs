@@ -0,0 +1,357 @@
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::VirtualAllocation;
+use crate::VirtualAllocationCreateFlags;
+use crate::VirtualAllocationCreateInfo;
+use crate::VirtualBlock;
+use crate::VirtualBlockCreateInfo;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Buffer of a `BufferSuballocator` and the virtual block managing its space.
+struct SuballocatorBlock {
+    id: u64,
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    mapped: *mut u8,
+    block: VirtualBlock,
+    size: vk::DeviceSize,
+    /// Bytes handed out as slices
+    used: vk::DeviceSize,
+    slice_count: usize,
+}
+
+impl SuballocatorBlock {
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<BufferSlice> {
+        let allocation_info = VirtualAllocationCreateInfo {
+            size,
+            alignment,
+            user_data: 0,
+            flags: VirtualAllocationCreateFlags::empty(),
+        };
+        let (allocation, offset) = unsafe { self.block.allocate(allocation_info)? };
+        self.used += size;
+        self.slice_count += 1;
+        Ok(BufferSlice {
+            buffer: self.buffer,
+            offset: offset.get(),
+            size,
+            alignment,
+            mapped: if self.mapped.is_null() {
+                self.mapped
+            } else {
+                unsafe { self.mapped.add(offset.get() as usize) }
+            },
+            block: self.id,
+            allocation,
+        })
+    }
+
+    fn free(&mut self, mut allocation: VirtualAllocation, size: vk::DeviceSize) -> VmaResult<()> {
+        unsafe { self.block.free(&mut allocation)? };
+        self.used -= size;
+        self.slice_count -= 1;
+        Ok(())
+    }
+}
+
+/// Range of a buffer handed out by a `BufferSuballocator`.
+///
+/// Slices are move-only handles freed with `BufferSuballocator::free`.
+#[derive(Debug)]
+pub struct BufferSlice {
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    mapped: *mut u8,
+    block: u64,
+    allocation: VirtualAllocation,
+}
+unsafe impl Send for BufferSlice {}
+
+impl BufferSlice {
+    /// Buffer holding the slice.
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    /// Offset of the slice in `BufferSlice::buffer`.
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    /// Size of the slice, in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Mapped memory of the slice, or null if the suballocator's buffers aren't mapped.
+    pub fn mapped_data(&self) -> *mut u8 {
+        self.mapped
+    }
+
+    /// Descriptor info of the slice.
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer,
+            offset: self.offset,
+            range: self.size,
+        }
+    }
+}
+
+/// Move of a `BufferSlice` planned by `BufferSuballocator::compact`.
+///
+/// The data must be copied with `SliceMove::src_buffer`, `SliceMove::dst_buffer` and
+/// `SliceMove::region`, and the old range is freed by `BufferSuballocator::finish_move` once the
+/// copy and every use of the old range are complete.
+pub struct SliceMove {
+    src_buffer: vk::Buffer,
+    dst_buffer: vk::Buffer,
+    region: vk::BufferCopy,
+    block: u64,
+    allocation: VirtualAllocation,
+}
+unsafe impl Send for SliceMove {}
+
+impl SliceMove {
+    /// Buffer the slice was in.
+    pub fn src_buffer(&self) -> vk::Buffer {
+        self.src_buffer
+    }
+
+    /// Buffer the slice is in now.
+    pub fn dst_buffer(&self) -> vk::Buffer {
+        self.dst_buffer
+    }
+
+    /// Copy of the slice data from its old to its new range.
+    pub fn region(&self) -> vk::BufferCopy {
+        self.region
+    }
+}
+
+/// Hands out slices of a few large buffers, for many small vertex, index or uniform buffers.
+///
+/// All buffers are created with the same usage, so a suballocator is meant per usage class.
+/// Space in each buffer is managed by a `VirtualBlock`, so slices take neither a memory
+/// allocation, which counts towards `maxMemoryAllocationCount`, nor a `vk::Buffer`. Buffers of
+/// `block_size` bytes, or larger for bigger slices, are created as needed.
+///
+/// `BufferSuballocator::compact` moves slices out of the last buffers into free space of the
+/// first ones, like a fast defragmentation, so that `BufferSuballocator::trim` can destroy the
+/// emptied buffers.
+pub struct BufferSuballocator {
+    allocator: Arc<Allocator>,
+    usage: vk::BufferUsageFlags,
+    block_size: vk::DeviceSize,
+    create_info: AllocationCreateInfo,
+    blocks: Vec<SuballocatorBlock>,
+    next_block_id: u64,
+}
+unsafe impl Send for BufferSuballocator {}
+
+impl Allocator {
+    /// Creates a `BufferSuballocator` of buffers with `usage`, `block_size` bytes each.
+    ///
+    /// # Safety
+    /// `create_info` is used for every buffer, see `Alloc::create_buffer`. Buffers are mapped if
+    /// it has `AllocationCreateFlags::MAPPED`.
+    pub unsafe fn create_buffer_suballocator(
+        self: &Arc<Self>,
+        usage: vk::BufferUsageFlags,
+        block_size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> BufferSuballocator {
+        BufferSuballocator {
+            allocator: self.clone(),
+            usage,
+            block_size,
+            create_info: create_info.clone(),
+            blocks: Vec::new(),
+            next_block_id: 0,
+        }
+    }
+}
+
+impl BufferSuballocator {
+    /// Allocates a slice of `size` bytes whose offset is aligned to `alignment`, a power of two.
+    #[track_caller]
+    pub fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<BufferSlice> {
+        if size == 0 {
+            return Err(VmaError::InvalidParameter("Buffer slices can't be empty"));
+        }
+        for block in &mut self.blocks {
+            if let Ok(slice) = block.allocate(size, alignment) {
+                return Ok(slice);
+            }
+        }
+        let block = self.create_block(size.max(self.block_size))?;
+        self.blocks.push(block);
+        self.blocks.last_mut().unwrap().allocate(size, alignment)
+    }
+
+    /// Frees a slice.
+    ///
+    /// Fails with `VmaError::WrongVirtualBlock` if it wasn't allocated from this suballocator.
+    ///
+    /// # Safety
+    /// The device must be done with the slice.
+    pub unsafe fn free(&mut self, slice: BufferSlice) -> VmaResult<()> {
+        self.block_mut(slice.block)?
+            .free(slice.allocation, slice.size)
+    }
+
+    /// Moves `slice` to the first buffer with room for it before its current one, if any.
+    ///
+    /// `slice` is updated in place and refers to its new range from now on. The returned move
+    /// describes the copy to record, and the old range stays allocated until
+    /// `BufferSuballocator::finish_move`.
+    pub fn compact(&mut self, slice: &mut BufferSlice) -> VmaResult<Option<SliceMove>> {
+        let current = self.block_index(slice.block)?;
+        for block in &mut self.blocks[..current] {
+            if let Ok(moved) = block.allocate(slice.size, slice.alignment) {
+                let old = std::mem::replace(slice, moved);
+                return Ok(Some(SliceMove {
+                    src_buffer: old.buffer,
+                    dst_buffer: slice.buffer,
+                    region: vk::BufferCopy {
+                        src_offset: old.offset,
+                        dst_offset: slice.offset,
+                        size: slice.size,
+                    },
+                    block: old.block,
+                    allocation: old.allocation,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Frees the old range of a slice moved by `BufferSuballocator::compact`.
+    ///
+    /// # Safety
+    /// The copy and every use of the old range must be complete.
+    pub unsafe fn finish_move(&mut self, slice_move: SliceMove) -> VmaResult<()> {
+        self.block_mut(slice_move.block)?
+            .free(slice_move.allocation, slice_move.region.size)
+    }
+
+    /// Destroys the buffers without slices, and returns how many were destroyed.
+    ///
+    /// # Safety
+    /// The device must be done with the slices freed from them.
+    pub unsafe fn trim(&mut self) -> usize {
+        let (empty, blocks) = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .partition::<Vec<_>, _>(|block| block.slice_count == 0);
+        self.blocks = blocks;
+        let count = empty.len();
+        for block in empty {
+            let _ = self.destroy_block(block);
+        }
+        count
+    }
+
+    /// Number of buffers.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Bytes handed out as slices, without alignment padding.
+    pub fn used_size(&self) -> vk::DeviceSize {
+        self.blocks.iter().map(|block| block.used).sum()
+    }
+
+    /// Total size of the buffers, in bytes.
+    pub fn total_size(&self) -> vk::DeviceSize {
+        self.blocks.iter().map(|block| block.size).sum()
+    }
+
+    /// Destroys all buffers, reporting errors that `Drop` would ignore.
+    ///
+    /// The device must not use the slices anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.destroy_blocks()
+    }
+
+    #[track_caller]
+    fn create_block(&mut self, size: vk::DeviceSize) -> VmaResult<SuballocatorBlock> {
+        let block = VirtualBlock::new(VirtualBlockCreateInfo {
+            size,
+            ..Default::default()
+        })?;
+        let buffer_info = vk::BufferCreateInfo::default().size(size).usage(self.usage);
+        let (buffer, allocation) = unsafe {
+            self.allocator
+                .create_buffer(&buffer_info, &self.create_info)?
+        };
+        let mapped = match self.allocator.get_allocation_info(&allocation) {
+            Ok(info) => info.mapped_data.cast(),
+            Err(err) => {
+                let _ = unsafe { self.allocator.destroy_buffer(buffer, allocation) };
+                return Err(err);
+            }
+        };
+        self.next_block_id += 1;
+        Ok(SuballocatorBlock {
+            id: self.next_block_id,
+            buffer,
+            allocation,
+            mapped,
+            block,
+            size,
+            used: 0,
+            slice_count: 0,
+        })
+    }
+
+    fn destroy_block(&self, mut block: SuballocatorBlock) -> VmaResult<()> {
+        unsafe {
+            block.block.clear();
+            self.allocator
+                .destroy_buffer(block.buffer, block.allocation)
+                .map_err(VmaError::from)
+        }
+    }
+
+    fn destroy_blocks(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for block in std::mem::take(&mut self.blocks) {
+            result = result.and(self.destroy_block(block));
+        }
+        result
+    }
+
+    fn block_index(&self, id: u64) -> VmaResult<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.id == id)
+            .ok_or(VmaError::WrongVirtualBlock)
+    }
+
+    fn block_mut(&mut self, id: u64) -> VmaResult<&mut SuballocatorBlock> {
+        let index = self.block_index(id)?;
+        Ok(&mut self.blocks[index])
+    }
+}
+
+/// Destroys all buffers, the device must not use them anymore.
+impl Drop for BufferSuballocator {
+    fn drop(&mut self) {
+        let _ = self.destroy_blocks();
+    }
+}
@@ -0,0 +1,94 @@
+use crate::Allocator;
+use ash::vk;
+
+/// Compile-time configuration of VMA, see `Allocator::build_config`.
+///
+/// Sizes are in bytes. With the `system-vma` feature, the fields describe the vendored VMA,
+/// which isn't built, and not the linked library. With the `pure-rust` feature, they describe
+/// the Rust implementation, which ignores the debug features it lacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildConfig {
+    /// VMA is a prebuilt library linked with the `system-vma` feature.
+    pub system_vma: bool,
+    /// VMA is replaced by its Rust implementation with the `pure-rust` feature.
+    pub pure_rust: bool,
+    /// `VMA_ASSERT` is compiled in, i.e. VMA isn't built with `NDEBUG`.
+    pub assertions: bool,
+    /// VMA is built with C++ exceptions and RTTI, i.e. without the `no-exceptions` feature.
+    pub exceptions: bool,
+    /// VMA allocates its CPU memory from Rust's global allocator, with the `rust-allocator`
+    /// feature enabled by default.
+    pub rust_allocator: bool,
+    /// `VMA_HEAVY_ASSERT` is enabled by the `heavy-assert` feature.
+    pub heavy_assertions: bool,
+    /// `VMA_DEBUG_MARGIN`, 0 without the `debug-margin` feature.
+    pub debug_margin: vk::DeviceSize,
+    /// `VMA_DEBUG_DETECT_CORRUPTION` is enabled by the `detect-corruption` feature.
+    pub detect_corruption: bool,
+    /// `VMA_DEBUG_INITIALIZE_ALLOCATIONS` is enabled by the `debug-init-allocations` feature.
+    pub initialize_allocations: bool,
+    /// `VMA_DEBUG_ALWAYS_DEDICATED_MEMORY` is enabled by the `debug-always-dedicated` feature.
+    pub always_dedicated_memory: bool,
+    /// `VMA_DEBUG_GLOBAL_MUTEX` is enabled by the `debug-global-mutex` feature.
+    pub global_mutex: bool,
+    /// `VMA_DEBUG_MIN_BUFFER_IMAGE_GRANULARITY`.
+    pub min_buffer_image_granularity: vk::DeviceSize,
+    /// `VMA_MIN_ALIGNMENT`.
+    pub min_alignment: vk::DeviceSize,
+    /// `VMA_SMALL_HEAP_MAX_SIZE`.
+    pub small_heap_max_size: vk::DeviceSize,
+    /// `VMA_DEFAULT_LARGE_HEAP_BLOCK_SIZE`.
+    pub large_heap_block_size: vk::DeviceSize,
+    /// `VMA_STATS_STRING_ENABLED` is enabled by the `stats-string` feature.
+    pub stats_string: bool,
+    /// The recorder of `Allocator::start_recording` is compiled in by the `recording` feature.
+    pub recording: bool,
+    /// VMA's debug and leak messages are forwarded by the `log` feature.
+    pub log: bool,
+    /// The wrapper tracks live allocations and maps, i.e. the `minimal-checks` feature is
+    /// disabled.
+    pub bookkeeping: bool,
+}
+
+const PURE_RUST: bool = cfg!(feature = "pure-rust");
+
+/// Parses a value passed on by build.rs, or returns VMA's default.
+fn size(value: Option<&str>, default: vk::DeviceSize) -> vk::DeviceSize {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+impl Allocator {
+    /// Returns the compile-time configuration of VMA, set by the crate features and build
+    /// environment variables.
+    pub fn build_config() -> BuildConfig {
+        BuildConfig {
+            system_vma: cfg!(feature = "system-vma"),
+            pure_rust: PURE_RUST,
+            assertions: option_env!("VK_MEM_VMA_ASSERT") == Some("1"),
+            exceptions: !PURE_RUST && !cfg!(feature = "no-exceptions"),
+            rust_allocator: cfg!(feature = "rust-allocator"),
+            heavy_assertions: !PURE_RUST && cfg!(feature = "heavy-assert"),
+            debug_margin: size(option_env!("VK_MEM_VMA_DEBUG_MARGIN"), 0),
+            detect_corruption: !PURE_RUST && cfg!(feature = "detect-corruption"),
+            initialize_allocations: !PURE_RUST && cfg!(feature = "debug-init-allocations"),
+            always_dedicated_memory: cfg!(feature = "debug-always-dedicated"),
+            global_mutex: !PURE_RUST && cfg!(feature = "debug-global-mutex"),
+            min_buffer_image_granularity: size(
+                option_env!("VK_MEM_VMA_DEBUG_MIN_BUFFER_IMAGE_GRANULARITY"),
+                1,
+            ),
+            min_alignment: size(option_env!("VK_MEM_VMA_MIN_ALIGNMENT"), 1),
+            small_heap_max_size: size(option_env!("VK_MEM_VMA_SMALL_HEAP_MAX_SIZE"), 1 << 30),
+            large_heap_block_size: size(
+                option_env!("VK_MEM_VMA_DEFAULT_LARGE_HEAP_BLOCK_SIZE"),
+                256 << 20,
+            ),
+            stats_string: cfg!(feature = "stats-string"),
+            recording: cfg!(feature = "recording"),
+            log: cfg!(feature = "log"),
+            bookkeeping: !cfg!(feature = "minimal-checks"),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::ffi;
+use crate::pool::pool_name;
+use crate::Allocation;
+use crate::Allocator;
+use crate::PoolHandle;
+use ash::vk;
+use ash::vk::Handle;
+
+thread_local! {
+    /// Pool the current thread is allocating from, to name the blocks VMA creates for it.
+    static CURRENT_POOL: Cell<ffi::VmaPool> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+/// Runs `f` with `pool` as the pool the current thread is allocating from.
+pub(crate) fn with_current_pool<R>(pool: PoolHandle, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT_POOL.replace(pool.0);
+    let result = f();
+    CURRENT_POOL.set(previous);
+    result
+}
+
+/// Names objects with `VK_EXT_debug_utils`, see `AllocatorCreateInfo::debug_utils`.
+///
+/// Boxed by the allocator, the box is the user data of the device memory callbacks.
+pub(crate) struct DebugNames {
+    debug_utils: ash::ext::debug_utils::Device,
+    /// `AllocatorCreateInfo::device_memory_callbacks`, called after naming
+    callbacks: Option<ffi::VmaDeviceMemoryCallbacks>,
+    block_count: AtomicU64,
+}
+
+impl DebugNames {
+    pub(crate) fn new(
+        debug_utils: &ash::ext::debug_utils::Device,
+        callbacks: Option<&ffi::VmaDeviceMemoryCallbacks>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            debug_utils: debug_utils.clone(),
+            callbacks: callbacks.copied(),
+            block_count: AtomicU64::new(0),
+        })
+    }
+
+    /// Device memory callbacks naming the blocks, to pass to VMA. `self` must not move while
+    /// they are in use.
+    pub(crate) fn device_memory_callbacks(&self) -> ffi::VmaDeviceMemoryCallbacks {
+        ffi::VmaDeviceMemoryCallbacks {
+            pfnAllocate: Some(allocate_device_memory),
+            pfnFree: Some(free_device_memory),
+            pUserData: self as *const Self as *mut _,
+        }
+    }
+
+    /// Sets the name of `handle`, or clears it if `name` is empty. Errors are ignored.
+    fn set_name<H: Handle>(&self, handle: H, name: &CStr) {
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(name);
+        let _ = unsafe { self.debug_utils.set_debug_utils_object_name(&name_info) };
+    }
+}
+
+unsafe extern "C" fn allocate_device_memory(
+    allocator: ffi::VmaAllocator,
+    memory_type: u32,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    user_data: *mut std::ffi::c_void,
+) {
+    let names = &*(user_data as *const DebugNames);
+    let index = names.block_count.fetch_add(1, Ordering::Relaxed);
+    let pool = PoolHandle(CURRENT_POOL.get());
+    let name = if pool.0.is_null() {
+        format!("vma block {} (memory type {})", index, memory_type)
+    } else {
+        match pool_name(allocator, pool) {
+            Some(name) => format!("vma pool '{}' block {}", name.to_string_lossy(), index),
+            None => format!("vma pool block {} (memory type {})", index, memory_type),
+        }
+    };
+    if let Ok(name) = CString::new(name) {
+        names.set_name(memory, &name);
+    }
+    if let Some(callbacks) = names.callbacks {
+        if let Some(pfn_allocate) = callbacks.pfnAllocate {
+            pfn_allocate(allocator, memory_type, memory, size, callbacks.pUserData);
+        }
+    }
+}
+
+unsafe extern "C" fn free_device_memory(
+    allocator: ffi::VmaAllocator,
+    memory_type: u32,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    user_data: *mut std::ffi::c_void,
+) {
+    let names = &*(user_data as *const DebugNames);
+    if let Some(callbacks) = names.callbacks {
+        if let Some(pfn_free) = callbacks.pfnFree {
+            pfn_free(allocator, memory_type, memory, size, callbacks.pUserData);
+        }
+    }
+}
+
+impl Allocator {
+    /// Records the buffer or image bound to a freshly made allocation, to name it after the
+    /// allocation with `AllocatorCreateInfo::debug_utils`.
+    pub(crate) fn track_resource<H: Handle>(&self, allocation: &Allocation, resource: H) {
+        if self.debug_names.is_none() {
+            return;
+        }
+        if let Some(live) = self.tracker.live().get_mut(&allocation.id) {
+            live.resource = Some((H::TYPE, resource.as_raw()));
+        }
+    }
+
+    /// Names the buffer or image bound to the allocation, if any, see
+    /// `Allocator::set_allocation_name`.
+    pub(crate) fn name_resource(&self, allocation: &Allocation, name: Option<&CStr>) {
+        let Some(names) = &self.debug_names else {
+            return;
+        };
+        let resource = self
+            .tracker
+            .live()
+            .get(&allocation.id)
+            .and_then(|live| live.resource);
+        let name = name.unwrap_or_default();
+        match resource {
+            Some((vk::ObjectType::BUFFER, raw)) => names.set_name(vk::Buffer::from_raw(raw), name),
+            Some((vk::ObjectType::IMAGE, raw)) => names.set_name(vk::Image::from_raw(raw), name),
+            _ => {}
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use crate::ffi::{self};
+use crate::{VmaError, VmaResult};
 use ash::vk;
 use ash::vk::PhysicalDevice;
 use ash::{Device, Instance};
 use bitflags::bitflags;
+use bytemuck::Pod;
+use std::ffi::CStr;
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::sync::Arc;
 
 /// Intended usage of memory.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
@@ -236,7 +242,7 @@ bitflags! {
         /// Set this flag to only try to allocate from existing `ash::vk::DeviceMemory` blocks and never create new such block.
         ///
         /// If new allocation cannot be placed in any of the existing blocks, allocation
-        /// fails with `ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY` error.
+        /// fails with `VmaError::OutOfDeviceMemory`.
         ///
         /// You should not use `AllocationCreateFlags::DEDICATED_MEMORY` and `AllocationCreateFlags::NEVER_ALLOCATE` at the same time. It makes no sense.
         const NEVER_ALLOCATE = ffi::VmaAllocationCreateFlagBits::VMA_ALLOCATION_CREATE_NEVER_ALLOCATE_BIT as u32;
@@ -347,7 +353,7 @@ bitflags! {
 
 bitflags! {
     /// Flags for configuring `AllocatorPool` construction.
-    #[derive(Default, Clone, Copy)]
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
     pub struct AllocatorPoolCreateFlags: u32 {
         /// Use this flag if you always allocate only buffers and linear images or only optimal images
         /// out of this pool and so buffer-image granularity can be ignored.
@@ -378,7 +384,9 @@ bitflags! {
         /// By using this flag, you can achieve behavior of free-at-once, stack,
         /// ring buffer, and double stack.
         ///
-        /// When using this flag, you must specify PoolCreateInfo::max_block_count == 1 (or 0 for default).
+        /// When using this flag, you must specify PoolCreateInfo::max_block_count == 1.
+        ///
+        /// Prefer setting the algorithm with `PoolCreateInfo::set_algorithm`.
         const LINEAR_ALGORITHM = ffi::VmaPoolCreateFlagBits::VMA_POOL_CREATE_LINEAR_ALGORITHM_BIT as u32;
 
         /// Bit mask to extract only `*_ALGORITHM` bits from entire set of flags.
@@ -386,17 +394,165 @@ bitflags! {
     }
 }
 
+/// Allocation algorithm used by a custom pool, stored in the `*_ALGORITHM` bits of
+/// `AllocatorPoolCreateFlags`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PoolAlgorithm {
+    /// General purpose algorithm, used when no algorithm bit is set.
+    #[default]
+    Default,
+    /// See `AllocatorPoolCreateFlags::LINEAR_ALGORITHM`.
+    ///
+    /// With several blocks the pool is a plain linear allocator. Using it as a ring buffer or a
+    /// double stack, with `AllocationCreateFlags::UPPER_ADDRESS`, needs a single block,
+    /// `PoolCreateInfo::max_block_count == 1`.
+    Linear,
+}
+
+impl From<PoolAlgorithm> for AllocatorPoolCreateFlags {
+    fn from(algorithm: PoolAlgorithm) -> Self {
+        match algorithm {
+            PoolAlgorithm::Default => AllocatorPoolCreateFlags::empty(),
+            PoolAlgorithm::Linear => AllocatorPoolCreateFlags::LINEAR_ALGORITHM,
+        }
+    }
+}
+
+/// Resource an allocation of `Alloc::allocate_memory2` is made for, so that a dedicated
+/// allocation can be bound to it with `vk::MemoryDedicatedAllocateInfo`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum MemoryResource {
+    /// A buffer, e.g. from `ash::Device::create_buffer`.
+    Buffer(vk::Buffer),
+    /// An image, e.g. from `ash::Device::create_image`.
+    Image(vk::Image),
+}
+
+/// Set of memory types by index, bit `i` standing for memory type `i`, like
+/// `vk::MemoryRequirements::memory_type_bits`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct MemoryTypeMask(pub u32);
+
+impl MemoryTypeMask {
+    /// Every memory type.
+    pub const ALL: Self = MemoryTypeMask(!0);
+
+    /// Memory types having all of `flags`.
+    pub fn from_properties(
+        properties: &vk::PhysicalDeviceMemoryProperties,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Self {
+        let types = &properties.memory_types[..properties.memory_type_count as usize];
+        MemoryTypeMask(
+            types
+                .iter()
+                .enumerate()
+                .filter(|(_, memory_type)| memory_type.property_flags.contains(flags))
+                .fold(0, |bits, (index, _)| bits | 1 << index),
+        )
+    }
+
+    /// Returns `true` if memory type `memory_type_index` is in the set.
+    pub fn contains(&self, memory_type_index: u32) -> bool {
+        memory_type_index < 32 && self.0 & (1 << memory_type_index) != 0
+    }
+}
+
+impl From<u32> for MemoryTypeMask {
+    fn from(memory_type_bits: u32) -> Self {
+        MemoryTypeMask(memory_type_bits)
+    }
+}
+
+/// Pointers to the Vulkan functions used by VMA, see `AllocatorCreateInfo::from_raw_parts`.
+///
+/// VMA only calls `vkGetInstanceProcAddr` and `vkGetDeviceProcAddr` to fetch functions that
+/// are left null, which these pointers can't be, so every function must be provided. See
+/// `AllocatorCreateInfo::from_entry` to let VMA fetch all of them instead.
+pub type VulkanFunctions = ffi::VmaVulkanFunctions;
+
+/// Vulkan instance and device of `AllocatorCreateInfo`.
+pub(crate) enum VulkanObjects<'a> {
+    /// Loaded with ash, the functions are taken from its function tables
+    Ash {
+        instance: VulkanRef<'a, Instance>,
+        device: VulkanRef<'a, Device>,
+    },
+    /// Raw handles with functions from another loader
+    Raw {
+        instance: vk::Instance,
+        device: vk::Device,
+        functions: Box<VulkanFunctions>,
+    },
+    /// Raw handles whose functions VMA fetches itself
+    Dynamic {
+        instance: vk::Instance,
+        device: vk::Device,
+        get_instance_proc_addr: vk::PFN_vkGetInstanceProcAddr,
+        get_device_proc_addr: vk::PFN_vkGetDeviceProcAddr,
+    },
+}
+
+impl VulkanObjects<'_> {
+    pub(crate) fn instance_handle(&self) -> vk::Instance {
+        match self {
+            VulkanObjects::Ash { instance, .. } => instance.handle(),
+            VulkanObjects::Raw { instance, .. } | VulkanObjects::Dynamic { instance, .. } => {
+                *instance
+            }
+        }
+    }
+
+    pub(crate) fn device_handle(&self) -> vk::Device {
+        match self {
+            VulkanObjects::Ash { device, .. } => device.handle(),
+            VulkanObjects::Raw { device, .. } | VulkanObjects::Dynamic { device, .. } => *device,
+        }
+    }
+
+    /// Returns the strong references the allocator has to keep, if any.
+    pub(crate) fn shared(&self) -> Option<(Arc<Device>, Arc<Instance>)> {
+        match self {
+            VulkanObjects::Ash { instance, device } => device.shared().zip(instance.shared()),
+            VulkanObjects::Raw { .. } | VulkanObjects::Dynamic { .. } => None,
+        }
+    }
+}
+
+/// Vulkan object referenced by `AllocatorCreateInfo`, either borrowed or shared with the allocator.
+pub(crate) enum VulkanRef<'a, T> {
+    Borrowed(&'a T),
+    Shared(Arc<T>),
+}
+
+impl<T> VulkanRef<'_, T> {
+    /// Returns the strong reference the allocator has to keep, if any.
+    pub(crate) fn shared(&self) -> Option<Arc<T>> {
+        match self {
+            VulkanRef::Borrowed(_) => None,
+            VulkanRef::Shared(object) => Some(object.clone()),
+        }
+    }
+}
+
+impl<T> Deref for VulkanRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            VulkanRef::Borrowed(object) => object,
+            VulkanRef::Shared(object) => object,
+        }
+    }
+}
+
 pub struct AllocatorCreateInfo<'a> {
     /// Vulkan physical device. It must be valid throughout whole lifetime of created allocator.
     pub(crate) physical_device: PhysicalDevice,
 
-    /// Vulkan device.
-    /// It must be valid throughout the whole lifetime of created allocator.
-    pub(crate) device: &'a Device,
-
-    /// Handle to Vulkan instance object.
-    /// Must be valid throughout the whole lifetime of created allocator.
-    pub(crate) instance: &'a Instance,
+    /// Vulkan instance and device, with the functions VMA calls.
+    /// They must be valid throughout the whole lifetime of created allocator.
+    pub(crate) vulkan: VulkanObjects<'a>,
 
     /// Flags for created allocator.
     pub flags: AllocatorCreateFlags,
@@ -407,6 +563,8 @@ pub struct AllocatorCreateInfo<'a> {
 
     /// Custom CPU memory allocation callbacks. Optional.
     /// When specified, will also be used for all CPU-side memory allocations.
+    /// Otherwise VMA allocates from Rust's global allocator, with the `rust-allocator` feature
+    /// enabled by default.
     pub allocation_callbacks: Option<&'a vk::AllocationCallbacks<'a>>,
 
     /// Informative callbacks for [`vk::AllocateMemory`], [`vk::FreeMemory`]. Optional.
@@ -451,6 +609,35 @@ pub struct AllocatorCreateInfo<'a> {
     /// Any of the elements may be equal to 0, which means not to use `VkExportMemoryAllocateInfoKHR` on this memory type.
     /// This is also the default in case of `pTypeExternalMemoryHandleTypes` = NULL.
     pub type_external_memory_handle_types: &'a [vk::ExternalMemoryHandleTypeFlagsKHR],
+    /// Set if the device was created with the `protectedMemory` feature enabled, see
+    /// `vk::PhysicalDeviceProtectedMemoryFeatures`.
+    ///
+    /// Vulkan can't be queried for enabled features, so this is required for
+    /// `Allocator::create_protected_pool`.
+    pub protected_memory: bool,
+    /// Number of physical devices of the device group the device was created with, see
+    /// `vk::DeviceGroupDeviceCreateInfo`. 1 for a device created from a single physical device.
+    ///
+    /// Required for `Allocator::create_device_group_pool`, for the same reason as
+    /// `protected_memory`.
+    pub device_group_size: u32,
+    /// Names the `vk::DeviceMemory` blocks allocated by VMA with `VK_EXT_debug_utils`, e.g.
+    /// "vma pool 'Textures' block 3", and the buffers and images of `Alloc::create_buffer` and
+    /// `Alloc::create_image` after their allocation, see `Allocator::set_allocation_name`.
+    ///
+    /// The extension must be enabled on the device. The loader is cloned, and
+    /// `device_memory_callbacks` are still called.
+    pub debug_utils: Option<&'a ash::ext::debug_utils::Device>,
+    /// Extensions the device was created with, see `vk::DeviceCreateInfo::enabled_extension_names`.
+    ///
+    /// When set, `Allocator::new` fails with `VmaError::MissingDeviceExtension` for flags whose
+    /// extension isn't enabled and wasn't promoted to `vulkan_api_version`, e.g.
+    /// `AllocatorCreateFlags::EXT_MEMORY_BUDGET` without `VK_EXT_memory_budget`. Features like
+    /// `bufferDeviceAddress` can't be queried for, and aren't checked.
+    pub enabled_device_extensions: Option<&'a [&'a CStr]>,
+    /// File and flags of `AllocatorCreateInfo::record_to`.
+    #[cfg(feature = "recording")]
+    pub(crate) recording: Option<(std::path::PathBuf, crate::RecordFlags)>,
 }
 
 impl<'a> AllocatorCreateInfo<'a> {
@@ -458,11 +645,85 @@ impl<'a> AllocatorCreateInfo<'a> {
         instance: &'a ash::Instance,
         device: &'a ash::Device,
         physical_device: ash::vk::PhysicalDevice,
+    ) -> AllocatorCreateInfo<'a> {
+        Self::with_vulkan(
+            VulkanObjects::Ash {
+                instance: VulkanRef::Borrowed(instance),
+                device: VulkanRef::Borrowed(device),
+            },
+            physical_device,
+        )
+    }
+
+    /// Like `AllocatorCreateInfo::new`, but the allocator keeps strong references to the
+    /// instance and device.
+    ///
+    /// This guarantees that the `Arc`s outlive the allocator, so the allocator can't end up being
+    /// destroyed after the device it was created from because of struct field or drop ordering.
+    /// It is still up to the application to only destroy the device once the last `Arc` is gone.
+    pub fn owned(
+        instance: Arc<ash::Instance>,
+        device: Arc<ash::Device>,
+        physical_device: ash::vk::PhysicalDevice,
+    ) -> AllocatorCreateInfo<'a> {
+        Self::with_vulkan(
+            VulkanObjects::Ash {
+                instance: VulkanRef::Shared(instance),
+                device: VulkanRef::Shared(device),
+            },
+            physical_device,
+        )
+    }
+
+    /// Like `AllocatorCreateInfo::new`, for instances and devices not loaded with ash, e.g. by
+    /// vulkano or a custom loader, which provides the `functions` VMA calls.
+    ///
+    /// `AllocatorCreateInfo::debug_utils` still needs an ash loader.
+    pub fn from_raw_parts(
+        instance: vk::Instance,
+        device: vk::Device,
+        physical_device: vk::PhysicalDevice,
+        functions: VulkanFunctions,
+    ) -> AllocatorCreateInfo<'a> {
+        Self::with_vulkan(
+            VulkanObjects::Raw {
+                instance,
+                device,
+                functions: Box::new(functions),
+            },
+            physical_device,
+        )
+    }
+
+    /// Like `AllocatorCreateInfo::new`, but VMA fetches every function it calls through the
+    /// `vkGetInstanceProcAddr` of `entry` and the `vkGetDeviceProcAddr` of `instance`.
+    ///
+    /// This doesn't depend on the function tables of ash being complete, e.g. for functions of
+    /// newer Vulkan versions or extensions that ash doesn't load.
+    pub fn from_entry(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: ash::vk::PhysicalDevice,
+    ) -> AllocatorCreateInfo<'a> {
+        Self::with_vulkan(
+            VulkanObjects::Dynamic {
+                instance: instance.handle(),
+                device: device.handle(),
+                get_instance_proc_addr: entry.static_fn().get_instance_proc_addr,
+                get_device_proc_addr: instance.fp_v1_0().get_device_proc_addr,
+            },
+            physical_device,
+        )
+    }
+
+    fn with_vulkan(
+        vulkan: VulkanObjects<'a>,
+        physical_device: ash::vk::PhysicalDevice,
     ) -> AllocatorCreateInfo<'a> {
         AllocatorCreateInfo {
             physical_device,
-            device,
-            instance,
+            vulkan,
             flags: AllocatorCreateFlags::empty(),
             preferred_large_heap_block_size: 0,
             allocation_callbacks: None,
@@ -470,6 +731,58 @@ impl<'a> AllocatorCreateInfo<'a> {
             heap_size_limits: &[],
             vulkan_api_version: 0,
             type_external_memory_handle_types: &[],
+            protected_memory: false,
+            device_group_size: 1,
+            debug_utils: None,
+            enabled_device_extensions: None,
+            #[cfg(feature = "recording")]
+            recording: None,
+        }
+    }
+
+    /// Records the allocator to the file at `path` from its creation on, see
+    /// `Allocator::start_recording`. The file is created or truncated by `Allocator::new`, which
+    /// fails with `VmaError::RecordingFailed` if it can't be.
+    #[cfg(feature = "recording")]
+    pub fn record_to(
+        mut self,
+        path: impl Into<std::path::PathBuf>,
+        flags: crate::RecordFlags,
+    ) -> Self {
+        self.recording = Some((path.into(), flags));
+        self
+    }
+}
+
+/// Converts everything except the Vulkan function table, which is left null.
+impl From<&AllocatorCreateInfo<'_>> for ffi::VmaAllocatorCreateInfo {
+    fn from(info: &AllocatorCreateInfo<'_>) -> Self {
+        ffi::VmaAllocatorCreateInfo {
+            flags: info.flags.bits(),
+            physicalDevice: info.physical_device,
+            device: info.vulkan.device_handle(),
+            preferredLargeHeapBlockSize: info.preferred_large_heap_block_size,
+            pAllocationCallbacks: info
+                .allocation_callbacks
+                .map(|a| unsafe { std::mem::transmute(a) })
+                .unwrap_or(std::ptr::null()),
+            pDeviceMemoryCallbacks: info
+                .device_memory_callbacks
+                .map(|a| a as *const _)
+                .unwrap_or(std::ptr::null()),
+            pHeapSizeLimit: if info.heap_size_limits.is_empty() {
+                std::ptr::null()
+            } else {
+                info.heap_size_limits.as_ptr()
+            },
+            instance: info.vulkan.instance_handle(),
+            vulkanApiVersion: info.vulkan_api_version,
+            pVulkanFunctions: std::ptr::null(),
+            pTypeExternalMemoryHandleTypes: if info.type_external_memory_handle_types.is_empty() {
+                std::ptr::null()
+            } else {
+                info.type_external_memory_handle_types.as_ptr()
+            },
         }
     }
 }
@@ -520,6 +833,47 @@ impl<'a> PoolCreateInfo<'a> {
         let info = info.push_next(next);
         self.memory_allocate_next = info.p_next;
     }
+
+    /// Returns the algorithm selected by `flags`.
+    pub fn algorithm(&self) -> PoolAlgorithm {
+        if self
+            .flags
+            .contains(AllocatorPoolCreateFlags::LINEAR_ALGORITHM)
+        {
+            PoolAlgorithm::Linear
+        } else {
+            PoolAlgorithm::Default
+        }
+    }
+
+    /// Replaces the algorithm bits of `flags`.
+    pub fn set_algorithm(&mut self, algorithm: PoolAlgorithm) {
+        self.flags.remove(AllocatorPoolCreateFlags::ALGORITHM_MASK);
+        self.flags |= algorithm.into();
+    }
+
+    /// Checks the options that VMA would otherwise only catch with an assertion.
+    pub fn validate(&self) -> VmaResult<()> {
+        if self.flags.bits() & !AllocatorPoolCreateFlags::all().bits() != 0 {
+            return Err(VmaError::InvalidParameter("Unknown pool create flags"));
+        }
+        if self.max_block_count != 0 && self.min_block_count > self.max_block_count {
+            return Err(VmaError::InvalidParameter(
+                "min_block_count exceeds max_block_count",
+            ));
+        }
+        if self.min_allocation_alignment != 0 && !self.min_allocation_alignment.is_power_of_two() {
+            return Err(VmaError::InvalidParameter(
+                "min_allocation_alignment must be a power of two",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.priority) {
+            return Err(VmaError::InvalidParameter(
+                "Pool priority must be between 0 and 1",
+            ));
+        }
+        Ok(())
+    }
 }
 impl Default for PoolCreateInfo<'_> {
     fn default() -> Self {
@@ -537,6 +891,38 @@ impl Default for PoolCreateInfo<'_> {
     }
 }
 
+impl From<&PoolCreateInfo<'_>> for ffi::VmaPoolCreateInfo {
+    fn from(info: &PoolCreateInfo<'_>) -> Self {
+        ffi::VmaPoolCreateInfo {
+            memoryTypeIndex: info.memory_type_index,
+            flags: info.flags.bits(),
+            blockSize: info.block_size,
+            minBlockCount: info.min_block_count,
+            maxBlockCount: info.max_block_count,
+            priority: info.priority,
+            minAllocationAlignment: info.min_allocation_alignment,
+            pMemoryAllocateNext: info.memory_allocate_next as *mut std::ffi::c_void,
+        }
+    }
+}
+
+/// The `pMemoryAllocateNext` chain is carried over as is, so it must outlive the result.
+impl From<&ffi::VmaPoolCreateInfo> for PoolCreateInfo<'_> {
+    fn from(info: &ffi::VmaPoolCreateInfo) -> Self {
+        Self {
+            memory_type_index: info.memoryTypeIndex,
+            flags: AllocatorPoolCreateFlags::from_bits_retain(info.flags),
+            block_size: info.blockSize,
+            min_block_count: info.minBlockCount,
+            max_block_count: info.maxBlockCount,
+            priority: info.priority,
+            min_allocation_alignment: info.minAllocationAlignment,
+            memory_allocate_next: info.pMemoryAllocateNext,
+            _marker: PhantomData,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AllocationCreateInfo {
     pub flags: AllocationCreateFlags,
@@ -594,9 +980,9 @@ impl Default for AllocationCreateInfo {
     }
 }
 
-impl From<&AllocationCreateInfo> for ffi::VmaAllocationCreateInfo {
-    fn from(info: &AllocationCreateInfo) -> Self {
-        let usage = match info.usage {
+impl From<MemoryUsage> for ffi::VmaMemoryUsage {
+    fn from(usage: MemoryUsage) -> Self {
+        match usage {
             MemoryUsage::Unknown => ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_UNKNOWN,
             #[allow(deprecated)]
             MemoryUsage::GpuOnly => ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_GPU_ONLY,
@@ -614,10 +1000,41 @@ impl From<&AllocationCreateInfo> for ffi::VmaAllocationCreateInfo {
                 ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_AUTO_PREFER_DEVICE
             }
             MemoryUsage::AutoPreferHost => ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_AUTO_PREFER_HOST,
+        }
+    }
+}
+
+/// Fails only for `VMA_MEMORY_USAGE_MAX_ENUM`, which is returned back as the error.
+impl TryFrom<ffi::VmaMemoryUsage> for MemoryUsage {
+    type Error = ffi::VmaMemoryUsage;
+
+    fn try_from(usage: ffi::VmaMemoryUsage) -> Result<Self, Self::Error> {
+        #[allow(deprecated)]
+        let usage = match usage {
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_UNKNOWN => MemoryUsage::Unknown,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_GPU_ONLY => MemoryUsage::GpuOnly,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_CPU_ONLY => MemoryUsage::CpuOnly,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_CPU_TO_GPU => MemoryUsage::CpuToGpu,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_GPU_TO_CPU => MemoryUsage::GpuToCpu,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_CPU_COPY => MemoryUsage::CpuCopy,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_GPU_LAZILY_ALLOCATED => MemoryUsage::GpuLazy,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_AUTO => MemoryUsage::Auto,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_AUTO_PREFER_DEVICE => {
+                MemoryUsage::AutoPreferDevice
+            }
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_AUTO_PREFER_HOST => MemoryUsage::AutoPreferHost,
+            ffi::VmaMemoryUsage::VMA_MEMORY_USAGE_MAX_ENUM => return Err(usage),
         };
+        Ok(usage)
+    }
+}
+
+/// The resulting `pool` is always null; it is filled in by the `Alloc` implementation in use.
+impl From<&AllocationCreateInfo> for ffi::VmaAllocationCreateInfo {
+    fn from(info: &AllocationCreateInfo) -> Self {
         ffi::VmaAllocationCreateInfo {
             flags: info.flags.bits(),
-            usage,
+            usage: info.usage.into(),
             requiredFlags: info.required_flags,
             preferredFlags: info.preferred_flags,
             memoryTypeBits: info.memory_type_bits,
@@ -628,8 +1045,26 @@ impl From<&AllocationCreateInfo> for ffi::VmaAllocationCreateInfo {
     }
 }
 
+/// The `pool` member is dropped, pools are selected through the `Alloc` trait instead.
+impl TryFrom<&ffi::VmaAllocationCreateInfo> for AllocationCreateInfo {
+    type Error = ffi::VmaMemoryUsage;
+
+    fn try_from(info: &ffi::VmaAllocationCreateInfo) -> Result<Self, Self::Error> {
+        Ok(Self {
+            flags: AllocationCreateFlags::from_bits_retain(info.flags),
+            usage: info.usage.try_into()?,
+            required_flags: info.requiredFlags,
+            preferred_flags: info.preferredFlags,
+            memory_type_bits: info.memoryTypeBits,
+            user_data: info.pUserData as _,
+            priority: info.priority,
+        })
+    }
+}
+
 /// Parameters of `Allocation` objects, that can be retrieved using `Allocator::get_allocation_info`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AllocationInfo {
     /// Memory type index that this allocation was allocated from. It never changes.
     pub memory_type: u32,
@@ -638,6 +1073,7 @@ pub struct AllocationInfo {
     /// Same memory object can be shared by multiple allocations.
     ///
     /// It can change after the allocation is moved during \\ref defragmentation.
+    #[cfg_attr(feature = "serde", serde(with = "serde_handle"))]
     pub device_memory: vk::DeviceMemory,
     /// Offset in `VkDeviceMemory` object to the beginning of this allocation, in bytes. `(deviceMemory, offset)` pair is unique to this allocation.
     ///
@@ -663,6 +1099,9 @@ pub struct AllocationInfo {
     ///
     /// It can change after call to vmaMapMemory(), vmaUnmapMemory().
     /// It can also change after the allocation is moved during defragmentation.
+    ///
+    /// Not serialized, deserialized allocation infos have a null pointer.
+    #[cfg_attr(feature = "serde", serde(skip, default = "std::ptr::null_mut"))]
     pub mapped_data: *mut ::std::os::raw::c_void,
     /// Custom general-purpose pointer that was passed as VmaAllocationCreateInfo::pUserData or set using vmaSetAllocationUserData().
     ///
@@ -688,6 +1127,78 @@ impl From<ffi::VmaAllocationInfo> for AllocationInfo {
     }
 }
 
+impl AllocationInfo {
+    /// Returns a pointer to the mapped byte at `offset`.
+    ///
+    /// Fails if the allocation isn't mapped or if `offset` is outside of the allocation.
+    pub fn ptr_at(&self, offset: vk::DeviceSize) -> VmaResult<*mut u8> {
+        self.checked_ptr(offset, 1, 1)
+    }
+
+    /// Returns a pointer to a `T` stored in the mapped memory at `offset`.
+    ///
+    /// Fails if the allocation isn't mapped, if the whole `T` doesn't fit in the allocation, or
+    /// if the pointer isn't suitably aligned for `T`.
+    pub fn typed_ptr_at<T: Pod>(&self, offset: vk::DeviceSize) -> VmaResult<*mut T> {
+        self.checked_ptr(offset, mem::size_of::<T>(), mem::align_of::<T>())
+            .map(|ptr| ptr as *mut T)
+    }
+
+    /// Writes `value` to the mapped memory at `offset`, see `AllocationInfo::typed_ptr_at`.
+    ///
+    /// # Safety
+    /// The allocation must still be mapped at `mapped_data`, and the device must not access the
+    /// written range concurrently.
+    pub unsafe fn write_pod_at<T: Pod>(&self, offset: vk::DeviceSize, value: &T) -> VmaResult<()> {
+        self.typed_ptr_at::<T>(offset)?.write(*value);
+        Ok(())
+    }
+
+    /// Writes `values` contiguously to the mapped memory starting at `offset`.
+    ///
+    /// # Safety
+    /// Same requirements as `AllocationInfo::write_pod_at`.
+    pub unsafe fn write_pod_slice_at<T: Pod>(
+        &self,
+        offset: vk::DeviceSize,
+        values: &[T],
+    ) -> VmaResult<()> {
+        let ptr = self.checked_ptr(offset, mem::size_of_val(values), mem::align_of::<T>())?;
+        std::ptr::copy_nonoverlapping(values.as_ptr(), ptr as *mut T, values.len());
+        Ok(())
+    }
+
+    /// Reads a `T` from the mapped memory at `offset`, see `AllocationInfo::typed_ptr_at`.
+    ///
+    /// # Safety
+    /// The allocation must still be mapped at `mapped_data`, and the device must not write the
+    /// read range concurrently.
+    pub unsafe fn read_pod_at<T: Pod>(&self, offset: vk::DeviceSize) -> VmaResult<T> {
+        Ok(self.typed_ptr_at::<T>(offset)?.read())
+    }
+
+    fn checked_ptr(&self, offset: vk::DeviceSize, size: usize, align: usize) -> VmaResult<*mut u8> {
+        if self.mapped_data.is_null() {
+            return Err(VmaError::InvalidParameter("Allocation is not mapped"));
+        }
+        let in_bounds = offset
+            .checked_add(size as vk::DeviceSize)
+            .is_some_and(|end| end <= self.size);
+        if !in_bounds {
+            return Err(VmaError::InvalidParameter(
+                "Range is outside of the allocation",
+            ));
+        }
+        let ptr = (self.mapped_data as *mut u8).wrapping_add(offset as usize);
+        if ptr.align_offset(align) != 0 {
+            return Err(VmaError::InvalidParameter(
+                "Pointer is not aligned for the requested type",
+            ));
+        }
+        Ok(ptr)
+    }
+}
+
 bitflags! {
     /// Flags for configuring `VirtualBlock` construction
     #[derive(Default)]
@@ -760,6 +1271,19 @@ pub struct VirtualBlockCreateInfo<'a> {
     pub allocation_callbacks: Option<&'a vk::AllocationCallbacks<'a>>,
 }
 
+impl From<&VirtualBlockCreateInfo<'_>> for ffi::VmaVirtualBlockCreateInfo {
+    fn from(info: &VirtualBlockCreateInfo<'_>) -> Self {
+        ffi::VmaVirtualBlockCreateInfo {
+            size: info.size,
+            flags: info.flags.bits(),
+            pAllocationCallbacks: info
+                .allocation_callbacks
+                .map(|a| unsafe { std::mem::transmute(a) })
+                .unwrap_or(std::ptr::null()),
+        }
+    }
+}
+
 /// Parameters of `VirtualAllocation` objects, that can be retrieved using `VirtualBlock::get_allocation_info`.
 #[derive(Debug, Clone, Copy)]
 pub struct VirtualAllocationInfo {
@@ -808,3 +1332,33 @@ impl From<VirtualAllocationCreateInfo> for ffi::VmaVirtualAllocationCreateInfo {
         (&info).into()
     }
 }
+
+impl From<&ffi::VmaVirtualAllocationCreateInfo> for VirtualAllocationCreateInfo {
+    fn from(info: &ffi::VmaVirtualAllocationCreateInfo) -> Self {
+        Self {
+            size: info.size,
+            alignment: info.alignment,
+            user_data: info.pUserData as _,
+            flags: VirtualAllocationCreateFlags::from_bits_retain(info.flags),
+        }
+    }
+}
+
+/// Serializes Vulkan handles as their raw `u64` value.
+#[cfg(feature = "serde")]
+mod serde_handle {
+    use ash::vk::Handle;
+
+    pub fn serialize<H: Handle + Copy, S: serde::Serializer>(
+        handle: &H,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(handle.as_raw())
+    }
+
+    pub fn deserialize<'de, H: Handle, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<H, D::Error> {
+        <u64 as serde::Deserialize>::deserialize(deserializer).map(H::from_raw)
+    }
+}
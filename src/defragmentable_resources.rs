@@ -0,0 +1,779 @@
+use std::collections::HashMap;
+
+use crate::defragmentation::DefragPass;
+use crate::ffi;
+use crate::tracking::BOOKKEEPING;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::AllocationId;
+use crate::Allocator;
+use crate::DefragMove;
+use crate::DefragMoveOperation;
+use crate::DefragStats;
+use crate::DefragmentationContext;
+use crate::MovedAllocation;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Buffers and images that `Allocator::defragment` may move, by allocation.
+///
+/// Resources are recreated from the parameters they were registered with. The `p_next` chains
+/// and queue family indices of the create infos aren't kept, so recreated resources use
+/// `vk::SharingMode::EXCLUSIVE`. Resources must be usable as both transfer source and destination,
+/// since the usage of recreated resources must match for their memory requirements to match.
+/// Allocations without a registered resource are never moved.
+///
+/// Defragmenting resources fails with `VmaError::InvalidParameter` with the `minimal-checks`
+/// feature, which doesn't keep track of allocations, so moves can't be matched to resources.
+pub struct DefragmentableResources<'a> {
+    device: &'a ash::Device,
+    resources: HashMap<AllocationId, Resource>,
+}
+
+enum Resource {
+    Buffer {
+        buffer: vk::Buffer,
+        create_info: vk::BufferCreateInfo<'static>,
+    },
+    Image {
+        image: vk::Image,
+        create_info: vk::ImageCreateInfo<'static>,
+        layout: vk::ImageLayout,
+    },
+}
+
+impl<'a> DefragmentableResources<'a> {
+    pub fn new(device: &'a ash::Device) -> Self {
+        DefragmentableResources {
+            device,
+            resources: HashMap::new(),
+        }
+    }
+
+    /// Registers `buffer`, created with `create_info` and bound to `allocation`.
+    pub fn register_buffer(
+        &mut self,
+        allocation: &Allocation,
+        buffer: vk::Buffer,
+        create_info: &vk::BufferCreateInfo,
+    ) {
+        debug_assert!(create_info
+            .usage
+            .contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST));
+        let create_info = vk::BufferCreateInfo::default()
+            .flags(create_info.flags)
+            .size(create_info.size)
+            .usage(create_info.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        self.resources.insert(
+            allocation.id(),
+            Resource::Buffer {
+                buffer,
+                create_info,
+            },
+        );
+    }
+
+    /// Registers `image`, created with `create_info` and bound to `allocation`.
+    ///
+    /// `layout` is the layout the image is in whenever `Allocator::defragment` runs, and the
+    /// layout of the recreated image. Images in `vk::ImageLayout::UNDEFINED` are recreated
+    /// without copying their contents. Multi-planar formats aren't supported.
+    pub fn register_image(
+        &mut self,
+        allocation: &Allocation,
+        image: vk::Image,
+        create_info: &vk::ImageCreateInfo,
+        layout: vk::ImageLayout,
+    ) {
+        debug_assert!(create_info
+            .usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST));
+        let create_info = vk::ImageCreateInfo::default()
+            .flags(create_info.flags)
+            .image_type(create_info.image_type)
+            .format(create_info.format)
+            .extent(create_info.extent)
+            .mip_levels(create_info.mip_levels)
+            .array_layers(create_info.array_layers)
+            .samples(create_info.samples)
+            .tiling(create_info.tiling)
+            .usage(create_info.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        self.resources.insert(
+            allocation.id(),
+            Resource::Image {
+                image,
+                create_info,
+                layout,
+            },
+        );
+    }
+
+    /// Forgets the resource of `allocation`, e.g. before destroying it.
+    pub fn unregister(&mut self, allocation: &Allocation) {
+        self.resources.remove(&allocation.id());
+    }
+
+    /// Current buffer of `allocation`, which changes when the allocation is moved.
+    pub fn buffer(&self, allocation: &Allocation) -> Option<vk::Buffer> {
+        match self.resources.get(&allocation.id()) {
+            Some(Resource::Buffer { buffer, .. }) => Some(*buffer),
+            _ => None,
+        }
+    }
+
+    /// Current image of `allocation`, which changes when the allocation is moved.
+    pub fn image(&self, allocation: &Allocation) -> Option<vk::Image> {
+        match self.resources.get(&allocation.id()) {
+            Some(Resource::Image { image, .. }) => Some(*image),
+            _ => None,
+        }
+    }
+
+    /// Creates the new resources of a pass at the destination of their moves, returning them
+    /// with their allocation. Moves of unregistered allocations are ignored, as well as moves
+    /// that are already ignored, e.g. because their allocation is pinned.
+    ///
+    /// On failure, resources created so far are destroyed again.
+    pub(crate) unsafe fn recreate(
+        &self,
+        allocator: &Allocator,
+        moves: &mut [DefragMove],
+    ) -> VmaResult<Vec<(AllocationId, RecreatedResource)>> {
+        let mut recreated = Vec::new();
+        for defrag_move in moves.iter_mut() {
+            if defrag_move.operation() != DefragMoveOperation::Copy {
+                continue;
+            }
+            let resource = defrag_move
+                .source_id()
+                .and_then(|id| Some((id, self.resources.get(&id)?)));
+            let Some((id, resource)) = resource else {
+                defrag_move.set_operation(DefragMoveOperation::Ignore);
+                continue;
+            };
+            match self.recreate_one(allocator, defrag_move, resource) {
+                Ok(new) => recreated.push((id, new)),
+                Err(err) => {
+                    self.destroy(recreated.into_iter().map(|(_, new)| new));
+                    return Err(err);
+                }
+            }
+        }
+        Ok(recreated)
+    }
+
+    unsafe fn recreate_one(
+        &self,
+        allocator: &Allocator,
+        defrag_move: &DefragMove,
+        resource: &Resource,
+    ) -> VmaResult<RecreatedResource> {
+        match resource {
+            Resource::Buffer { create_info, .. } => {
+                let buffer = self.device.create_buffer(create_info, None)?;
+                let bound =
+                    ffi::vmaBindBufferMemory(allocator.internal, defrag_move.destination, buffer)
+                        .result();
+                if let Err(err) = bound {
+                    self.device.destroy_buffer(buffer, None);
+                    return Err(err.into());
+                }
+                Ok(RecreatedResource::Buffer(buffer))
+            }
+            Resource::Image { create_info, .. } => {
+                let image = self.device.create_image(create_info, None)?;
+                let bound =
+                    ffi::vmaBindImageMemory(allocator.internal, defrag_move.destination, image)
+                        .result();
+                if let Err(err) = bound {
+                    self.device.destroy_image(image, None);
+                    return Err(err.into());
+                }
+                Ok(RecreatedResource::Image(image))
+            }
+        }
+    }
+
+    /// Records the copies from the current resources to the recreated ones into `cmd`.
+    pub(crate) unsafe fn record_copies(
+        &self,
+        cmd: vk::CommandBuffer,
+        recreated: &[(AllocationId, RecreatedResource)],
+    ) {
+        let device = self.device;
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for (id, new) in recreated {
+            if let (
+                Some(Resource::Image {
+                    image,
+                    create_info,
+                    layout,
+                }),
+                RecreatedResource::Image(new_image),
+            ) = (self.resources.get(id), new)
+            {
+                if *layout == vk::ImageLayout::UNDEFINED {
+                    continue;
+                }
+                let range = full_range(create_info);
+                before.push(
+                    vk::ImageMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+                        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                        .old_layout(*layout)
+                        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(*image)
+                        .subresource_range(range),
+                );
+                before.push(
+                    vk::ImageMemoryBarrier::default()
+                        .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .old_layout(vk::ImageLayout::UNDEFINED)
+                        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(*new_image)
+                        .subresource_range(range),
+                );
+                after.push(
+                    vk::ImageMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(
+                            vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                        )
+                        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .new_layout(*layout)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(*new_image)
+                        .subresource_range(range),
+                );
+            }
+        }
+
+        let memory_before = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[memory_before],
+            &[],
+            &before,
+        );
+
+        for (id, new) in recreated {
+            match (self.resources.get(id), new) {
+                (
+                    Some(Resource::Buffer {
+                        buffer,
+                        create_info,
+                    }),
+                    RecreatedResource::Buffer(new_buffer),
+                ) => {
+                    let region = vk::BufferCopy::default().size(create_info.size);
+                    device.cmd_copy_buffer(cmd, *buffer, *new_buffer, &[region]);
+                }
+                (
+                    Some(Resource::Image {
+                        image,
+                        create_info,
+                        layout,
+                    }),
+                    RecreatedResource::Image(new_image),
+                ) if *layout != vk::ImageLayout::UNDEFINED => {
+                    let range = full_range(create_info);
+                    let regions: Vec<vk::ImageCopy> = (0..create_info.mip_levels)
+                        .map(|mip_level| {
+                            let layers = vk::ImageSubresourceLayers::default()
+                                .aspect_mask(range.aspect_mask)
+                                .mip_level(mip_level)
+                                .base_array_layer(0)
+                                .layer_count(create_info.array_layers);
+                            vk::ImageCopy::default()
+                                .src_subresource(layers)
+                                .dst_subresource(layers)
+                                .extent(mip_extent(create_info.extent, mip_level))
+                        })
+                        .collect();
+                    device.cmd_copy_image(
+                        cmd,
+                        *image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        *new_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &regions,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let memory_after = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE);
+        device.cmd_pipeline_barrier(
+            cmd,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[memory_after],
+            &[],
+            &after,
+        );
+    }
+
+    /// Destroys the old resources once the copies are done, replacing them with the recreated
+    /// ones.
+    pub(crate) unsafe fn replace(&mut self, recreated: Vec<(AllocationId, RecreatedResource)>) {
+        for (id, new) in recreated {
+            match (self.resources.get_mut(&id), new) {
+                (Some(Resource::Buffer { buffer, .. }), RecreatedResource::Buffer(new_buffer)) => {
+                    self.device.destroy_buffer(*buffer, None);
+                    *buffer = new_buffer;
+                }
+                (Some(Resource::Image { image, .. }), RecreatedResource::Image(new_image)) => {
+                    self.device.destroy_image(*image, None);
+                    *image = new_image;
+                }
+                (_, new) => self.destroy(std::iter::once(new)),
+            }
+        }
+    }
+
+    /// Destroys recreated resources that won't be used, e.g. because the pass failed.
+    pub(crate) unsafe fn destroy(&self, recreated: impl IntoIterator<Item = RecreatedResource>) {
+        destroy_recreated(self.device, recreated);
+    }
+
+    pub(crate) fn device(&self) -> &ash::Device {
+        self.device
+    }
+}
+
+/// Resource created at the destination of a move, see `DefragmentableResources::recreate`.
+pub(crate) enum RecreatedResource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+unsafe fn destroy_recreated(
+    device: &ash::Device,
+    recreated: impl IntoIterator<Item = RecreatedResource>,
+) {
+    for new in recreated {
+        match new {
+            RecreatedResource::Buffer(buffer) => device.destroy_buffer(buffer, None),
+            RecreatedResource::Image(image) => device.destroy_image(image, None),
+        }
+    }
+}
+
+fn full_range(create_info: &vk::ImageCreateInfo) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(aspect_mask(create_info.format))
+        .base_mip_level(0)
+        .level_count(create_info.mip_levels)
+        .base_array_layer(0)
+        .layer_count(create_info.array_layers)
+}
+
+fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+fn mip_extent(extent: vk::Extent3D, mip_level: u32) -> vk::Extent3D {
+    vk::Extent3D {
+        width: (extent.width >> mip_level).max(1),
+        height: (extent.height >> mip_level).max(1),
+        depth: (extent.depth >> mip_level).max(1),
+    }
+}
+
+impl Allocator {
+    /// Defragments memory, moving the registered buffers and images of `resources`.
+    ///
+    /// Each pass recreates the moved resources at their new place, records the copies into
+    /// `cmd` and calls `submit` with it, which must submit `cmd` and wait until it completed.
+    /// The old resources are then destroyed, and `DefragmentableResources::buffer` and
+    /// `DefragmentableResources::image` return the new ones. Moves of allocations without a
+    /// registered resource are ignored.
+    ///
+    /// If recreating a resource or `submit` fails, the moves of the pass are ignored and the
+    /// error is returned.
+    ///
+    /// # Safety
+    /// The device must not use the registered resources while this runs, images must be in
+    /// their registered layout, and `cmd` must be a primary command buffer in the initial state
+    /// from a pool that allows resetting it. Descriptors and other references to the old
+    /// resources are invalid afterwards.
+    pub unsafe fn defragment(
+        &self,
+        info: &ffi::VmaDefragmentationInfo,
+        resources: &mut DefragmentableResources,
+        cmd: vk::CommandBuffer,
+        submit: impl FnMut(vk::CommandBuffer) -> VmaResult<()>,
+    ) -> VmaResult<DefragStats> {
+        self.defragment_passes(info, resources, cmd, submit, u32::MAX)
+    }
+
+    /// `Allocator::defragment` stopping after `max_passes` passes.
+    unsafe fn defragment_passes(
+        &self,
+        info: &ffi::VmaDefragmentationInfo,
+        resources: &mut DefragmentableResources,
+        cmd: vk::CommandBuffer,
+        mut submit: impl FnMut(vk::CommandBuffer) -> VmaResult<()>,
+        max_passes: u32,
+    ) -> VmaResult<DefragStats> {
+        check_bookkeeping()?;
+        let context = self.begin_defragmentation(info)?;
+        for _ in 0..max_passes {
+            let mut recreated = Vec::new();
+            let more = context.begin_pass(|moves| {
+                recreated = resources.recreate(self, moves)?;
+                if recreated.is_empty() {
+                    return Ok(());
+                }
+                let copied = record_and_submit(resources, cmd, &recreated, &mut submit);
+                if copied.is_err() {
+                    resources.destroy(recreated.drain(..).map(|(_, new)| new));
+                }
+                copied
+            })?;
+            resources.replace(recreated);
+            if !more {
+                break;
+            }
+        }
+        Ok(context.end())
+    }
+
+    /// Allocates like `Alloc::allocate_memory`, defragmenting the default pools with `fallback`
+    /// and retrying once if the allocation fails with `VmaError::OutOfDeviceMemory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::allocate_memory` and `Allocator::defragment`.
+    #[track_caller]
+    pub unsafe fn allocate_with_defrag_fallback(
+        &self,
+        memory_requirements: &vk::MemoryRequirements,
+        create_info: &AllocationCreateInfo,
+        fallback: &mut DefragFallback<'_, '_, impl FnMut(vk::CommandBuffer) -> VmaResult<()>>,
+    ) -> VmaResult<Allocation> {
+        match self.allocate_memory(memory_requirements, create_info) {
+            Err(err) if err.is_out_of_device_memory() => {
+                fallback.run(self)?;
+                self.allocate_memory(memory_requirements, create_info)
+            }
+            result => result,
+        }
+    }
+
+    /// `Alloc::create_buffer` with the fallback of `Allocator::allocate_with_defrag_fallback`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::create_buffer` and `Allocator::defragment`.
+    #[track_caller]
+    pub unsafe fn create_buffer_with_defrag_fallback(
+        &self,
+        buffer_info: &vk::BufferCreateInfo,
+        create_info: &AllocationCreateInfo,
+        fallback: &mut DefragFallback<'_, '_, impl FnMut(vk::CommandBuffer) -> VmaResult<()>>,
+    ) -> VmaResult<(vk::Buffer, Allocation)> {
+        match self.create_buffer(buffer_info, create_info) {
+            Err(err) if err.is_out_of_device_memory() => {
+                fallback.run(self)?;
+                self.create_buffer(buffer_info, create_info)
+            }
+            result => result,
+        }
+    }
+
+    /// `Alloc::create_image` with the fallback of `Allocator::allocate_with_defrag_fallback`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::create_image` and `Allocator::defragment`.
+    #[track_caller]
+    pub unsafe fn create_image_with_defrag_fallback(
+        &self,
+        image_info: &vk::ImageCreateInfo,
+        create_info: &AllocationCreateInfo,
+        fallback: &mut DefragFallback<'_, '_, impl FnMut(vk::CommandBuffer) -> VmaResult<()>>,
+    ) -> VmaResult<(vk::Image, Allocation)> {
+        match self.create_image(image_info, create_info) {
+            Err(err) if err.is_out_of_device_memory() => {
+                fallback.run(self)?;
+                self.create_image(image_info, create_info)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Bounded defragmentation run when an allocation fails, see
+/// `Allocator::allocate_with_defrag_fallback`.
+///
+/// Runs like `Allocator::defragment` on the default pools, for at most 4 passes by default.
+pub struct DefragFallback<'a, 'b, F> {
+    resources: &'a mut DefragmentableResources<'b>,
+    cmd: vk::CommandBuffer,
+    submit: F,
+    max_passes: u32,
+}
+
+impl<'a, 'b, F: FnMut(vk::CommandBuffer) -> VmaResult<()>> DefragFallback<'a, 'b, F> {
+    /// See `Allocator::defragment` for `resources`, `cmd` and `submit`.
+    pub fn new(
+        resources: &'a mut DefragmentableResources<'b>,
+        cmd: vk::CommandBuffer,
+        submit: F,
+    ) -> Self {
+        DefragFallback {
+            resources,
+            cmd,
+            submit,
+            max_passes: 4,
+        }
+    }
+
+    /// Sets the maximum number of passes run per failed allocation.
+    pub fn max_passes(mut self, max_passes: u32) -> Self {
+        self.max_passes = max_passes;
+        self
+    }
+
+    unsafe fn run(&mut self, allocator: &Allocator) -> VmaResult<DefragStats> {
+        let info = ffi::VmaDefragmentationInfo {
+            flags: 0,
+            pool: std::ptr::null_mut(),
+            maxBytesPerPass: 0,
+            maxAllocationsPerPass: 0,
+            pfnBreakCallback: None,
+            pBreakCallbackUserData: std::ptr::null_mut(),
+        };
+        allocator.defragment_passes(
+            &info,
+            self.resources,
+            self.cmd,
+            &mut self.submit,
+            self.max_passes,
+        )
+    }
+}
+
+/// Fails with the `minimal-checks` feature, without which moves can't be matched to resources.
+fn check_bookkeeping() -> VmaResult<()> {
+    if !BOOKKEEPING {
+        return Err(VmaError::InvalidParameter(
+            "Defragmenting resources isn't available with the minimal-checks feature",
+        ));
+    }
+    Ok(())
+}
+
+unsafe fn record_and_submit(
+    resources: &DefragmentableResources,
+    cmd: vk::CommandBuffer,
+    recreated: &[(AllocationId, RecreatedResource)],
+    submit: &mut impl FnMut(vk::CommandBuffer) -> VmaResult<()>,
+) -> VmaResult<()> {
+    let device = resources.device();
+    device.reset_command_buffer(cmd, vk::CommandBufferResetFlags::empty())?;
+    let begin_info =
+        vk::CommandBufferBeginInfo::default().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    device.begin_command_buffer(cmd, &begin_info)?;
+    resources.record_copies(cmd, recreated);
+    device.end_command_buffer(cmd)?;
+    submit(cmd)
+}
+
+/// Defragmentation whose copies run on a dedicated queue, see
+/// `Allocator::begin_async_defragmentation`.
+///
+/// Each pass is submitted to the queue with a signal of the timeline semaphore, and only ended by
+/// a later `AsyncDefragmentation::poll` once the semaphore reached the signaled value, so
+/// rendering can go on meanwhile.
+pub struct AsyncDefragmentation<'a> {
+    /// Taken by `AsyncDefragmentation::end`
+    context: Option<DefragmentationContext<'a>>,
+    device: ash::Device,
+    queue: vk::Queue,
+    cmd: vk::CommandBuffer,
+    semaphore: vk::Semaphore,
+    /// Value signaled by the last submitted pass
+    value: u64,
+    in_flight: Option<(DefragPass, Vec<(AllocationId, RecreatedResource)>)>,
+    done: bool,
+}
+
+impl Allocator {
+    /// Begins a defragmentation whose copies are submitted to `queue`, e.g. a transfer queue.
+    ///
+    /// Drive it with `AsyncDefragmentation::poll`. Each pass records its copies into `cmd` and
+    /// signals the next value of the timeline `semaphore`, starting after `value`. Waiting on
+    /// the semaphore lets other queues know when resources moved.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::defragment`, for as long as the defragmentation runs.
+    /// `cmd` must be allocated from a pool of the family of `queue`, `semaphore` must be a
+    /// timeline semaphore whose value is `value`, and signaled only by the defragmentation until
+    /// it ends.
+    pub unsafe fn begin_async_defragmentation(
+        &self,
+        info: &ffi::VmaDefragmentationInfo,
+        resources: &DefragmentableResources,
+        queue: vk::Queue,
+        cmd: vk::CommandBuffer,
+        semaphore: vk::Semaphore,
+        value: u64,
+    ) -> VmaResult<AsyncDefragmentation<'_>> {
+        check_bookkeeping()?;
+        Ok(AsyncDefragmentation {
+            context: Some(self.begin_defragmentation(info)?),
+            device: resources.device().clone(),
+            queue,
+            cmd,
+            semaphore,
+            value,
+            in_flight: None,
+            done: false,
+        })
+    }
+}
+
+impl<'a> AsyncDefragmentation<'a> {
+    fn context(&self) -> &DefragmentationContext<'a> {
+        self.context.as_ref().unwrap()
+    }
+
+    /// Advances the defragmentation without blocking.
+    ///
+    /// Ends the pass in flight once its copies completed, replacing its resources in
+    /// `resources`, then submits the next pass. Returns `true` once defragmentation is done.
+    ///
+    /// If a pass fails, its moves are ignored and the error is returned.
+    ///
+    /// # Safety
+    /// `resources` must be the resources the defragmentation began with.
+    pub unsafe fn poll(&mut self, resources: &mut DefragmentableResources) -> VmaResult<bool> {
+        if let Some((pass, recreated)) = self.in_flight.take() {
+            if self.device.get_semaphore_counter_value(self.semaphore)? < self.value {
+                self.in_flight = Some((pass, recreated));
+                return Ok(false);
+            }
+            let more = self.context().finish_pass(pass, false);
+            resources.replace(recreated);
+            self.done = !more?;
+        }
+        while !self.done {
+            let Some(mut pass) = self.context().start_pass()? else {
+                self.done = true;
+                break;
+            };
+            let recreated = match resources.recreate(self.context().allocator(), &mut pass.moves) {
+                Ok(recreated) => recreated,
+                Err(err) => {
+                    let _ = self.context().finish_pass(pass, true);
+                    return Err(err);
+                }
+            };
+            if recreated.is_empty() {
+                self.done = !self.context().finish_pass(pass, false)?;
+                continue;
+            }
+            let (device, queue, semaphore) = (&self.device, self.queue, self.semaphore);
+            let signal_value = self.value + 1;
+            let submitted = record_and_submit(resources, self.cmd, &recreated, &mut |cmd| {
+                let command_buffers = [cmd];
+                let semaphores = [semaphore];
+                let values = [signal_value];
+                let mut timeline_info =
+                    vk::TimelineSemaphoreSubmitInfo::default().signal_semaphore_values(&values);
+                let submit_info = vk::SubmitInfo::default()
+                    .command_buffers(&command_buffers)
+                    .signal_semaphores(&semaphores)
+                    .push_next(&mut timeline_info);
+                device.queue_submit(queue, &[submit_info], vk::Fence::null())?;
+                Ok(())
+            });
+            if let Err(err) = submitted {
+                resources.destroy(recreated.into_iter().map(|(_, new)| new));
+                let _ = self.context().finish_pass(pass, true);
+                return Err(err);
+            }
+            self.value = signal_value;
+            self.in_flight = Some((pass, recreated));
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Statistics of the last pass that was ended, see `DefragmentationContext::last_pass_stats`.
+    pub fn last_pass_stats(&self) -> DefragStats {
+        self.context().last_pass_stats()
+    }
+
+    /// Allocations moved by the last pass that was ended, see
+    /// `DefragmentationContext::last_pass_moves`.
+    pub fn last_pass_moves(&self) -> impl Iterator<Item = MovedAllocation> {
+        self.context().last_pass_moves()
+    }
+
+    /// Value of the semaphore signaled by the last submitted pass.
+    pub fn signal_value(&self) -> u64 {
+        self.value
+    }
+
+    /// Ends the defragmentation, see `DefragmentationContext::end`.
+    ///
+    /// A pass still in flight is waited for and its moves are ignored.
+    pub fn end(mut self) -> DefragStats {
+        self.abandon_in_flight();
+        self.context.take().unwrap().end()
+    }
+
+    fn abandon_in_flight(&mut self) {
+        if let Some((pass, recreated)) = self.in_flight.take() {
+            unsafe {
+                let semaphores = [self.semaphore];
+                let values = [self.value];
+                let wait_info = vk::SemaphoreWaitInfo::default()
+                    .semaphores(&semaphores)
+                    .values(&values);
+                let _ = self.device.wait_semaphores(&wait_info, u64::MAX);
+                let _ = self.context().finish_pass(pass, true);
+                destroy_recreated(&self.device, recreated.into_iter().map(|(_, new)| new));
+            }
+        }
+    }
+}
+
+/// Waits for the pass in flight, if any, and ignores its moves.
+impl Drop for AsyncDefragmentation<'_> {
+    fn drop(&mut self) {
+        if self.context.is_some() {
+            self.abandon_in_flight();
+        }
+    }
+}
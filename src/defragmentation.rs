@@ -1,13 +1,176 @@
 use crate::ffi;
+use crate::tracking::{raw_allocation_info, BOOKKEEPING};
+use crate::Allocation;
+use crate::AllocationId;
+use crate::AllocationInfo;
 use crate::Allocator;
-use ash::prelude::VkResult;
+use crate::AllocatorPool;
+use crate::PoolHandle;
+use crate::{VmaError, VmaResult};
 use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::fmt;
 
 pub use ffi::VmaDefragmentationMove as DefragmentationMove;
 pub use ffi::VmaDefragmentationStats as DefragmentationStats;
+/// Running defragmentation of a custom pool or of the default pools.
+///
+/// Contexts of different pools can run at the same time, e.g. on worker threads, while a pool
+/// can only be defragmented by one context at a time.
 pub struct DefragmentationContext<'a> {
     allocator: &'a Allocator,
     raw: ffi::VmaDefragmentationContext,
+    pool: PoolHandle,
+    last_pass_stats: Cell<DefragStats>,
+    last_pass_moves: RefCell<Vec<MovedAllocation>>,
+}
+
+// VMA synchronizes defragmentations of different pools, and a context is only used by one thread at a time
+unsafe impl Send for DefragmentationContext<'_> {}
+
+/// Statistics of a defragmentation, or of a single pass of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DefragStats {
+    /// Total number of bytes that have been copied while moving allocations to different places.
+    pub bytes_moved: vk::DeviceSize,
+    /// Total number of bytes that have been released to the system by freeing empty
+    /// `vk::DeviceMemory` objects.
+    pub bytes_freed: vk::DeviceSize,
+    /// Number of allocations that have been moved to different places.
+    pub allocations_moved: u32,
+    /// Number of empty `vk::DeviceMemory` objects that have been released to the system.
+    pub device_memory_blocks_freed: u32,
+}
+
+impl From<ffi::VmaDefragmentationStats> for DefragStats {
+    fn from(stats: ffi::VmaDefragmentationStats) -> Self {
+        DefragStats {
+            bytes_moved: stats.bytesMoved,
+            bytes_freed: stats.bytesFreed,
+            allocations_moved: stats.allocationsMoved,
+            device_memory_blocks_freed: stats.deviceMemoryBlocksFreed,
+        }
+    }
+}
+
+impl fmt::Display for DefragStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allocation(s) moved ({} bytes), {} block(s) freed ({} bytes)",
+            self.allocations_moved,
+            self.bytes_moved,
+            self.device_memory_blocks_freed,
+            self.bytes_freed
+        )
+    }
+}
+
+/// Allocation moved by a defragmentation pass, see `DefragmentationContext::last_pass_moves`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovedAllocation {
+    /// Id of the moved allocation.
+    pub id: AllocationId,
+    /// Memory object the allocation was in before the pass.
+    pub old_memory: vk::DeviceMemory,
+    /// Offset of the allocation in `old_memory`.
+    pub old_offset: vk::DeviceSize,
+    /// Memory object the allocation is in now.
+    pub new_memory: vk::DeviceMemory,
+    /// Offset of the allocation in `new_memory`.
+    pub new_offset: vk::DeviceSize,
+}
+
+/// What the end of a defragmentation pass does with a `DefragMove`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefragMoveOperation {
+    /// The resource was recreated at the destination and its data copied, the allocation now
+    /// points to the destination memory.
+    #[default]
+    Copy,
+    /// The allocation can't be moved and stays where it is.
+    Ignore,
+    /// The resource was destroyed and the allocation is freed.
+    ///
+    /// The `Allocation` handle must not be used anymore and must not be freed again.
+    Destroy,
+}
+
+impl From<DefragMoveOperation> for ffi::VmaDefragmentationMoveOperation {
+    fn from(operation: DefragMoveOperation) -> Self {
+        match operation {
+            DefragMoveOperation::Copy => {
+                ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_COPY
+            }
+            DefragMoveOperation::Ignore => {
+                ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_IGNORE
+            }
+            DefragMoveOperation::Destroy => {
+                ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_DESTROY
+            }
+        }
+    }
+}
+
+/// Move of an allocation proposed by a defragmentation pass, see
+/// `DefragmentationContext::begin_pass`.
+///
+/// For each move, either recreate the buffer or image at the destination, copy its data and
+/// destroy the old one, or change its operation.
+#[derive(Debug)]
+pub struct DefragMove {
+    operation: DefragMoveOperation,
+    source: ffi::VmaAllocation,
+    source_id: Option<AllocationId>,
+    pinned: bool,
+    source_info: AllocationInfo,
+    pub(crate) destination: ffi::VmaAllocation,
+    destination_info: AllocationInfo,
+}
+
+impl DefragMove {
+    /// Operation performed at the end of the pass, `DefragMoveOperation::Copy` by default.
+    pub fn operation(&self) -> DefragMoveOperation {
+        self.operation
+    }
+
+    /// Sets the operation performed at the end of the pass.
+    ///
+    /// Moves of pinned allocations stay `DefragMoveOperation::Ignore`, see
+    /// `Allocator::set_allocation_pinned`.
+    pub fn set_operation(&mut self, operation: DefragMoveOperation) {
+        if !self.pinned {
+            self.operation = operation;
+        }
+    }
+
+    /// Returns `true` if the allocation to move is pinned, in which case it isn't moved.
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Id of the allocation to move, `None` for allocations the wrapper doesn't track, e.g.
+    /// made through the [`ffi`] functions.
+    pub fn source_id(&self) -> Option<AllocationId> {
+        self.source_id
+    }
+
+    /// Returns `true` if `allocation` is the allocation to move.
+    pub fn is_source(&self, allocation: &Allocation) -> bool {
+        allocation.raw == self.source
+    }
+
+    /// Current memory of the allocation to move.
+    pub fn source_info(&self) -> &AllocationInfo {
+        &self.source_info
+    }
+
+    /// Memory where the allocation will be moved to, which the new buffer or image must be bound
+    /// to.
+    pub fn destination_info(&self) -> &AllocationInfo {
+        &self.destination_info
+    }
 }
 
 impl<'a> Drop for DefragmentationContext<'a> {
@@ -15,12 +178,13 @@ impl<'a> Drop for DefragmentationContext<'a> {
         unsafe {
             ffi::vmaEndDefragmentation(self.allocator.internal, self.raw, std::ptr::null_mut());
         }
+        self.allocator.untrack_defragmentation(self.pool);
     }
 }
 
 impl<'a> DefragmentationContext<'a> {
-    /// Ends defragmentation process.
-    pub fn end(self) -> DefragmentationStats {
+    /// Ends defragmentation process, returning the statistics of all its passes.
+    pub fn end(self) -> DefragStats {
         let mut stats = DefragmentationStats {
             bytesMoved: 0,
             bytesFreed: 0,
@@ -30,53 +194,245 @@ impl<'a> DefragmentationContext<'a> {
         unsafe {
             ffi::vmaEndDefragmentation(self.allocator.internal, self.raw, &mut stats);
         }
+        self.allocator.untrack_defragmentation(self.pool);
         std::mem::forget(self);
-        stats
+        stats.into()
     }
 
-    /// Returns `false` if no more moves are possible or `true` if more defragmentations are possible.
-    pub fn begin_pass(&self, mover: impl FnOnce(&mut [DefragmentationMove]) -> ()) -> bool {
-        let mut pass_info = ffi::VmaDefragmentationPassMoveInfo {
+    /// Statistics of the last pass that was ended.
+    ///
+    /// Freed blocks and bytes are measured from the heap budgets around the end of the pass, so
+    /// they include memory freed or allocated by other threads meanwhile.
+    pub fn last_pass_stats(&self) -> DefragStats {
+        self.last_pass_stats.get()
+    }
+
+    /// Allocations moved by the last pass that was ended, e.g. to patch descriptors or device
+    /// addresses referring to them.
+    ///
+    /// Only allocations made through the wrapper are listed; moves that were ignored or destroyed
+    /// aren't. Nothing is listed with the `minimal-checks` feature, which doesn't keep track of
+    /// allocations.
+    pub fn last_pass_moves(&self) -> impl Iterator<Item = MovedAllocation> {
+        self.last_pass_moves.borrow().clone().into_iter()
+    }
+
+    /// Runs a defragmentation pass, handing its moves to `mover`.
+    ///
+    /// Returns `false` if no more moves are possible or `true` if more defragmentations are
+    /// possible. If `mover` fails, all moves of the pass are ignored and its error is returned.
+    /// Vulkan errors are converted with `From<VmaError>`.
+    pub fn begin_pass<E: From<VmaError>>(
+        &self,
+        mut mover: impl FnMut(&mut [DefragMove]) -> Result<(), E>,
+    ) -> Result<bool, E> {
+        let Some(mut pass) = self.start_pass()? else {
+            return Ok(false);
+        };
+        let moved = mover(&mut pass.moves);
+        let more = self.finish_pass(pass, moved.is_err());
+        moved?;
+        Ok(more?)
+    }
+
+    pub(crate) fn allocator(&self) -> &'a Allocator {
+        self.allocator
+    }
+
+    /// Begins a pass, returning `None` if no more moves are possible.
+    pub(crate) fn start_pass(&self) -> VmaResult<Option<DefragPass>> {
+        let allocator = self.allocator;
+        let mut info = ffi::VmaDefragmentationPassMoveInfo {
             moveCount: 0,
             pMoves: std::ptr::null_mut(),
         };
-        let result = unsafe {
-            ffi::vmaBeginDefragmentationPass(self.allocator.internal, self.raw, &mut pass_info)
-        };
-        if result == vk::Result::SUCCESS {
-            return false;
+        let result =
+            unsafe { ffi::vmaBeginDefragmentationPass(allocator.internal, self.raw, &mut info) };
+        match result {
+            vk::Result::SUCCESS => return Ok(None),
+            vk::Result::INCOMPLETE => {}
+            err => return Err(err.into()),
         }
-        debug_assert_eq!(result, vk::Result::INCOMPLETE);
-        let moves = unsafe {
-            std::slice::from_raw_parts_mut(pass_info.pMoves, pass_info.moveCount as usize)
-        };
-        mover(moves);
+        let raw_moves = unsafe { std::slice::from_raw_parts(info.pMoves, info.moveCount as usize) };
+        let ids = allocator.ids_by_raw();
+        let moves = raw_moves
+            .iter()
+            .map(|raw| {
+                let (source_id, pinned) = match ids.get(&raw.srcAllocation) {
+                    Some(&(id, pinned)) => (Some(id), pinned),
+                    None => (None, false),
+                };
+                DefragMove {
+                    operation: if pinned {
+                        DefragMoveOperation::Ignore
+                    } else {
+                        DefragMoveOperation::Copy
+                    },
+                    source: raw.srcAllocation,
+                    source_id,
+                    pinned,
+                    source_info: raw_allocation_info(allocator, raw.srcAllocation),
+                    destination: raw.dstTmpAllocation,
+                    destination_info: raw_allocation_info(allocator, raw.dstTmpAllocation),
+                }
+            })
+            .collect();
+        Ok(Some(DefragPass { info, moves }))
+    }
 
-        let result = unsafe {
-            ffi::vmaEndDefragmentationPass(self.allocator.internal, self.raw, &mut pass_info)
+    /// Ends a pass begun with `DefragmentationContext::start_pass`, performing the operations
+    /// of its moves, or ignoring all of them with `ignore`.
+    ///
+    /// Returns `true` if more defragmentations are possible.
+    pub(crate) fn finish_pass(&self, mut pass: DefragPass, ignore: bool) -> VmaResult<bool> {
+        let allocator = self.allocator;
+        let raw_moves = unsafe {
+            std::slice::from_raw_parts_mut(pass.info.pMoves, pass.info.moveCount as usize)
         };
-
-        return result == vk::Result::INCOMPLETE;
+        for (raw, defrag_move) in raw_moves.iter_mut().zip(&pass.moves) {
+            raw.operation = if ignore {
+                DefragMoveOperation::Ignore.into()
+            } else {
+                defrag_move.operation.into()
+            };
+        }
+        let mut stats = DefragStats::default();
+        let mut moved = Vec::new();
+        if !ignore {
+            for defrag_move in &pass.moves {
+                if defrag_move.operation == DefragMoveOperation::Copy {
+                    stats.allocations_moved += 1;
+                    stats.bytes_moved += defrag_move.source_info.size;
+                    if let Some(id) = defrag_move.source_id {
+                        moved.push(MovedAllocation {
+                            id,
+                            old_memory: defrag_move.source_info.device_memory,
+                            old_offset: defrag_move.source_info.offset,
+                            new_memory: defrag_move.destination_info.device_memory,
+                            new_offset: defrag_move.destination_info.offset,
+                        });
+                    }
+                }
+            }
+        }
+        let (blocks_before, bytes_before) = block_totals(allocator);
+        let result =
+            unsafe { ffi::vmaEndDefragmentationPass(allocator.internal, self.raw, &mut pass.info) };
+        let (blocks_after, bytes_after) = block_totals(allocator);
+        stats.device_memory_blocks_freed = blocks_before.saturating_sub(blocks_after);
+        stats.bytes_freed = bytes_before.saturating_sub(bytes_after);
+        self.last_pass_stats.set(stats);
+        *self.last_pass_moves.borrow_mut() = moved;
+        if !ignore {
+            for defrag_move in &pass.moves {
+                if let (DefragMoveOperation::Destroy, Some(id)) =
+                    (defrag_move.operation, defrag_move.source_id)
+                {
+                    allocator.untrack_id(id, &defrag_move.source_info);
+                }
+            }
+        }
+        match result {
+            vk::Result::SUCCESS => Ok(false),
+            vk::Result::INCOMPLETE => Ok(true),
+            err => Err(err.into()),
+        }
     }
 }
 
+/// Pass in progress, see `DefragmentationContext::start_pass`.
+pub(crate) struct DefragPass {
+    info: ffi::VmaDefragmentationPassMoveInfo,
+    pub(crate) moves: Vec<DefragMove>,
+}
+
+/// Number of blocks and bytes in blocks over all heaps.
+fn block_totals(allocator: &Allocator) -> (u32, vk::DeviceSize) {
+    let budgets = allocator.get_heap_budgets().unwrap_or_default();
+    budgets.iter().fold((0, 0), |(blocks, bytes), budget| {
+        (
+            blocks + budget.statistics.block_count,
+            bytes + budget.statistics.block_bytes,
+        )
+    })
+}
+
 impl Allocator {
+    /// Pins or unpins an allocation.
+    ///
+    /// Pinned allocations are never moved by defragmentation: their moves are always
+    /// `DefragMoveOperation::Ignore`. Use this e.g. for persistently mapped buffers whose
+    /// pointers are cached elsewhere.
+    ///
+    /// Fails with `VmaError::InvalidParameter` with the `minimal-checks` feature, which doesn't
+    /// keep track of allocations.
+    pub fn set_allocation_pinned(&self, allocation: &Allocation, pinned: bool) -> VmaResult<()> {
+        if !BOOKKEEPING {
+            return Err(VmaError::InvalidParameter(
+                "Pinning allocations isn't available with the minimal-checks feature",
+            ));
+        }
+        self.check_allocation(allocation)?;
+        if let Some(live) = self.tracker.live().get_mut(&allocation.id) {
+            live.pinned = pinned;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the allocation was pinned with `Allocator::set_allocation_pinned`.
+    pub fn is_allocation_pinned(&self, allocation: &Allocation) -> bool {
+        self.tracker
+            .live()
+            .get(&allocation.id)
+            .is_some_and(|live| live.pinned)
+    }
+
     /// Begins defragmentation process.
     ///
+    /// `info.pool` selects the custom pool to defragment, or the default pools if null, see
+    /// `AllocatorPool::begin_defragmentation`. Fails with `VmaError::InvalidParameter` if a
+    /// defragmentation of the same pool is still running.
+    ///
     /// ## Returns
     /// `VK_SUCCESS` if defragmentation can begin.
     /// `VK_ERROR_FEATURE_NOT_PRESENT` if defragmentation is not supported.
+    ///
+    /// # Safety
+    /// `info.pool` must be null or a pool of this allocator.
     pub unsafe fn begin_defragmentation(
         &self,
         info: &ffi::VmaDefragmentationInfo,
-    ) -> VkResult<DefragmentationContext> {
+    ) -> VmaResult<DefragmentationContext<'_>> {
+        let pool = PoolHandle(info.pool);
+        self.track_defragmentation(pool)?;
         let mut context: ffi::VmaDefragmentationContext = std::ptr::null_mut();
-
-        ffi::vmaBeginDefragmentation(self.internal, info, &mut context).result()?;
+        if let Err(err) = ffi::vmaBeginDefragmentation(self.internal, info, &mut context).result() {
+            self.untrack_defragmentation(pool);
+            return Err(err.into());
+        }
 
         Ok(DefragmentationContext {
             allocator: self,
             raw: context,
+            pool,
+            last_pass_stats: Cell::new(DefragStats::default()),
+            last_pass_moves: RefCell::new(Vec::new()),
         })
     }
 }
+
+impl AllocatorPool {
+    /// Begins a defragmentation of this pool, see `Allocator::begin_defragmentation`.
+    ///
+    /// `info.pool` is ignored.
+    pub fn begin_defragmentation(
+        &self,
+        info: &ffi::VmaDefragmentationInfo,
+    ) -> VmaResult<DefragmentationContext<'_>> {
+        let info = ffi::VmaDefragmentationInfo {
+            pool: self.pool.0,
+            ..*info
+        };
+        unsafe { self.allocator.begin_defragmentation(&info) }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::Allocation;
+use crate::Allocator;
+use crate::VmaResult;
+use ash::vk;
+
+/// Resource whose destruction was deferred by a `DeletionQueue` or `FrameDeletionQueue`.
+enum DeferredResource {
+    Buffer(vk::Buffer, Allocation),
+    Image(vk::Image, Allocation),
+    Memory(Allocation),
+}
+
+impl DeferredResource {
+    fn allocation(&self) -> &Allocation {
+        match self {
+            DeferredResource::Buffer(_, allocation)
+            | DeferredResource::Image(_, allocation)
+            | DeferredResource::Memory(allocation) => allocation,
+        }
+    }
+
+    unsafe fn destroy(self, allocator: &Allocator) -> VmaResult<()> {
+        Ok(match self {
+            DeferredResource::Buffer(buffer, allocation) => {
+                allocator.destroy_buffer(buffer, allocation)
+            }
+            DeferredResource::Image(image, allocation) => {
+                allocator.destroy_image(image, allocation)
+            }
+            DeferredResource::Memory(allocation) => allocator.free_memory(allocation),
+        }?)
+    }
+}
+
+/// Defers the destruction of buffers, images and allocations until the device is done with
+/// them.
+///
+/// Resources are tagged with the frame index, fence or timeline semaphore value signaled once
+/// the last command buffer using them completes, and `DeletionQueue::collect` destroys them once
+/// that value is reached. Resources still pending are destroyed when the queue is dropped.
+pub struct DeletionQueue {
+    allocator: Arc<Allocator>,
+    frames: BTreeMap<u64, Vec<DeferredResource>>,
+}
+unsafe impl Send for DeletionQueue {}
+
+impl Allocator {
+    /// Creates an empty `DeletionQueue`.
+    pub fn create_deletion_queue(self: &Arc<Self>) -> DeletionQueue {
+        DeletionQueue {
+            allocator: self.clone(),
+            frames: BTreeMap::new(),
+        }
+    }
+}
+
+impl DeletionQueue {
+    /// Destroys `buffer` and frees `allocation` once `frame_index` is collected, see
+    /// `Allocator::destroy_buffer`.
+    pub fn defer_destroy_buffer(
+        &mut self,
+        buffer: vk::Buffer,
+        allocation: Allocation,
+        frame_index: u64,
+    ) {
+        self.defer(frame_index, DeferredResource::Buffer(buffer, allocation));
+    }
+
+    /// Destroys `image` and frees `allocation` once `frame_index` is collected, see
+    /// `Allocator::destroy_image`.
+    pub fn defer_destroy_image(
+        &mut self,
+        image: vk::Image,
+        allocation: Allocation,
+        frame_index: u64,
+    ) {
+        self.defer(frame_index, DeferredResource::Image(image, allocation));
+    }
+
+    /// Frees `allocation` once `frame_index` is collected, see `Allocator::free_memory`.
+    pub fn defer_free_memory(&mut self, allocation: Allocation, frame_index: u64) {
+        self.defer(frame_index, DeferredResource::Memory(allocation));
+    }
+
+    /// Destroys the resources of all frames whose index is at most `completed_frame_index`,
+    /// oldest first.
+    ///
+    /// Returns the number of resources that were destroyed. Fails like
+    /// `Allocator::free_memory`, e.g. if an allocation of a retired frame is still mapped, in
+    /// which case that frame and the following ones stay pending.
+    ///
+    /// # Safety
+    /// The device must be done with all resources deferred up to `completed_frame_index`.
+    pub unsafe fn collect(&mut self, completed_frame_index: u64) -> VmaResult<usize> {
+        let mut collected = 0;
+        while let Some(entry) = self.frames.first_entry() {
+            if *entry.key() > completed_frame_index {
+                break;
+            }
+            for resource in entry.get() {
+                self.allocator.check_freeable(resource.allocation())?;
+            }
+            let resources = entry.remove();
+            collected += resources.len();
+            for resource in resources {
+                resource.destroy(&self.allocator)?;
+            }
+        }
+        Ok(collected)
+    }
+
+    /// Destroys all pending resources, reporting errors that `Drop` would ignore.
+    ///
+    /// Every resource is destroyed that can be, and the first error is returned. The device must
+    /// not use them anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.destroy_pending()
+    }
+
+    /// Number of resources whose destruction is pending.
+    pub fn pending_count(&self) -> usize {
+        self.frames.values().map(Vec::len).sum()
+    }
+
+    fn destroy_pending(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for resource in std::mem::take(&mut self.frames).into_values().flatten() {
+            result = result.and(unsafe { resource.destroy(&self.allocator) });
+        }
+        result
+    }
+
+    fn defer(&mut self, frame_index: u64, resource: DeferredResource) {
+        self.frames.entry(frame_index).or_default().push(resource);
+    }
+}
+
+/// Destroys all pending resources, the device must not use them anymore.
+impl Drop for DeletionQueue {
+    fn drop(&mut self) {
+        let _ = self.destroy_pending();
+    }
+}
+
+/// `DeletionQueue` with a number of frames in flight fixed at compile time.
+///
+/// Resources are deferred to the current frame, and each of the `N` frames has an inline slot
+/// reused every `N` frames, so collecting a frame is a single indexed slot rather than a search
+/// through pending frames.
+pub struct FrameDeletionQueue<const N: usize> {
+    allocator: Arc<Allocator>,
+    frames: [Vec<DeferredResource>; N],
+    /// Slot of the current frame
+    current: usize,
+}
+unsafe impl<const N: usize> Send for FrameDeletionQueue<N> {}
+
+impl Allocator {
+    /// Creates an empty `FrameDeletionQueue` with `N` frames in flight.
+    pub fn create_frame_deletion_queue<const N: usize>(self: &Arc<Self>) -> FrameDeletionQueue<N> {
+        const { assert!(N > 0, "Deletion queues need at least one frame") };
+        FrameDeletionQueue {
+            allocator: self.clone(),
+            frames: std::array::from_fn(|_| Vec::new()),
+            current: 0,
+        }
+    }
+}
+
+impl<const N: usize> FrameDeletionQueue<N> {
+    /// Destroys `buffer` and frees `allocation` once the current frame's slot is reused, see
+    /// `Allocator::destroy_buffer`.
+    pub fn defer_destroy_buffer(&mut self, buffer: vk::Buffer, allocation: Allocation) {
+        self.frames[self.current].push(DeferredResource::Buffer(buffer, allocation));
+    }
+
+    /// Destroys `image` and frees `allocation` once the current frame's slot is reused, see
+    /// `Allocator::destroy_image`.
+    pub fn defer_destroy_image(&mut self, image: vk::Image, allocation: Allocation) {
+        self.frames[self.current].push(DeferredResource::Image(image, allocation));
+    }
+
+    /// Frees `allocation` once the current frame's slot is reused, see `Allocator::free_memory`.
+    pub fn defer_free_memory(&mut self, allocation: Allocation) {
+        self.frames[self.current].push(DeferredResource::Memory(allocation));
+    }
+
+    /// Starts frame `frame_index`, destroying the resources deferred by the previous user of its
+    /// slot.
+    ///
+    /// Returns the number of resources that were destroyed. Fails like
+    /// `Allocator::free_memory`, e.g. if one of them is still mapped, in which case nothing is
+    /// destroyed and the current frame is left unchanged.
+    ///
+    /// # Safety
+    /// The device must be done with frame `frame_index - N`.
+    pub unsafe fn begin_frame(&mut self, frame_index: u64) -> VmaResult<usize> {
+        let current = (frame_index % N as u64) as usize;
+        for resource in &self.frames[current] {
+            self.allocator.check_freeable(resource.allocation())?;
+        }
+        let resources = std::mem::take(&mut self.frames[current]);
+        self.current = current;
+        let collected = resources.len();
+        for resource in resources {
+            resource.destroy(&self.allocator)?;
+        }
+        Ok(collected)
+    }
+
+    /// Destroys all pending resources, reporting errors that `Drop` would ignore.
+    ///
+    /// Every resource is destroyed that can be, and the first error is returned. The device must
+    /// not use them anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.destroy_pending()
+    }
+
+    /// Number of resources whose destruction is pending.
+    pub fn pending_count(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
+    }
+
+    fn destroy_pending(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for resource in self.frames.iter_mut().flat_map(std::mem::take) {
+            result = result.and(unsafe { resource.destroy(&self.allocator) });
+        }
+        result
+    }
+}
+
+/// Destroys all pending resources, the device must not use them anymore.
+impl<const N: usize> Drop for FrameDeletionQueue<N> {
+    fn drop(&mut self) {
+        let _ = self.destroy_pending();
+    }
+}
@@ -0,0 +1,142 @@
+use crate::definitions::*;
+use crate::VirtualAllocation;
+use crate::VirtualBlock;
+use crate::VirtualOffset;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Suballocator for ranges of descriptors in a descriptor buffer, see `VK_EXT_descriptor_buffer`.
+///
+/// The underlying `VirtualBlock` is sized in descriptors rather than bytes. Every range starts at
+/// a byte offset that is a multiple of both the descriptor size and the offset alignment, e.g.
+/// `vk::PhysicalDeviceDescriptorBufferPropertiesEXT::descriptor_buffer_offset_alignment`, so it
+/// can be passed to `vkCmdSetDescriptorBufferOffsetsEXT` as is.
+pub struct DescriptorHeapSuballocator {
+    block: VirtualBlock,
+    descriptor_size: vk::DeviceSize,
+    /// Offset alignment in descriptors
+    alignment: vk::DeviceSize,
+}
+
+/// Range of descriptors allocated with `DescriptorHeapSuballocator::allocate_descriptors`.
+#[derive(Debug)]
+pub struct DescriptorAllocation {
+    allocation: VirtualAllocation,
+    offset: VirtualOffset,
+    count: u64,
+    descriptor_size: vk::DeviceSize,
+}
+
+impl DescriptorAllocation {
+    /// Index of the first descriptor of the range in the heap.
+    pub fn first_descriptor(&self) -> u64 {
+        self.offset.get()
+    }
+
+    /// Number of descriptors in the range.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Byte offset of the range in the descriptor buffer.
+    pub fn byte_offset(&self) -> vk::DeviceSize {
+        self.offset.get() * self.descriptor_size
+    }
+
+    /// Size of the range in bytes.
+    pub fn byte_size(&self) -> vk::DeviceSize {
+        self.count * self.descriptor_size
+    }
+}
+
+impl DescriptorHeapSuballocator {
+    /// Creates a suballocator for a heap of `descriptor_count` descriptors of
+    /// `descriptor_size` bytes each, with ranges starting at multiples of `offset_alignment`
+    /// bytes.
+    ///
+    /// Fails with `VmaError::InvalidParameter` if `descriptor_size` is 0 or `offset_alignment`
+    /// isn't a power of two.
+    pub fn new(
+        descriptor_count: u64,
+        descriptor_size: vk::DeviceSize,
+        offset_alignment: vk::DeviceSize,
+    ) -> VmaResult<Self> {
+        if descriptor_size == 0 {
+            return Err(VmaError::InvalidParameter("Descriptor size must not be 0"));
+        }
+        if !offset_alignment.is_power_of_two() {
+            return Err(VmaError::InvalidParameter(
+                "Descriptor offset alignment must be a power of two",
+            ));
+        }
+        // A multiple of both in descriptors: lcm(size, alignment) / size, which is a power of
+        // two as well.
+        let alignment = offset_alignment / gcd(descriptor_size, offset_alignment);
+        let block = VirtualBlock::new(VirtualBlockCreateInfo {
+            size: descriptor_count,
+            ..Default::default()
+        })?;
+        Ok(DescriptorHeapSuballocator {
+            block,
+            descriptor_size,
+            alignment,
+        })
+    }
+
+    /// Allocates a range of `count` consecutive descriptors.
+    ///
+    /// Fails with `VmaError::OutOfDeviceMemory` if the heap has no room for it.
+    pub fn allocate_descriptors(&mut self, count: u64) -> VmaResult<DescriptorAllocation> {
+        if count == 0 {
+            return Err(VmaError::InvalidParameter(
+                "Descriptor ranges must not be empty",
+            ));
+        }
+        let allocation_info = VirtualAllocationCreateInfo {
+            size: count,
+            alignment: self.alignment,
+            user_data: 0,
+            flags: VirtualAllocationCreateFlags::empty(),
+        };
+        let (allocation, offset) = unsafe { self.block.allocate(allocation_info)? };
+        Ok(DescriptorAllocation {
+            allocation,
+            offset,
+            count,
+            descriptor_size: self.descriptor_size,
+        })
+    }
+
+    /// Frees a range of descriptors.
+    ///
+    /// Fails with `VmaError::WrongVirtualBlock` if the range was allocated from another heap, or
+    /// before `DescriptorHeapSuballocator::reset`.
+    pub fn free(&mut self, allocation: DescriptorAllocation) -> VmaResult<()> {
+        let mut allocation = allocation.allocation;
+        unsafe { self.block.free(&mut allocation) }
+    }
+
+    /// Frees all ranges at once.
+    pub fn reset(&mut self) {
+        unsafe { self.block.clear() };
+    }
+
+    /// Size of a descriptor in bytes.
+    pub fn descriptor_size(&self) -> vk::DeviceSize {
+        self.descriptor_size
+    }
+}
+
+/// Frees the remaining ranges, so that the block can be destroyed.
+impl Drop for DescriptorHeapSuballocator {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
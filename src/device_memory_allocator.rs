@@ -0,0 +1,166 @@
+use std::ffi::CString;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::Statistics;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Where the memory of a `DeviceMemoryAllocator` allocation lives, like gpu-allocator's
+/// `MemoryLocation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Device local memory, not accessible from the host.
+    GpuOnly,
+    /// Host visible memory written by the host and read by the device, e.g. for uploads.
+    CpuToGpu,
+    /// Host visible memory written by the device and read by the host, e.g. for readbacks.
+    GpuToCpu,
+}
+
+/// Allocation requested from a `DeviceMemoryAllocator`.
+#[derive(Clone, Copy)]
+pub struct DeviceMemoryRequest<'a> {
+    /// Requirements of the buffer or image the memory is for.
+    pub requirements: vk::MemoryRequirements,
+    /// Memory the allocation should be made in.
+    pub location: MemoryLocation,
+    /// Whether the memory is for a buffer or a linear image, as opposed to an optimal tiling
+    /// image. Suballocators keep `bufferImageGranularity` between linear and non-linear
+    /// neighbors only. `Allocator` doesn't tell VMA, which then keeps it everywhere.
+    pub linear: bool,
+    /// Name of the allocation for debugging, if any.
+    pub name: Option<&'a str>,
+}
+
+/// Place of an allocation in device memory, to bind it to a buffer or an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceMemoryRange {
+    /// Memory block the allocation lives in.
+    pub memory: vk::DeviceMemory,
+    /// Offset of the allocation in `memory`.
+    pub offset: vk::DeviceSize,
+    /// Size of the allocation.
+    pub size: vk::DeviceSize,
+}
+
+/// Minimal interface of a device memory allocator, implemented by `Allocator`.
+///
+/// Engines supporting several allocator backends, e.g. `vk-mem` and `gpu-allocator`, can
+/// implement it for the other backends and switch between them.
+pub trait DeviceMemoryAllocator {
+    /// Allocation handle, freed with `DeviceMemoryAllocator::free`.
+    type Allocation;
+    /// Error of the fallible methods.
+    type Error;
+
+    /// Allocates memory for `request`.
+    ///
+    /// # Safety
+    /// `request.requirements` must be requirements of a resource of the allocator's device.
+    unsafe fn allocate(
+        &self,
+        request: &DeviceMemoryRequest<'_>,
+    ) -> Result<Self::Allocation, Self::Error>;
+
+    /// Frees the allocation.
+    ///
+    /// # Safety
+    /// Resources bound to the allocation must not be in use anymore.
+    unsafe fn free(&self, allocation: Self::Allocation) -> Result<(), Self::Error>;
+
+    /// Returns the memory, offset and size of the allocation.
+    fn memory_range(&self, allocation: &Self::Allocation)
+        -> Result<DeviceMemoryRange, Self::Error>;
+
+    /// Maps the allocation.
+    ///
+    /// # Safety
+    /// The allocation must be in host visible memory, and unmapped after use.
+    unsafe fn map(&self, allocation: &mut Self::Allocation) -> Result<*mut u8, Self::Error>;
+
+    /// Unmaps an allocation mapped with `DeviceMemoryAllocator::map`.
+    ///
+    /// # Safety
+    /// The pointer returned by `DeviceMemoryAllocator::map` must not be used anymore.
+    unsafe fn unmap(&self, allocation: &mut Self::Allocation);
+
+    /// Sets the debug name of the allocation.
+    fn set_name(&self, allocation: &Self::Allocation, name: &str) -> Result<(), Self::Error>;
+
+    /// Reports the memory allocated over all memory types.
+    fn report(&self) -> Result<Statistics, Self::Error>;
+}
+
+impl DeviceMemoryAllocator for Allocator {
+    type Allocation = Allocation;
+    type Error = VmaError;
+
+    /// Allocates with `Alloc::allocate_memory`, requiring device local memory for
+    /// `MemoryLocation::GpuOnly`, and host visible and coherent memory otherwise, preferably
+    /// device local for `MemoryLocation::CpuToGpu` and host cached for
+    /// `MemoryLocation::GpuToCpu`. `DeviceMemoryRequest::linear` is ignored.
+    #[track_caller]
+    unsafe fn allocate(&self, request: &DeviceMemoryRequest<'_>) -> VmaResult<Allocation> {
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let (required_flags, preferred_flags) = match request.location {
+            MemoryLocation::GpuOnly => (
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                vk::MemoryPropertyFlags::empty(),
+            ),
+            MemoryLocation::CpuToGpu => (host_visible, vk::MemoryPropertyFlags::DEVICE_LOCAL),
+            MemoryLocation::GpuToCpu => (host_visible, vk::MemoryPropertyFlags::HOST_CACHED),
+        };
+        let name = request.name.map(CString::new).transpose().map_err(|_| {
+            VmaError::InvalidParameter("Allocation names can't contain null characters")
+        })?;
+        let allocation = self.allocate_memory(
+            &request.requirements,
+            &AllocationCreateInfo {
+                required_flags,
+                preferred_flags,
+                ..Default::default()
+            },
+        )?;
+        if let Some(name) = &name {
+            self.set_allocation_name(&allocation, Some(name));
+        }
+        Ok(allocation)
+    }
+
+    unsafe fn free(&self, allocation: Allocation) -> VmaResult<()> {
+        Ok(self.free_memory(allocation)?)
+    }
+
+    fn memory_range(&self, allocation: &Allocation) -> VmaResult<DeviceMemoryRange> {
+        let info = self.get_allocation_info(allocation)?;
+        Ok(DeviceMemoryRange {
+            memory: info.device_memory,
+            offset: info.offset,
+            size: info.size,
+        })
+    }
+
+    unsafe fn map(&self, allocation: &mut Allocation) -> VmaResult<*mut u8> {
+        self.map_memory(allocation)
+    }
+
+    unsafe fn unmap(&self, allocation: &mut Allocation) {
+        self.unmap_memory(allocation)
+    }
+
+    fn set_name(&self, allocation: &Allocation, name: &str) -> VmaResult<()> {
+        let name = CString::new(name).map_err(|_| {
+            VmaError::InvalidParameter("Allocation names can't contain null characters")
+        })?;
+        self.set_allocation_name(allocation, Some(&name));
+        Ok(())
+    }
+
+    fn report(&self) -> VmaResult<Statistics> {
+        Ok(self.calculate_statistics()?.total.statistics)
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use crate::stack_allocator::{allocate_linear, free_stack};
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::VmaResult;
+use ash::vk;
+
+/// Two stacks growing towards each other in a linear pool with a single block.
+///
+/// The bottom stack grows from the beginning of the block, the top stack from its end using
+/// `AllocationCreateFlags::UPPER_ADDRESS`. Allocations are owned by the allocator and freed as a
+/// whole per stack, e.g. with level data at the bottom and transient data at the top.
+///
+/// An allocation that doesn't fit between the two stacks fails with
+/// `VmaError::OutOfDeviceMemory`.
+pub struct DoubleStackAllocator {
+    pool: AllocatorPool,
+    create_info: AllocationCreateInfo,
+    memory_type_bits: u32,
+    bottom: Vec<Allocation>,
+    top: Vec<Allocation>,
+}
+
+impl Allocator {
+    /// Creates a `DoubleStackAllocator` of `size` bytes in the given memory type.
+    ///
+    /// # Safety
+    /// `create_info` is used for every allocation, see `Alloc::allocate_memory`. Its
+    /// `AllocationCreateFlags::UPPER_ADDRESS` flag is ignored.
+    pub unsafe fn create_double_stack_allocator(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<DoubleStackAllocator> {
+        let mut create_info = create_info.clone();
+        create_info
+            .flags
+            .remove(AllocationCreateFlags::UPPER_ADDRESS);
+        Ok(DoubleStackAllocator {
+            pool: self.create_linear_pool(memory_type_index, size)?,
+            create_info,
+            memory_type_bits: 1 << memory_type_index,
+            bottom: Vec::new(),
+            top: Vec::new(),
+        })
+    }
+}
+
+impl DoubleStackAllocator {
+    /// Pool backing the stacks.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.pool
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` on the bottom stack.
+    ///
+    /// Returns the allocation, owned by the stack until `DoubleStackAllocator::reset_bottom`, and
+    /// its offset in the block.
+    #[track_caller]
+    pub fn allocate_bottom(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&Allocation, vk::DeviceSize)> {
+        let (allocation, offset) = allocate_linear(
+            &self.pool,
+            self.memory_type_bits,
+            size,
+            alignment,
+            &self.create_info,
+        )?;
+        self.bottom.push(allocation);
+        Ok((self.bottom.last().unwrap(), offset))
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` on the top stack.
+    ///
+    /// Returns the allocation, owned by the stack until `DoubleStackAllocator::reset_top`, and its
+    /// offset in the block.
+    #[track_caller]
+    pub fn allocate_top(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&Allocation, vk::DeviceSize)> {
+        let mut create_info = self.create_info.clone();
+        create_info.flags |= AllocationCreateFlags::UPPER_ADDRESS;
+        let (allocation, offset) = allocate_linear(
+            &self.pool,
+            self.memory_type_bits,
+            size,
+            alignment,
+            &create_info,
+        )?;
+        self.top.push(allocation);
+        Ok((self.top.last().unwrap(), offset))
+    }
+
+    /// Frees all allocations of the bottom stack.
+    pub fn reset_bottom(&mut self) -> VmaResult<()> {
+        free_stack(&self.pool, &mut self.bottom, 0)
+    }
+
+    /// Frees all allocations of the top stack.
+    pub fn reset_top(&mut self) -> VmaResult<()> {
+        free_stack(&self.pool, &mut self.top, 0)
+    }
+
+    /// Frees both stacks, reporting errors that `Drop` would ignore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.reset_top().and(self.reset_bottom())
+    }
+
+    /// Number of allocations on the bottom stack.
+    pub fn bottom_len(&self) -> usize {
+        self.bottom.len()
+    }
+
+    /// Number of allocations on the top stack.
+    pub fn top_len(&self) -> usize {
+        self.top.len()
+    }
+}
+
+/// Frees both stacks, the device must not use them anymore.
+impl Drop for DoubleStackAllocator {
+    fn drop(&mut self) {
+        let _ = self.reset_top();
+        let _ = self.reset_bottom();
+    }
+}
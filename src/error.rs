@@ -0,0 +1,407 @@
+use crate::{AllocationId, AllocationIdentity, HeapBudget, LeakReport, LeakedVirtualAllocation};
+use ash::vk;
+use std::fmt;
+use std::panic::Location;
+
+/// Error returned by the wrapper.
+///
+/// Besides errors reported by Vulkan or VMA, this covers mistakes caught by the wrapper itself
+/// before calling into VMA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmaError {
+    /// A Vulkan or VMA call failed with a result code that has no variant of its own.
+    ///
+    /// `VmaError::from` turns the common codes into their variants, and `VmaError::result`
+    /// returns the code of any variant.
+    VulkanError(vk::Result),
+    /// Device memory ran out, `vk::Result::ERROR_OUT_OF_DEVICE_MEMORY`, also returned when an
+    /// allocation would exceed a budget, e.g. with `AllocationCreateFlags::WITHIN_BUDGET`.
+    ///
+    /// Allocations made by VMA come with what was requested and the budgets at the time.
+    OutOfDeviceMemory(Option<Box<OutOfMemoryReport>>),
+    /// An allocation of `Alloc::allocate_memory_within_budget` was denied, because it would
+    /// exceed the budget of the heaps it could be made in.
+    BudgetExceeded(Box<BudgetDenial>),
+    /// Host memory ran out, `vk::Result::ERROR_OUT_OF_HOST_MEMORY`.
+    OutOfHostMemory,
+    /// No memory type has the requested properties, or the requested feature isn't enabled,
+    /// `vk::Result::ERROR_FEATURE_NOT_PRESENT`.
+    FeatureNotPresent,
+    /// Mapping memory failed, `vk::Result::ERROR_MEMORY_MAP_FAILED`.
+    MemoryMapFailed,
+    /// Too many objects of a kind were created, e.g. more than `maxMemoryAllocationCount`
+    /// blocks, `vk::Result::ERROR_TOO_MANY_OBJECTS`.
+    TooManyObjects,
+    /// A pool has enough room left, but not in one piece, `vk::Result::ERROR_FRAGMENTED_POOL`.
+    FragmentedPool,
+    /// An external memory handle was rejected, `vk::Result::ERROR_INVALID_EXTERNAL_HANDLE`.
+    InvalidExternalHandle,
+    /// A check failed, e.g. `Allocator::check_corruption_in` found corrupted margins,
+    /// `vk::Result::ERROR_VALIDATION_FAILED_EXT`.
+    ValidationFailed,
+    /// The device was lost, `vk::Result::ERROR_DEVICE_LOST`.
+    DeviceLost,
+    /// A parameter was rejected by the wrapper.
+    InvalidParameter(&'static str),
+    /// An allocation was rejected by the wrapper, e.g. a range outside of it was accessed.
+    InvalidAllocation {
+        /// The rejected allocation.
+        allocation: AllocationIdentity,
+        /// Why it was rejected.
+        reason: &'static str,
+    },
+    /// A pool was destroyed while allocations made from it were still alive.
+    ///
+    /// Its destruction was deferred, see `AllocatorPool::destroy`.
+    PoolNotEmpty {
+        /// Name of the pool, if it has one.
+        name: Option<String>,
+        /// Number of allocations still alive in the pool.
+        allocation_count: u32,
+    },
+    /// An allocation would have pushed a pool over its quota, see `AllocatorPool::set_quota`.
+    ///
+    /// The allocation was freed again.
+    PoolQuotaExceeded {
+        /// Name of the pool, if it has one.
+        name: Option<String>,
+        /// Quota of the pool, in bytes.
+        quota: vk::DeviceSize,
+        /// Bytes allocated from the pool without the rejected allocation.
+        usage: vk::DeviceSize,
+        /// Size of the rejected allocation, in bytes.
+        size: vk::DeviceSize,
+    },
+    /// The device lacks functions VMA needs for the Vulkan version and flags the allocator was
+    /// created with, see `Allocator::new`.
+    UnsupportedVulkanFeature {
+        /// Names of the missing functions.
+        functions: Vec<&'static str>,
+    },
+    /// An allocator flag was set, but its extension is missing from
+    /// `AllocatorCreateInfo::enabled_device_extensions`.
+    MissingDeviceExtension {
+        /// Name of the `AllocatorCreateFlags` flag, e.g. `"EXT_MEMORY_BUDGET"`.
+        flag: &'static str,
+        /// Name of the extension, e.g. `"VK_EXT_memory_budget"`.
+        extension: &'static str,
+    },
+    /// A protected pool was requested, but the allocator wasn't created with
+    /// `AllocatorCreateInfo::protected_memory`.
+    ProtectedMemoryNotEnabled,
+    /// A device mask was requested, but the allocator wasn't created with a
+    /// `AllocatorCreateInfo::device_group_size` above 1.
+    DeviceGroupNotEnabled,
+    /// Corruption detection was requested, but the crate was built without the
+    /// `detect-corruption` feature.
+    CorruptionDetectionNotEnabled,
+    /// A virtual allocation was used with a block it wasn't made from, or with its block after
+    /// `VirtualBlock::clear`.
+    WrongVirtualBlock,
+    /// A virtual block was destroyed while allocations made from it were still alive.
+    ///
+    /// They were freed, see `VirtualBlock::destroy`.
+    VirtualBlockNotEmpty {
+        /// Allocations that were still alive, ordered by offset.
+        leaked: Vec<LeakedVirtualAllocation>,
+    },
+    /// The VMA library linked with the `system-vma` feature isn't the version the bindings were
+    /// generated for, see `VMA_VERSION`.
+    IncompatibleVersion {
+        /// Version of the linked library, read from its header at build time.
+        version: &'static str,
+    },
+    /// The file of `AllocatorCreateInfo::record_to` couldn't be created.
+    RecordingFailed(std::io::ErrorKind),
+    /// The allocator was destroyed while allocations were still alive.
+    Leaked(LeakReport),
+    /// The allocation was used after being freed.
+    StaleAllocation(AllocationId),
+    /// The allocation was freed while it was still mapped with `Allocator::map_memory`.
+    ///
+    /// The allocation is left alive.
+    StillMapped {
+        /// The allocation that was left alive.
+        allocation: AllocationIdentity,
+        /// Where the oldest outstanding map was made.
+        location: &'static Location<'static>,
+    },
+}
+
+/// Allocation that failed for lack of device memory, see `VmaError::OutOfDeviceMemory`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutOfMemoryReport {
+    /// Requested size in bytes, or `None` for images and buffers created beforehand.
+    pub size: Option<vk::DeviceSize>,
+    /// Memory types the allocation could be made in, in VMA's order of preference, or the
+    /// memory type of the custom pool.
+    pub memory_type_candidates: Vec<u32>,
+    /// Whether the allocation was made from a custom pool.
+    pub custom_pool: bool,
+    /// Name of the custom pool, if it has one.
+    pub pool_name: Option<String>,
+    /// Budgets of all heaps right after the failure.
+    pub budgets: Vec<HeapBudget>,
+}
+
+impl fmt::Display for OutOfMemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.size {
+            Some(size) => write!(f, "allocating {} bytes", size)?,
+            None => write!(f, "allocating a buffer or image")?,
+        }
+        if self.custom_pool {
+            write!(
+                f,
+                " from pool {}",
+                self.pool_name.as_deref().unwrap_or("<unnamed>")
+            )?;
+        }
+        write!(f, " in memory type(s) {:?}", self.memory_type_candidates)?;
+        for budget in &self.budgets {
+            write!(f, "\n  {}", budget)?;
+        }
+        Ok(())
+    }
+}
+
+/// Allocation denied by `Alloc::allocate_memory_within_budget`, see `VmaError::BudgetExceeded`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetDenial {
+    /// Requested size in bytes.
+    pub size: vk::DeviceSize,
+    /// Heaps of the memory types the allocation could be made in, in VMA's order of
+    /// preference, that lacked the budget for it.
+    pub heaps: Vec<HeapShortfall>,
+    /// What was requested, with the budgets of all heaps.
+    pub report: OutOfMemoryReport,
+}
+
+/// Heap that lacked the budget for an allocation, see `BudgetDenial`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapShortfall {
+    /// Index of the heap in `vk::PhysicalDeviceMemoryProperties::memory_heaps`.
+    pub heap_index: u32,
+    /// Estimated current memory usage of the program in the heap, in bytes.
+    pub usage: vk::DeviceSize,
+    /// Estimated amount of memory available to the program in the heap, in bytes.
+    pub budget: vk::DeviceSize,
+    /// Bytes that would have to be freed in the heap for the allocation to fit its budget.
+    pub missing: vk::DeviceSize,
+}
+
+impl fmt::Display for BudgetDenial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Allocating {} bytes would exceed the budget", self.size)?;
+        for heap in &self.heaps {
+            write!(
+                f,
+                "\n  heap {}: {} of {} bytes used, {} bytes missing",
+                heap.heap_index, heap.usage, heap.budget, heap.missing
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Result type of wrapper functions that can fail with a `VmaError`.
+pub type VmaResult<T> = Result<T, VmaError>;
+
+/// Picks the variant of `result`, `VmaError::VulkanError` if it has none.
+impl From<vk::Result> for VmaError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => VmaError::OutOfDeviceMemory(None),
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY => VmaError::OutOfHostMemory,
+            vk::Result::ERROR_FEATURE_NOT_PRESENT => VmaError::FeatureNotPresent,
+            vk::Result::ERROR_MEMORY_MAP_FAILED => VmaError::MemoryMapFailed,
+            vk::Result::ERROR_TOO_MANY_OBJECTS => VmaError::TooManyObjects,
+            vk::Result::ERROR_FRAGMENTED_POOL => VmaError::FragmentedPool,
+            vk::Result::ERROR_INVALID_EXTERNAL_HANDLE => VmaError::InvalidExternalHandle,
+            vk::Result::ERROR_VALIDATION_FAILED_EXT => VmaError::ValidationFailed,
+            vk::Result::ERROR_DEVICE_LOST => VmaError::DeviceLost,
+            result => VmaError::VulkanError(result),
+        }
+    }
+}
+
+impl VmaError {
+    /// Vulkan result code of the error, if it comes from Vulkan or VMA.
+    pub fn result(&self) -> Option<vk::Result> {
+        Some(match self {
+            VmaError::VulkanError(result) => *result,
+            VmaError::OutOfDeviceMemory(_) | VmaError::BudgetExceeded(_) => {
+                vk::Result::ERROR_OUT_OF_DEVICE_MEMORY
+            }
+            VmaError::OutOfHostMemory => vk::Result::ERROR_OUT_OF_HOST_MEMORY,
+            VmaError::FeatureNotPresent => vk::Result::ERROR_FEATURE_NOT_PRESENT,
+            VmaError::MemoryMapFailed => vk::Result::ERROR_MEMORY_MAP_FAILED,
+            VmaError::TooManyObjects => vk::Result::ERROR_TOO_MANY_OBJECTS,
+            VmaError::FragmentedPool => vk::Result::ERROR_FRAGMENTED_POOL,
+            VmaError::InvalidExternalHandle => vk::Result::ERROR_INVALID_EXTERNAL_HANDLE,
+            VmaError::ValidationFailed => vk::Result::ERROR_VALIDATION_FAILED_EXT,
+            VmaError::DeviceLost => vk::Result::ERROR_DEVICE_LOST,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` for `VmaError::OutOfDeviceMemory` and `VmaError::BudgetExceeded`, e.g. to
+    /// retry after freeing memory.
+    pub fn is_out_of_device_memory(&self) -> bool {
+        matches!(
+            self,
+            VmaError::OutOfDeviceMemory(_) | VmaError::BudgetExceeded(_)
+        )
+    }
+}
+
+impl fmt::Display for VmaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmaError::VulkanError(result) => write!(f, "Vulkan error: {}", result),
+            VmaError::OutOfDeviceMemory(Some(report)) => {
+                write!(f, "Out of device memory {}", report)
+            }
+            VmaError::OutOfDeviceMemory(None) => write!(f, "Out of device memory"),
+            VmaError::BudgetExceeded(denial) => write!(f, "{}", denial),
+            VmaError::OutOfHostMemory => write!(f, "Out of host memory"),
+            VmaError::FeatureNotPresent => write!(
+                f,
+                "No memory type has the requested properties, or the feature isn't enabled"
+            ),
+            VmaError::MemoryMapFailed => write!(f, "Mapping memory failed"),
+            VmaError::TooManyObjects => write!(
+                f,
+                "Too many objects, e.g. memory blocks over maxMemoryAllocationCount"
+            ),
+            VmaError::FragmentedPool => write!(f, "Pool too fragmented for the allocation"),
+            VmaError::InvalidExternalHandle => write!(f, "Invalid external memory handle"),
+            VmaError::ValidationFailed => write!(f, "Validation failed, memory may be corrupted"),
+            VmaError::DeviceLost => write!(f, "Device lost"),
+            VmaError::InvalidParameter(message) => write!(f, "Invalid parameter: {}", message),
+            VmaError::InvalidAllocation { allocation, reason } => {
+                write!(f, "Invalid allocation {}: {}", allocation, reason)
+            }
+            VmaError::PoolNotEmpty {
+                name,
+                allocation_count,
+            } => write!(
+                f,
+                "Pool {} still has {} live allocation(s), its destruction was deferred",
+                name.as_deref().unwrap_or("<unnamed>"),
+                allocation_count
+            ),
+            VmaError::PoolQuotaExceeded {
+                name,
+                quota,
+                usage,
+                size,
+            } => write!(
+                f,
+                "Allocating {} bytes from pool {} would exceed its quota of {} bytes, {} bytes are in use",
+                size,
+                name.as_deref().unwrap_or("<unnamed>"),
+                quota,
+                usage
+            ),
+            VmaError::UnsupportedVulkanFeature { functions } => write!(
+                f,
+                "The device doesn't provide {}, required by the Vulkan version and flags of the allocator",
+                functions.join(", ")
+            ),
+            VmaError::MissingDeviceExtension { flag, extension } => write!(
+                f,
+                "AllocatorCreateFlags::{} requires the {} device extension, which isn't enabled",
+                flag, extension
+            ),
+            VmaError::ProtectedMemoryNotEnabled => write!(
+                f,
+                "Protected memory requires the protectedMemory device feature, see AllocatorCreateInfo::protected_memory"
+            ),
+            VmaError::DeviceGroupNotEnabled => write!(
+                f,
+                "Device masks require a device group of several physical devices, see AllocatorCreateInfo::device_group_size"
+            ),
+            VmaError::CorruptionDetectionNotEnabled => write!(
+                f,
+                "Corruption detection requires the detect-corruption feature of vk-mem"
+            ),
+            VmaError::WrongVirtualBlock => {
+                write!(f, "Virtual allocation doesn't belong to this virtual block")
+            }
+            VmaError::VirtualBlockNotEmpty { leaked } => {
+                write!(
+                    f,
+                    "Virtual block destroyed with {} live allocation(s), they were freed",
+                    leaked.len()
+                )?;
+                for allocation in leaked {
+                    write!(
+                        f,
+                        "\n  {} bytes at offset {}",
+                        allocation.size, allocation.offset
+                    )?;
+                }
+                Ok(())
+            }
+            VmaError::IncompatibleVersion { version } => write!(
+                f,
+                "VMA {} is linked, but the bindings are for VMA {}.{}",
+                version,
+                crate::VMA_VERSION.0,
+                crate::VMA_VERSION.1
+            ),
+            VmaError::RecordingFailed(kind) => {
+                write!(f, "Couldn't create the recording file: {}", kind)
+            }
+            VmaError::Leaked(report) => write!(f, "Allocator destroyed with {}", report),
+            VmaError::StaleAllocation(id) => write!(f, "Allocation {} was already freed", id),
+            VmaError::StillMapped {
+                allocation,
+                location,
+            } => write!(
+                f,
+                "Allocation {} is still mapped, it was mapped at {}",
+                allocation, location
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VmaError {}
+
+/// Error returned by functions that free an allocation, handing back what wasn't freed.
+///
+/// The allocation is taken by value, so on failure it's returned here and can still be freed
+/// once the error is dealt with, e.g. after unmapping it. Dropping it leaks the memory.
+pub struct FreeError<T = crate::Allocation> {
+    /// Why the allocation wasn't freed.
+    pub error: VmaError,
+    /// The allocation that wasn't freed, or all of them for functions freeing several.
+    pub allocation: T,
+}
+
+/// Result type of functions that free an allocation.
+pub type FreeResult<T = crate::Allocation> = Result<(), FreeError<T>>;
+
+impl<T> From<FreeError<T>> for VmaError {
+    fn from(err: FreeError<T>) -> Self {
+        err.error
+    }
+}
+
+impl<T> fmt::Debug for FreeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FreeError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for FreeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> std::error::Error for FreeError<T> {}
@@ -0,0 +1,334 @@
+use std::ffi::c_void;
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::sync::Arc;
+
+use crate::ffi;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::PoolCreateInfo;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Device memory of an allocation exported to another API or process, e.g. with
+/// `Allocator::export_fd`.
+///
+/// The handle refers to the whole `vk::DeviceMemory` block the allocation lives in: the
+/// importer allocates `memory_size` bytes from it, and finds the allocation at `offset`.
+#[derive(Debug)]
+pub struct ExportedMemory<H> {
+    /// Handle to the device memory, owned by the caller
+    pub handle: H,
+    /// Memory the handle was exported from
+    pub memory: vk::DeviceMemory,
+    /// Size of `memory`, equal to `size` for allocations with dedicated memory
+    pub memory_size: vk::DeviceSize,
+    /// Offset of the allocation in `memory`
+    pub offset: vk::DeviceSize,
+    /// Size of the allocation
+    pub size: vk::DeviceSize,
+}
+
+/// Loader of an extension exporting device memory as handles of other APIs, see
+/// `Allocator::create_exported_buffer`.
+pub trait MemoryExporter {
+    /// Handle the memory is exported as
+    type Handle;
+
+    /// Exports `memory` as a new handle of `handle_type`.
+    ///
+    /// # Safety
+    /// The loader must be loaded for the device of `memory`, which must be allocated with
+    /// `vk::ExportMemoryAllocateInfo` including `handle_type`.
+    unsafe fn export_memory(
+        &self,
+        memory: vk::DeviceMemory,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<Self::Handle>;
+}
+
+#[cfg(unix)]
+impl MemoryExporter for ash::khr::external_memory_fd::Device {
+    type Handle = OwnedFd;
+
+    unsafe fn export_memory(
+        &self,
+        memory: vk::DeviceMemory,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<OwnedFd> {
+        let get_fd_info = vk::MemoryGetFdInfoKHR::default()
+            .memory(memory)
+            .handle_type(handle_type);
+        Ok(OwnedFd::from_raw_fd(self.get_memory_fd(&get_fd_info)?))
+    }
+}
+
+impl MemoryExporter for ash::khr::external_memory_win32::Device {
+    type Handle = vk::HANDLE;
+
+    unsafe fn export_memory(
+        &self,
+        memory: vk::DeviceMemory,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<vk::HANDLE> {
+        let get_handle_info = vk::MemoryGetWin32HandleInfoKHR::default()
+            .memory(memory)
+            .handle_type(handle_type);
+        Ok(self.get_memory_win32_handle(&get_handle_info)?)
+    }
+}
+
+impl Allocator {
+    /// Creates a pool whose device memory can be exported with `handle_types`, by attaching
+    /// `vk::ExportMemoryAllocateInfo` to every block it allocates.
+    ///
+    /// The structure is owned by the pool, so it lives as long as VMA uses it. Fails with
+    /// `VmaError::InvalidParameter` if `handle_types` is empty.
+    pub fn create_exportable_pool(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        handle_types: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<AllocatorPool> {
+        if handle_types.is_empty() {
+            return Err(VmaError::InvalidParameter(
+                "An exportable pool needs at least one handle type",
+            ));
+        }
+        let export_info =
+            Box::new(vk::ExportMemoryAllocateInfo::default().handle_types(handle_types));
+        let pool = self.create_pool(&PoolCreateInfo {
+            memory_type_index,
+            memory_allocate_next: &*export_info as *const vk::ExportMemoryAllocateInfo as *const _,
+            ..Default::default()
+        })?;
+        self.keep_pool_next(pool.pool, export_info);
+        Ok(pool)
+    }
+
+    /// Wraps `handle` with the memory the allocation lives in and its place in it.
+    fn exported_memory<H>(&self, allocation: &Allocation, handle: H) -> ExportedMemory<H> {
+        let mut info: ffi::VmaAllocationInfo2 = unsafe { std::mem::zeroed() };
+        unsafe { ffi::vmaGetAllocationInfo2(self.internal, allocation.raw, &mut info) };
+        ExportedMemory {
+            handle,
+            memory: info.allocationInfo.deviceMemory,
+            memory_size: info.blockSize,
+            offset: info.allocationInfo.offset,
+            size: info.allocationInfo.size,
+        }
+    }
+
+    /// Exports the device memory of the allocation as a POSIX file descriptor, for sharing it
+    /// with CUDA, another Vulkan device or another process.
+    ///
+    /// The allocation must come from a pool created with `Allocator::create_exportable_pool`,
+    /// or otherwise be allocated with `vk::ExportMemoryAllocateInfo` including `handle_type`.
+    /// Each call returns a new descriptor, closed when the returned `OwnedFd` is dropped.
+    ///
+    /// # Safety
+    /// `external_memory_fd` must be loaded for the device of the allocator, and `handle_type`
+    /// must be a file descriptor handle type the memory was allocated as exportable with.
+    #[cfg(unix)]
+    pub unsafe fn export_fd(
+        &self,
+        allocation: &Allocation,
+        external_memory_fd: &ash::khr::external_memory_fd::Device,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<ExportedMemory<OwnedFd>> {
+        let info = self.get_allocation_info(allocation)?;
+        let fd = external_memory_fd.export_memory(info.device_memory, handle_type)?;
+        Ok(self.exported_memory(allocation, fd))
+    }
+
+    /// Creates a buffer with dedicated memory exported as a handle of `handle_type`, ready to
+    /// be registered with CUDA, OpenCL or D3D.
+    ///
+    /// `vk::ExternalMemoryBufferCreateInfo` is chained to `buffer_info`, and the memory is
+    /// allocated from an exportable pool like `Allocator::create_exportable_pool` creates, in
+    /// the memory type chosen for `allocation_info`. The pool is dropped right away, so its
+    /// destruction is deferred until the buffer is freed, and done by the next call or by
+    /// `Allocator::destroy_deferred_pools`.
+    ///
+    /// # Safety
+    /// `exporter` must be loaded for the device of the allocator, and `handle_type` must be
+    /// a handle type it exports. `buffer_info` must not chain
+    /// `vk::ExternalMemoryBufferCreateInfo` already.
+    #[track_caller]
+    pub unsafe fn create_exported_buffer<E: MemoryExporter>(
+        self: &Arc<Self>,
+        buffer_info: &vk::BufferCreateInfo,
+        allocation_info: &AllocationCreateInfo,
+        exporter: &E,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VmaResult<(vk::Buffer, Allocation, ExportedMemory<E::Handle>)> {
+        self.destroy_deferred_pools();
+        let mut external_info =
+            vk::ExternalMemoryBufferCreateInfo::default().handle_types(handle_type);
+        let buffer_info = (*buffer_info).push_next(&mut external_info);
+        let memory_type_index =
+            self.find_memory_type_index_for_buffer_info(&buffer_info, allocation_info)?;
+        let pool = self.create_exportable_pool(memory_type_index, handle_type)?;
+        let (buffer, allocation) = pool.create_buffer(
+            &buffer_info,
+            &AllocationCreateInfo {
+                flags: allocation_info.flags | AllocationCreateFlags::DEDICATED_MEMORY,
+                ..allocation_info.clone()
+            },
+        )?;
+        let info = self.get_allocation_info(&allocation)?;
+        match exporter.export_memory(info.device_memory, handle_type) {
+            Ok(handle) => {
+                let memory = self.exported_memory(&allocation, handle);
+                Ok((buffer, allocation, memory))
+            }
+            Err(err) => {
+                self.destroy_buffer(buffer, allocation)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Creates a pool made of a single block importing `size` bytes of existing host memory at
+    /// `host_pointer` with `VK_EXT_external_memory_host`, e.g. a memory-mapped file, to use it
+    /// without copying.
+    ///
+    /// The memory type is chosen among `memory_type_bits` and the types the pointer can be
+    /// imported as, preferring host cached memory. Allocate from the pool with
+    /// `AllocationCreateFlags::NEVER_ALLOCATE`, and create buffers with
+    /// `vk::ExternalMemoryBufferCreateInfo` including `HOST_ALLOCATION_EXT`.
+    ///
+    /// Fails with `VmaError::InvalidParameter` unless `host_pointer` and `size` are multiples
+    /// of `properties.min_imported_host_pointer_alignment`.
+    ///
+    /// # Safety
+    /// `external_memory_host` must be loaded for the device of the allocator, `properties`
+    /// must be the properties of its physical device, and the memory at `host_pointer` must
+    /// stay allocated until the pool is destroyed.
+    pub unsafe fn create_host_pointer_pool(
+        self: &Arc<Self>,
+        external_memory_host: &ash::ext::external_memory_host::Device,
+        properties: &vk::PhysicalDeviceExternalMemoryHostPropertiesEXT,
+        host_pointer: *mut c_void,
+        size: vk::DeviceSize,
+        memory_type_bits: u32,
+    ) -> VmaResult<AllocatorPool> {
+        let alignment = properties.min_imported_host_pointer_alignment;
+        if !(host_pointer as vk::DeviceSize).is_multiple_of(alignment)
+            || !size.is_multiple_of(alignment)
+        {
+            return Err(VmaError::InvalidParameter(
+                "Imported host pointers and sizes must be aligned to minImportedHostPointerAlignment",
+            ));
+        }
+        let handle_type = vk::ExternalMemoryHandleTypeFlags::HOST_ALLOCATION_EXT;
+        let mut host_pointer_properties = vk::MemoryHostPointerPropertiesEXT::default();
+        (external_memory_host
+            .fp()
+            .get_memory_host_pointer_properties_ext)(
+            external_memory_host.device(),
+            handle_type,
+            host_pointer,
+            &mut host_pointer_properties,
+        )
+        .result()?;
+        let allocation_info = AllocationCreateInfo {
+            required_flags: vk::MemoryPropertyFlags::HOST_VISIBLE,
+            preferred_flags: vk::MemoryPropertyFlags::HOST_CACHED,
+            ..Default::default()
+        };
+        let memory_type_index = self.find_memory_type_index(
+            memory_type_bits & host_pointer_properties.memory_type_bits,
+            &allocation_info,
+        )?;
+        let import_info = Box::new(
+            vk::ImportMemoryHostPointerInfoEXT::default()
+                .handle_type(handle_type)
+                .host_pointer(host_pointer),
+        );
+        let pool = self.create_pool(&PoolCreateInfo {
+            memory_type_index,
+            block_size: size,
+            min_block_count: 1,
+            max_block_count: 1,
+            memory_allocate_next: &*import_info as *const vk::ImportMemoryHostPointerInfoEXT
+                as *const _,
+            ..Default::default()
+        })?;
+        self.keep_pool_next(pool.pool, import_info);
+        Ok(pool)
+    }
+
+    /// Imports an Android hardware buffer as the dedicated memory of a new image, for camera
+    /// and media interop with `VK_ANDROID_external_memory_android_hardware_buffer`.
+    ///
+    /// `image_info` must chain `vk::ExternalMemoryImageCreateInfo` including
+    /// `ANDROID_HARDWARE_BUFFER_ANDROID`, and `vk::ExternalFormatANDROID` for external formats.
+    /// The memory type is chosen among the types the buffer can be imported as, like
+    /// `Alloc::find_memory_type_index` does with `allocation_info`.
+    ///
+    /// The import is done by a pool owning `vk::ImportAndroidHardwareBufferInfoANDROID`,
+    /// returned along with the image. Destroy it after the image.
+    ///
+    /// # Safety
+    /// `external_memory_android` must be loaded for the device of the allocator, and `buffer`
+    /// must be a valid hardware buffer. Vulkan holds a reference to it until the image is
+    /// destroyed.
+    #[cfg(feature = "android-hardware-buffer")]
+    pub unsafe fn import_android_hardware_buffer(
+        self: &Arc<Self>,
+        external_memory_android: &ash::android::external_memory_android_hardware_buffer::Device,
+        buffer: *mut vk::AHardwareBuffer,
+        image_info: &vk::ImageCreateInfo,
+        allocation_info: &AllocationCreateInfo,
+    ) -> VmaResult<(AllocatorPool, vk::Image, Allocation)> {
+        let mut properties = vk::AndroidHardwareBufferPropertiesANDROID::default();
+        external_memory_android.get_android_hardware_buffer_properties(buffer, &mut properties)?;
+        let memory_type_index =
+            self.find_memory_type_index(properties.memory_type_bits, allocation_info)?;
+        let import_info =
+            Box::new(vk::ImportAndroidHardwareBufferInfoANDROID::default().buffer(buffer));
+        let pool = self.create_pool(&PoolCreateInfo {
+            memory_type_index,
+            memory_allocate_next: &*import_info as *const vk::ImportAndroidHardwareBufferInfoANDROID
+                as *const _,
+            ..Default::default()
+        })?;
+        self.keep_pool_next(pool.pool, import_info);
+        let (image, allocation) = pool.create_image(
+            image_info,
+            &AllocationCreateInfo {
+                flags: allocation_info.flags | AllocationCreateFlags::DEDICATED_MEMORY,
+                ..allocation_info.clone()
+            },
+        )?;
+        Ok((pool, image, allocation))
+    }
+
+    /// Fetches the Android hardware buffer backing the allocation, for passing it to the
+    /// media or camera APIs.
+    ///
+    /// The allocation must come from a pool created with `Allocator::create_exportable_pool`
+    /// including `ANDROID_HARDWARE_BUFFER_ANDROID`, and images must be allocated with
+    /// `AllocationCreateFlags::DEDICATED_MEMORY`. The returned buffer holds a new reference,
+    /// released by the caller with `AHardwareBuffer_release`.
+    ///
+    /// # Safety
+    /// `external_memory_android` must be loaded for the device of the allocator.
+    #[cfg(feature = "android-hardware-buffer")]
+    pub unsafe fn export_android_hardware_buffer(
+        &self,
+        allocation: &Allocation,
+        external_memory_android: &ash::android::external_memory_android_hardware_buffer::Device,
+    ) -> VmaResult<ExportedMemory<*mut vk::AHardwareBuffer>> {
+        let info = self.get_allocation_info(allocation)?;
+        let get_buffer_info =
+            vk::MemoryGetAndroidHardwareBufferInfoANDROID::default().memory(info.device_memory);
+        let buffer =
+            external_memory_android.get_memory_android_hardware_buffer(&get_buffer_info)?;
+        Ok(self.exported_memory(allocation, buffer))
+    }
+}
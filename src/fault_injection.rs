@@ -0,0 +1,185 @@
+use std::sync::{MutexGuard, PoisonError};
+
+use crate::ffi;
+use crate::AllocationRequest;
+use crate::Allocator;
+use crate::PoolHandle;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Makes allocations fail on purpose, to test out-of-memory handling deterministically.
+///
+/// Set it with `Allocator::set_fault_injector`. An allocation fails if any of the conditions
+/// matches, with `VmaError::OutOfDeviceMemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultInjector {
+    /// Fails every `fail_every`th allocation, counting from when the injector was set. 0 never
+    /// fails.
+    pub fail_every: u64,
+    /// Fails allocations larger than this many bytes, as requested when the size is known
+    /// before VMA makes them.
+    pub max_size: Option<vk::DeviceSize>,
+    /// Fails allocations made in memory types whose bit is set, e.g. `1 << index`.
+    pub memory_type_bits: u32,
+}
+
+/// Injector along with its counters.
+#[derive(Default)]
+pub(crate) struct FaultInjection {
+    injector: Option<FaultInjector>,
+    allocation_count: u64,
+    fault_count: u64,
+}
+
+impl Allocator {
+    /// Makes allocations through the wrapper fail as described by `injector`, or stops failing
+    /// them with `None`. Counters start over.
+    ///
+    /// All allocation functions are covered, including the ones of custom pools and of the
+    /// allocators built on them. Faults are decided before VMA is called, from the requested
+    /// size and the memory type VMA picks first for the request, so a failed allocation doesn't
+    /// touch device memory. Only the size of images and of memory for existing buffers and
+    /// images, and the memory type of the latter outside of custom pools, are known once VMA made
+    /// the allocation, which is then freed again before the error is returned, like with
+    /// `AllocatorPool::set_quota`.
+    pub fn set_fault_injector(&self, injector: Option<FaultInjector>) {
+        *self.fault_injection() = FaultInjection {
+            injector,
+            ..Default::default()
+        };
+    }
+
+    /// Returns the injector set with `Allocator::set_fault_injector`.
+    pub fn fault_injector(&self) -> Option<FaultInjector> {
+        self.fault_injection().injector
+    }
+
+    /// Number of allocations failed by the injector since it was set.
+    pub fn injected_fault_count(&self) -> u64 {
+        self.fault_injection().fault_count
+    }
+
+    fn fault_injection(&self) -> MutexGuard<'_, FaultInjection> {
+        self.fault_injection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Fails with `VmaError::OutOfDeviceMemory` if the injector fails the allocations of
+    /// `request` about to be made in `pool`, before VMA is called.
+    ///
+    /// The size of the request and the memory type VMA picks first for it are checked here,
+    /// what's only known once VMA made the allocations is left to
+    /// `Allocator::inject_late_faults`.
+    pub(crate) fn inject_faults(
+        &self,
+        pool: PoolHandle,
+        create_info: &ffi::VmaAllocationCreateInfo,
+        request: AllocationRequest<'_>,
+    ) -> VmaResult<()> {
+        let mut injection = self.fault_injection();
+        let Some(injector) = injection.injector else {
+            return Ok(());
+        };
+        let (size, count) = match request {
+            AllocationRequest::Memory(requirements, count) => (Some(requirements.size), count),
+            AllocationRequest::Buffer(buffer_info) => (Some(buffer_info.size), 1),
+            AllocationRequest::Image(_) | AllocationRequest::Resource => (None, 1),
+        };
+        let counted = injection.allocation_count;
+        injection.allocation_count += count as u64;
+        let failed = (injector.fail_every != 0
+            && injection.allocation_count / injector.fail_every > counted / injector.fail_every)
+            || size
+                .zip(injector.max_size)
+                .is_some_and(|(size, max_size)| size > max_size)
+            || (injector.memory_type_bits != 0
+                && self
+                    .requested_memory_type(pool, create_info, request)
+                    .is_some_and(|index| injector.memory_type_bits & (1 << index) != 0));
+        if !failed {
+            return Ok(());
+        }
+        injection.fault_count += 1;
+        Err(VmaError::OutOfDeviceMemory(None))
+    }
+
+    /// Fails with `VmaError::OutOfDeviceMemory` if the injector fails any of the freshly made
+    /// `allocations` of `request`, for the conditions `Allocator::inject_faults` couldn't check:
+    /// the size of images and of memory for existing resources, and the memory type of the
+    /// latter outside of custom pools. The caller frees them in that case.
+    pub(crate) fn inject_late_faults(
+        &self,
+        pool: PoolHandle,
+        request: AllocationRequest<'_>,
+        allocations: &[ffi::VmaAllocation],
+    ) -> VmaResult<()> {
+        let size_known = request.size().is_some();
+        let memory_type_known =
+            !pool.0.is_null() || !matches!(request, AllocationRequest::Resource);
+        if size_known && memory_type_known {
+            return Ok(());
+        }
+        let mut injection = self.fault_injection();
+        let Some(injector) = injection.injector else {
+            return Ok(());
+        };
+        let failed = allocations.iter().any(|&raw| {
+            let mut info: ffi::VmaAllocationInfo = unsafe { std::mem::zeroed() };
+            unsafe { ffi::vmaGetAllocationInfo(self.internal, raw, &mut info) };
+            (!size_known
+                && injector
+                    .max_size
+                    .is_some_and(|max_size| info.size > max_size))
+                || (!memory_type_known && injector.memory_type_bits & (1 << info.memoryType) != 0)
+        });
+        if !failed {
+            return Ok(());
+        }
+        injection.fault_count += 1;
+        Err(VmaError::OutOfDeviceMemory(None))
+    }
+
+    /// Memory type an allocation of `request` will be made in, if known before VMA makes it:
+    /// the one of a custom `pool`, or else the one VMA picks first.
+    fn requested_memory_type(
+        &self,
+        pool: PoolHandle,
+        create_info: &ffi::VmaAllocationCreateInfo,
+        request: AllocationRequest<'_>,
+    ) -> Option<u32> {
+        if !pool.0.is_null() {
+            return self
+                .tracker
+                .pools()
+                .iter()
+                .find(|live| live.handle.0 == pool.0)
+                .map(|live| live.create_info.memory_type_index);
+        }
+        let mut memory_type_index = 0;
+        let result = unsafe {
+            match request {
+                AllocationRequest::Memory(requirements, _) => ffi::vmaFindMemoryTypeIndex(
+                    self.internal,
+                    requirements.memory_type_bits,
+                    create_info,
+                    &mut memory_type_index,
+                ),
+                AllocationRequest::Buffer(buffer_info) => ffi::vmaFindMemoryTypeIndexForBufferInfo(
+                    self.internal,
+                    buffer_info,
+                    create_info,
+                    &mut memory_type_index,
+                ),
+                AllocationRequest::Image(image_info) => ffi::vmaFindMemoryTypeIndexForImageInfo(
+                    self.internal,
+                    image_info,
+                    create_info,
+                    &mut memory_type_index,
+                ),
+                AllocationRequest::Resource => return None,
+            }
+        };
+        (result == vk::Result::SUCCESS).then_some(memory_type_index)
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Linear pool and buffers of one frame of a `FrameArena`.
+struct FrameSlot {
+    pool: AllocatorPool,
+    buffers: Vec<(vk::Buffer, Allocation)>,
+}
+
+impl FrameSlot {
+    /// Destroys all buffers of the slot, leaving it untouched on failure.
+    fn reset(&mut self) -> VmaResult<()> {
+        let allocator = self.pool.allocator();
+        for (_, allocation) in &self.buffers {
+            allocator.check_freeable(allocation)?;
+        }
+        for (buffer, allocation) in self.buffers.drain(..) {
+            unsafe { allocator.destroy_buffer(buffer, allocation)? };
+        }
+        Ok(())
+    }
+}
+
+/// Transient buffers valid for a single frame, e.g. for dynamic per-draw data.
+///
+/// Each of the `frame_count` frames in flight bump allocates its buffers from its own linear
+/// pool with a single block. `FrameArena::reset` starts a frame by freeing everything its slot
+/// was used for `frame_count` frames earlier, which is the cheapest case of the linear algorithm.
+///
+/// A buffer that doesn't fit in the rest of the frame's block fails with
+/// `VmaError::OutOfDeviceMemory`.
+pub struct FrameArena {
+    slots: Vec<FrameSlot>,
+    create_info: AllocationCreateInfo,
+    /// Slot of the current frame
+    current: usize,
+}
+
+impl Allocator {
+    /// Creates a `FrameArena` with `frame_count` frames of `frame_size` bytes each, in the given
+    /// memory type.
+    ///
+    /// # Safety
+    /// `create_info` is used for every buffer, see `Alloc::create_buffer`.
+    pub unsafe fn create_frame_arena(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        frame_size: vk::DeviceSize,
+        frame_count: usize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<FrameArena> {
+        if frame_count == 0 {
+            return Err(VmaError::InvalidParameter(
+                "Frame arenas need at least one frame",
+            ));
+        }
+        let slots = (0..frame_count)
+            .map(|_| {
+                Ok(FrameSlot {
+                    pool: self.create_linear_pool(memory_type_index, frame_size)?,
+                    buffers: Vec::new(),
+                })
+            })
+            .collect::<VmaResult<_>>()?;
+        Ok(FrameArena {
+            slots,
+            create_info: create_info.clone(),
+            current: 0,
+        })
+    }
+}
+
+impl FrameArena {
+    /// Creates a buffer of `size` bytes with `usage` for the current frame.
+    ///
+    /// The buffer and its allocation are owned by the arena, and destroyed by the
+    /// `FrameArena::reset` that reuses the frame's slot.
+    #[track_caller]
+    pub fn alloc_buffer(
+        &mut self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> VmaResult<(vk::Buffer, &Allocation)> {
+        let slot = &mut self.slots[self.current];
+        let buffer_info = vk::BufferCreateInfo::default().size(size).usage(usage);
+        let (buffer, allocation) =
+            unsafe { slot.pool.create_buffer(&buffer_info, &self.create_info)? };
+        slot.buffers.push((buffer, allocation));
+        let (buffer, allocation) = slot.buffers.last().unwrap();
+        Ok((*buffer, allocation))
+    }
+
+    /// Starts frame `frame_index`, destroying the buffers of its slot.
+    ///
+    /// Fails like `Allocator::free_memory`, e.g. if one of them is still mapped, in which case
+    /// nothing is destroyed and the current frame is left unchanged.
+    ///
+    /// # Safety
+    /// The device must be done with frame `frame_index - frame_count`, the previous user of the
+    /// slot.
+    pub unsafe fn reset(&mut self, frame_index: u64) -> VmaResult<()> {
+        let current = (frame_index % self.slots.len() as u64) as usize;
+        self.slots[current].reset()?;
+        self.current = current;
+        Ok(())
+    }
+
+    /// Destroys the buffers of all frames, reporting errors that `Drop` would ignore.
+    ///
+    /// Slots that fail are left untouched, and the first error is returned. The device must not
+    /// use the buffers anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.reset_all()
+    }
+
+    /// Pool backing the current frame.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.slots[self.current].pool
+    }
+
+    /// Number of buffers of the current frame.
+    pub fn len(&self) -> usize {
+        self.slots[self.current].buffers.len()
+    }
+
+    /// Returns `true` if no buffer was created for the current frame.
+    pub fn is_empty(&self) -> bool {
+        self.slots[self.current].buffers.is_empty()
+    }
+
+    fn reset_all(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for slot in &mut self.slots {
+            result = result.and(slot.reset());
+        }
+        result
+    }
+}
+
+/// Destroys all buffers, the device must not use them anymore.
+impl Drop for FrameArena {
+    fn drop(&mut self) {
+        let _ = self.reset_all();
+    }
+}
+
+/// `FrameArena` with a frame count fixed at compile time.
+///
+/// The slots are kept inline rather than in a `Vec`, and frame indices are reduced modulo the
+/// constant `N`.
+pub struct FixedFrameArena<const N: usize> {
+    slots: [FrameSlot; N],
+    create_info: AllocationCreateInfo,
+    /// Slot of the current frame
+    current: usize,
+}
+
+impl Allocator {
+    /// Creates a `FixedFrameArena` with `N` frames of `frame_size` bytes each, in the given
+    /// memory type.
+    ///
+    /// # Safety
+    /// `create_info` is used for every buffer, see `Alloc::create_buffer`.
+    pub unsafe fn create_fixed_frame_arena<const N: usize>(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        frame_size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<FixedFrameArena<N>> {
+        const { assert!(N > 0, "Frame arenas need at least one frame") };
+        let slots = (0..N)
+            .map(|_| {
+                Ok(FrameSlot {
+                    pool: self.create_linear_pool(memory_type_index, frame_size)?,
+                    buffers: Vec::new(),
+                })
+            })
+            .collect::<VmaResult<Vec<_>>>()?;
+        let Ok(slots) = slots.try_into() else {
+            unreachable!()
+        };
+        Ok(FixedFrameArena {
+            slots,
+            create_info: create_info.clone(),
+            current: 0,
+        })
+    }
+}
+
+impl<const N: usize> FixedFrameArena<N> {
+    /// Creates a buffer of `size` bytes with `usage` for the current frame, see
+    /// `FrameArena::alloc_buffer`.
+    #[track_caller]
+    pub fn alloc_buffer(
+        &mut self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> VmaResult<(vk::Buffer, &Allocation)> {
+        let slot = &mut self.slots[self.current];
+        let buffer_info = vk::BufferCreateInfo::default().size(size).usage(usage);
+        let (buffer, allocation) =
+            unsafe { slot.pool.create_buffer(&buffer_info, &self.create_info)? };
+        slot.buffers.push((buffer, allocation));
+        let (buffer, allocation) = slot.buffers.last().unwrap();
+        Ok((*buffer, allocation))
+    }
+
+    /// Starts frame `frame_index`, destroying the buffers of its slot, see `FrameArena::reset`.
+    ///
+    /// # Safety
+    /// The device must be done with frame `frame_index - N`, the previous user of the slot.
+    pub unsafe fn reset(&mut self, frame_index: u64) -> VmaResult<()> {
+        let current = (frame_index % N as u64) as usize;
+        self.slots[current].reset()?;
+        self.current = current;
+        Ok(())
+    }
+
+    /// Destroys the buffers of all frames, reporting errors that `Drop` would ignore.
+    ///
+    /// Slots that fail are left untouched, and the first error is returned. The device must not
+    /// use the buffers anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.reset_all()
+    }
+
+    /// Pool backing the current frame.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.slots[self.current].pool
+    }
+
+    /// Number of buffers of the current frame.
+    pub fn len(&self) -> usize {
+        self.slots[self.current].buffers.len()
+    }
+
+    /// Returns `true` if no buffer was created for the current frame.
+    pub fn is_empty(&self) -> bool {
+        self.slots[self.current].buffers.is_empty()
+    }
+
+    fn reset_all(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for slot in &mut self.slots {
+            result = result.and(slot.reset());
+        }
+        result
+    }
+}
+
+/// Destroys all buffers, the device must not use them anymore.
+impl<const N: usize> Drop for FixedFrameArena<N> {
+    fn drop(&mut self) {
+        let _ = self.reset_all();
+    }
+}
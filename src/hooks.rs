@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+use crate::ffi;
+use crate::AllocationId;
+use crate::AllocationInfo;
+use crate::Allocator;
+use crate::PoolHandle;
+use ash::vk;
+
+/// Whether an `AllocationEvent` reports a new allocation or a freed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationEventKind {
+    Allocated,
+    Freed,
+}
+
+/// Allocation or free of a single allocation, see `Allocator::add_allocation_hook`.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationEvent {
+    pub kind: AllocationEventKind,
+    /// Id of the allocation.
+    pub id: AllocationId,
+    /// Size of the allocation, in bytes.
+    pub size: vk::DeviceSize,
+    /// Memory type index of the allocation.
+    pub memory_type: u32,
+    /// Custom pool of the allocation, see `AllocatorPool::as_raw`, or null for the default
+    /// pools.
+    ///
+    /// `None` if it isn't known, for frees with the `minimal-checks` feature, which doesn't keep
+    /// track of the pools of allocations.
+    pub pool: Option<ffi::VmaPool>,
+    /// User data of the allocation, see `AllocationCreateInfo::user_data`.
+    pub user_data: usize,
+}
+unsafe impl Send for AllocationEvent {}
+unsafe impl Sync for AllocationEvent {}
+
+/// Identifier of a hook added with `Allocator::add_allocation_hook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocationHookId(u64);
+
+type Hook = Box<dyn Fn(&AllocationEvent) + Send + Sync>;
+
+/// Hooks registered on an `Allocator`.
+pub(crate) struct AllocationHooks {
+    hooks: RwLock<(u64, Vec<(AllocationHookId, Hook)>)>,
+    /// Number of hooks, so that allocating and freeing don't take the lock when there are none
+    count: AtomicUsize,
+}
+
+impl AllocationHooks {
+    pub(crate) fn new() -> Self {
+        Self {
+            hooks: RwLock::new((0, Vec::new())),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, (u64, Vec<(AllocationHookId, Hook)>)> {
+        self.hooks.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.count.load(Ordering::Relaxed) == 0
+    }
+}
+
+impl Allocator {
+    /// Registers `hook` to be called on every allocation and free made through the wrapper,
+    /// including allocations for buffers and images and allocations of custom pools.
+    ///
+    /// Unlike `ffi::VmaDeviceMemoryCallbacks`, which only report whole `vk::DeviceMemory`
+    /// blocks, hooks see each allocation, e.g. for budget accounting per subsystem. Hooks are
+    /// called on the thread making the allocation or free, after it was made and right before
+    /// it is freed respectively, and must not add or remove hooks.
+    pub fn add_allocation_hook(
+        &self,
+        hook: impl Fn(&AllocationEvent) + Send + Sync + 'static,
+    ) -> AllocationHookId {
+        let mut hooks = self
+            .hooks
+            .hooks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        hooks.0 += 1;
+        let id = AllocationHookId(hooks.0);
+        hooks.1.push((id, Box::new(hook)));
+        self.hooks.count.store(hooks.1.len(), Ordering::Relaxed);
+        id
+    }
+
+    /// Unregisters a hook added with `Allocator::add_allocation_hook`.
+    ///
+    /// Returns `false` if it was already removed.
+    pub fn remove_allocation_hook(&self, id: AllocationHookId) -> bool {
+        let mut hooks = self
+            .hooks
+            .hooks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let len = hooks.1.len();
+        hooks.1.retain(|(hook_id, _)| *hook_id != id);
+        self.hooks.count.store(hooks.1.len(), Ordering::Relaxed);
+        hooks.1.len() != len
+    }
+
+    /// Calls the hooks for an allocation described by `info`.
+    pub(crate) fn notify_hooks(
+        &self,
+        kind: AllocationEventKind,
+        id: AllocationId,
+        pool: Option<PoolHandle>,
+        info: &AllocationInfo,
+    ) {
+        let event = AllocationEvent {
+            kind,
+            id,
+            size: info.size,
+            memory_type: info.memory_type,
+            pool: pool.map(|pool| pool.0),
+            user_data: info.user_data,
+        };
+        for (_, hook) in &self.hooks.read().1 {
+            hook(&event);
+        }
+    }
+}
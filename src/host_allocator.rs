@@ -0,0 +1,51 @@
+use std::alloc::{self, Layout};
+use std::os::raw::c_void;
+use std::ptr;
+
+/// Bytes right before every allocation holding the size and alignment of its layout, since
+/// `vma_rust_free` only gets the pointer.
+const HEADER_SIZE: usize = 2 * std::mem::size_of::<usize>();
+
+/// Offset of an allocation aligned to `alignment` from the start of its layout.
+fn offset(alignment: usize) -> usize {
+    HEADER_SIZE.next_multiple_of(alignment)
+}
+
+/// Allocates VMA's CPU memory for `VMA_SYSTEM_ALIGNED_MALLOC` in wrapper.cpp, from Rust's
+/// global allocator.
+///
+/// VMA only uses it without `AllocatorCreateInfo::allocation_callbacks`. Returns null if the
+/// allocation fails.
+#[no_mangle]
+unsafe extern "C" fn vma_rust_alloc(size: usize, alignment: usize) -> *mut c_void {
+    let alignment = alignment.max(std::mem::align_of::<usize>());
+    let layout = offset(alignment)
+        .checked_add(size)
+        .and_then(|size| Layout::from_size_align(size, alignment).ok());
+    let Some(layout) = layout else {
+        return ptr::null_mut();
+    };
+    let base = alloc::alloc(layout);
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    let data = base.add(offset(alignment));
+    let header = data.cast::<usize>().sub(2);
+    header.write(layout.size());
+    header.add(1).write(layout.align());
+    data.cast()
+}
+
+/// Frees memory of `vma_rust_alloc` for `VMA_SYSTEM_ALIGNED_FREE` in wrapper.cpp.
+#[no_mangle]
+unsafe extern "C" fn vma_rust_free(data: *mut c_void) {
+    if data.is_null() {
+        return;
+    }
+    let header = data.cast::<usize>().sub(2);
+    let (size, alignment) = (header.read(), header.add(1).read());
+    alloc::dealloc(
+        data.cast::<u8>().sub(offset(alignment)),
+        Layout::from_size_align_unchecked(size, alignment),
+    );
+}
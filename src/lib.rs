@@ -1,26 +1,334 @@
 //! Easy to use, high performance memory manager for Vulkan.
 
+mod assertions;
+mod buffer_suballocator;
+mod build_config;
+mod debug_names;
 mod definitions;
+mod defragmentable_resources;
 mod defragmentation;
-mod ffi;
+mod deletion_queue;
+mod descriptor_heap;
+mod device_memory_allocator;
+mod double_stack_allocator;
+mod error;
+mod external_memory;
+#[cfg(feature = "fault-injection")]
+mod fault_injection;
+/// Raw bindings to the VMA C API, generated by bindgen.
+///
+/// This is meant for functionality that isn't wrapped yet. Wrapper types convert to and from
+/// their ffi counterparts with `From`/`TryFrom`, so mixing both layers doesn't require
+/// translating every field by hand.
+pub mod ffi;
+mod frame_arena;
+mod hooks;
+#[cfg(feature = "rust-allocator")]
+mod host_allocator;
+#[cfg(feature = "log")]
+mod logging;
+mod managed;
+mod mapping;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod pool;
+#[cfg(feature = "recording")]
+mod recording;
+mod registry;
+mod ring_allocator;
+#[cfg(feature = "pure-rust")]
+mod rust_backend;
+#[cfg(feature = "pure-rust")]
+mod rust_vma;
+mod scratch_pool;
+mod single_threaded;
+mod snapshot;
+mod stack_allocator;
+mod staging_belt;
+mod statistics;
+mod stats_sampler;
+mod sub_allocator;
+mod tracking;
+mod transfer;
+mod uniform_arena;
+mod upload_queue;
 mod virtual_block;
+mod virtual_block_allocator;
+/// Visualization of the memory map of an allocator, like VMA's `VmaDumpVis.py`.
+pub mod viz;
+pub use assertions::*;
+pub use buffer_suballocator::*;
+pub use build_config::*;
 pub use definitions::*;
+pub use defragmentable_resources::*;
 pub use defragmentation::*;
+pub use deletion_queue::*;
+pub use descriptor_heap::*;
+pub use device_memory_allocator::*;
+pub use double_stack_allocator::*;
+pub use error::*;
+pub use external_memory::*;
+#[cfg(feature = "fault-injection")]
+pub use fault_injection::*;
+pub use frame_arena::*;
+pub use hooks::*;
+pub use managed::*;
+pub use mapping::*;
+#[cfg(feature = "metrics")]
+pub use metrics::*;
 pub use pool::*;
+#[cfg(feature = "recording")]
+pub use recording::*;
+pub use registry::*;
+pub use ring_allocator::*;
+#[cfg(feature = "pure-rust")]
+pub use rust_backend::*;
+pub use scratch_pool::*;
+pub use single_threaded::*;
+pub use snapshot::*;
+pub use stack_allocator::*;
+pub use staging_belt::*;
+pub use statistics::*;
+pub use stats_sampler::*;
+pub use sub_allocator::*;
+pub use tracking::*;
+pub use uniform_arena::*;
+pub use upload_queue::*;
 pub use virtual_block::*;
+pub use virtual_block_allocator::*;
 
-use ash::prelude::VkResult;
 use ash::vk;
+use debug_names::DebugNames;
+use std::ffi::CStr;
 use std::mem;
+use std::panic::Location;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Major and minor version of VMA the bindings were generated for.
+pub const VMA_VERSION: (u32, u32) = (3, 1);
+
+/// Fails unless the VMA library linked with the `system-vma` feature is a `VMA_VERSION`
+/// release, whose structures match the bindings.
+///
+/// VMA can't be queried for its version, so build.rs reads it from the library's header.
+pub(crate) fn check_vma_version() -> VmaResult<()> {
+    #[cfg(feature = "system-vma")]
+    {
+        let version = env!("VK_MEM_SYSTEM_VMA_VERSION");
+        let mut parts = version.split('.').map(str::parse::<u32>);
+        if !matches!(
+            (parts.next(), parts.next()),
+            (Some(Ok(major)), Some(Ok(minor))) if (major, minor) == VMA_VERSION
+        ) {
+            return Err(VmaError::IncompatibleVersion { version });
+        }
+    }
+    Ok(())
+}
+
+/// Device functions VMA calls for a Vulkan version and allocator flag bits, by their core names and
+/// by the names VMA fetches them with below that version.
+const VERSIONED_DEVICE_FUNCTIONS: [(u32, u32, &CStr, &CStr); 6] = [
+    (
+        vk::API_VERSION_1_1,
+        AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION.bits(),
+        c"vkGetBufferMemoryRequirements2",
+        c"vkGetBufferMemoryRequirements2KHR",
+    ),
+    (
+        vk::API_VERSION_1_1,
+        AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION.bits(),
+        c"vkGetImageMemoryRequirements2",
+        c"vkGetImageMemoryRequirements2KHR",
+    ),
+    (
+        vk::API_VERSION_1_1,
+        AllocatorCreateFlags::KHR_BIND_MEMORY2.bits(),
+        c"vkBindBufferMemory2",
+        c"vkBindBufferMemory2KHR",
+    ),
+    (
+        vk::API_VERSION_1_1,
+        AllocatorCreateFlags::KHR_BIND_MEMORY2.bits(),
+        c"vkBindImageMemory2",
+        c"vkBindImageMemory2KHR",
+    ),
+    (
+        vk::API_VERSION_1_3,
+        AllocatorCreateFlags::KHR_MAINTENANCE4.bits(),
+        c"vkGetDeviceBufferMemoryRequirements",
+        c"vkGetDeviceBufferMemoryRequirementsKHR",
+    ),
+    (
+        vk::API_VERSION_1_3,
+        AllocatorCreateFlags::KHR_MAINTENANCE4.bits(),
+        c"vkGetDeviceImageMemoryRequirements",
+        c"vkGetDeviceImageMemoryRequirementsKHR",
+    ),
+];
+
+/// Fails with `VmaError::UnsupportedVulkanFeature` if the device lacks functions VMA would call
+/// for `create_info.vulkan_api_version` and `create_info.flags`.
+///
+/// Missing functions are null for VMA and panicking stubs in the function tables of ash, both
+/// crash at their first call. Functions given to `AllocatorCreateInfo::from_raw_parts`, and
+/// instance functions, aren't checked.
+unsafe fn check_device_functions(create_info: &AllocatorCreateInfo) -> VmaResult<()> {
+    // The tables of ash are loaded with core names, VMA fetches extension names by itself
+    let (device, get_device_proc_addr, core_names) = match &create_info.vulkan {
+        VulkanObjects::Ash { instance, device } => (
+            device.handle(),
+            instance.fp_v1_0().get_device_proc_addr,
+            true,
+        ),
+        VulkanObjects::Dynamic {
+            device,
+            get_device_proc_addr,
+            ..
+        } => (*device, *get_device_proc_addr, false),
+        VulkanObjects::Raw { .. } => return Ok(()),
+    };
+    // Patch versions are ignored by VMA
+    let api_version = create_info.vulkan_api_version & !0xfff;
+    let missing: Vec<&'static str> = VERSIONED_DEVICE_FUNCTIONS
+        .iter()
+        .filter_map(|(version, flag, core_name, extension_name)| {
+            let name = if api_version >= *version {
+                core_name
+            } else if create_info.flags.bits() & flag != 0 {
+                if core_names {
+                    core_name
+                } else {
+                    extension_name
+                }
+            } else {
+                return None;
+            };
+            get_device_proc_addr(device, name.as_ptr())
+                .is_none()
+                .then(|| name.to_str().unwrap())
+        })
+        .collect();
+    if !missing.is_empty() {
+        return Err(VmaError::UnsupportedVulkanFeature { functions: missing });
+    }
+    Ok(())
+}
+
+/// Allocator flags with the device extension each needs, and the Vulkan version it was
+/// promoted to, if any.
+const FLAG_EXTENSIONS: [(u32, &str, &CStr, Option<u32>); 9] = [
+    (
+        AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION.bits(),
+        "KHR_DEDICATED_ALLOCATION",
+        ash::khr::get_memory_requirements2::NAME,
+        Some(vk::API_VERSION_1_1),
+    ),
+    (
+        AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION.bits(),
+        "KHR_DEDICATED_ALLOCATION",
+        ash::khr::dedicated_allocation::NAME,
+        Some(vk::API_VERSION_1_1),
+    ),
+    (
+        AllocatorCreateFlags::KHR_BIND_MEMORY2.bits(),
+        "KHR_BIND_MEMORY2",
+        ash::khr::bind_memory2::NAME,
+        Some(vk::API_VERSION_1_1),
+    ),
+    (
+        AllocatorCreateFlags::EXT_MEMORY_BUDGET.bits(),
+        "EXT_MEMORY_BUDGET",
+        ash::ext::memory_budget::NAME,
+        None,
+    ),
+    (
+        AllocatorCreateFlags::AMD_DEVICE_COHERENT_MEMORY.bits(),
+        "AMD_DEVICE_COHERENT_MEMORY",
+        ash::amd::device_coherent_memory::NAME,
+        None,
+    ),
+    (
+        AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS.bits(),
+        "BUFFER_DEVICE_ADDRESS",
+        ash::khr::buffer_device_address::NAME,
+        Some(vk::API_VERSION_1_2),
+    ),
+    (
+        AllocatorCreateFlags::EXT_MEMORY_PRIORITY.bits(),
+        "EXT_MEMORY_PRIORITY",
+        ash::ext::memory_priority::NAME,
+        None,
+    ),
+    (
+        AllocatorCreateFlags::KHR_MAINTENANCE4.bits(),
+        "KHR_MAINTENANCE4",
+        ash::khr::maintenance4::NAME,
+        Some(vk::API_VERSION_1_3),
+    ),
+    (
+        AllocatorCreateFlags::KHR_MAINTENANCE5.bits(),
+        "KHR_MAINTENANCE5",
+        ash::khr::maintenance5::NAME,
+        None,
+    ),
+];
+
+/// Fails with `VmaError::MissingDeviceExtension` if a flag of `create_info` needs an extension
+/// missing from `AllocatorCreateInfo::enabled_device_extensions`, when they are given.
+fn check_flag_extensions(create_info: &AllocatorCreateInfo) -> VmaResult<()> {
+    let Some(enabled) = create_info.enabled_device_extensions else {
+        return Ok(());
+    };
+    let api_version = create_info.vulkan_api_version & !0xfff;
+    for (flag, flag_name, extension, promoted) in FLAG_EXTENSIONS {
+        if create_info.flags.bits() & flag != 0
+            && promoted.is_none_or(|version| api_version < version)
+            && !enabled.contains(&extension)
+        {
+            return Err(VmaError::MissingDeviceExtension {
+                flag: flag_name,
+                extension: extension.to_str().unwrap(),
+            });
+        }
+    }
+    Ok(())
+}
 
 /// Main allocator object
 pub struct Allocator {
     /// Pointer to internal VmaAllocator instance
     internal: ffi::VmaAllocator,
+    /// Pools dropped while they still had live allocations
+    deferred_pools: Mutex<Vec<PoolHandle>>,
+    /// Live allocations, for leak reports
+    tracker: AllocationTracker,
+    /// Hooks added with `Allocator::add_allocation_hook`
+    hooks: AllocationHooks,
+    /// Recording started with `Allocator::start_recording`
+    #[cfg(feature = "recording")]
+    recorder: CallRecorder,
+    /// Patterns set with `Allocator::set_fill_patterns`
+    fill_patterns: AtomicFillPatterns,
+    /// Set with `AllocatorCreateInfo::debug_utils`, must outlive the VMA allocator
+    debug_names: Option<Box<DebugNames>>,
+    /// Injector set with `Allocator::set_fault_injector`
+    #[cfg(feature = "fault-injection")]
+    fault_injection: Mutex<FaultInjection>,
+    /// Whether the device was created with the `protectedMemory` feature enabled
+    protected_memory: bool,
+    /// `AllocatorCreateInfo::device_group_size`
+    device_group_size: u32,
+    /// `AllocatorCreateInfo::flags`
+    flags: AllocatorCreateFlags,
+    /// Device and instance kept alive when created with `AllocatorCreateInfo::owned`,
+    /// released after the VMA allocator is destroyed
+    _owned_vulkan: Option<(Arc<ash::Device>, Arc<ash::Instance>)>,
+    /// Whether the VMA allocator is destroyed on drop, false with `Allocator::from_raw_borrowed`
+    owns_internal: bool,
 }
 
-// Allocator is internally thread safe unless AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED is used (then you need to add synchronization!)
+// Allocator is internally thread safe, AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED is only accepted by SingleThreadedAllocator
 unsafe impl Send for Allocator {}
 unsafe impl Sync for Allocator {}
 
@@ -40,144 +348,285 @@ unsafe impl Sync for Allocator {}
 /// use `Allocator::get_allocation_info`.
 ///
 /// Some kinds allocations can be in lost state.
-#[derive(Clone, Copy, Debug)]
-pub struct Allocation(ffi::VmaAllocation);
+///
+/// `Allocation` is a move-only handle: functions that free an allocation take it by value, so it
+/// can't be freed twice or used after it was freed.
+#[derive(Debug)]
+pub struct Allocation {
+    raw: ffi::VmaAllocation,
+    id: AllocationId,
+}
 unsafe impl Send for Allocation {}
 unsafe impl Sync for Allocation {}
 
+impl Allocation {
+    /// Takes over a `VmaAllocation` that was created outside of this crate, e.g. by C++ code
+    /// sharing the same `VmaAllocator`.
+    ///
+    /// `pool` is the custom pool the allocation was made from, or `None` for the default pools,
+    /// and is reported by hooks, the registry and recordings.
+    ///
+    /// # Safety
+    /// `raw` must be a valid allocation made from `allocator`, in `pool` if given, and from now
+    /// on it must only be freed through the wrapper.
+    #[track_caller]
+    pub unsafe fn adopt(
+        raw: ffi::VmaAllocation,
+        allocator: &Allocator,
+        pool: Option<&AllocatorPool>,
+    ) -> Allocation {
+        debug_assert!(!raw.is_null(), "Attempted to adopt a null VmaAllocation");
+        debug_assert!(!allocator.internal.is_null());
+        debug_assert!(pool.is_none_or(|pool| std::ptr::eq(pool.allocator.as_ref(), allocator)));
+        let pool = pool.map_or(PoolHandle(std::ptr::null_mut()), |pool| pool.pool);
+        allocator.track_existing(raw, pool, Location::caller())
+    }
+
+    /// Returns the raw `VmaAllocation` handle, for use with the [`ffi`] functions.
+    ///
+    /// Freeing the allocation through the raw handle bypasses leak tracking, see
+    /// `Allocator::destroy`.
+    pub fn as_raw(&self) -> ffi::VmaAllocation {
+        self.raw
+    }
+
+    /// Returns the id of this allocation.
+    pub fn id(&self) -> AllocationId {
+        self.id
+    }
+
+    /// Creates a second handle to the same allocation.
+    ///
+    /// # Safety
+    /// Only one of the handles may be freed, and none of them may be used after that.
+    pub unsafe fn clone_raw(&self) -> Allocation {
+        Allocation {
+            raw: self.raw,
+            id: self.id,
+        }
+    }
+}
+
 impl Allocator {
     /// Construct a new `Allocator` using the provided options.
     ///
     /// # Safety
-    /// [`AllocatorCreateInfo::instance`], [`AllocatorCreateInfo::device`] and
-    /// [`AllocatorCreateInfo::physical_device`] must be valid throughout the lifetime of the allocator.
-    pub unsafe fn new(create_info: AllocatorCreateInfo) -> VkResult<Self> {
+    /// The instance, device and physical device of `create_info`, and the functions given to
+    /// `AllocatorCreateInfo::from_raw_parts`, must be valid throughout the lifetime of the allocator.
+    /// With `AllocatorCreateInfo::owned`, the allocator keeps the instance and device `Arc`s alive
+    /// for its whole lifetime.
+    ///
+    /// `AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED` is rejected with
+    /// `VmaError::InvalidParameter`, since `Allocator` can be shared between threads. Use
+    /// `SingleThreadedAllocator::new` instead.
+    ///
+    /// Fails with `VmaError::UnsupportedVulkanFeature` if the device lacks functions needed for
+    /// `AllocatorCreateInfo::vulkan_api_version` and the flags, e.g. a Vulkan 1.3 allocator
+    /// for a Vulkan 1.2 device, and with `VmaError::MissingDeviceExtension` for flags whose
+    /// extension is missing from `AllocatorCreateInfo::enabled_device_extensions`.
+    pub unsafe fn new(create_info: AllocatorCreateInfo) -> VmaResult<Self> {
+        if create_info
+            .flags
+            .contains(AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED)
+        {
+            return Err(VmaError::InvalidParameter(
+                "EXTERNALLY_SYNCHRONIZED requires a SingleThreadedAllocator",
+            ));
+        }
+        check_vma_version()?;
+        Self::create(create_info)
+    }
+
+    pub(crate) unsafe fn create(create_info: AllocatorCreateInfo) -> VmaResult<Self> {
+        // VMA only fetches functions that are left null, and every function VMA calls is set.
+        // Panicking can't unwind out of VMA, so these report the function as missing instead.
         unsafe extern "system" fn get_instance_proc_addr_stub(
             _instance: vk::Instance,
             _p_name: *const ::std::os::raw::c_char,
         ) -> vk::PFN_vkVoidFunction {
-            panic!("VMA_DYNAMIC_VULKAN_FUNCTIONS is unsupported")
+            None
         }
 
         unsafe extern "system" fn get_get_device_proc_stub(
             _device: vk::Device,
             _p_name: *const ::std::os::raw::c_char,
         ) -> vk::PFN_vkVoidFunction {
-            panic!("VMA_DYNAMIC_VULKAN_FUNCTIONS is unsupported")
+            None
         }
 
-        let mut raw_create_info = ffi::VmaAllocatorCreateInfo {
-            flags: create_info.flags.bits(),
-            physicalDevice: create_info.physical_device,
-            device: create_info.device.handle(),
-            preferredLargeHeapBlockSize: create_info.preferred_large_heap_block_size,
-            pAllocationCallbacks: create_info
-                .allocation_callbacks
-                .map(|a| unsafe { std::mem::transmute(a) })
-                .unwrap_or(std::ptr::null()),
-            pDeviceMemoryCallbacks: create_info
-                .device_memory_callbacks
-                .map(|a| a as *const _)
-                .unwrap_or(std::ptr::null()),
-            pHeapSizeLimit: if create_info.heap_size_limits.is_empty() {
-                std::ptr::null()
-            } else {
-                create_info.heap_size_limits.as_ptr()
-            },
-            instance: create_info.instance.handle(),
-            vulkanApiVersion: create_info.vulkan_api_version,
-            pVulkanFunctions: std::ptr::null(),
-            pTypeExternalMemoryHandleTypes: if create_info
-                .type_external_memory_handle_types
-                .is_empty()
-            {
-                std::ptr::null()
-            } else {
-                create_info.type_external_memory_handle_types.as_ptr()
-            },
-        };
+        check_flag_extensions(&create_info)?;
+        check_device_functions(&create_info)?;
+
+        let mut raw_create_info: ffi::VmaAllocatorCreateInfo = (&create_info).into();
 
         #[cfg(feature = "loaded")]
-        let routed_functions = ffi::VmaVulkanFunctions {
-            vkGetInstanceProcAddr: get_instance_proc_addr_stub,
-            vkGetDeviceProcAddr: get_get_device_proc_stub,
-            vkGetPhysicalDeviceProperties: create_info
-                .instance
-                .fp_v1_0()
-                .get_physical_device_properties,
-            vkGetPhysicalDeviceMemoryProperties: create_info
-                .instance
-                .fp_v1_0()
-                .get_physical_device_memory_properties,
-            vkAllocateMemory: create_info.device.fp_v1_0().allocate_memory,
-            vkFreeMemory: create_info.device.fp_v1_0().free_memory,
-            vkMapMemory: create_info.device.fp_v1_0().map_memory,
-            vkUnmapMemory: create_info.device.fp_v1_0().unmap_memory,
-            vkFlushMappedMemoryRanges: create_info.device.fp_v1_0().flush_mapped_memory_ranges,
-            vkInvalidateMappedMemoryRanges: create_info
-                .device
-                .fp_v1_0()
-                .invalidate_mapped_memory_ranges,
-            vkBindBufferMemory: create_info.device.fp_v1_0().bind_buffer_memory,
-            vkBindImageMemory: create_info.device.fp_v1_0().bind_image_memory,
-            vkGetBufferMemoryRequirements: create_info
-                .device
-                .fp_v1_0()
-                .get_buffer_memory_requirements,
-            vkGetImageMemoryRequirements: create_info
-                .device
-                .fp_v1_0()
-                .get_image_memory_requirements,
-            vkCreateBuffer: create_info.device.fp_v1_0().create_buffer,
-            vkDestroyBuffer: create_info.device.fp_v1_0().destroy_buffer,
-            vkCreateImage: create_info.device.fp_v1_0().create_image,
-            vkDestroyImage: create_info.device.fp_v1_0().destroy_image,
-            vkCmdCopyBuffer: create_info.device.fp_v1_0().cmd_copy_buffer,
-            vkGetBufferMemoryRequirements2KHR: create_info
-                .device
-                .fp_v1_1()
-                .get_buffer_memory_requirements2,
-            vkGetImageMemoryRequirements2KHR: create_info
-                .device
-                .fp_v1_1()
-                .get_image_memory_requirements2,
-            vkBindBufferMemory2KHR: create_info.device.fp_v1_1().bind_buffer_memory2,
-            vkBindImageMemory2KHR: create_info.device.fp_v1_1().bind_image_memory2,
-            vkGetPhysicalDeviceMemoryProperties2KHR: create_info
-                .instance
-                .fp_v1_1()
-                .get_physical_device_memory_properties2,
-            vkGetDeviceBufferMemoryRequirements: create_info
-                .device
-                .fp_v1_3()
-                .get_device_buffer_memory_requirements,
-            vkGetDeviceImageMemoryRequirements: create_info
-                .device
-                .fp_v1_3()
-                .get_device_image_memory_requirements,
+        let routed_functions = match &create_info.vulkan {
+            VulkanObjects::Ash { instance, device } => Some(ffi::VmaVulkanFunctions {
+                vkGetInstanceProcAddr: get_instance_proc_addr_stub,
+                vkGetDeviceProcAddr: get_get_device_proc_stub,
+                vkGetPhysicalDeviceProperties: instance.fp_v1_0().get_physical_device_properties,
+                vkGetPhysicalDeviceMemoryProperties: instance
+                    .fp_v1_0()
+                    .get_physical_device_memory_properties,
+                vkAllocateMemory: device.fp_v1_0().allocate_memory,
+                vkFreeMemory: device.fp_v1_0().free_memory,
+                vkMapMemory: device.fp_v1_0().map_memory,
+                vkUnmapMemory: device.fp_v1_0().unmap_memory,
+                vkFlushMappedMemoryRanges: device.fp_v1_0().flush_mapped_memory_ranges,
+                vkInvalidateMappedMemoryRanges: device.fp_v1_0().invalidate_mapped_memory_ranges,
+                vkBindBufferMemory: device.fp_v1_0().bind_buffer_memory,
+                vkBindImageMemory: device.fp_v1_0().bind_image_memory,
+                vkGetBufferMemoryRequirements: device.fp_v1_0().get_buffer_memory_requirements,
+                vkGetImageMemoryRequirements: device.fp_v1_0().get_image_memory_requirements,
+                vkCreateBuffer: device.fp_v1_0().create_buffer,
+                vkDestroyBuffer: device.fp_v1_0().destroy_buffer,
+                vkCreateImage: device.fp_v1_0().create_image,
+                vkDestroyImage: device.fp_v1_0().destroy_image,
+                vkCmdCopyBuffer: device.fp_v1_0().cmd_copy_buffer,
+                vkGetBufferMemoryRequirements2KHR: device.fp_v1_1().get_buffer_memory_requirements2,
+                vkGetImageMemoryRequirements2KHR: device.fp_v1_1().get_image_memory_requirements2,
+                vkBindBufferMemory2KHR: device.fp_v1_1().bind_buffer_memory2,
+                vkBindImageMemory2KHR: device.fp_v1_1().bind_image_memory2,
+                vkGetPhysicalDeviceMemoryProperties2KHR: instance
+                    .fp_v1_1()
+                    .get_physical_device_memory_properties2,
+                vkGetDeviceBufferMemoryRequirements: device
+                    .fp_v1_3()
+                    .get_device_buffer_memory_requirements,
+                vkGetDeviceImageMemoryRequirements: device
+                    .fp_v1_3()
+                    .get_device_image_memory_requirements,
+            }),
+            VulkanObjects::Raw { .. } | VulkanObjects::Dynamic { .. } => None,
         };
         #[cfg(feature = "loaded")]
+        if let Some(functions) = &routed_functions {
+            raw_create_info.pVulkanFunctions = functions;
+        }
+        if let VulkanObjects::Raw { functions, .. } = &create_info.vulkan {
+            raw_create_info.pVulkanFunctions = &**functions;
+        }
+        // Only the two loader functions are set, VMA fetches the ones left null. The table is
+        // never read on the Rust side, where its null function pointers would be invalid.
+        let mut dynamic_functions = mem::MaybeUninit::<ffi::VmaVulkanFunctions>::zeroed();
+        if let VulkanObjects::Dynamic {
+            get_instance_proc_addr,
+            get_device_proc_addr,
+            ..
+        } = &create_info.vulkan
         {
-            raw_create_info.pVulkanFunctions = &routed_functions;
+            let functions = dynamic_functions.as_mut_ptr();
+            std::ptr::addr_of_mut!((*functions).vkGetInstanceProcAddr)
+                .write(*get_instance_proc_addr);
+            std::ptr::addr_of_mut!((*functions).vkGetDeviceProcAddr).write(*get_device_proc_addr);
+            raw_create_info.pVulkanFunctions = functions;
+        }
+        let debug_names = create_info
+            .debug_utils
+            .map(|debug_utils| DebugNames::new(debug_utils, create_info.device_memory_callbacks));
+        let device_memory_callbacks = debug_names
+            .as_ref()
+            .map(|names| names.device_memory_callbacks());
+        if let Some(callbacks) = &device_memory_callbacks {
+            raw_create_info.pDeviceMemoryCallbacks = callbacks;
         }
         unsafe {
             let mut internal: ffi::VmaAllocator = mem::zeroed();
             ffi::vmaCreateAllocator(&raw_create_info, &mut internal).result()?;
 
-            Ok(Allocator { internal })
+            let mut allocator = Self::wrap(internal, true);
+            allocator.debug_names = debug_names;
+            allocator.protected_memory = create_info.protected_memory;
+            allocator.device_group_size = create_info.device_group_size;
+            allocator.flags = create_info.flags;
+            allocator._owned_vulkan = create_info.vulkan.shared();
+            #[cfg(feature = "recording")]
+            if let Some((path, flags)) = &create_info.recording {
+                let file = std::fs::File::create(path)
+                    .map_err(|err| VmaError::RecordingFailed(err.kind()))?;
+                allocator.start_recording_with_flags(file, *flags)?;
+            }
+            Ok(allocator)
+        }
+    }
+
+    /// Wraps `internal` with nothing tracked yet.
+    fn wrap(internal: ffi::VmaAllocator, owns_internal: bool) -> Self {
+        Allocator {
+            internal,
+            deferred_pools: Mutex::new(Vec::new()),
+            tracker: AllocationTracker::new(),
+            hooks: AllocationHooks::new(),
+            #[cfg(feature = "recording")]
+            recorder: CallRecorder::new(),
+            fill_patterns: AtomicFillPatterns::new(),
+            debug_names: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injection: Mutex::new(FaultInjection::default()),
+            protected_memory: false,
+            device_group_size: 1,
+            flags: AllocatorCreateFlags::empty(),
+            _owned_vulkan: None,
+            owns_internal,
         }
     }
 
+    /// Takes over a `VmaAllocator` created outside of this crate, e.g. by C++ code, and
+    /// destroys it when dropped.
+    ///
+    /// Allocations made before are not tracked, adopt them with `Allocation::adopt`.
+    /// `Allocator::create_protected_pool` is unavailable, since the features of the device
+    /// aren't known.
+    ///
+    /// # Safety
+    /// `raw` must be a valid allocator, destroyed only by the returned `Allocator`. It must not
+    /// be created with `VMA_ALLOCATOR_CREATE_EXTERNALLY_SYNCHRONIZED_BIT`.
+    pub unsafe fn from_raw(raw: ffi::VmaAllocator) -> Self {
+        debug_assert!(!raw.is_null(), "Attempted to wrap a null VmaAllocator");
+        Self::wrap(raw, true)
+    }
+
+    /// Wraps a `VmaAllocator` owned by someone else, e.g. C++ code sharing it with Rust,
+    /// without destroying it when dropped.
+    ///
+    /// Allocations made through the wrapper are still tracked, and reported as leaks if alive
+    /// when it is dropped.
+    ///
+    /// # Safety
+    /// `raw` must be a valid allocator that outlives the returned `Allocator`. It must not be
+    /// created with `VMA_ALLOCATOR_CREATE_EXTERNALLY_SYNCHRONIZED_BIT`.
+    pub unsafe fn from_raw_borrowed(raw: ffi::VmaAllocator) -> Self {
+        debug_assert!(!raw.is_null(), "Attempted to wrap a null VmaAllocator");
+        Self::wrap(raw, false)
+    }
+
+    /// Returns the raw `VmaAllocator` handle, for use with the [`ffi`] functions or sharing it
+    /// with C++ code.
+    pub fn as_raw(&self) -> ffi::VmaAllocator {
+        self.internal
+    }
+
+    /// Releases the `VmaAllocator` without destroying it, the caller is now responsible for
+    /// calling `vmaDestroyAllocator`.
+    ///
+    /// Pools whose destruction was deferred are destroyed if empty, and live allocations are
+    /// no longer tracked. The debug names of `AllocatorCreateInfo::debug_utils` and the device
+    /// kept alive by `AllocatorCreateInfo::owned` are leaked, since the VMA allocator still uses
+    /// them.
+    pub fn into_raw(mut self) -> ffi::VmaAllocator {
+        self.destroy_deferred_pools();
+        mem::forget(self.debug_names.take());
+        mem::forget(self._owned_vulkan.take());
+        mem::replace(&mut self.internal, std::ptr::null_mut())
+    }
+
     /// The allocator fetches `vk::PhysicalDeviceProperties` from the physical device.
     /// You can get it here, without fetching it again on your own.
-    pub unsafe fn get_physical_device_properties(&self) -> VkResult<vk::PhysicalDeviceProperties> {
-        let mut properties = vk::PhysicalDeviceProperties::default();
-        ffi::vmaGetPhysicalDeviceProperties(
-            self.internal,
-            &mut properties as *mut _ as *mut *const _,
-        );
+    pub unsafe fn get_physical_device_properties(&self) -> VmaResult<vk::PhysicalDeviceProperties> {
+        let mut properties: *const vk::PhysicalDeviceProperties = std::ptr::null();
+        ffi::vmaGetPhysicalDeviceProperties(self.internal, &mut properties);
 
-        Ok(properties)
+        Ok(*properties)
     }
 
     /// The allocator fetches `vk::PhysicalDeviceMemoryProperties` from the physical device.
@@ -189,6 +638,30 @@ impl Allocator {
         &*properties
     }
 
+    /// Iterates over all memory types of the physical device.
+    ///
+    /// Each item is `(memory_type_index, memory_type, heap_index, heap_size)`. Property flags are
+    /// fetched through `vmaGetMemoryTypeProperties` and heap sizes come from the memory properties
+    /// cached by the allocator, so they already reflect `AllocatorCreateInfo::heap_size_limits`.
+    pub fn memory_types(
+        &self,
+    ) -> impl Iterator<Item = (u32, vk::MemoryType, u32, vk::DeviceSize)> + '_ {
+        let properties = unsafe { self.get_memory_properties() };
+        (0..properties.memory_type_count).map(move |index| {
+            let mut property_flags = vk::MemoryPropertyFlags::empty();
+            unsafe {
+                ffi::vmaGetMemoryTypeProperties(self.internal, index, &mut property_flags);
+            }
+            let heap_index = properties.memory_types[index as usize].heap_index;
+            let memory_type = vk::MemoryType {
+                property_flags,
+                heap_index,
+            };
+            let heap_size = properties.memory_heaps[heap_index as usize].size;
+            (index, memory_type, heap_index, heap_size)
+        })
+    }
+
     /// Sets index of the current frame.
     ///
     /// This function must be used if you make allocations with `AllocationCreateFlags::CAN_BECOME_LOST` and
@@ -199,36 +672,68 @@ impl Allocator {
         ffi::vmaSetCurrentFrameIndex(self.internal, frame_index);
     }
 
+    /// Builds the statistics of the allocator as a JSON string.
+    ///
+    /// With `detailed_map`, the string also lists every block with its allocations and free
+    /// ranges, see `viz::MemoryMap`. Requires the `stats-string` feature, enabled by default.
+    #[cfg(feature = "stats-string")]
+    pub fn build_stats_string(&self, detailed_map: bool) -> String {
+        unsafe {
+            let mut raw: *mut ::std::os::raw::c_char = std::ptr::null_mut();
+            ffi::vmaBuildStatsString(self.internal, &mut raw, detailed_map as vk::Bool32);
+            if raw.is_null() {
+                return String::new();
+            }
+            let string = CStr::from_ptr(raw).to_string_lossy().into_owned();
+            ffi::vmaFreeStatsString(self.internal, raw);
+            string
+        }
+    }
+
     /// Retrieves statistics from current state of the `Allocator`.
-    pub fn calculate_statistics(&self) -> VkResult<ffi::VmaTotalStatistics> {
+    pub fn calculate_statistics(&self) -> VmaResult<TotalStatistics> {
         unsafe {
             let mut vma_stats: ffi::VmaTotalStatistics = mem::zeroed();
             ffi::vmaCalculateStatistics(self.internal, &mut vma_stats);
-            Ok(vma_stats)
+            Ok((&vma_stats).into())
         }
     }
 
     /// Retrieves information about current memory usage and budget for all memory heaps.
     ///
+    /// Budgets are in heap order, each along with the index, flags and size of its heap.
+    ///
     /// This function is called "get" not "calculate" because it is very fast, suitable to be called
-    /// every frame or every allocation. For more detailed statistics use vmaCalculateStatistics().
+    /// every frame or every allocation. For more detailed statistics use `Allocator::calculate_statistics`.
     ///
     /// Note that when using allocator from multiple threads, returned information may immediately
     /// become outdated.
-    pub fn get_heap_budgets(&self) -> VkResult<Vec<ffi::VmaBudget>> {
+    pub fn get_heap_budgets(&self) -> VmaResult<Vec<HeapBudget>> {
         unsafe {
-            let len = self.get_memory_properties().memory_heap_count as usize;
+            let properties = self.get_memory_properties();
+            let len = properties.memory_heap_count as usize;
             let mut vma_budgets: Vec<ffi::VmaBudget> = Vec::with_capacity(len);
             ffi::vmaGetHeapBudgets(self.internal, vma_budgets.as_mut_ptr());
             vma_budgets.set_len(len);
-            Ok(vma_budgets)
+            Ok(vma_budgets
+                .iter()
+                .zip(&properties.memory_heaps)
+                .enumerate()
+                .map(|(index, (budget, heap))| HeapBudget::new(index as u32, heap, &budget.into()))
+                .collect())
         }
     }
 
     /// Frees memory previously allocated using `Allocator::allocate_memory`,
     /// `Allocator::allocate_memory_for_buffer`, or `Allocator::allocate_memory_for_image`.
-    pub unsafe fn free_memory(&self, allocation: &mut Allocation) {
-        ffi::vmaFreeMemory(self.internal, allocation.0);
+    ///
+    /// Fails with `VmaError::StillMapped` if the allocation has outstanding maps made with
+    /// `Allocator::map_memory`. The allocation is not freed in that case, it's handed back in the
+    /// `FreeError` so it can be freed once unmapped.
+    pub unsafe fn free_memory(&self, allocation: Allocation) -> FreeResult {
+        let allocation = self.untrack_for_free(allocation)?;
+        ffi::vmaFreeMemory(self.internal, allocation.raw);
+        Ok(())
     }
 
     /// Frees memory and destroys multiple allocations.
@@ -240,12 +745,29 @@ impl Allocator {
     /// It may be internally optimized to be more efficient than calling 'Allocator::free_memory` `allocations.len()` times.
     ///
     /// Allocations in 'allocations' slice can come from any memory pools and types.
-    pub unsafe fn free_memory_pages(&self, allocations: &mut [Allocation]) {
-        ffi::vmaFreeMemoryPages(
-            self.internal,
-            allocations.len(),
-            allocations.as_ptr() as *mut _,
-        );
+    ///
+    /// If any of the allocations can't be freed, see `Allocator::free_memory`, none of them are
+    /// and they are all handed back in the `FreeError`.
+    pub unsafe fn free_memory_pages(
+        &self,
+        allocations: impl IntoIterator<Item = Allocation>,
+    ) -> FreeResult<Vec<Allocation>> {
+        let allocations: Vec<Allocation> = allocations.into_iter().collect();
+        if let Err(error) = allocations.iter().try_for_each(|a| self.check_freeable(a)) {
+            return Err(FreeError {
+                error,
+                allocation: allocations,
+            });
+        }
+        let raw: Vec<ffi::VmaAllocation> = allocations
+            .iter()
+            .map(|a| {
+                self.untrack(a);
+                a.raw
+            })
+            .collect();
+        ffi::vmaFreeMemoryPages(self.internal, raw.len(), raw.as_ptr());
+        Ok(())
     }
 
     /// Returns current information about specified allocation and atomically marks it as used in current frame.
@@ -261,11 +783,12 @@ impl Allocator {
     /// you can avoid calling it too often.
     ///
     /// If you just want to check if allocation is not lost, `Allocator::touch_allocation` will work faster.
-    pub fn get_allocation_info(&self, allocation: &Allocation) -> AllocationInfo {
+    pub fn get_allocation_info(&self, allocation: &Allocation) -> VmaResult<AllocationInfo> {
+        self.check_allocation(allocation)?;
         unsafe {
             let mut allocation_info: ffi::VmaAllocationInfo = mem::zeroed();
-            ffi::vmaGetAllocationInfo(self.internal, allocation.0, &mut allocation_info);
-            allocation_info.into()
+            ffi::vmaGetAllocationInfo(self.internal, allocation.raw, &mut allocation_info);
+            Ok(allocation_info.into())
         }
     }
 
@@ -286,7 +809,22 @@ impl Allocator {
         allocation: &mut Allocation,
         user_data: *mut ::std::os::raw::c_void,
     ) {
-        ffi::vmaSetAllocationUserData(self.internal, allocation.0, user_data);
+        self.assert_live(allocation);
+        ffi::vmaSetAllocationUserData(self.internal, allocation.raw, user_data);
+    }
+
+    /// Sets the name of the allocation, listed in `Allocator::live_allocations` and leak
+    /// reports. `None` clears it.
+    ///
+    /// With `AllocatorCreateInfo::debug_utils`, the buffer or image created along with the
+    /// allocation is given the same name.
+    ///
+    /// The string is copied, it doesn't need to outlive the call.
+    pub fn set_allocation_name(&self, allocation: &Allocation, name: Option<&CStr>) {
+        self.assert_live(allocation);
+        self.name_resource(allocation, name);
+        let name_ptr = name.map_or(std::ptr::null(), CStr::as_ptr);
+        unsafe { ffi::vmaSetAllocationName(self.internal, allocation.raw, name_ptr) };
     }
 
     /// Maps memory represented by given allocation and returns pointer to it.
@@ -318,21 +856,41 @@ impl Allocator {
     /// `Allocator::map_memory`. You must not call `Allocator::unmap_memory` additional
     /// time to free the "0-th" mapping made automatically due to `AllocationCreateFlags::MAPPED` flag.
     ///
-    /// This function fails when used on allocation made in memory type that is not
-    /// `vk::MemoryPropertyFlags::HOST_VISIBLE`.
+    /// This function fails with `VmaError::InvalidAllocation` when used on allocation made in
+    /// memory type that is not `vk::MemoryPropertyFlags::HOST_VISIBLE`. The properties of the
+    /// memory type the allocation ended up in are checked, so this also works for allocations
+    /// made with `MemoryUsage::Auto` and no `required_flags`.
     ///
     /// This function always fails when called for allocation that was created with
     /// `AllocationCreateFlags::CAN_BECOME_LOST` flag. Such allocations cannot be mapped.
-    pub unsafe fn map_memory(&self, allocation: &mut Allocation) -> VkResult<*mut u8> {
+    #[track_caller]
+    pub unsafe fn map_memory(&self, allocation: &mut Allocation) -> VmaResult<*mut u8> {
+        self.check_allocation(allocation)?;
+        let mut property_flags = vk::MemoryPropertyFlags::empty();
+        ffi::vmaGetAllocationMemoryProperties(self.internal, allocation.raw, &mut property_flags);
+        if !property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return self.invalid_allocation(allocation, "Allocation is not host visible");
+        }
         let mut mapped_data: *mut ::std::os::raw::c_void = ::std::ptr::null_mut();
-        ffi::vmaMapMemory(self.internal, allocation.0, &mut mapped_data).result()?;
+        ffi::vmaMapMemory(self.internal, allocation.raw, &mut mapped_data).result()?;
+        self.track_map(allocation, Location::caller());
+        #[cfg(feature = "recording")]
+        self.record(|_| RecordedCall::Map {
+            id: allocation.id.get(),
+        });
 
         Ok(mapped_data as *mut u8)
     }
 
     /// Unmaps memory represented by given allocation, mapped previously using `Allocator::map_memory`.
     pub unsafe fn unmap_memory(&self, allocation: &mut Allocation) {
-        ffi::vmaUnmapMemory(self.internal, allocation.0);
+        self.assert_live(allocation);
+        self.untrack_map(allocation);
+        #[cfg(feature = "recording")]
+        self.record(|_| RecordedCall::Unmap {
+            id: allocation.id.get(),
+        });
+        ffi::vmaUnmapMemory(self.internal, allocation.raw);
     }
 
     /// Flushes memory of given allocation.
@@ -349,8 +907,11 @@ impl Allocator {
         allocation: &Allocation,
         offset: vk::DeviceSize,
         size: vk::DeviceSize,
-    ) -> VkResult<()> {
-        unsafe { ffi::vmaFlushAllocation(self.internal, allocation.0, offset, size).result() }
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        unsafe {
+            Ok(ffi::vmaFlushAllocation(self.internal, allocation.raw, offset, size).result()?)
+        }
     }
 
     /// Invalidates memory of given allocation.
@@ -367,25 +928,55 @@ impl Allocator {
         allocation: &Allocation,
         offset: vk::DeviceSize,
         size: vk::DeviceSize,
-    ) -> VkResult<()> {
-        unsafe { ffi::vmaInvalidateAllocation(self.internal, allocation.0, offset, size).result() }
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        unsafe {
+            Ok(
+                ffi::vmaInvalidateAllocation(self.internal, allocation.raw, offset, size)
+                    .result()?,
+            )
+        }
     }
 
     /// Checks magic number in margins around all allocations in given memory types (in both default and custom pools) in search for corruptions.
     ///
-    /// `memory_type_bits` bit mask, where each bit set means that a memory type with that index should be checked.
-    ///
-    /// Corruption detection is enabled only when `VMA_DEBUG_DETECT_CORRUPTION` macro is defined to nonzero,
-    /// `VMA_DEBUG_MARGIN` is defined to nonzero and only for memory types that are `HOST_VISIBLE` and `HOST_COHERENT`.
+    /// Corruption detection is enabled only with the `detect-corruption` feature, which defines
+    /// `VMA_DEBUG_DETECT_CORRUPTION` and `VMA_DEBUG_MARGIN`, and only for memory types that are
+    /// `HOST_VISIBLE` and `HOST_COHERENT`. `AllocatorPool::check_corruption` checks a single
+    /// pool.
     ///
     /// Possible error values:
     ///
-    /// - `vk::Result::ERROR_FEATURE_NOT_PRESENT` - corruption detection is not enabled for any of specified memory types.
-    /// - `vk::Result::ERROR_VALIDATION_FAILED_EXT` - corruption detection has been performed and found memory corruptions around one of the allocations.
+    /// - `VmaError::CorruptionDetectionNotEnabled` - the crate was built without the `detect-corruption` feature.
+    /// - `VmaError::FeatureNotPresent` - corruption detection is not enabled for any of specified memory types.
+    /// - `VmaError::ValidationFailed` - corruption detection has been performed and found memory corruptions around one of the allocations.
     ///   `VMA_ASSERT` is also fired in that case.
     /// - Other value: Error returned by Vulkan, e.g. memory mapping failure.
-    pub unsafe fn check_corruption(&self, memory_types: vk::MemoryPropertyFlags) -> VkResult<()> {
-        ffi::vmaCheckCorruption(self.internal, memory_types.as_raw()).result()
+    pub fn check_corruption_in(&self, memory_types: MemoryTypeMask) -> VmaResult<()> {
+        if !cfg!(feature = "detect-corruption") {
+            return Err(VmaError::CorruptionDetectionNotEnabled);
+        }
+        unsafe { Ok(ffi::vmaCheckCorruption(self.internal, memory_types.0).result()?) }
+    }
+
+    /// Checks the margins of all allocations, see `Allocator::check_corruption_in`.
+    pub fn check_corruption_all(&self) -> VmaResult<()> {
+        self.check_corruption_in(MemoryTypeMask::ALL)
+    }
+
+    /// Checks the margins of all allocations in memory types having all of `memory_types`.
+    ///
+    /// VMA takes a mask of memory type indices, not property flags, which this used to pass
+    /// as is.
+    #[deprecated(
+        since = "0.4.0",
+        note = "use check_corruption_in with a MemoryTypeMask, or check_corruption_all"
+    )]
+    pub unsafe fn check_corruption(&self, memory_types: vk::MemoryPropertyFlags) -> VmaResult<()> {
+        self.check_corruption_in(MemoryTypeMask::from_properties(
+            self.get_memory_properties(),
+            memory_types,
+        ))
     }
 
     /// Binds buffer to allocation.
@@ -405,8 +996,9 @@ impl Allocator {
         &self,
         allocation: &Allocation,
         buffer: vk::Buffer,
-    ) -> VkResult<()> {
-        ffi::vmaBindBufferMemory(self.internal, allocation.0, buffer).result()
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        Ok(ffi::vmaBindBufferMemory(self.internal, allocation.raw, buffer).result()?)
     }
 
     /// Binds buffer to allocation with additional parameters.
@@ -426,15 +1018,16 @@ impl Allocator {
         allocation_local_offset: vk::DeviceSize,
         buffer: vk::Buffer,
         next: *const ::std::os::raw::c_void,
-    ) -> VkResult<()> {
-        ffi::vmaBindBufferMemory2(
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        Ok(ffi::vmaBindBufferMemory2(
             self.internal,
-            allocation.0,
+            allocation.raw,
             allocation_local_offset,
             buffer,
             next,
         )
-        .result()
+        .result()?)
     }
 
     /// Binds image to allocation.
@@ -454,8 +1047,9 @@ impl Allocator {
         &self,
         allocation: &Allocation,
         image: vk::Image,
-    ) -> VkResult<()> {
-        ffi::vmaBindImageMemory(self.internal, allocation.0, image).result()
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        Ok(ffi::vmaBindImageMemory(self.internal, allocation.raw, image).result()?)
     }
 
     /// Binds image to allocation with additional parameters.
@@ -475,15 +1069,16 @@ impl Allocator {
         allocation_local_offset: vk::DeviceSize,
         image: vk::Image,
         next: *const ::std::os::raw::c_void,
-    ) -> VkResult<()> {
-        ffi::vmaBindImageMemory2(
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        Ok(ffi::vmaBindImageMemory2(
             self.internal,
-            allocation.0,
+            allocation.raw,
             allocation_local_offset,
             image,
             next,
         )
-        .result()
+        .result()?)
     }
 
     /// Destroys Vulkan buffer and frees allocated memory.
@@ -495,9 +1090,14 @@ impl Allocator {
     /// Allocator::free_memory(allocator, allocation);
     /// ```
     ///
-    /// It it safe to pass null as `buffer` and/or `allocation`.
-    pub unsafe fn destroy_buffer(&self, buffer: vk::Buffer, allocation: &mut Allocation) {
-        ffi::vmaDestroyBuffer(self.internal, buffer, allocation.0);
+    /// It it safe to pass null as `buffer`.
+    ///
+    /// Fails like `Allocator::free_memory`, in which case neither the buffer nor the allocation
+    /// are destroyed, and the allocation is handed back in the `FreeError`.
+    pub unsafe fn destroy_buffer(&self, buffer: vk::Buffer, allocation: Allocation) -> FreeResult {
+        let allocation = self.untrack_for_free(allocation)?;
+        ffi::vmaDestroyBuffer(self.internal, buffer, allocation.raw);
+        Ok(())
     }
 
     /// Destroys Vulkan image and frees allocated memory.
@@ -509,9 +1109,14 @@ impl Allocator {
     /// Allocator::free_memory(allocator, allocation);
     /// ```
     ///
-    /// It it safe to pass null as `image` and/or `allocation`.
-    pub unsafe fn destroy_image(&self, image: vk::Image, allocation: &mut Allocation) {
-        ffi::vmaDestroyImage(self.internal, image, allocation.0);
+    /// It it safe to pass null as `image`.
+    ///
+    /// Fails like `Allocator::free_memory`, in which case neither the image nor the allocation
+    /// are destroyed, and the allocation is handed back in the `FreeError`.
+    pub unsafe fn destroy_image(&self, image: vk::Image, allocation: Allocation) -> FreeResult {
+        let allocation = self.untrack_for_free(allocation)?;
+        ffi::vmaDestroyImage(self.internal, image, allocation.raw);
+        Ok(())
     }
     /// Flushes memory of given set of allocations."]
     ///
@@ -526,16 +1131,19 @@ impl Allocator {
         allocations: impl IntoIterator<Item = &'a Allocation>,
         offsets: Option<&[vk::DeviceSize]>,
         sizes: Option<&[vk::DeviceSize]>,
-    ) -> VkResult<()> {
-        let allocations: Vec<ffi::VmaAllocation> = allocations.into_iter().map(|a| a.0).collect();
-        ffi::vmaFlushAllocations(
+    ) -> VmaResult<()> {
+        let allocations = allocations
+            .into_iter()
+            .map(|a| self.check_allocation(a).map(|()| a.raw))
+            .collect::<VmaResult<Vec<ffi::VmaAllocation>>>()?;
+        Ok(ffi::vmaFlushAllocations(
             self.internal,
             allocations.len() as u32,
             allocations.as_ptr() as *mut _,
             offsets.map_or(std::ptr::null(), |offsets| offsets.as_ptr()),
             sizes.map_or(std::ptr::null(), |sizes| sizes.as_ptr()),
         )
-        .result()
+        .result()?)
     }
 
     /// Invalidates memory of given set of allocations."]
@@ -551,24 +1159,71 @@ impl Allocator {
         allocations: impl IntoIterator<Item = &'a Allocation>,
         offsets: Option<&[vk::DeviceSize]>,
         sizes: Option<&[vk::DeviceSize]>,
-    ) -> VkResult<()> {
-        let allocations: Vec<ffi::VmaAllocation> = allocations.into_iter().map(|a| a.0).collect();
-        ffi::vmaInvalidateAllocations(
+    ) -> VmaResult<()> {
+        let allocations = allocations
+            .into_iter()
+            .map(|a| self.check_allocation(a).map(|()| a.raw))
+            .collect::<VmaResult<Vec<ffi::VmaAllocation>>>()?;
+        Ok(ffi::vmaInvalidateAllocations(
             self.internal,
             allocations.len() as u32,
             allocations.as_ptr() as *mut _,
             offsets.map_or(std::ptr::null(), |offsets| offsets.as_ptr()),
             sizes.map_or(std::ptr::null(), |sizes| sizes.as_ptr()),
         )
-        .result()
+        .result()?)
+    }
+}
+
+impl Allocator {
+    /// Destroys the allocator.
+    ///
+    /// Destroying an allocator that still has live allocations would trigger an assertion inside
+    /// VMA. Instead, `VmaError::Leaked` is returned with the list of allocations that weren't
+    /// freed, and the underlying `VmaAllocator` is left alive on purpose.
+    ///
+    /// Dropping the allocator performs the same check, but can't return the report: it's logged
+    /// as a warning with the `log` feature, and the `VmaAllocator` is leaked. Allocations freed
+    /// through the raw [`ffi`] functions are still reported as leaked.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        if let Some(report) = self.leak_report() {
+            self.internal = std::ptr::null_mut();
+            return Err(VmaError::Leaked(report));
+        }
+        Ok(())
     }
 }
 
 /// Custom `Drop` implementation to clean up internal allocation instance
+///
+/// Leaks are logged as a warning with the `log` feature, and fail a debug assertion with the
+/// `panic-on-leak` feature unless the thread is already panicking, see `Allocator::destroy`.
 impl Drop for Allocator {
     fn drop(&mut self) {
+        if self.internal.is_null() {
+            return;
+        }
+        if let Some(report) = self.leak_report() {
+            self.internal = std::ptr::null_mut();
+            let err = VmaError::Leaked(report);
+            #[cfg(feature = "log")]
+            log::warn!(target: "vk_mem", "{}", err);
+            #[cfg(feature = "panic-on-leak")]
+            debug_assert!(std::thread::panicking(), "{}", err);
+            let _ = err;
+            return;
+        }
+        let deferred_pools = self
+            .deferred_pools
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
         unsafe {
-            ffi::vmaDestroyAllocator(self.internal);
+            for pool in deferred_pools.drain(..) {
+                ffi::vmaDestroyPool(self.internal, pool.0);
+            }
+            if self.owns_internal {
+                ffi::vmaDestroyAllocator(self.internal);
+            }
             self.internal = std::ptr::null_mut();
         }
     }
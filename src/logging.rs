@@ -0,0 +1,15 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+
+/// Receives `VMA_DEBUG_LOG_FORMAT` and `VMA_LEAK_LOG_FORMAT` messages from wrapper.cpp.
+///
+/// Leak messages, e.g. allocations still alive when a pool or the allocator is destroyed, are
+/// logged as warnings, everything else as debug messages, both with the `vk_mem` target.
+#[no_mangle]
+unsafe extern "C" fn vma_rust_log(level: c_int, message: *const c_char) {
+    let message = CStr::from_ptr(message).to_string_lossy();
+    match level {
+        1 => log::warn!(target: "vk_mem", "{}", message),
+        _ => log::debug!(target: "vk_mem", "{}", message),
+    }
+}
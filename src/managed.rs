@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::AllocationInfo;
+use crate::Allocator;
+use crate::MappedMemory;
+use crate::{FreeError, FreeResult, VmaResult};
+use ash::vk;
+
+/// Buffer bundled with its allocation, destroyed when dropped.
+///
+/// Keeps the allocator alive through an `Arc`, and caches the `AllocationInfo` that was current
+/// at creation, see `ManagedBuffer::update_info`.
+pub struct ManagedBuffer {
+    allocator: Arc<Allocator>,
+    buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    info: AllocationInfo,
+}
+
+/// Image bundled with its allocation, destroyed when dropped.
+///
+/// Keeps the allocator alive through an `Arc`, and caches the `AllocationInfo` that was current
+/// at creation, see `ManagedImage::update_info`.
+pub struct ManagedImage {
+    allocator: Arc<Allocator>,
+    image: vk::Image,
+    allocation: Option<Allocation>,
+    info: AllocationInfo,
+}
+
+// Only the handles are shared between threads, the allocator is internally synchronized.
+unsafe impl Send for ManagedBuffer {}
+unsafe impl Sync for ManagedBuffer {}
+unsafe impl Send for ManagedImage {}
+unsafe impl Sync for ManagedImage {}
+
+impl Allocator {
+    /// Creates a buffer with `Allocator::create_buffer` and wraps it in a `ManagedBuffer`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::create_buffer`. The device must not use the buffer
+    /// anymore when the `ManagedBuffer` is dropped.
+    #[track_caller]
+    pub unsafe fn create_managed_buffer(
+        self: &Arc<Self>,
+        buffer_info: &vk::BufferCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<ManagedBuffer> {
+        let (buffer, allocation) = self.create_buffer(buffer_info, create_info)?;
+        ManagedBuffer::new(self.clone(), buffer, allocation)
+    }
+
+    /// Creates an image with `Allocator::create_image` and wraps it in a `ManagedImage`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::create_image`. The device must not use the image
+    /// anymore when the `ManagedImage` is dropped.
+    #[track_caller]
+    pub unsafe fn create_managed_image(
+        self: &Arc<Self>,
+        image_info: &vk::ImageCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<ManagedImage> {
+        let (image, allocation) = self.create_image(image_info, create_info)?;
+        ManagedImage::new(self.clone(), image, allocation)
+    }
+}
+
+impl ManagedBuffer {
+    /// Takes ownership of a buffer and its allocation, e.g. created from an `AllocatorPool`.
+    ///
+    /// # Safety
+    /// `allocation` must have been made by `allocator` and be bound to `buffer`. The device must
+    /// not use the buffer anymore when the `ManagedBuffer` is dropped.
+    pub unsafe fn new(
+        allocator: Arc<Allocator>,
+        buffer: vk::Buffer,
+        allocation: Allocation,
+    ) -> VmaResult<Self> {
+        let info = allocator.get_allocation_info(&allocation)?;
+        Ok(Self {
+            allocator,
+            buffer,
+            allocation: Some(allocation),
+            info,
+        })
+    }
+
+    /// The buffer handle.
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    /// The allocation backing the buffer.
+    pub fn allocation(&self) -> &Allocation {
+        self.allocation.as_ref().unwrap()
+    }
+
+    /// Allocation info cached at creation or by the last `ManagedBuffer::update_info`.
+    pub fn info(&self) -> &AllocationInfo {
+        &self.info
+    }
+
+    /// Refreshes the cached allocation info, e.g. after defragmentation moved the allocation.
+    pub fn update_info(&mut self) -> VmaResult<&AllocationInfo> {
+        self.info = self.allocator.get_allocation_info(self.allocation())?;
+        Ok(&self.info)
+    }
+
+    /// Size of the allocation, in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.info.size
+    }
+
+    /// Maps the buffer memory, see `Allocator::map`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map`.
+    #[track_caller]
+    pub unsafe fn map(&mut self) -> VmaResult<MappedMemory<'_>> {
+        self.allocator.map(self.allocation.as_mut().unwrap())
+    }
+
+    /// Destroys the buffer and frees its memory, reporting errors that `Drop` would ignore.
+    ///
+    /// The buffer is handed back in the `FreeError` if it couldn't be destroyed.
+    pub fn destroy(mut self) -> FreeResult<Box<Self>> {
+        let allocation = self.allocation.take().unwrap();
+        unsafe { self.allocator.destroy_buffer(self.buffer, allocation) }.map_err(|err| {
+            self.allocation = Some(err.allocation);
+            FreeError {
+                error: err.error,
+                allocation: Box::new(self),
+            }
+        })
+    }
+
+    /// Releases the buffer and allocation without destroying them.
+    pub fn into_raw(mut self) -> (vk::Buffer, Allocation) {
+        (self.buffer, self.allocation.take().unwrap())
+    }
+}
+
+impl Drop for ManagedBuffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            let _ = unsafe { self.allocator.destroy_buffer(self.buffer, allocation) };
+        }
+    }
+}
+
+impl ManagedImage {
+    /// Takes ownership of an image and its allocation, e.g. created from an `AllocatorPool`.
+    ///
+    /// # Safety
+    /// `allocation` must have been made by `allocator` and be bound to `image`. The device must
+    /// not use the image anymore when the `ManagedImage` is dropped.
+    pub unsafe fn new(
+        allocator: Arc<Allocator>,
+        image: vk::Image,
+        allocation: Allocation,
+    ) -> VmaResult<Self> {
+        let info = allocator.get_allocation_info(&allocation)?;
+        Ok(Self {
+            allocator,
+            image,
+            allocation: Some(allocation),
+            info,
+        })
+    }
+
+    /// The image handle.
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+
+    /// The allocation backing the image.
+    pub fn allocation(&self) -> &Allocation {
+        self.allocation.as_ref().unwrap()
+    }
+
+    /// Allocation info cached at creation or by the last `ManagedImage::update_info`.
+    pub fn info(&self) -> &AllocationInfo {
+        &self.info
+    }
+
+    /// Refreshes the cached allocation info, e.g. after defragmentation moved the allocation.
+    pub fn update_info(&mut self) -> VmaResult<&AllocationInfo> {
+        self.info = self.allocator.get_allocation_info(self.allocation())?;
+        Ok(&self.info)
+    }
+
+    /// Size of the allocation, in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.info.size
+    }
+
+    /// Maps the image memory, see `Allocator::map`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map`.
+    #[track_caller]
+    pub unsafe fn map(&mut self) -> VmaResult<MappedMemory<'_>> {
+        self.allocator.map(self.allocation.as_mut().unwrap())
+    }
+
+    /// Destroys the image and frees its memory, reporting errors that `Drop` would ignore.
+    ///
+    /// The image is handed back in the `FreeError` if it couldn't be destroyed.
+    pub fn destroy(mut self) -> FreeResult<Box<Self>> {
+        let allocation = self.allocation.take().unwrap();
+        unsafe { self.allocator.destroy_image(self.image, allocation) }.map_err(|err| {
+            self.allocation = Some(err.allocation);
+            FreeError {
+                error: err.error,
+                allocation: Box::new(self),
+            }
+        })
+    }
+
+    /// Releases the image and allocation without destroying them.
+    pub fn into_raw(mut self) -> (vk::Image, Allocation) {
+        (self.image, self.allocation.take().unwrap())
+    }
+}
+
+impl Drop for ManagedImage {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            let _ = unsafe { self.allocator.destroy_image(self.image, allocation) };
+        }
+    }
+}
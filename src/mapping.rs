@@ -0,0 +1,286 @@
+use crate::ffi;
+use crate::Allocation;
+use crate::Allocator;
+use crate::VmaError;
+use crate::VmaResult;
+use ash::vk;
+use bytemuck::Pod;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Host mapping of an `Allocation`, unmapped when dropped.
+///
+/// Dereferences to the whole allocation as a byte slice, using the allocation size reported by
+/// `Allocator::get_allocation_info`.
+pub struct MappedMemory<'a> {
+    allocator: &'a Allocator,
+    allocation: &'a mut Allocation,
+    data: *mut u8,
+    size: usize,
+    flush_on_drop: bool,
+}
+
+impl<'a> MappedMemory<'a> {
+    /// Flushes the whole allocation right before it gets unmapped.
+    ///
+    /// Errors from the flush are ignored, call `MappedMemory::flush` for error handling.
+    pub fn flush_on_drop(mut self) -> Self {
+        self.flush_on_drop = true;
+        self
+    }
+
+    /// Flushes a range of the mapped memory, see `Allocator::flush_allocation`.
+    pub fn flush(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> VmaResult<()> {
+        self.allocator
+            .flush_allocation(self.allocation, offset, size)
+    }
+
+    /// Invalidates a range of the mapped memory, see `Allocator::invalidate_allocation`.
+    pub fn invalidate(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> VmaResult<()> {
+        self.allocator
+            .invalidate_allocation(self.allocation, offset, size)
+    }
+
+    /// Pointer to the first byte of the mapped allocation.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.data
+    }
+
+    /// Reinterprets the mapping as a slice of `T`.
+    ///
+    /// The slice holds as many whole `T` as fit in the allocation, trailing bytes are not
+    /// accessible through it. Fails if `T` is zero-sized, if the allocation is smaller than a
+    /// single `T`, or if the mapped pointer isn't suitably aligned for `T`.
+    pub fn into_slice<T: Pod>(self) -> VmaResult<MappedSlice<'a, T>> {
+        let element_size = std::mem::size_of::<T>();
+        if element_size == 0 {
+            return Err(VmaError::InvalidParameter(
+                "Cannot map an allocation as a slice of zero-sized type",
+            ));
+        }
+        if self.size < element_size {
+            return self.allocator.invalid_allocation(
+                self.allocation,
+                "Allocation is smaller than the requested element type",
+            );
+        }
+        if self.data.align_offset(std::mem::align_of::<T>()) != 0 {
+            return self.allocator.invalid_allocation(
+                self.allocation,
+                "Mapped pointer is not aligned for the requested element type",
+            );
+        }
+        Ok(MappedSlice {
+            len: self.size / element_size,
+            memory: self,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl Deref for MappedMemory<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.size) }
+    }
+}
+
+impl DerefMut for MappedMemory<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.size) }
+    }
+}
+
+impl Drop for MappedMemory<'_> {
+    fn drop(&mut self) {
+        if self.flush_on_drop {
+            let _ = self.flush(0, vk::WHOLE_SIZE);
+        }
+        unsafe {
+            self.allocator.unmap_memory(self.allocation);
+        }
+    }
+}
+
+/// Typed host mapping of an `Allocation`, unmapped when dropped.
+///
+/// Created by `Allocator::map_memory_as_slice` or `MappedMemory::into_slice`, dereferences to a
+/// slice of `T`.
+pub struct MappedSlice<'a, T: Pod> {
+    memory: MappedMemory<'a>,
+    len: usize,
+    _marker: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: Pod> MappedSlice<'a, T> {
+    /// Flushes the whole allocation right before it gets unmapped, see
+    /// `MappedMemory::flush_on_drop`.
+    pub fn flush_on_drop(mut self) -> Self {
+        self.memory.flush_on_drop = true;
+        self
+    }
+
+    /// Flushes a range of the mapped memory, in bytes. See `Allocator::flush_allocation`.
+    pub fn flush(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> VmaResult<()> {
+        self.memory.flush(offset, size)
+    }
+
+    /// Invalidates a range of the mapped memory, in bytes. See
+    /// `Allocator::invalidate_allocation`.
+    pub fn invalidate(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> VmaResult<()> {
+        self.memory.invalidate(offset, size)
+    }
+
+    /// Returns the untyped mapping.
+    pub fn into_bytes(self) -> MappedMemory<'a> {
+        self.memory
+    }
+}
+
+impl<T: Pod> Deref for MappedSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.memory.data as *const T, self.len) }
+    }
+}
+
+impl<T: Pod> DerefMut for MappedSlice<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.memory.data as *mut T, self.len) }
+    }
+}
+
+impl Allocator {
+    /// Maps the allocation and returns a guard that unmaps it when dropped.
+    ///
+    /// The same rules as for `Allocator::map_memory` apply, mapping is reference-counted so an
+    /// allocation created with `AllocationCreateFlags::MAPPED` can be mapped this way as well.
+    ///
+    /// # Safety
+    /// `allocation` must be a valid allocation of this allocator, and the device must not access
+    /// the memory while the slice is in use.
+    #[track_caller]
+    pub unsafe fn map<'a>(&'a self, allocation: &'a mut Allocation) -> VmaResult<MappedMemory<'a>> {
+        let size = self.get_allocation_info(allocation)?.size as usize;
+        let data = self.map_memory(allocation)?;
+        Ok(MappedMemory {
+            allocator: self,
+            allocation,
+            data,
+            size,
+            flush_on_drop: false,
+        })
+    }
+
+    /// Maps the allocation as a slice of `T` and returns a guard that unmaps it when dropped.
+    ///
+    /// See `MappedMemory::into_slice` for the size and alignment checks. The allocation is not
+    /// left mapped if a check fails.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map`.
+    #[track_caller]
+    pub unsafe fn map_memory_as_slice<'a, T: Pod>(
+        &'a self,
+        allocation: &'a mut Allocation,
+    ) -> VmaResult<MappedSlice<'a, T>> {
+        self.map(allocation)?.into_slice()
+    }
+}
+
+/// Byte patterns written over host-visible allocations, see `Allocator::set_fill_patterns`.
+///
+/// The defaults are the patterns of `VMA_DEBUG_INITIALIZE_ALLOCATIONS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillPatterns {
+    /// Written over new allocations.
+    pub allocated: u8,
+    /// Written over allocations right before they are freed.
+    pub freed: u8,
+}
+
+impl Default for FillPatterns {
+    fn default() -> Self {
+        Self {
+            allocated: 0xDC,
+            freed: 0xEF,
+        }
+    }
+}
+
+/// Optional `FillPatterns` packed into an atomic, so that allocating and freeing don't take a
+/// lock to find out they're off.
+pub(crate) struct AtomicFillPatterns(AtomicU32);
+
+impl AtomicFillPatterns {
+    const ENABLED: u32 = 1 << 16;
+
+    pub(crate) fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    fn load(&self) -> Option<FillPatterns> {
+        let bits = self.0.load(Ordering::Relaxed);
+        (bits & Self::ENABLED != 0).then_some(FillPatterns {
+            allocated: bits as u8,
+            freed: (bits >> 8) as u8,
+        })
+    }
+
+    fn store(&self, patterns: Option<FillPatterns>) {
+        let bits = patterns.map_or(0, |patterns| {
+            Self::ENABLED | (patterns.freed as u32) << 8 | patterns.allocated as u32
+        });
+        self.0.store(bits, Ordering::Relaxed);
+    }
+}
+
+impl Allocator {
+    /// Fills new host-visible allocations made through the wrapper with
+    /// `FillPatterns::allocated`, and allocations about to be freed with `FillPatterns::freed`,
+    /// so that reads of uninitialized or freed memory stand out. `None` turns it off, which is
+    /// the default.
+    ///
+    /// Unlike the `debug-init-allocations` feature, this can be toggled at runtime and with any
+    /// patterns, but it only covers allocations made through the wrapper and maps each of them
+    /// twice. Allocations adopted with `Allocation::adopt` are only filled when freed.
+    pub fn set_fill_patterns(&self, patterns: Option<FillPatterns>) {
+        self.fill_patterns.store(patterns);
+    }
+
+    /// Returns the patterns set with `Allocator::set_fill_patterns`.
+    pub fn fill_patterns(&self) -> Option<FillPatterns> {
+        self.fill_patterns.load()
+    }
+
+    /// Writes the pattern selected by `pattern` over the allocation, if fill patterns are set
+    /// and the allocation is host visible. Mapping failures are ignored.
+    pub(crate) fn fill_allocation(
+        &self,
+        raw: ffi::VmaAllocation,
+        pattern: impl FnOnce(FillPatterns) -> u8,
+    ) {
+        let Some(patterns) = self.fill_patterns() else {
+            return;
+        };
+        unsafe {
+            let mut flags = vk::MemoryPropertyFlags::empty();
+            ffi::vmaGetAllocationMemoryProperties(self.internal, raw, &mut flags);
+            if !flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+                return;
+            }
+            let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+            ffi::vmaGetAllocationInfo(self.internal, raw, &mut info);
+            let mut data = std::ptr::null_mut();
+            if ffi::vmaMapMemory(self.internal, raw, &mut data) != vk::Result::SUCCESS {
+                return;
+            }
+            std::ptr::write_bytes(data as *mut u8, pattern(patterns), info.size as usize);
+            let _ = ffi::vmaFlushAllocation(self.internal, raw, 0, vk::WHOLE_SIZE);
+            ffi::vmaUnmapMemory(self.internal, raw);
+        }
+    }
+}
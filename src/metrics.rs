@@ -0,0 +1,183 @@
+use std::fmt::Write;
+
+use crate::pool::{pool_name, pool_statistics};
+use crate::Allocator;
+use crate::HeapBudget;
+use crate::Statistics;
+
+/// Name, help text and value of a gauge recorded for each heap or pool
+type Gauge<T> = (&'static str, &'static str, fn(&T) -> u64);
+
+/// Receiver of the allocator metrics, see `Allocator::record_metrics`.
+///
+/// Implement it to forward the metrics to an observability stack, e.g. as gauges of the
+/// `metrics` crate. `PrometheusRecorder` formats them for a Prometheus scrape endpoint.
+pub trait MetricsRecorder {
+    /// Called once per metric, before its values.
+    fn describe(&mut self, name: &'static str, help: &'static str) {
+        let _ = (name, help);
+    }
+
+    /// Records the current value of a gauge.
+    fn gauge(&mut self, name: &'static str, labels: &[(&'static str, &str)], value: u64);
+}
+
+/// `MetricsRecorder` writing the Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct PrometheusRecorder {
+    text: String,
+}
+
+impl PrometheusRecorder {
+    /// Creates a recorder with no metrics written yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text written so far.
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn describe(&mut self, name: &'static str, help: &'static str) {
+        // Writing to a String can't fail.
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} gauge", name);
+    }
+
+    fn gauge(&mut self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        self.text.push_str(name);
+        for (index, (label, label_value)) in labels.iter().enumerate() {
+            self.text.push(if index == 0 { '{' } else { ',' });
+            let _ = write!(self.text, "{}=\"", label);
+            for c in label_value.chars() {
+                match c {
+                    '\\' => self.text.push_str("\\\\"),
+                    '"' => self.text.push_str("\\\""),
+                    '\n' => self.text.push_str("\\n"),
+                    c => self.text.push(c),
+                }
+            }
+            self.text.push('"');
+        }
+        if !labels.is_empty() {
+            self.text.push('}');
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+}
+
+impl Allocator {
+    /// Records the current memory usage of the allocator into `recorder`.
+    ///
+    /// Gauges are recorded per heap, from `Allocator::get_heap_budgets`, and per custom pool,
+    /// labeled with the pool name or "pool N" and its memory type. All values are cheap to
+    /// query, so this can be called on every scrape.
+    pub fn record_metrics(&self, recorder: &mut impl MetricsRecorder) {
+        let budgets = self.get_heap_budgets().unwrap_or_default();
+        let heaps: Vec<String> = budgets
+            .iter()
+            .map(|budget| budget.heap_index.to_string())
+            .collect();
+        let heap_gauges: [Gauge<HeapBudget>; 6] = [
+            (
+                "vk_mem_heap_block_bytes",
+                "Bytes of device memory blocks allocated in the heap.",
+                |budget| budget.statistics.block_bytes,
+            ),
+            (
+                "vk_mem_heap_allocation_bytes",
+                "Bytes of allocations in the heap.",
+                |budget| budget.statistics.allocation_bytes,
+            ),
+            (
+                "vk_mem_heap_block_count",
+                "Number of device memory blocks allocated in the heap.",
+                |budget| budget.statistics.block_count.into(),
+            ),
+            (
+                "vk_mem_heap_allocation_count",
+                "Number of allocations in the heap.",
+                |budget| budget.statistics.allocation_count.into(),
+            ),
+            (
+                "vk_mem_heap_usage_bytes",
+                "Estimated memory usage of the program in the heap.",
+                |budget| budget.usage,
+            ),
+            (
+                "vk_mem_heap_budget_bytes",
+                "Estimated memory available to the program in the heap.",
+                |budget| budget.budget,
+            ),
+        ];
+        for (name, help, value) in heap_gauges {
+            recorder.describe(name, help);
+            for (budget, heap) in budgets.iter().zip(&heaps) {
+                recorder.gauge(name, &[("heap", heap)], value(budget));
+            }
+        }
+
+        recorder.describe(
+            "vk_mem_live_allocations",
+            "Number of allocations made through the wrapper that are still alive.",
+        );
+        recorder.gauge(
+            "vk_mem_live_allocations",
+            &[],
+            self.tracker.live().len() as u64,
+        );
+
+        let pools: Vec<_> = self
+            .tracker
+            .pools()
+            .iter()
+            .enumerate()
+            .map(|(index, pool)| {
+                let name = unsafe { pool_name(self.internal, pool.handle) }
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| format!("pool {}", index));
+                let memory_type = pool.create_info.memory_type_index.to_string();
+                let statistics = unsafe { pool_statistics(self.internal, pool.handle) };
+                (name, memory_type, statistics)
+            })
+            .collect();
+        let pool_gauges: [Gauge<Statistics>; 3] = [
+            (
+                "vk_mem_pool_allocation_count",
+                "Number of allocations in the custom pool.",
+                |statistics| statistics.allocation_count.into(),
+            ),
+            (
+                "vk_mem_pool_allocation_bytes",
+                "Bytes of allocations in the custom pool.",
+                |statistics| statistics.allocation_bytes,
+            ),
+            (
+                "vk_mem_pool_block_bytes",
+                "Bytes of device memory blocks allocated by the custom pool.",
+                |statistics| statistics.block_bytes,
+            ),
+        ];
+        for (name, help, value) in pool_gauges {
+            recorder.describe(name, help);
+            for (pool, memory_type, statistics) in &pools {
+                recorder.gauge(
+                    name,
+                    &[("pool", pool), ("memory_type", memory_type)],
+                    value(statistics),
+                );
+            }
+        }
+    }
+
+    /// Formats the metrics of `Allocator::record_metrics` in the Prometheus text exposition
+    /// format.
+    pub fn to_prometheus(&self) -> String {
+        let mut recorder = PrometheusRecorder::new();
+        self.record_metrics(&mut recorder);
+        recorder.finish()
+    }
+}
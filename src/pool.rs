@@ -1,19 +1,65 @@
 use std::ffi::CStr;
-use std::sync::Arc;
+use std::panic::Location;
+use std::sync::{Arc, Mutex, PoisonError};
 
+use crate::debug_names::with_current_pool;
 use crate::ffi;
 use crate::Allocation;
+use crate::AllocationCreateFlags;
 use crate::AllocationCreateInfo;
 use crate::Allocator;
+use crate::AllocatorCreateFlags;
+use crate::BudgetDenial;
+use crate::DetailedStatistics;
+use crate::HeapShortfall;
+use crate::MemoryResource;
+use crate::OutOfMemoryReport;
+use crate::PoolAlgorithm;
 use crate::PoolCreateInfo;
-use ash::prelude::VkResult;
+#[cfg(feature = "recording")]
+use crate::RecordedResource;
+use crate::Statistics;
+use crate::{VmaError, VmaResult};
 use ash::vk;
 #[derive(Clone, Copy)]
-pub struct PoolHandle(ffi::VmaPool);
+pub struct PoolHandle(pub(crate) ffi::VmaPool);
+
+/// What an allocation is made for, as far as it's known before VMA makes it.
+#[derive(Clone, Copy)]
+pub(crate) enum AllocationRequest<'a> {
+    /// A number of allocations with the same requirements
+    Memory(&'a vk::MemoryRequirements, usize),
+    Buffer(&'a vk::BufferCreateInfo<'a>),
+    Image(
+        #[cfg_attr(not(feature = "fault-injection"), allow(dead_code))] &'a vk::ImageCreateInfo<'a>,
+    ),
+    /// Memory for an existing buffer or image, whose requirements only VMA queries
+    Resource,
+}
+
+impl AllocationRequest<'_> {
+    /// Total size of the allocations, if known.
+    pub(crate) fn size(&self) -> Option<vk::DeviceSize> {
+        match *self {
+            AllocationRequest::Memory(requirements, count) => {
+                requirements.size.checked_mul(count as vk::DeviceSize)
+            }
+            AllocationRequest::Buffer(buffer_info) => Some(buffer_info.size),
+            AllocationRequest::Image(_) | AllocationRequest::Resource => None,
+        }
+    }
+
+    fn memory_type_bits(&self) -> u32 {
+        match *self {
+            AllocationRequest::Memory(requirements, _) => requirements.memory_type_bits,
+            _ => !0,
+        }
+    }
+}
 
 /// Represents custom memory pool handle.
 pub struct AllocatorPool {
-    allocator: Arc<Allocator>,
+    pub(crate) allocator: Arc<Allocator>,
     pub(crate) pool: PoolHandle,
 }
 unsafe impl Send for AllocatorPool {}
@@ -21,20 +67,15 @@ unsafe impl Sync for AllocatorPool {}
 
 impl Allocator {
     /// Allocates Vulkan device memory and creates `AllocatorPool` object.
-    pub fn create_pool(self: &Arc<Self>, create_info: &PoolCreateInfo) -> VkResult<AllocatorPool> {
+    ///
+    /// `create_info` is checked with `PoolCreateInfo::validate` first.
+    pub fn create_pool(self: &Arc<Self>, create_info: &PoolCreateInfo) -> VmaResult<AllocatorPool> {
+        create_info.validate()?;
         unsafe {
             let mut ffi_pool: ffi::VmaPool = std::mem::zeroed();
-            let raw_info = ffi::VmaPoolCreateInfo {
-                memoryTypeIndex: create_info.memory_type_index,
-                flags: create_info.flags.bits(),
-                blockSize: create_info.block_size,
-                minBlockCount: create_info.min_block_count,
-                maxBlockCount: create_info.max_block_count,
-                priority: create_info.priority,
-                minAllocationAlignment: create_info.min_allocation_alignment,
-                pMemoryAllocateNext: create_info.memory_allocate_next as *mut std::ffi::c_void,
-            };
+            let raw_info: ffi::VmaPoolCreateInfo = create_info.into();
             ffi::vmaCreatePool(self.internal, &raw_info, &mut ffi_pool).result()?;
+            self.track_pool(PoolHandle(ffi_pool), create_info);
             Ok(AllocatorPool {
                 pool: PoolHandle(ffi_pool),
                 allocator: self.clone(),
@@ -42,19 +83,411 @@ impl Allocator {
         }
     }
 
+    /// Creates a linear pool made of a single block of `size` bytes, for the allocators
+    /// built on top of `AllocatorPoolCreateFlags::LINEAR_ALGORITHM`.
+    pub(crate) fn create_linear_pool(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+    ) -> VmaResult<AllocatorPool> {
+        let mut create_info = PoolCreateInfo {
+            memory_type_index,
+            block_size: size,
+            min_block_count: 1,
+            max_block_count: 1,
+            ..Default::default()
+        };
+        create_info.set_algorithm(PoolAlgorithm::Linear);
+        self.create_pool(&create_info)
+    }
+
+    /// Creates a pool in a memory type with `vk::MemoryPropertyFlags::PROTECTED`, for protected
+    /// content such as DRM video.
+    ///
+    /// The memory type is chosen among `memory_type_bits` like `Alloc::find_memory_type_index`
+    /// does, preferring device local memory, and `create_info.memory_type_index` is ignored.
+    /// Buffers and images allocated from the pool must be created with
+    /// `vk::BufferCreateFlags::PROTECTED` and `vk::ImageCreateFlags::PROTECTED`.
+    ///
+    /// Fails with `VmaError::ProtectedMemoryNotEnabled` unless the allocator was created with
+    /// `AllocatorCreateInfo::protected_memory`, and with `VmaError::FeatureNotPresent` if no
+    /// memory type is protected.
+    pub fn create_protected_pool(
+        self: &Arc<Self>,
+        memory_type_bits: u32,
+        create_info: &PoolCreateInfo,
+    ) -> VmaResult<AllocatorPool> {
+        if !self.protected_memory {
+            return Err(VmaError::ProtectedMemoryNotEnabled);
+        }
+        let allocation_info = AllocationCreateInfo {
+            required_flags: vk::MemoryPropertyFlags::PROTECTED,
+            preferred_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ..Default::default()
+        };
+        let memory_type_index =
+            unsafe { self.find_memory_type_index(memory_type_bits, &allocation_info)? };
+        self.create_pool(&PoolCreateInfo {
+            memory_type_index,
+            ..create_info.clone()
+        })
+    }
+
+    /// Creates a pool whose blocks are allocated on the physical devices of `device_mask`, by
+    /// attaching `vk::MemoryAllocateFlagsInfo` with `vk::MemoryAllocateFlags::DEVICE_MASK` to
+    /// `create_info.memory_allocate_next`.
+    ///
+    /// Allocations that need another device mask have to come from another pool. Allocations in
+    /// multi-instance heaps, with `vk::MemoryHeapFlags::MULTI_INSTANCE`, get one instance of
+    /// the memory on each of these devices.
+    ///
+    /// Fails with `VmaError::DeviceGroupNotEnabled` unless the allocator was created with
+    /// `AllocatorCreateInfo::device_group_size` above 1, and with `VmaError::InvalidParameter`
+    /// if `device_mask` is empty, names devices outside of the group, or if the allocator was
+    /// created with `AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS`, since VMA attaches its own
+    /// `vk::MemoryAllocateFlagsInfo` then.
+    pub fn create_device_group_pool(
+        self: &Arc<Self>,
+        device_mask: u32,
+        create_info: &PoolCreateInfo,
+    ) -> VmaResult<AllocatorPool> {
+        if self.device_group_size <= 1 {
+            return Err(VmaError::DeviceGroupNotEnabled);
+        }
+        if device_mask == 0 || device_mask.checked_shr(self.device_group_size).unwrap_or(0) != 0 {
+            return Err(VmaError::InvalidParameter(
+                "The device mask must name devices of the device group",
+            ));
+        }
+        if self
+            .flags
+            .contains(AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS)
+        {
+            return Err(VmaError::InvalidParameter(
+                "Device masks can't be combined with BUFFER_DEVICE_ADDRESS",
+            ));
+        }
+        let mut flags_info = Box::new(
+            vk::MemoryAllocateFlagsInfo::default()
+                .flags(vk::MemoryAllocateFlags::DEVICE_MASK)
+                .device_mask(device_mask),
+        );
+        flags_info.p_next = create_info.memory_allocate_next;
+        let pool = self.create_pool(&PoolCreateInfo {
+            memory_allocate_next: &*flags_info as *const vk::MemoryAllocateFlagsInfo as *const _,
+            ..create_info.clone()
+        })?;
+        self.keep_pool_next(pool.pool, flags_info);
+        Ok(pool)
+    }
+
     pub fn default_pool(self: &Arc<Self>) -> AllocatorPool {
         AllocatorPool {
             pool: PoolHandle(std::ptr::null_mut()),
             allocator: self.clone(),
         }
     }
+
+    /// Destroys pools whose destruction was deferred, once all their allocations are freed.
+    ///
+    /// Returns the number of deferred pools that still have live allocations.
+    pub fn destroy_deferred_pools(&self) -> usize {
+        let mut pools = self
+            .deferred_pools
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        pools.retain(|&pool| unsafe {
+            if pool_statistics(self.internal, pool).allocation_count > 0 {
+                return true;
+            }
+            self.untrack_pool(pool);
+            ffi::vmaDestroyPool(self.internal, pool.0);
+            false
+        });
+        pools.len()
+    }
+
+    fn destroy_pool(&self, pool: PoolHandle) -> VmaResult<()> {
+        if pool.0.is_null() {
+            return Ok(());
+        }
+        self.release_reserved_blocks(pool, usize::MAX);
+        unsafe {
+            let allocation_count = pool_statistics(self.internal, pool).allocation_count;
+            if allocation_count > 0 {
+                let name = pool_name(self.internal, pool).map(|name| name.to_string_lossy().into());
+                self.deferred_pools
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(pool);
+                return Err(VmaError::PoolNotEmpty {
+                    name,
+                    allocation_count,
+                });
+            }
+            self.untrack_pool(pool);
+            ffi::vmaDestroyPool(self.internal, pool.0);
+        }
+        Ok(())
+    }
+
+    /// Runs `allocate` for `request`, handing a block reserved with `AllocatorPool::reserve` over
+    /// to the allocation if it doesn't fit in the other blocks of the pool.
+    ///
+    /// Fails without calling `allocate` with `VmaError::PoolQuotaExceeded` if the size of the
+    /// request doesn't fit in the quota of the pool, or with `VmaError::OutOfDeviceMemory` if the
+    /// fault injector fails it. Fails with `VmaError::OutOfDeviceMemory` if there is still no
+    /// room. The caller holds the `Allocator::pool_quota_lock` of the pool.
+    pub(crate) fn allocate_with_reservation(
+        &self,
+        pool: PoolHandle,
+        create_info: &mut ffi::VmaAllocationCreateInfo,
+        request: AllocationRequest<'_>,
+        mut allocate: impl FnMut(&ffi::VmaAllocationCreateInfo) -> vk::Result,
+    ) -> VmaResult<()> {
+        let size = request.size();
+        if let Some(size) = size {
+            self.check_pool_quota_for(pool, size)?;
+        }
+        #[cfg(feature = "fault-injection")]
+        self.inject_faults(pool, create_info, request)?;
+        let no_probe =
+            AllocationCreateFlags::NEVER_ALLOCATE | AllocationCreateFlags::DEDICATED_MEMORY;
+        if create_info.flags & no_probe.bits() == 0 && self.has_reserved_blocks(pool) {
+            let flags = create_info.flags;
+            create_info.flags |= AllocationCreateFlags::NEVER_ALLOCATE.bits();
+            let result = with_current_pool(pool, || allocate(create_info));
+            create_info.flags = flags;
+            if result != vk::Result::ERROR_OUT_OF_DEVICE_MEMORY {
+                return Ok(result.result()?);
+            }
+            self.release_reserved_blocks(pool, 1);
+        }
+        let result = with_current_pool(pool, || allocate(create_info));
+        if result == vk::Result::ERROR_OUT_OF_DEVICE_MEMORY {
+            return Err(self.out_of_memory_error(
+                pool,
+                create_info,
+                size,
+                request.memory_type_bits(),
+            ));
+        }
+        Ok(result.result()?)
+    }
+
+    /// Describes an allocation that failed with `vk::Result::ERROR_OUT_OF_DEVICE_MEMORY`.
+    fn out_of_memory_error(
+        &self,
+        pool: PoolHandle,
+        create_info: &ffi::VmaAllocationCreateInfo,
+        size: Option<vk::DeviceSize>,
+        memory_type_bits: u32,
+    ) -> VmaError {
+        let custom_pool = !pool.0.is_null();
+        let memory_type_candidates = if custom_pool {
+            self.tracker
+                .pools()
+                .iter()
+                .filter(|live| live.handle.0 == pool.0)
+                .map(|live| live.create_info.memory_type_index)
+                .collect()
+        } else {
+            // Asks VMA for its next choice until none is left, it ignores `create_info.pool`
+            let mut candidates = Vec::new();
+            let mut bits = memory_type_bits;
+            let mut memory_type_index = 0;
+            while bits != 0
+                && unsafe {
+                    ffi::vmaFindMemoryTypeIndex(
+                        self.internal,
+                        bits,
+                        create_info,
+                        &mut memory_type_index,
+                    )
+                } == vk::Result::SUCCESS
+            {
+                candidates.push(memory_type_index);
+                bits &= !(1 << memory_type_index);
+            }
+            candidates
+        };
+        VmaError::OutOfDeviceMemory(Some(Box::new(OutOfMemoryReport {
+            size,
+            memory_type_candidates,
+            custom_pool,
+            pool_name: custom_pool
+                .then(|| unsafe { pool_name(self.internal, pool) })
+                .flatten()
+                .map(|name| name.to_string_lossy().into()),
+            budgets: self.get_heap_budgets().unwrap_or_default(),
+        })))
+    }
+
+    /// Turns the `VmaError::OutOfDeviceMemory` of a `WITHIN_BUDGET` allocation of `size` bytes
+    /// into `VmaError::BudgetExceeded`, if a candidate heap lacked the budget for it. Other
+    /// errors are returned unchanged.
+    pub(crate) fn budget_denial(&self, err: VmaError, size: vk::DeviceSize) -> VmaError {
+        let VmaError::OutOfDeviceMemory(Some(report)) = err else {
+            return err;
+        };
+        let properties = unsafe { self.get_memory_properties() };
+        let mut heaps: Vec<HeapShortfall> = Vec::new();
+        for &memory_type_index in &report.memory_type_candidates {
+            let heap_index = properties.memory_types[memory_type_index as usize].heap_index;
+            if heaps.iter().any(|heap| heap.heap_index == heap_index) {
+                continue;
+            }
+            let Some(budget) = report.budgets.get(heap_index as usize) else {
+                continue;
+            };
+            let needed = budget.usage.saturating_add(size);
+            if needed > budget.budget {
+                heaps.push(HeapShortfall {
+                    heap_index,
+                    usage: budget.usage,
+                    budget: budget.budget,
+                    missing: needed - budget.budget,
+                });
+            }
+        }
+        if heaps.is_empty() {
+            return VmaError::OutOfDeviceMemory(Some(report));
+        }
+        VmaError::BudgetExceeded(Box::new(BudgetDenial {
+            size,
+            heaps,
+            report: *report,
+        }))
+    }
+
+    /// Checks the freshly made `allocations` of `request` against the pool quota and the fault
+    /// injector, for what couldn't be checked before VMA made them. The caller frees them if this
+    /// fails.
+    pub(crate) fn check_new_allocations(
+        &self,
+        pool: PoolHandle,
+        request: AllocationRequest<'_>,
+        allocations: &[ffi::VmaAllocation],
+    ) -> VmaResult<()> {
+        #[cfg(feature = "fault-injection")]
+        self.inject_late_faults(pool, request, allocations)?;
+        #[cfg(not(feature = "fault-injection"))]
+        let _ = request;
+        self.check_pool_quota(pool, allocations)
+    }
+
+    /// Lock serializing the allocations of `pool` while it has a quota, so that concurrent
+    /// allocations don't count each other's bytes against it. Callers hold it from before
+    /// `Allocator::allocate_with_reservation` until after `Allocator::check_new_allocations`.
+    pub(crate) fn pool_quota_lock(&self, pool: PoolHandle) -> Option<Arc<Mutex<()>>> {
+        if pool.0.is_null() {
+            return None;
+        }
+        self.tracker
+            .pools()
+            .iter()
+            .find(|live| live.handle.0 == pool.0 && live.quota.is_some())
+            .map(|live| live.quota_lock.clone())
+    }
+
+    /// Returns the quota of `pool` and the bytes allocated from it, if it has a quota.
+    fn pool_quota_usage(&self, pool: PoolHandle) -> Option<(vk::DeviceSize, vk::DeviceSize)> {
+        if pool.0.is_null() {
+            return None;
+        }
+        let (quota, reserved_bytes) = self
+            .tracker
+            .pools()
+            .iter()
+            .find(|live| live.handle.0 == pool.0)
+            .and_then(|live| Some((live.quota?, live.reserved_bytes())))?;
+        let usage = unsafe { pool_statistics(self.internal, pool).allocation_bytes }
+            .saturating_sub(reserved_bytes);
+        Some((quota, usage))
+    }
+
+    /// Fails with `VmaError::PoolQuotaExceeded` if allocating `size` bytes would push the pool
+    /// over its quota.
+    fn check_pool_quota_for(&self, pool: PoolHandle, size: vk::DeviceSize) -> VmaResult<()> {
+        let Some((quota, usage)) = self.pool_quota_usage(pool) else {
+            return Ok(());
+        };
+        if usage.saturating_add(size) <= quota {
+            return Ok(());
+        }
+        Err(VmaError::PoolQuotaExceeded {
+            name: unsafe { pool_name(self.internal, pool) }
+                .map(|name| name.to_string_lossy().into()),
+            quota,
+            usage,
+            size,
+        })
+    }
+
+    /// Fails with `VmaError::PoolQuotaExceeded` if the freshly made `allocations` pushed the pool
+    /// over its quota. The caller frees them in that case.
+    ///
+    /// This is the fallback for allocations whose size isn't known before VMA makes them, e.g.
+    /// images, the others are checked by `Allocator::allocate_with_reservation`.
+    fn check_pool_quota(
+        &self,
+        pool: PoolHandle,
+        allocations: &[ffi::VmaAllocation],
+    ) -> VmaResult<()> {
+        let Some((quota, usage)) = self.pool_quota_usage(pool) else {
+            return Ok(());
+        };
+        if usage <= quota {
+            return Ok(());
+        }
+        let size = allocations
+            .iter()
+            .map(|&raw| unsafe {
+                let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+                ffi::vmaGetAllocationInfo(self.internal, raw, &mut info);
+                info.size
+            })
+            .sum();
+        Err(VmaError::PoolQuotaExceeded {
+            name: unsafe { pool_name(self.internal, pool) }
+                .map(|name| name.to_string_lossy().into()),
+            quota,
+            usage: usage.saturating_sub(size),
+            size,
+        })
+    }
+}
+
+pub(crate) unsafe fn pool_statistics(allocator: ffi::VmaAllocator, pool: PoolHandle) -> Statistics {
+    let mut pool_stats: ffi::VmaStatistics = std::mem::zeroed();
+    ffi::vmaGetPoolStatistics(allocator, pool.0, &mut pool_stats);
+    (&pool_stats).into()
+}
+
+pub(crate) unsafe fn pool_detailed_statistics(
+    allocator: ffi::VmaAllocator,
+    pool: PoolHandle,
+) -> DetailedStatistics {
+    let mut pool_stats: ffi::VmaDetailedStatistics = std::mem::zeroed();
+    ffi::vmaCalculatePoolStatistics(allocator, pool.0, &mut pool_stats);
+    (&pool_stats).into()
+}
+
+pub(crate) unsafe fn pool_name<'a>(
+    allocator: ffi::VmaAllocator,
+    pool: PoolHandle,
+) -> Option<&'a CStr> {
+    let mut ptr: *const ::std::os::raw::c_char = std::ptr::null();
+    ffi::vmaGetPoolName(allocator, pool.0, &mut ptr);
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr))
 }
 
 impl Drop for AllocatorPool {
     fn drop(&mut self) {
-        unsafe {
-            ffi::vmaDestroyPool(self.allocator.internal, self.pool.0);
-        }
+        let _ = self.allocator.destroy_pool(self.pool);
     }
 }
 
@@ -75,47 +508,145 @@ impl AllocatorPool {
         if self.pool.0.is_null() {
             return None;
         }
-        let mut ptr: *const ::std::os::raw::c_char = std::ptr::null();
-        unsafe {
-            ffi::vmaGetPoolName(self.allocator.internal, self.pool.0, &mut ptr);
-            if ptr.is_null() {
-                return None;
+        unsafe { pool_name(self.allocator.internal, self.pool) }
+    }
+    /// Preallocates memory blocks until the pool holds at least `bytes`, so that later
+    /// allocations don't have to wait for `vkAllocateMemory`.
+    ///
+    /// VMA frees empty blocks beyond the first one, so each new block is kept alive by a
+    /// placeholder allocation spanning the whole block. A placeholder is released when an
+    /// allocation doesn't fit in the other blocks of the pool, and the remaining ones when the
+    /// pool is destroyed. Placeholders count towards the pool statistics.
+    ///
+    /// Requires an explicit `PoolCreateInfo::block_size`. Fails with the Vulkan error if a block
+    /// can't be allocated, e.g. once `PoolCreateInfo::max_block_count` is reached, in which case
+    /// the blocks allocated so far stay reserved.
+    pub fn reserve(&self, bytes: vk::DeviceSize) -> VmaResult<()> {
+        let allocator = self.allocator.as_ref();
+        let (memory_type_index, block_size) = allocator
+            .tracker
+            .pools()
+            .iter()
+            .find(|pool| pool.handle.0 == self.pool.0)
+            .map(|pool| {
+                (
+                    pool.create_info.memory_type_index,
+                    pool.create_info.block_size,
+                )
+            })
+            .ok_or(VmaError::InvalidParameter(
+                "Only custom pools can reserve memory",
+            ))?;
+        if block_size == 0 {
+            return Err(VmaError::InvalidParameter(
+                "Reserving memory requires an explicit block size",
+            ));
+        }
+        let requirements = vk::MemoryRequirements {
+            size: block_size,
+            alignment: 1,
+            memory_type_bits: 1 << memory_type_index,
+        };
+        let create_info = ffi::VmaAllocationCreateInfo {
+            pool: self.pool.0,
+            ..(&AllocationCreateInfo::default()).into()
+        };
+        while unsafe { pool_statistics(allocator.internal, self.pool).block_bytes } < bytes {
+            unsafe {
+                let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
+                ffi::vmaAllocateMemory(
+                    allocator.internal,
+                    &requirements,
+                    &create_info,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+                .result()?;
+                allocator.track_reserved_block(self.pool, allocation);
             }
-            Some(CStr::from_ptr(ptr))
         }
+        Ok(())
     }
-    /// Retrieves statistics of existing `AllocatorPool` object.
-    pub fn get_statistics(&self) -> VkResult<ffi::VmaStatistics> {
-        unsafe {
-            let mut pool_stats: ffi::VmaStatistics = std::mem::zeroed();
-            ffi::vmaGetPoolStatistics(self.allocator.internal, self.pool.0, &mut pool_stats);
-            Ok(pool_stats)
+
+    /// Limits the bytes allocated from the pool, or removes the limit with `None`.
+    ///
+    /// Unlike `PoolCreateInfo::max_block_count`, this caps the total size of the allocations
+    /// regardless of the block sizes. Allocations that would exceed the quota fail with
+    /// `VmaError::PoolQuotaExceeded`, existing allocations are not affected. Blocks reserved with
+    /// `AllocatorPool::reserve` only count once they are used.
+    pub fn set_quota(&self, quota: Option<vk::DeviceSize>) -> VmaResult<()> {
+        match self
+            .allocator
+            .tracker
+            .pools()
+            .iter_mut()
+            .find(|pool| pool.handle.0 == self.pool.0)
+        {
+            Some(pool) => {
+                pool.quota = quota;
+                Ok(())
+            }
+            None => Err(VmaError::InvalidParameter(
+                "Only custom pools can have a quota",
+            )),
         }
     }
 
+    /// Returns the quota set with `AllocatorPool::set_quota`.
+    pub fn quota(&self) -> Option<vk::DeviceSize> {
+        self.allocator
+            .tracker
+            .pools()
+            .iter()
+            .find(|pool| pool.handle.0 == self.pool.0)
+            .and_then(|pool| pool.quota)
+    }
+
+    /// Returns the raw `VmaPool` handle, for use with the [`ffi`] functions.
+    pub fn as_raw(&self) -> ffi::VmaPool {
+        self.pool.0
+    }
+
     /// Retrieves statistics of existing `AllocatorPool` object.
-    pub fn calculate_statistics(&self) -> VkResult<ffi::VmaDetailedStatistics> {
-        unsafe {
-            let mut pool_stats: ffi::VmaDetailedStatistics = std::mem::zeroed();
-            ffi::vmaCalculatePoolStatistics(self.allocator.internal, self.pool.0, &mut pool_stats);
-            Ok(pool_stats)
-        }
+    pub fn get_statistics(&self) -> VmaResult<Statistics> {
+        unsafe { Ok(pool_statistics(self.allocator.internal, self.pool)) }
+    }
+
+    /// Destroys the pool.
+    ///
+    /// Destroying a pool that still has live allocations would trigger an assertion inside VMA.
+    /// Instead, the allocator keeps such a pool alive until `Allocator::destroy_deferred_pools`
+    /// finds it empty, and `VmaError::PoolNotEmpty` is returned. Dropping the pool defers its
+    /// destruction the same way, without reporting the error.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        let pool = std::mem::replace(&mut self.pool, PoolHandle(std::ptr::null_mut()));
+        self.allocator.destroy_pool(pool)
+    }
+
+    /// Retrieves statistics of existing `AllocatorPool` object.
+    pub fn calculate_statistics(&self) -> VmaResult<DetailedStatistics> {
+        unsafe { Ok(pool_detailed_statistics(self.allocator.internal, self.pool)) }
     }
 
     /// Checks magic number in margins around all allocations in given memory pool in search for corruptions.
     ///
-    /// Corruption detection is enabled only when `VMA_DEBUG_DETECT_CORRUPTION` macro is defined to nonzero,
-    /// `VMA_DEBUG_MARGIN` is defined to nonzero and the pool is created in memory type that is
-    /// `ash::vk::MemoryPropertyFlags::HOST_VISIBLE` and `ash::vk::MemoryPropertyFlags::HOST_COHERENT`.
+    /// Corruption detection is enabled only with the `detect-corruption` feature, which defines
+    /// `VMA_DEBUG_DETECT_CORRUPTION` and `VMA_DEBUG_MARGIN`, and only if the pool is created in
+    /// memory type that is `ash::vk::MemoryPropertyFlags::HOST_VISIBLE` and
+    /// `ash::vk::MemoryPropertyFlags::HOST_COHERENT`.
     ///
     /// Possible error values:
     ///
-    /// - `ash::vk::Result::ERROR_FEATURE_NOT_PRESENT` - corruption detection is not enabled for specified pool.
-    /// - `ash::vk::Result::ERROR_VALIDATION_FAILED_EXT` - corruption detection has been performed and found memory corruptions around one of the allocations.
+    /// - `VmaError::CorruptionDetectionNotEnabled` - the crate was built without the `detect-corruption` feature.
+    /// - `VmaError::FeatureNotPresent` - corruption detection is not enabled for specified pool.
+    /// - `VmaError::ValidationFailed` - corruption detection has been performed and found memory corruptions around one of the allocations.
     ///   `VMA_ASSERT` is also fired in that case.
     /// - Other value: Error returned by Vulkan, e.g. memory mapping failure.
-    pub fn check_corruption(&self) -> VkResult<()> {
-        unsafe { ffi::vmaCheckPoolCorruption(self.allocator.internal, self.pool.0).result() }
+    pub fn check_corruption(&self) -> VmaResult<()> {
+        if !cfg!(feature = "detect-corruption") {
+            return Err(VmaError::CorruptionDetectionNotEnabled);
+        }
+        unsafe { Ok(ffi::vmaCheckPoolCorruption(self.allocator.internal, self.pool.0).result()?) }
     }
 }
 
@@ -131,7 +662,7 @@ pub trait Alloc {
     /// - Matches intended usage.
     /// - Has as many flags from `allocation_info.preferred_flags` as possible.
     ///
-    /// Returns ash::vk::Result::ERROR_FEATURE_NOT_PRESENT if not found. Receiving such a result
+    /// Returns `VmaError::FeatureNotPresent` if not found. Receiving such a result
     /// from this function or any other allocating function probably means that your
     /// device doesn't support any memory type with requested features for the specific
     /// type of resource you want to use it for. Please check parameters of your
@@ -140,7 +671,7 @@ pub trait Alloc {
         &self,
         memory_type_bits: u32,
         allocation_info: &AllocationCreateInfo,
-    ) -> VkResult<u32> {
+    ) -> VmaResult<u32> {
         let mut memory_type_index: u32 = 0;
         let mut allocation_info: ffi::VmaAllocationCreateInfo = allocation_info.into();
         allocation_info.pool = self.pool().0;
@@ -169,7 +700,7 @@ pub trait Alloc {
         &self,
         buffer_info: &ash::vk::BufferCreateInfo,
         allocation_info: &AllocationCreateInfo,
-    ) -> VkResult<u32> {
+    ) -> VmaResult<u32> {
         let mut allocation_info: ffi::VmaAllocationCreateInfo = allocation_info.into();
         allocation_info.pool = self.pool().0;
         let mut memory_type_index: u32 = 0;
@@ -198,7 +729,7 @@ pub trait Alloc {
         &self,
         image_info: ash::vk::ImageCreateInfo,
         allocation_info: &AllocationCreateInfo,
-    ) -> VkResult<u32> {
+    ) -> VmaResult<u32> {
         let mut allocation_info: ffi::VmaAllocationCreateInfo = allocation_info.into();
         allocation_info.pool = self.pool().0;
         let mut memory_type_index: u32 = 0;
@@ -219,24 +750,131 @@ pub trait Alloc {
     ///
     /// It is recommended to use `Allocator::allocate_memory_for_buffer`, `Allocator::allocate_memory_for_image`,
     /// `Allocator::create_buffer`, `Allocator::create_image` instead whenever possible.
+    #[track_caller]
     unsafe fn allocate_memory(
         &self,
         memory_requirements: &ash::vk::MemoryRequirements,
         create_info: &AllocationCreateInfo,
-    ) -> VkResult<Allocation> {
+    ) -> VmaResult<Allocation> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
-        ffi::vmaAllocateMemory(
-            self.allocator().internal,
-            memory_requirements,
-            &create_info,
-            &mut allocation,
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Memory(memory_requirements, 1);
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaAllocateMemory(
+                    self.allocator().internal,
+                    memory_requirements,
+                    create_info,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaFreeMemory(self.allocator().internal, allocation);
+            return Err(err);
+        }
+
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation(&allocation, self.pool(), &create_info, || {
+                RecordedResource::Memory(*memory_requirements)
+            });
+        Ok(allocation)
+    }
+
+    /// Allocates like `Alloc::allocate_memory` with `AllocationCreateFlags::WITHIN_BUDGET`.
+    ///
+    /// If the allocation is denied because it would exceed the budget, this fails with
+    /// `VmaError::BudgetExceeded`, listing the heaps that lacked space with their usage, budget
+    /// and the number of bytes missing, e.g. to decide how much to evict before retrying.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::allocate_memory`.
+    #[track_caller]
+    unsafe fn allocate_memory_within_budget(
+        &self,
+        memory_requirements: &ash::vk::MemoryRequirements,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        let mut create_info = create_info.clone();
+        create_info.flags |= AllocationCreateFlags::WITHIN_BUDGET;
+        self.allocate_memory(memory_requirements, &create_info)
+            .map_err(|err| {
+                self.allocator()
+                    .budget_denial(err, memory_requirements.size)
+            })
+    }
 
-        Ok(Allocation(allocation))
+    /// General purpose memory allocation using requirements queried with
+    /// `vkGetBufferMemoryRequirements2`, `vkGetImageMemoryRequirements2` or similar.
+    ///
+    /// `dedicated_requirements` is the `vk::MemoryDedicatedRequirements` structure that was chained
+    /// into the query, and `resource` the buffer or image it was made for, if it exists yet. If
+    /// the resource requires a dedicated allocation, or prefers one and
+    /// `AllocationCreateFlags::NEVER_ALLOCATE` is not used, the allocation is made with
+    /// `AllocationCreateFlags::DEDICATED_MEMORY`. Given a resource, it's then made with
+    /// `Alloc::allocate_memory_for_buffer` or `Alloc::allocate_memory_for_image`, which chain
+    /// `vk::MemoryDedicatedAllocateInfo` naming it, as long as the allocator uses Vulkan 1.1 or
+    /// `AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION`.
+    ///
+    /// Fails with `VmaError::InvalidParameter` if a dedicated allocation is required, but
+    /// `resource` is `None` or `AllocationCreateFlags::NEVER_ALLOCATE` is used.
+    ///
+    /// This is useful for resources created outside of this library, e.g. video decode images,
+    /// which would otherwise lose that information through `Allocator::allocate_memory`.
+    #[track_caller]
+    unsafe fn allocate_memory2(
+        &self,
+        memory_requirements: &ash::vk::MemoryRequirements2,
+        dedicated_requirements: &ash::vk::MemoryDedicatedRequirements,
+        resource: Option<MemoryResource>,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        let mut create_info = create_info.clone();
+        let never_allocate = create_info
+            .flags
+            .contains(AllocationCreateFlags::NEVER_ALLOCATE);
+        let requires_dedicated = dedicated_requirements.requires_dedicated_allocation == vk::TRUE;
+        if requires_dedicated && never_allocate {
+            return Err(VmaError::InvalidParameter(
+                "A dedicated allocation is required, but NEVER_ALLOCATE was used",
+            ));
+        }
+        if requires_dedicated && resource.is_none() {
+            return Err(VmaError::InvalidParameter(
+                "A dedicated allocation is required, but no resource was given",
+            ));
+        }
+        let prefers_dedicated =
+            dedicated_requirements.prefers_dedicated_allocation == vk::TRUE && !never_allocate;
+        if !requires_dedicated && !prefers_dedicated {
+            return self.allocate_memory(&memory_requirements.memory_requirements, &create_info);
+        }
+        create_info.flags |= AllocationCreateFlags::DEDICATED_MEMORY;
+        match resource {
+            Some(MemoryResource::Buffer(buffer)) => {
+                self.allocate_memory_for_buffer(buffer, &create_info)
+            }
+            Some(MemoryResource::Image(image)) => {
+                self.allocate_memory_for_image(image, &create_info)
+            }
+            None => self.allocate_memory(&memory_requirements.memory_requirements, &create_info),
+        }
     }
 
     /// General purpose memory allocation for multiple allocation objects at once.
@@ -248,29 +886,60 @@ pub trait Alloc {
     /// It may be internally optimized to be more efficient than calling `Allocator::allocate_memory` `allocations.len()` times.
     ///
     /// All allocations are made using same parameters. All of them are created out of the same memory pool and type.
+    #[track_caller]
     unsafe fn allocate_memory_pages(
         &self,
         memory_requirements: &ash::vk::MemoryRequirements,
         create_info: &AllocationCreateInfo,
         allocation_count: usize,
-    ) -> VkResult<Vec<Allocation>> {
+    ) -> VmaResult<Vec<Allocation>> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut allocations: Vec<ffi::VmaAllocation> = vec![std::mem::zeroed(); allocation_count];
-        ffi::vmaAllocateMemoryPages(
-            self.allocator().internal,
-            memory_requirements,
-            &create_info,
-            allocation_count,
-            allocations.as_mut_ptr(),
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Memory(memory_requirements, allocation_count);
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaAllocateMemoryPages(
+                    self.allocator().internal,
+                    memory_requirements,
+                    create_info,
+                    allocation_count,
+                    allocations.as_mut_ptr(),
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) = self
+            .allocator()
+            .check_new_allocations(self.pool(), request, &allocations)
+        {
+            ffi::vmaFreeMemoryPages(
+                self.allocator().internal,
+                allocations.len(),
+                allocations.as_ptr(),
+            );
+            return Err(err);
+        }
 
+        let location = Location::caller();
         let allocations: Vec<Allocation> = allocations
             .into_iter()
-            .map(|alloc| Allocation(alloc))
+            .map(|alloc| self.allocator().track(alloc, self.pool(), location))
             .collect();
+        #[cfg(feature = "recording")]
+        for allocation in &allocations {
+            self.allocator()
+                .record_allocation(allocation, self.pool(), &create_info, || {
+                    RecordedResource::Memory(*memory_requirements)
+                });
+        }
 
         Ok(allocations)
     }
@@ -278,48 +947,98 @@ pub trait Alloc {
     /// Buffer specialized memory allocation.
     ///
     /// You should free the memory using `Allocator::free_memory` or 'Allocator::free_memory_pages'.
+    #[track_caller]
     unsafe fn allocate_memory_for_buffer(
         &self,
         buffer: ash::vk::Buffer,
         create_info: &AllocationCreateInfo,
-    ) -> VkResult<Allocation> {
+    ) -> VmaResult<Allocation> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
         let mut allocation_info: ffi::VmaAllocationInfo = std::mem::zeroed();
-        ffi::vmaAllocateMemoryForBuffer(
-            self.allocator().internal,
-            buffer,
-            &create_info,
-            &mut allocation,
-            &mut allocation_info,
-        )
-        .result()?;
+        let request = AllocationRequest::Resource;
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaAllocateMemoryForBuffer(
+                    self.allocator().internal,
+                    buffer,
+                    create_info,
+                    &mut allocation,
+                    &mut allocation_info,
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaFreeMemory(self.allocator().internal, allocation);
+            return Err(err);
+        }
 
-        Ok(Allocation(allocation))
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation_of(&allocation, self.pool(), &create_info);
+        Ok(allocation)
     }
 
     /// Image specialized memory allocation.
     ///
     /// You should free the memory using `Allocator::free_memory` or 'Allocator::free_memory_pages'.
+    #[track_caller]
     unsafe fn allocate_memory_for_image(
         &self,
         image: ash::vk::Image,
         create_info: &AllocationCreateInfo,
-    ) -> VkResult<Allocation> {
+    ) -> VmaResult<Allocation> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
-        ffi::vmaAllocateMemoryForImage(
-            self.allocator().internal,
-            image,
-            &create_info,
-            &mut allocation,
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Resource;
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaAllocateMemoryForImage(
+                    self.allocator().internal,
+                    image,
+                    create_info,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaFreeMemory(self.allocator().internal, allocation);
+            return Err(err);
+        }
 
-        Ok(Allocation(allocation))
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation_of(&allocation, self.pool(), &create_info);
+        Ok(allocation)
     }
 
     /// This function automatically creates a buffer, allocates appropriate memory
@@ -335,54 +1054,119 @@ pub trait Alloc {
     /// and if dedicated allocation is possible (AllocationCreateInfo::pool is null
     /// and `AllocationCreateFlags::NEVER_ALLOCATE` is not used), it creates dedicated
     /// allocation for this buffer, just like when using `AllocationCreateFlags::DEDICATED_MEMORY`.
+    ///
+    /// The `p_next` chain of `buffer_info` is passed to `vkCreateBuffer` unchanged, so extension
+    /// structures like `vk::ExternalMemoryBufferCreateInfo` or `vk::BufferOpaqueCaptureAddressCreateInfo`
+    /// can be attached with `push_next`. The chained structures only need to outlive this call.
+    /// Structures that need a matching `pNext` on the memory allocation, like
+    /// `vk::ExportMemoryAllocateInfo`, have to be provided separately through a custom pool
+    /// (`PoolCreateInfo::push_next`) or `AllocatorCreateInfo::type_external_memory_handle_types`.
+    #[track_caller]
     unsafe fn create_buffer(
         &self,
         buffer_info: &ash::vk::BufferCreateInfo,
         create_info: &AllocationCreateInfo,
-    ) -> VkResult<(ash::vk::Buffer, Allocation)> {
+    ) -> VmaResult<(ash::vk::Buffer, Allocation)> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut buffer = vk::Buffer::null();
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
-        ffi::vmaCreateBuffer(
-            self.allocator().internal,
-            &*buffer_info,
-            &create_info,
-            &mut buffer,
-            &mut allocation,
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Buffer(buffer_info);
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaCreateBuffer(
+                    self.allocator().internal,
+                    &*buffer_info,
+                    create_info,
+                    &mut buffer,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaDestroyBuffer(self.allocator().internal, buffer, allocation);
+            return Err(err);
+        }
 
-        Ok((buffer, Allocation(allocation)))
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        self.allocator().track_resource(&allocation, buffer);
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation(&allocation, self.pool(), &create_info, || {
+                RecordedResource::buffer(buffer_info, 0)
+            });
+        Ok((buffer, allocation))
     }
     /// brief Creates a buffer with additional minimum alignment.
     ///
     /// Similar to vmaCreateBuffer() but provides additional parameter `minAlignment` which allows to specify custom,
     /// minimum alignment to be used when placing the buffer inside a larger memory block, which may be needed e.g.
     /// for interop with OpenGL.
+    ///
+    /// The `p_next` chain of `buffer_info` is handled the same way as in `Allocator::create_buffer`.
+    #[track_caller]
     unsafe fn create_buffer_with_alignment(
         &self,
         buffer_info: &ash::vk::BufferCreateInfo,
         create_info: &AllocationCreateInfo,
         min_alignment: vk::DeviceSize,
-    ) -> VkResult<(ash::vk::Buffer, Allocation)> {
+    ) -> VmaResult<(ash::vk::Buffer, Allocation)> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut buffer = vk::Buffer::null();
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
-        ffi::vmaCreateBufferWithAlignment(
-            self.allocator().internal,
-            &*buffer_info,
-            &create_info,
-            min_alignment,
-            &mut buffer,
-            &mut allocation,
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Buffer(buffer_info);
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaCreateBufferWithAlignment(
+                    self.allocator().internal,
+                    &*buffer_info,
+                    create_info,
+                    min_alignment,
+                    &mut buffer,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaDestroyBuffer(self.allocator().internal, buffer, allocation);
+            return Err(err);
+        }
 
-        Ok((buffer, Allocation(allocation)))
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        self.allocator().track_resource(&allocation, buffer);
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation(&allocation, self.pool(), &create_info, || {
+                RecordedResource::buffer(buffer_info, min_alignment)
+            });
+        Ok((buffer, allocation))
     }
     /// This function automatically creates an image, allocates appropriate memory
     /// for it, and binds the image with the memory.
@@ -401,26 +1185,61 @@ pub trait Alloc {
     /// If `VK_ERROR_VALIDAITON_FAILED_EXT` is returned, VMA may have encountered a problem
     /// that is not caught by the validation layers. One example is if you try to create a 0x0
     /// image, a panic will occur and `VK_ERROR_VALIDAITON_FAILED_EXT` is thrown.
+    ///
+    /// The `p_next` chain of `image_info` is passed to `vkCreateImage` unchanged, so extension
+    /// structures like `vk::ExternalMemoryImageCreateInfo` or `vk::ImageFormatListCreateInfo`
+    /// can be attached with `push_next`. The chained structures only need to outlive this call.
+    /// Structures that need a matching `pNext` on the memory allocation, like
+    /// `vk::ExportMemoryAllocateInfo`, have to be provided separately through a custom pool
+    /// (`PoolCreateInfo::push_next`) or `AllocatorCreateInfo::type_external_memory_handle_types`.
+    #[track_caller]
     unsafe fn create_image(
         &self,
         image_info: &ash::vk::ImageCreateInfo,
         create_info: &AllocationCreateInfo,
-    ) -> VkResult<(ash::vk::Image, Allocation)> {
+    ) -> VmaResult<(ash::vk::Image, Allocation)> {
         let mut create_info: ffi::VmaAllocationCreateInfo = create_info.into();
         create_info.pool = self.pool().0;
         let mut image = vk::Image::null();
         let mut allocation: ffi::VmaAllocation = std::mem::zeroed();
-        ffi::vmaCreateImage(
-            self.allocator().internal,
-            &*image_info,
-            &create_info,
-            &mut image,
-            &mut allocation,
-            std::ptr::null_mut(),
-        )
-        .result()?;
+        let request = AllocationRequest::Image(image_info);
+        let quota_lock = self.allocator().pool_quota_lock(self.pool());
+        let _quota_guard = quota_lock
+            .as_deref()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner));
+        self.allocator().allocate_with_reservation(
+            self.pool(),
+            &mut create_info,
+            request,
+            |create_info| {
+                ffi::vmaCreateImage(
+                    self.allocator().internal,
+                    &*image_info,
+                    create_info,
+                    &mut image,
+                    &mut allocation,
+                    std::ptr::null_mut(),
+                )
+            },
+        )?;
+        if let Err(err) =
+            self.allocator()
+                .check_new_allocations(self.pool(), request, &[allocation])
+        {
+            ffi::vmaDestroyImage(self.allocator().internal, image, allocation);
+            return Err(err);
+        }
 
-        Ok((image, Allocation(allocation)))
+        let allocation = self
+            .allocator()
+            .track(allocation, self.pool(), Location::caller());
+        self.allocator().track_resource(&allocation, image);
+        #[cfg(feature = "recording")]
+        self.allocator()
+            .record_allocation(&allocation, self.pool(), &create_info, || {
+                RecordedResource::image(image_info)
+            });
+        Ok((image, allocation))
     }
 }
 
@@ -0,0 +1,764 @@
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::ffi;
+use crate::tracking::raw_allocation_info;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::AllocatorPoolCreateFlags;
+use crate::MemoryUsage;
+use crate::PoolCreateInfo;
+use crate::PoolHandle;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Magic number and version at the start of a recording.
+const HEADER: &[u8; 8] = b"VMAREC\0\x01";
+
+/// Memory usages by their index in a recording.
+#[allow(deprecated)]
+const USAGES: [MemoryUsage; 10] = [
+    MemoryUsage::Unknown,
+    MemoryUsage::GpuOnly,
+    MemoryUsage::CpuOnly,
+    MemoryUsage::CpuToGpu,
+    MemoryUsage::GpuToCpu,
+    MemoryUsage::CpuCopy,
+    MemoryUsage::GpuLazy,
+    MemoryUsage::Auto,
+    MemoryUsage::AutoPreferDevice,
+    MemoryUsage::AutoPreferHost,
+];
+
+bitflags::bitflags! {
+    /// Flags of `Allocator::start_recording_with_flags`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct RecordFlags: u32 {
+        /// Flushes the writer after every call, so that the recording is complete up to the
+        /// last call if the application crashes. Much slower.
+        const FLUSH_AFTER_CALL = 0x1;
+    }
+}
+
+/// Allocator call logged by `Allocator::start_recording`.
+///
+/// Allocations and custom pools are identified by the ids they had when they were recorded,
+/// `Replayer` maps them to the ones it makes.
+#[derive(Clone)]
+pub enum RecordedCall {
+    /// `Allocator::create_pool`. The `memory_allocate_next` chain isn't recorded.
+    CreatePool {
+        pool: u32,
+        create_info: PoolCreateInfo<'static>,
+    },
+    /// Destruction of a custom pool.
+    DestroyPool { pool: u32 },
+    /// Allocation made through the `Alloc` trait, once per allocation for
+    /// `Alloc::allocate_memory_pages`. `AllocationCreateInfo::user_data` isn't recorded.
+    Allocate {
+        /// Id of the allocation, see `AllocationId::get`.
+        id: u64,
+        /// Custom pool the allocation was made from, `None` for the default pools.
+        pool: Option<u32>,
+        create_info: AllocationCreateInfo,
+        resource: RecordedResource,
+    },
+    /// Free of an allocation, including the destruction of its buffer or image.
+    Free { id: u64 },
+    /// `Allocator::map_memory`.
+    Map { id: u64 },
+    /// `Allocator::unmap_memory`.
+    Unmap { id: u64 },
+}
+unsafe impl Send for RecordedCall {}
+unsafe impl Sync for RecordedCall {}
+
+/// What a `RecordedCall::Allocate` allocated memory for.
+#[derive(Clone, Copy)]
+pub enum RecordedResource {
+    /// Memory with the given requirements, from `Alloc::allocate_memory`.
+    ///
+    /// `Alloc::allocate_memory_for_buffer` and `Alloc::allocate_memory_for_image` are
+    /// recorded with the size and memory type of the resulting allocation, since the
+    /// requirements of their resource are queried by VMA.
+    Memory(vk::MemoryRequirements),
+    /// Buffer from `Alloc::create_buffer` or `Alloc::create_buffer_with_alignment`.
+    Buffer {
+        flags: vk::BufferCreateFlags,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        /// Minimum alignment of `Alloc::create_buffer_with_alignment`, 0 otherwise.
+        min_alignment: vk::DeviceSize,
+    },
+    /// Image from `Alloc::create_image`.
+    Image {
+        flags: vk::ImageCreateFlags,
+        image_type: vk::ImageType,
+        format: vk::Format,
+        extent: vk::Extent3D,
+        mip_levels: u32,
+        array_layers: u32,
+        samples: vk::SampleCountFlags,
+        tiling: vk::ImageTiling,
+        usage: vk::ImageUsageFlags,
+    },
+}
+
+impl RecordedResource {
+    pub(crate) fn buffer(info: &vk::BufferCreateInfo, min_alignment: vk::DeviceSize) -> Self {
+        RecordedResource::Buffer {
+            flags: info.flags,
+            size: info.size,
+            usage: info.usage,
+            min_alignment,
+        }
+    }
+
+    pub(crate) fn image(info: &vk::ImageCreateInfo) -> Self {
+        RecordedResource::Image {
+            flags: info.flags,
+            image_type: info.image_type,
+            format: info.format,
+            extent: info.extent,
+            mip_levels: info.mip_levels,
+            array_layers: info.array_layers,
+            samples: info.samples,
+            tiling: info.tiling,
+            usage: info.usage,
+        }
+    }
+}
+
+impl RecordedCall {
+    /// Reads a whole recording written by `Allocator::start_recording`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` if it isn't a recording, and with
+    /// `io::ErrorKind::UnexpectedEof` if it is truncated.
+    pub fn read_all(reader: impl Read) -> io::Result<Vec<RecordedCall>> {
+        let mut reader = Reader(io::BufReader::new(reader));
+        let mut header = [0; 8];
+        reader.0.read_exact(&mut header)?;
+        if &header != HEADER {
+            return Err(invalid_data("Not a recording of allocator calls"));
+        }
+        let mut calls = Vec::new();
+        while let Some(call) = reader.call()? {
+            calls.push(call);
+        }
+        Ok(calls)
+    }
+
+    /// Writes `calls` in the format of `Allocator::start_recording`, e.g. after editing a
+    /// recording to try another allocation strategy.
+    pub fn write_all<'a>(
+        writer: impl Write,
+        calls: impl IntoIterator<Item = &'a RecordedCall>,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(HEADER)?;
+        for call in calls {
+            call.write(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        match self {
+            RecordedCall::CreatePool { pool, create_info } => {
+                w.write_all(&[0])?;
+                w.write_all(&pool.to_le_bytes())?;
+                w.write_all(&create_info.memory_type_index.to_le_bytes())?;
+                w.write_all(&create_info.flags.bits().to_le_bytes())?;
+                w.write_all(&create_info.block_size.to_le_bytes())?;
+                w.write_all(&(create_info.min_block_count as u64).to_le_bytes())?;
+                w.write_all(&(create_info.max_block_count as u64).to_le_bytes())?;
+                w.write_all(&create_info.priority.to_le_bytes())?;
+                w.write_all(&create_info.min_allocation_alignment.to_le_bytes())
+            }
+            RecordedCall::DestroyPool { pool } => {
+                w.write_all(&[1])?;
+                w.write_all(&pool.to_le_bytes())
+            }
+            RecordedCall::Allocate {
+                id,
+                pool,
+                create_info,
+                resource,
+            } => {
+                w.write_all(&[2])?;
+                w.write_all(&id.to_le_bytes())?;
+                w.write_all(&pool.unwrap_or(u32::MAX).to_le_bytes())?;
+                w.write_all(&create_info.flags.bits().to_le_bytes())?;
+                let usage = USAGES.iter().position(|&u| u == create_info.usage);
+                w.write_all(&[usage.unwrap_or(0) as u8])?;
+                w.write_all(&create_info.required_flags.as_raw().to_le_bytes())?;
+                w.write_all(&create_info.preferred_flags.as_raw().to_le_bytes())?;
+                w.write_all(&create_info.memory_type_bits.to_le_bytes())?;
+                w.write_all(&create_info.priority.to_le_bytes())?;
+                match resource {
+                    RecordedResource::Memory(requirements) => {
+                        w.write_all(&[0])?;
+                        w.write_all(&requirements.size.to_le_bytes())?;
+                        w.write_all(&requirements.alignment.to_le_bytes())?;
+                        w.write_all(&requirements.memory_type_bits.to_le_bytes())
+                    }
+                    RecordedResource::Buffer {
+                        flags,
+                        size,
+                        usage,
+                        min_alignment,
+                    } => {
+                        w.write_all(&[1])?;
+                        w.write_all(&flags.as_raw().to_le_bytes())?;
+                        w.write_all(&size.to_le_bytes())?;
+                        w.write_all(&usage.as_raw().to_le_bytes())?;
+                        w.write_all(&min_alignment.to_le_bytes())
+                    }
+                    RecordedResource::Image {
+                        flags,
+                        image_type,
+                        format,
+                        extent,
+                        mip_levels,
+                        array_layers,
+                        samples,
+                        tiling,
+                        usage,
+                    } => {
+                        w.write_all(&[2])?;
+                        w.write_all(&flags.as_raw().to_le_bytes())?;
+                        w.write_all(&image_type.as_raw().to_le_bytes())?;
+                        w.write_all(&format.as_raw().to_le_bytes())?;
+                        w.write_all(&extent.width.to_le_bytes())?;
+                        w.write_all(&extent.height.to_le_bytes())?;
+                        w.write_all(&extent.depth.to_le_bytes())?;
+                        w.write_all(&mip_levels.to_le_bytes())?;
+                        w.write_all(&array_layers.to_le_bytes())?;
+                        w.write_all(&samples.as_raw().to_le_bytes())?;
+                        w.write_all(&tiling.as_raw().to_le_bytes())?;
+                        w.write_all(&usage.as_raw().to_le_bytes())
+                    }
+                }
+            }
+            RecordedCall::Free { id } => {
+                w.write_all(&[3])?;
+                w.write_all(&id.to_le_bytes())
+            }
+            RecordedCall::Map { id } => {
+                w.write_all(&[4])?;
+                w.write_all(&id.to_le_bytes())
+            }
+            RecordedCall::Unmap { id } => {
+                w.write_all(&[5])?;
+                w.write_all(&id.to_le_bytes())
+            }
+        }
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<R>(R);
+
+impl<R: Read> Reader<R> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        self.bytes().map(i32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.bytes().map(f32::from_le_bytes)
+    }
+
+    /// Next call, `None` at the end of the recording.
+    fn call(&mut self) -> io::Result<Option<RecordedCall>> {
+        let mut tag = [0];
+        if self.0.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let call = match tag[0] {
+            0 => RecordedCall::CreatePool {
+                pool: self.u32()?,
+                create_info: PoolCreateInfo {
+                    memory_type_index: self.u32()?,
+                    flags: AllocatorPoolCreateFlags::from_bits_retain(self.u32()?),
+                    block_size: self.u64()?,
+                    min_block_count: self.u64()? as usize,
+                    max_block_count: self.u64()? as usize,
+                    priority: self.f32()?,
+                    min_allocation_alignment: self.u64()?,
+                    ..Default::default()
+                },
+            },
+            1 => RecordedCall::DestroyPool { pool: self.u32()? },
+            2 => {
+                let id = self.u64()?;
+                let pool = Some(self.u32()?).filter(|&pool| pool != u32::MAX);
+                let create_info = AllocationCreateInfo {
+                    flags: AllocationCreateFlags::from_bits_retain(self.u32()?),
+                    usage: *USAGES
+                        .get(self.bytes::<1>()?[0] as usize)
+                        .ok_or_else(|| invalid_data("Unknown memory usage"))?,
+                    required_flags: vk::MemoryPropertyFlags::from_raw(self.u32()?),
+                    preferred_flags: vk::MemoryPropertyFlags::from_raw(self.u32()?),
+                    memory_type_bits: self.u32()?,
+                    user_data: 0,
+                    priority: self.f32()?,
+                };
+                let resource = match self.bytes::<1>()?[0] {
+                    0 => RecordedResource::Memory(vk::MemoryRequirements {
+                        size: self.u64()?,
+                        alignment: self.u64()?,
+                        memory_type_bits: self.u32()?,
+                    }),
+                    1 => RecordedResource::Buffer {
+                        flags: vk::BufferCreateFlags::from_raw(self.u32()?),
+                        size: self.u64()?,
+                        usage: vk::BufferUsageFlags::from_raw(self.u32()?),
+                        min_alignment: self.u64()?,
+                    },
+                    2 => RecordedResource::Image {
+                        flags: vk::ImageCreateFlags::from_raw(self.u32()?),
+                        image_type: vk::ImageType::from_raw(self.i32()?),
+                        format: vk::Format::from_raw(self.i32()?),
+                        extent: vk::Extent3D {
+                            width: self.u32()?,
+                            height: self.u32()?,
+                            depth: self.u32()?,
+                        },
+                        mip_levels: self.u32()?,
+                        array_layers: self.u32()?,
+                        samples: vk::SampleCountFlags::from_raw(self.u32()?),
+                        tiling: vk::ImageTiling::from_raw(self.i32()?),
+                        usage: vk::ImageUsageFlags::from_raw(self.u32()?),
+                    },
+                    _ => return Err(invalid_data("Unknown recorded resource")),
+                };
+                RecordedCall::Allocate {
+                    id,
+                    pool,
+                    create_info,
+                    resource,
+                }
+            }
+            3 => RecordedCall::Free { id: self.u64()? },
+            4 => RecordedCall::Map { id: self.u64()? },
+            5 => RecordedCall::Unmap { id: self.u64()? },
+            _ => return Err(invalid_data("Unknown recorded call")),
+        };
+        Ok(Some(call))
+    }
+}
+
+/// Recording started with `Allocator::start_recording`, if any.
+pub(crate) struct CallRecorder {
+    active: AtomicBool,
+    state: Mutex<Option<RecorderState>>,
+}
+
+pub(crate) struct RecorderState {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    /// Recorded ids of the live custom pools, by handle
+    pools: HashMap<ffi::VmaPool, u32>,
+    next_pool: u32,
+    flags: RecordFlags,
+    /// First write error, reported by `Allocator::stop_recording`
+    error: Option<io::Error>,
+}
+
+impl CallRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            state: Mutex::new(None),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, Option<RecorderState>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl RecorderState {
+    /// Recorded id of a custom pool, `None` for the default pools.
+    pub(crate) fn pool(&self, pool: PoolHandle) -> Option<u32> {
+        self.pools.get(&pool.0).copied()
+    }
+
+    fn write(&mut self, call: &RecordedCall) {
+        if self.error.is_none() {
+            let mut result = call.write(&mut self.writer);
+            if self.flags.contains(RecordFlags::FLUSH_AFTER_CALL) {
+                result = result.and_then(|()| self.writer.flush());
+            }
+            self.error = result.err();
+        }
+    }
+}
+
+impl Allocator {
+    /// Starts logging every allocation, free, map and unmap made through the wrapper, along
+    /// with the creation and destruction of custom pools, to `writer` in a compact binary
+    /// format.
+    ///
+    /// Read the recording back with `RecordedCall::read_all` and reproduce it against another
+    /// allocator with `Replayer`, e.g. to debug fragmentation offline or to compare allocation
+    /// strategies on real workloads. Custom pools that already exist are recorded first;
+    /// allocations made before the recording started are not, and `Replayer` ignores their
+    /// frees. Calls made concurrently from several threads are recorded in the order they
+    /// complete.
+    ///
+    /// Fails with `VmaError::InvalidParameter` if a recording is already running.
+    pub fn start_recording(&self, writer: impl Write + Send + 'static) -> VmaResult<()> {
+        self.start_recording_with_flags(writer, RecordFlags::empty())
+    }
+
+    /// Like `Allocator::start_recording`, with `flags`.
+    pub fn start_recording_with_flags(
+        &self,
+        writer: impl Write + Send + 'static,
+        flags: RecordFlags,
+    ) -> VmaResult<()> {
+        let mut state = self.recorder.state();
+        if state.is_some() {
+            return Err(VmaError::InvalidParameter(
+                "The allocator is already recording",
+            ));
+        }
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        let mut writer = BufWriter::new(writer);
+        let error = writer.write_all(HEADER).err();
+        let recorder = state.insert(RecorderState {
+            writer,
+            pools: HashMap::new(),
+            next_pool: 0,
+            flags,
+            error,
+        });
+        for pool in self.tracker.pools().iter() {
+            record_pool(recorder, pool.handle, &pool.create_info);
+        }
+        self.recorder.active.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Stops the recording started with `Allocator::start_recording` and flushes it.
+    ///
+    /// Returns the first error writing the recording, if any. Does nothing if the allocator
+    /// isn't recording.
+    pub fn stop_recording(&self) -> io::Result<()> {
+        self.recorder.active.store(false, Ordering::Release);
+        match self.recorder.state().take() {
+            Some(mut state) => match state.error {
+                Some(err) => Err(err),
+                None => state.writer.flush(),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` if `Allocator::start_recording` is logging the calls.
+    pub fn is_recording(&self) -> bool {
+        self.recorder.active.load(Ordering::Acquire)
+    }
+
+    /// Records the call made by `call`, if recording.
+    pub(crate) fn record(&self, call: impl FnOnce(&RecorderState) -> RecordedCall) {
+        if !self.is_recording() {
+            return;
+        }
+        if let Some(state) = self.recorder.state().as_mut() {
+            let call = call(state);
+            state.write(&call);
+        }
+    }
+
+    /// Records a freshly made allocation of the `Alloc` trait.
+    pub(crate) fn record_allocation(
+        &self,
+        allocation: &Allocation,
+        pool: PoolHandle,
+        create_info: &ffi::VmaAllocationCreateInfo,
+        resource: impl FnOnce() -> RecordedResource,
+    ) {
+        self.record(|state| RecordedCall::Allocate {
+            id: allocation.id().get(),
+            pool: state.pool(pool),
+            create_info: AllocationCreateInfo {
+                user_data: 0,
+                ..create_info.try_into().unwrap_or_default()
+            },
+            resource: resource(),
+        });
+    }
+
+    /// Records an allocation whose memory requirements are only known to VMA, with the size and
+    /// memory type it ended up with.
+    pub(crate) fn record_allocation_of(
+        &self,
+        allocation: &Allocation,
+        pool: PoolHandle,
+        create_info: &ffi::VmaAllocationCreateInfo,
+    ) {
+        self.record_allocation(allocation, pool, create_info, || {
+            let info = raw_allocation_info(self, allocation.raw);
+            RecordedResource::Memory(vk::MemoryRequirements {
+                size: info.size,
+                alignment: 1,
+                memory_type_bits: 1 << info.memory_type,
+            })
+        });
+    }
+
+    /// Records a freshly created custom pool.
+    pub(crate) fn record_pool(&self, handle: PoolHandle, create_info: &PoolCreateInfo) {
+        if !self.is_recording() {
+            return;
+        }
+        if let Some(state) = self.recorder.state().as_mut() {
+            record_pool(state, handle, create_info);
+        }
+    }
+
+    /// Records the destruction of a custom pool.
+    pub(crate) fn record_pool_destruction(&self, handle: PoolHandle) {
+        if !self.is_recording() {
+            return;
+        }
+        if let Some(state) = self.recorder.state().as_mut() {
+            if let Some(pool) = state.pools.remove(&handle.0) {
+                state.write(&RecordedCall::DestroyPool { pool });
+            }
+        }
+    }
+}
+
+fn record_pool(state: &mut RecorderState, handle: PoolHandle, create_info: &PoolCreateInfo) {
+    let pool = state.next_pool;
+    state.next_pool += 1;
+    state.pools.insert(handle.0, pool);
+    state.write(&RecordedCall::CreatePool {
+        pool,
+        create_info: PoolCreateInfo {
+            memory_allocate_next: std::ptr::null(),
+            _marker: std::marker::PhantomData,
+            ..*create_info
+        },
+    });
+}
+
+/// Reproduces a recording of `Allocator::start_recording` against another allocator.
+///
+/// Allocations and pools made by the replayer are freed when it is dropped, so its allocator
+/// can be inspected in between calls, e.g. with `Allocator::calculate_statistics` or
+/// `viz::MemoryMap::capture`.
+pub struct Replayer {
+    allocator: Arc<Allocator>,
+    pools: HashMap<u32, AllocatorPool>,
+    allocations: HashMap<u64, ReplayedAllocation>,
+}
+
+struct ReplayedAllocation {
+    allocation: Allocation,
+    resource: Resource,
+    map_count: u32,
+}
+
+enum Resource {
+    Memory,
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+impl Allocator {
+    /// Creates a `Replayer` making its allocations from this allocator.
+    pub fn create_replayer(self: &Arc<Self>) -> Replayer {
+        Replayer {
+            allocator: self.clone(),
+            pools: HashMap::new(),
+            allocations: HashMap::new(),
+        }
+    }
+}
+
+impl Replayer {
+    /// Replays a single call.
+    ///
+    /// Calls that refer to allocations or pools the replayer doesn't know, e.g. because they
+    /// were made before the recording started or failed to replay, are ignored.
+    ///
+    /// # Safety
+    /// Buffers and images of the recording must be valid to create on the device of the
+    /// allocator, which isn't checked if the recording comes from another device.
+    pub unsafe fn replay(&mut self, call: &RecordedCall) -> VmaResult<()> {
+        match call {
+            RecordedCall::CreatePool { pool, create_info } => {
+                let created = self.allocator.create_pool(create_info)?;
+                self.pools.insert(*pool, created);
+            }
+            RecordedCall::DestroyPool { pool } => {
+                self.pools.remove(pool);
+            }
+            RecordedCall::Allocate {
+                id,
+                pool,
+                create_info,
+                resource,
+            } => {
+                let alloc: &dyn Alloc = match pool {
+                    Some(pool) => match self.pools.get(pool) {
+                        Some(pool) => pool,
+                        None => return Ok(()),
+                    },
+                    None => self.allocator.as_ref(),
+                };
+                let (allocation, resource) = match *resource {
+                    RecordedResource::Memory(requirements) => (
+                        alloc.allocate_memory(&requirements, create_info)?,
+                        Resource::Memory,
+                    ),
+                    RecordedResource::Buffer {
+                        flags,
+                        size,
+                        usage,
+                        min_alignment,
+                    } => {
+                        let buffer_info = vk::BufferCreateInfo::default()
+                            .flags(flags)
+                            .size(size)
+                            .usage(usage);
+                        let (buffer, allocation) = if min_alignment == 0 {
+                            alloc.create_buffer(&buffer_info, create_info)?
+                        } else {
+                            alloc.create_buffer_with_alignment(
+                                &buffer_info,
+                                create_info,
+                                min_alignment,
+                            )?
+                        };
+                        (allocation, Resource::Buffer(buffer))
+                    }
+                    RecordedResource::Image {
+                        flags,
+                        image_type,
+                        format,
+                        extent,
+                        mip_levels,
+                        array_layers,
+                        samples,
+                        tiling,
+                        usage,
+                    } => {
+                        let image_info = vk::ImageCreateInfo::default()
+                            .flags(flags)
+                            .image_type(image_type)
+                            .format(format)
+                            .extent(extent)
+                            .mip_levels(mip_levels)
+                            .array_layers(array_layers)
+                            .samples(samples)
+                            .tiling(tiling)
+                            .usage(usage);
+                        let (image, allocation) = alloc.create_image(&image_info, create_info)?;
+                        (allocation, Resource::Image(image))
+                    }
+                };
+                self.allocations.insert(
+                    *id,
+                    ReplayedAllocation {
+                        allocation,
+                        resource,
+                        map_count: 0,
+                    },
+                );
+            }
+            RecordedCall::Free { id } => {
+                if let Some(replayed) = self.allocations.remove(id) {
+                    self.free(replayed)?;
+                }
+            }
+            RecordedCall::Map { id } => {
+                if let Some(replayed) = self.allocations.get_mut(id) {
+                    self.allocator.map_memory(&mut replayed.allocation)?;
+                    replayed.map_count += 1;
+                }
+            }
+            RecordedCall::Unmap { id } => {
+                if let Some(replayed) = self.allocations.get_mut(id) {
+                    if replayed.map_count > 0 {
+                        self.allocator.unmap_memory(&mut replayed.allocation);
+                        replayed.map_count -= 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays `calls` in order, stopping at the first one that fails.
+    ///
+    /// The error is returned along with the index of the failed call.
+    ///
+    /// # Safety
+    /// See `Replayer::replay`.
+    pub unsafe fn replay_all<'a>(
+        &mut self,
+        calls: impl IntoIterator<Item = &'a RecordedCall>,
+    ) -> Result<(), (usize, VmaError)> {
+        for (index, call) in calls.into_iter().enumerate() {
+            self.replay(call).map_err(|err| (index, err))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the allocator the calls are replayed against.
+    pub fn allocator(&self) -> &Arc<Allocator> {
+        &self.allocator
+    }
+
+    /// Number of replayed allocations that are still alive.
+    pub fn live_allocation_count(&self) -> usize {
+        self.allocations.len()
+    }
+
+    unsafe fn free(&self, mut replayed: ReplayedAllocation) -> VmaResult<()> {
+        for _ in 0..replayed.map_count {
+            self.allocator.unmap_memory(&mut replayed.allocation);
+        }
+        Ok(match replayed.resource {
+            Resource::Memory => self.allocator.free_memory(replayed.allocation),
+            Resource::Buffer(buffer) => self.allocator.destroy_buffer(buffer, replayed.allocation),
+            Resource::Image(image) => self.allocator.destroy_image(image, replayed.allocation),
+        }?)
+    }
+}
+
+/// Frees the replayed allocations, then destroys the replayed pools.
+impl Drop for Replayer {
+    fn drop(&mut self) {
+        for (_, replayed) in std::mem::take(&mut self.allocations) {
+            let _ = unsafe { self.free(replayed) };
+        }
+    }
+}
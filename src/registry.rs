@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt;
+use std::panic::Location;
+
+use crate::ffi;
+use crate::pool::pool_name;
+#[cfg(feature = "backtrace")]
+use crate::AllocationBacktrace;
+use crate::AllocationId;
+use crate::Allocator;
+use crate::ByteSize;
+use ash::vk;
+
+/// Live allocation made through the wrapper, see `Allocator::live_allocations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationRecord {
+    /// Id of the allocation.
+    pub id: AllocationId,
+    /// Size of the allocation, in bytes.
+    pub size: vk::DeviceSize,
+    /// Memory type index of the allocation.
+    pub memory_type: u32,
+    /// Custom pool of the allocation, see `AllocatorPool::as_raw`, or null for the default
+    /// pools.
+    pub pool: ffi::VmaPool,
+    /// Name set with `Allocator::set_allocation_name`, if any.
+    pub name: Option<String>,
+    /// User data of the allocation, see `AllocationCreateInfo::user_data`.
+    pub user_data: usize,
+    /// Where the allocation was made.
+    pub location: &'static Location<'static>,
+    /// Call stack of the allocation.
+    #[cfg(feature = "backtrace")]
+    pub backtrace: AllocationBacktrace,
+}
+unsafe impl Send for AllocationRecord {}
+unsafe impl Sync for AllocationRecord {}
+
+/// Breakdown of the live allocations of an allocator, see `Allocator::report`.
+#[derive(Debug, Clone)]
+pub struct AllocationReport {
+    /// Live allocations, ordered by id.
+    pub allocations: Vec<AllocationRecord>,
+    /// Labels of the custom pools, by handle
+    pools: Vec<(ffi::VmaPool, String)>,
+}
+unsafe impl Send for AllocationReport {}
+unsafe impl Sync for AllocationReport {}
+
+impl AllocationReport {
+    /// Total size of the allocations, in bytes.
+    pub fn total_bytes(&self) -> vk::DeviceSize {
+        self.allocations
+            .iter()
+            .map(|allocation| allocation.size)
+            .sum()
+    }
+
+    /// Number and total size of the allocations by creation location, largest first.
+    pub fn by_location(&self) -> Vec<(&'static Location<'static>, usize, vk::DeviceSize)> {
+        let mut locations: HashMap<&'static Location<'static>, (usize, vk::DeviceSize)> =
+            HashMap::new();
+        for allocation in &self.allocations {
+            let (count, bytes) = locations.entry(allocation.location).or_default();
+            *count += 1;
+            *bytes += allocation.size;
+        }
+        let mut locations: Vec<_> = locations
+            .into_iter()
+            .map(|(location, (count, bytes))| (location, count, bytes))
+            .collect();
+        locations.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        locations
+    }
+
+    fn pool_label(&self, pool: ffi::VmaPool) -> &str {
+        self.pools
+            .iter()
+            .find(|(handle, _)| *handle == pool)
+            .map_or("default pools", |(_, label)| label)
+    }
+}
+
+/// Totals, followed by the allocations by creation location and by pool.
+impl fmt::Display for AllocationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} live allocation(s), {}",
+            self.allocations.len(),
+            ByteSize(self.total_bytes())
+        )?;
+        if self.allocations.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\nby location:")?;
+        for (location, count, bytes) in self.by_location() {
+            write!(
+                f,
+                "\n  {}: {} allocation(s), {}",
+                location,
+                count,
+                ByteSize(bytes)
+            )?;
+        }
+        let mut pools: Vec<(&str, usize, vk::DeviceSize)> = Vec::new();
+        for allocation in &self.allocations {
+            let label = self.pool_label(allocation.pool);
+            match pools.iter_mut().find(|(pool, _, _)| *pool == label) {
+                Some((_, count, bytes)) => {
+                    *count += 1;
+                    *bytes += allocation.size;
+                }
+                None => pools.push((label, 1, allocation.size)),
+            }
+        }
+        write!(f, "\nby pool:")?;
+        for (label, count, bytes) in pools {
+            write!(
+                f,
+                "\n  {}: {} allocation(s), {}",
+                label,
+                count,
+                ByteSize(bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Allocator {
+    /// Lists the live allocations made through the wrapper, ordered by id.
+    ///
+    /// Every allocation is already tracked for leak reports, so the registry costs nothing until
+    /// it's queried. Allocations adopted with `Allocation::adopt` are listed with the location
+    /// they were adopted at.
+    pub fn live_allocations(&self) -> Vec<AllocationRecord> {
+        let mut allocations: Vec<AllocationRecord> = self
+            .tracker
+            .live()
+            .iter()
+            .map(|(&id, allocation)| unsafe {
+                let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+                ffi::vmaGetAllocationInfo(self.internal, allocation.raw, &mut info);
+                AllocationRecord {
+                    id,
+                    size: info.size,
+                    memory_type: info.memoryType,
+                    pool: allocation.pool.0,
+                    name: (!info.pName.is_null())
+                        .then(|| CStr::from_ptr(info.pName).to_string_lossy().into_owned()),
+                    user_data: info.pUserData as usize,
+                    location: allocation.location,
+                    #[cfg(feature = "backtrace")]
+                    backtrace: allocation.backtrace.clone(),
+                }
+            })
+            .collect();
+        allocations.sort_by_key(|allocation| allocation.id);
+        allocations
+    }
+
+    /// Breaks the live allocations down by creation location and by pool, e.g. to hunt leaks.
+    pub fn report(&self) -> AllocationReport {
+        let pools = self
+            .tracker
+            .pools()
+            .iter()
+            .enumerate()
+            .map(|(index, pool)| {
+                let name = unsafe { pool_name(self.internal, pool.handle) }
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| format!("pool {}", index));
+                let label = format!("{} (type {})", name, pool.create_info.memory_type_index);
+                (pool.handle.0, label)
+            })
+            .collect();
+        AllocationReport {
+            allocations: self.live_allocations(),
+            pools,
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::stack_allocator::allocate_linear;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Ring buffer of per-frame allocations, built on a linear pool with a single block.
+///
+/// Allocations pushed during a frame are owned by the ring. `RingAllocator::end_frame` tags them
+/// with the fence or timeline semaphore value signaled once the device is done with the frame,
+/// and `RingAllocator::retire_frame` frees the frames whose value was reached, oldest first. VMA
+/// then reuses the space at the beginning of the block once the end is reached.
+///
+/// A push that doesn't fit in the free part of the ring fails with
+/// `VmaError::OutOfDeviceMemory`.
+pub struct RingAllocator {
+    pool: AllocatorPool,
+    create_info: AllocationCreateInfo,
+    memory_type_bits: u32,
+    /// Allocations pushed since the last `RingAllocator::end_frame`
+    current: Vec<Allocation>,
+    /// Ended frames that weren't retired yet, oldest first
+    in_flight: VecDeque<(u64, Vec<Allocation>)>,
+}
+
+impl Allocator {
+    /// Creates a `RingAllocator` of `size` bytes in the given memory type.
+    ///
+    /// # Safety
+    /// `create_info` is used for every push, see `Alloc::allocate_memory`.
+    pub unsafe fn create_ring_allocator(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<RingAllocator> {
+        Ok(RingAllocator {
+            pool: self.create_linear_pool(memory_type_index, size)?,
+            create_info: create_info.clone(),
+            memory_type_bits: 1 << memory_type_index,
+            current: Vec::new(),
+            in_flight: VecDeque::new(),
+        })
+    }
+}
+
+impl RingAllocator {
+    /// Pool backing the ring.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.pool
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` for the current frame.
+    ///
+    /// Returns the allocation, owned by the ring until its frame is retired, and its offset in
+    /// the block of the ring.
+    #[track_caller]
+    pub fn push(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&Allocation, vk::DeviceSize)> {
+        let (allocation, offset) = allocate_linear(
+            &self.pool,
+            self.memory_type_bits,
+            size,
+            alignment,
+            &self.create_info,
+        )?;
+        self.current.push(allocation);
+        Ok((self.current.last().unwrap(), offset))
+    }
+
+    /// Ends the current frame, its allocations are freed once `fence_value` is retired.
+    ///
+    /// Values must increase from one frame to the next.
+    pub fn end_frame(&mut self, fence_value: u64) {
+        debug_assert!(self
+            .in_flight
+            .back()
+            .is_none_or(|&(last, _)| last <= fence_value));
+        if !self.current.is_empty() {
+            self.in_flight
+                .push_back((fence_value, std::mem::take(&mut self.current)));
+        }
+    }
+
+    /// Frees the allocations of all ended frames whose fence value is at most `completed_value`.
+    ///
+    /// Returns the number of frames that were retired. Fails like `Allocator::free_memory_pages`,
+    /// e.g. if an allocation of a retired frame is still mapped, in which case that frame and the
+    /// following ones stay in flight.
+    pub fn retire_frame(&mut self, completed_value: u64) -> VmaResult<usize> {
+        let mut retired = 0;
+        let allocator = self.pool.allocator();
+        while let Some((fence_value, allocations)) = self.in_flight.front() {
+            if *fence_value > completed_value {
+                break;
+            }
+            for allocation in allocations {
+                allocator.check_freeable(allocation)?;
+            }
+            let (_, allocations) = self.in_flight.pop_front().unwrap();
+            unsafe { allocator.free_memory_pages(allocations)? };
+            retired += 1;
+        }
+        Ok(retired)
+    }
+
+    /// Number of ended frames that weren't retired yet.
+    pub fn frames_in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Frees all remaining allocations, reporting errors that `Drop` would ignore.
+    ///
+    /// The device must not use them anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.free_all()
+    }
+
+    fn free_all(&mut self) -> VmaResult<()> {
+        let allocator = self.pool.allocator();
+        let frames = self.in_flight.drain(..).map(|(_, allocations)| allocations);
+        let mut result = Ok(());
+        for allocations in frames.chain(std::iter::once(std::mem::take(&mut self.current))) {
+            result = result
+                .and(unsafe { allocator.free_memory_pages(allocations) }.map_err(VmaError::from));
+        }
+        result
+    }
+}
+
+/// Frees all remaining allocations, the device must not use them anymore.
+impl Drop for RingAllocator {
+    fn drop(&mut self) {
+        let _ = self.free_all();
+    }
+}
+
+/// `RingAllocator` with a number of frames in flight fixed at compile time.
+///
+/// Instead of tagging frames with fence values, each of the `N` frames has an inline slot that
+/// `FrameRing::begin_frame` frees when the slot is reused `N` frames later.
+pub struct FrameRing<const N: usize> {
+    pool: AllocatorPool,
+    create_info: AllocationCreateInfo,
+    memory_type_bits: u32,
+    frames: [Vec<Allocation>; N],
+    /// Slot of the current frame
+    current: usize,
+}
+
+impl Allocator {
+    /// Creates a `FrameRing` of `size` bytes in the given memory type, with `N` frames in flight.
+    ///
+    /// # Safety
+    /// `create_info` is used for every push, see `Alloc::allocate_memory`.
+    pub unsafe fn create_frame_ring<const N: usize>(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<FrameRing<N>> {
+        const { assert!(N > 0, "Frame rings need at least one frame") };
+        Ok(FrameRing {
+            pool: self.create_linear_pool(memory_type_index, size)?,
+            create_info: create_info.clone(),
+            memory_type_bits: 1 << memory_type_index,
+            frames: std::array::from_fn(|_| Vec::new()),
+            current: 0,
+        })
+    }
+}
+
+impl<const N: usize> FrameRing<N> {
+    /// Pool backing the ring.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.pool
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` for the current frame, see
+    /// `RingAllocator::push`.
+    #[track_caller]
+    pub fn push(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&Allocation, vk::DeviceSize)> {
+        let (allocation, offset) = allocate_linear(
+            &self.pool,
+            self.memory_type_bits,
+            size,
+            alignment,
+            &self.create_info,
+        )?;
+        let frame = &mut self.frames[self.current];
+        frame.push(allocation);
+        Ok((frame.last().unwrap(), offset))
+    }
+
+    /// Starts frame `frame_index`, freeing the allocations pushed by the previous user of its
+    /// slot.
+    ///
+    /// Returns the number of allocations that were freed. Fails like
+    /// `Allocator::free_memory_pages`, e.g. if one of them is still mapped, in which case nothing
+    /// is freed and the current frame is left unchanged.
+    ///
+    /// # Safety
+    /// The device must be done with frame `frame_index - N`.
+    pub unsafe fn begin_frame(&mut self, frame_index: u64) -> VmaResult<usize> {
+        let current = (frame_index % N as u64) as usize;
+        let allocator = self.pool.allocator();
+        for allocation in &self.frames[current] {
+            allocator.check_freeable(allocation)?;
+        }
+        let allocations = std::mem::take(&mut self.frames[current]);
+        self.current = current;
+        let freed = allocations.len();
+        allocator.free_memory_pages(allocations)?;
+        Ok(freed)
+    }
+
+    /// Frees all remaining allocations, reporting errors that `Drop` would ignore.
+    ///
+    /// The device must not use them anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.free_all()
+    }
+
+    fn free_all(&mut self) -> VmaResult<()> {
+        let allocator = self.pool.allocator();
+        let mut result = Ok(());
+        for allocations in self.frames.iter_mut().map(std::mem::take) {
+            result = result
+                .and(unsafe { allocator.free_memory_pages(allocations) }.map_err(VmaError::from));
+        }
+        result
+    }
+}
+
+/// Frees all remaining allocations, the device must not use them anymore.
+impl<const N: usize> Drop for FrameRing<N> {
+    fn drop(&mut self) {
+        let _ = self.free_all();
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::BTreeMap;
+
+/// Second level lists per power of two, as a power of two.
+const SL_BITS: u32 = 4;
+const SL_COUNT: usize = 1 << SL_BITS;
+const FL_COUNT: usize = 64 - SL_BITS as usize + 1;
+
+/// First and second level indices of the free list holding ranges of `size` bytes.
+fn mapping(size: u64) -> (usize, usize) {
+    if size < SL_COUNT as u64 {
+        (0, size as usize)
+    } else {
+        let log2 = 63 - size.leading_zeros();
+        let sl = (size >> (log2 - SL_BITS)) as usize - SL_COUNT;
+        ((log2 - SL_BITS + 1) as usize, sl)
+    }
+}
+
+/// Rounds `size` up so that every range of its free list is at least `size` bytes.
+fn round_up(size: u64) -> Option<u64> {
+    if size < SL_COUNT as u64 {
+        Some(size)
+    } else {
+        let log2 = 63 - size.leading_zeros();
+        size.checked_add((1 << (log2 - SL_BITS)) - 1)
+    }
+}
+
+/// Allocated or free range of a `TlsfBlock`.
+#[derive(Clone, Copy)]
+struct Node {
+    offset: u64,
+    size: u64,
+    free: bool,
+    /// Neighboring ranges in offset order
+    prev_phys: Option<usize>,
+    next_phys: Option<usize>,
+    /// Neighbors in the free list, for free ranges
+    prev_free: Option<usize>,
+    next_free: Option<usize>,
+}
+
+/// Range allocated from a `TlsfBlock`, freed with `TlsfBlock::free`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TlsfAllocation {
+    node: usize,
+    offset: u64,
+    size: u64,
+}
+
+impl TlsfAllocation {
+    /// Offset of the range in its block.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Size of the range, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Two-level segregated fit allocator of ranges in a block of `size` bytes, the algorithm VMA
+/// uses by default, written in Rust.
+///
+/// Like a `VirtualBlock`, it only manages offsets, e.g. of the `vk::DeviceMemory` blocks of an
+/// `Allocator` built with the `pure-rust` feature. Allocation and free take constant time, and
+/// adjacent free ranges are merged.
+pub struct TlsfBlock {
+    size: u64,
+    nodes: Vec<Node>,
+    unused_nodes: Vec<usize>,
+    fl_bitmap: u64,
+    sl_bitmaps: [u32; FL_COUNT],
+    heads: [[Option<usize>; SL_COUNT]; FL_COUNT],
+    allocated: u64,
+    allocation_count: usize,
+}
+
+impl TlsfBlock {
+    /// Creates a block of `size` bytes, all free.
+    pub fn new(size: u64) -> Self {
+        let mut block = TlsfBlock {
+            size,
+            nodes: Vec::new(),
+            unused_nodes: Vec::new(),
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            heads: [[None; SL_COUNT]; FL_COUNT],
+            allocated: 0,
+            allocation_count: 0,
+        };
+        if size > 0 {
+            let node = block.new_node(Node {
+                offset: 0,
+                size,
+                free: true,
+                prev_phys: None,
+                next_phys: None,
+                prev_free: None,
+                next_free: None,
+            });
+            block.insert_free(node);
+        }
+        block
+    }
+
+    /// Allocates `size` bytes at an offset aligned to `alignment`, a power of two, or returns
+    /// `None` if there is no room for them. Empty ranges can't be allocated.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<TlsfAllocation> {
+        if size == 0 {
+            return None;
+        }
+        let fits = |node: &Node| {
+            let padding = node.offset.next_multiple_of(alignment) - node.offset;
+            node.size >= size + padding
+        };
+        // The first range of the list of `size` may fit, e.g. for a block of exactly `size`
+        // bytes. Otherwise any range of a list of at least `size + alignment - 1` bytes does.
+        let (fl, sl) = mapping(size);
+        let node = self.heads[fl][sl]
+            .filter(|&node| fits(&self.nodes[node]))
+            .or_else(|| {
+                let (fl, sl) = mapping(round_up(size.checked_add(alignment - 1)?)?);
+                self.find_free(fl, sl)
+            })?;
+        self.remove_free(node);
+
+        let aligned = self.nodes[node].offset.next_multiple_of(alignment);
+        let padding = aligned - self.nodes[node].offset;
+        if padding > 0 {
+            // Free neighbors are always merged, so the previous range is allocated
+            let front = self.new_node(Node {
+                offset: self.nodes[node].offset,
+                size: padding,
+                free: true,
+                prev_phys: self.nodes[node].prev_phys,
+                next_phys: Some(node),
+                prev_free: None,
+                next_free: None,
+            });
+            if let Some(prev) = self.nodes[node].prev_phys {
+                self.nodes[prev].next_phys = Some(front);
+            }
+            self.nodes[node].offset = aligned;
+            self.nodes[node].size -= padding;
+            self.nodes[node].prev_phys = Some(front);
+            self.insert_free(front);
+        }
+        let remaining = self.nodes[node].size - size;
+        if remaining > 0 {
+            let back = self.new_node(Node {
+                offset: aligned + size,
+                size: remaining,
+                free: true,
+                prev_phys: Some(node),
+                next_phys: self.nodes[node].next_phys,
+                prev_free: None,
+                next_free: None,
+            });
+            if let Some(next) = self.nodes[node].next_phys {
+                self.nodes[next].prev_phys = Some(back);
+            }
+            self.nodes[node].size = size;
+            self.nodes[node].next_phys = Some(back);
+            self.insert_free(back);
+        }
+        self.nodes[node].free = false;
+        self.allocated += size;
+        self.allocation_count += 1;
+        Some(TlsfAllocation {
+            node,
+            offset: aligned,
+            size,
+        })
+    }
+
+    /// Frees a range, merging it with its free neighbors.
+    ///
+    /// # Safety
+    /// `allocation` must have been allocated from this block.
+    pub unsafe fn free(&mut self, allocation: TlsfAllocation) {
+        let mut node = allocation.node;
+        debug_assert!(!self.nodes[node].free, "Range freed twice");
+        self.nodes[node].free = true;
+        self.allocated -= allocation.size;
+        self.allocation_count -= 1;
+        if let Some(next) = self.nodes[node]
+            .next_phys
+            .filter(|&next| self.nodes[next].free)
+        {
+            self.remove_free(next);
+            self.merge(node, next);
+        }
+        if let Some(prev) = self.nodes[node]
+            .prev_phys
+            .filter(|&prev| self.nodes[prev].free)
+        {
+            self.remove_free(prev);
+            self.merge(prev, node);
+            node = prev;
+        }
+        self.insert_free(node);
+    }
+
+    /// Size of the block, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes allocated, without alignment padding.
+    pub fn allocated_size(&self) -> u64 {
+        self.allocated
+    }
+
+    /// Number of live allocations.
+    pub fn allocation_count(&self) -> usize {
+        self.allocation_count
+    }
+
+    /// Returns `true` if nothing is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.allocation_count == 0
+    }
+
+    fn new_node(&mut self, node: Node) -> usize {
+        match self.unused_nodes.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Merges `next` into `node`, its previous range.
+    fn merge(&mut self, node: usize, next: usize) {
+        self.nodes[node].size += self.nodes[next].size;
+        self.nodes[node].next_phys = self.nodes[next].next_phys;
+        if let Some(after) = self.nodes[next].next_phys {
+            self.nodes[after].prev_phys = Some(node);
+        }
+        self.unused_nodes.push(next);
+    }
+
+    /// First free range of the first non-empty list at or after `(fl, sl)`.
+    fn find_free(&self, fl: usize, sl: usize) -> Option<usize> {
+        let sl_map = self.sl_bitmaps[fl] & (!0u32 << sl);
+        let (fl, sl) = if sl_map != 0 {
+            (fl, sl_map.trailing_zeros() as usize)
+        } else {
+            let fl_map = self.fl_bitmap & (!0u64 << (fl + 1));
+            if fl_map == 0 {
+                return None;
+            }
+            let fl = fl_map.trailing_zeros() as usize;
+            (fl, self.sl_bitmaps[fl].trailing_zeros() as usize)
+        };
+        self.heads[fl][sl]
+    }
+
+    fn insert_free(&mut self, node: usize) {
+        let (fl, sl) = mapping(self.nodes[node].size);
+        let head = self.heads[fl][sl];
+        self.nodes[node].prev_free = None;
+        self.nodes[node].next_free = head;
+        if let Some(head) = head {
+            self.nodes[head].prev_free = Some(node);
+        }
+        self.heads[fl][sl] = Some(node);
+        self.sl_bitmaps[fl] |= 1 << sl;
+        self.fl_bitmap |= 1 << fl;
+    }
+
+    fn remove_free(&mut self, node: usize) {
+        let (fl, sl) = mapping(self.nodes[node].size);
+        let Node {
+            prev_free,
+            next_free,
+            ..
+        } = self.nodes[node];
+        match prev_free {
+            Some(prev) => self.nodes[prev].next_free = next_free,
+            None => self.heads[fl][sl] = next_free,
+        }
+        if let Some(next) = next_free {
+            self.nodes[next].prev_free = prev_free;
+        }
+        if self.heads[fl][sl].is_none() {
+            self.sl_bitmaps[fl] &= !(1 << sl);
+            if self.sl_bitmaps[fl] == 0 {
+                self.fl_bitmap &= !(1 << fl);
+            }
+        }
+    }
+}
+
+/// Allocator of ranges in a block of `size` bytes one after another, VMA's linear algorithm.
+///
+/// Ranges are allocated after the last one, wrapping around to the start of the block once the
+/// ones there were freed, which makes a ring buffer. Ranges at the upper address grow down from
+/// the end of the block instead, which makes a double stack.
+struct LinearBlock {
+    size: u64,
+    /// Sizes of the ranges at the lower address by offset
+    lower: BTreeMap<u64, u64>,
+    /// Sizes of the ranges at the upper address by offset
+    upper: BTreeMap<u64, u64>,
+    /// End of the last range allocated at the lower address
+    next: u64,
+}
+
+impl LinearBlock {
+    fn allocate(&mut self, size: u64, alignment: u64, upper: bool) -> Option<u64> {
+        if size == 0 {
+            return None;
+        }
+        let limit = self
+            .upper
+            .first_key_value()
+            .map_or(self.size, |(&offset, _)| offset);
+        if upper {
+            let lower_end = self
+                .lower
+                .last_key_value()
+                .map_or(0, |(&offset, &size)| offset + size);
+            let offset = limit.checked_sub(size)? / alignment * alignment;
+            if offset < lower_end {
+                return None;
+            }
+            self.upper.insert(offset, size);
+            return Some(offset);
+        }
+        // Ranges don't overlap, so only the last one starting before `end` may reach `offset`.
+        let fits = |offset: u64| {
+            offset.checked_add(size).is_some_and(|end| {
+                end <= limit
+                    && self
+                        .lower
+                        .range(..end)
+                        .next_back()
+                        .is_none_or(|(&other, &other_size)| other + other_size <= offset)
+            })
+        };
+        let after = self.next.checked_next_multiple_of(alignment)?;
+        let offset = if fits(after) {
+            after
+        } else if self.upper.is_empty() && fits(0) {
+            0
+        } else {
+            return None;
+        };
+        self.lower.insert(offset, size);
+        self.next = offset + size;
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: u64, upper: bool) {
+        if upper {
+            self.upper.remove(&offset);
+        } else if let Some(size) = self.lower.remove(&offset) {
+            if offset + size == self.next {
+                self.next = self
+                    .lower
+                    .range(..offset)
+                    .next_back()
+                    .map_or(0, |(&other, &other_size)| other + other_size);
+            }
+        }
+    }
+}
+
+/// Kind of resource of an allocation, deciding whether it may share a page of
+/// `bufferImageGranularity` bytes with its neighbors, like VMA's `VmaSuballocationType`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SuballocationType {
+    Unknown,
+    Buffer,
+    ImageUnknown,
+    ImageLinear,
+    ImageOptimal,
+}
+
+impl SuballocationType {
+    /// Returns `true` if allocations of both kinds can't share a page.
+    fn conflicts(self, other: SuballocationType) -> bool {
+        use SuballocationType::*;
+        match (self.min(other), self.max(other)) {
+            (Unknown, _) | (ImageUnknown, _) => true,
+            (Buffer, other) => matches!(other, ImageUnknown | ImageOptimal),
+            (ImageLinear, other) => other == ImageOptimal,
+            (ImageOptimal, _) => false,
+        }
+    }
+
+    /// Name of the kind in VMA's JSON statistics.
+    #[cfg(feature = "stats-string")]
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SuballocationType::Unknown => "UNKNOWN",
+            SuballocationType::Buffer => "BUFFER",
+            SuballocationType::ImageUnknown => "IMAGE_UNKNOWN",
+            SuballocationType::ImageLinear => "IMAGE_LINEAR",
+            SuballocationType::ImageOptimal => "IMAGE_OPTIMAL",
+        }
+    }
+}
+
+enum Algorithm {
+    Tlsf(Box<TlsfBlock>),
+    Linear(LinearBlock),
+}
+
+/// Allocation of `BlockRanges`.
+struct Range {
+    size: u64,
+    kind: SuballocationType,
+    /// Range reserved in the block, larger than `size` if it was padded to pages of its own
+    reserved: Option<TlsfAllocation>,
+    upper: bool,
+}
+
+/// Ranges of a block allocated with TLSF or the linear algorithm, the metadata of a VMA block.
+///
+/// Like in VMA, a range only gets pages of `granularity` bytes of its own if it would share one
+/// with a neighbor of a conflicting `SuballocationType`.
+pub(crate) struct BlockRanges {
+    algorithm: Algorithm,
+    /// Allocations by offset
+    ranges: BTreeMap<u64, Range>,
+    allocated: u64,
+}
+
+impl BlockRanges {
+    /// Creates a block of `size` bytes, all free.
+    pub(crate) fn new(size: u64, linear: bool) -> Self {
+        BlockRanges {
+            algorithm: if linear {
+                Algorithm::Linear(LinearBlock {
+                    size,
+                    lower: BTreeMap::new(),
+                    upper: BTreeMap::new(),
+                    next: 0,
+                })
+            } else {
+                Algorithm::Tlsf(Box::new(TlsfBlock::new(size)))
+            },
+            ranges: BTreeMap::new(),
+            allocated: 0,
+        }
+    }
+
+    /// Allocates `size` bytes at an offset aligned to `alignment`, a power of two, at the upper
+    /// address if `upper` is set, which only the linear algorithm supports. Returns the offset,
+    /// or `None` if there is no room.
+    pub(crate) fn allocate(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        kind: SuballocationType,
+        granularity: u64,
+        upper: bool,
+    ) -> Option<u64> {
+        let (offset, reserved) = self.reserve(size, alignment, upper)?;
+        let (offset, reserved) = if self.conflicts(offset, size, kind, granularity) {
+            self.release(offset, reserved, upper);
+            self.reserve(
+                size.checked_next_multiple_of(granularity)?,
+                alignment.max(granularity),
+                upper,
+            )?
+        } else {
+            (offset, reserved)
+        };
+        self.ranges.insert(
+            offset,
+            Range {
+                size,
+                kind,
+                reserved,
+                upper,
+            },
+        );
+        self.allocated += size;
+        Some(offset)
+    }
+
+    /// Frees the range at `offset`, returning `false` if there is none.
+    pub(crate) fn free(&mut self, offset: u64) -> bool {
+        let Some(range) = self.ranges.remove(&offset) else {
+            return false;
+        };
+        self.allocated -= range.size;
+        self.release(offset, range.reserved, range.upper);
+        true
+    }
+
+    /// Frees all ranges.
+    pub(crate) fn clear(&mut self) {
+        *self = BlockRanges::new(self.size(), matches!(self.algorithm, Algorithm::Linear(_)));
+    }
+
+    /// Size of the block, in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        match &self.algorithm {
+            Algorithm::Tlsf(block) => block.size(),
+            Algorithm::Linear(block) => block.size,
+        }
+    }
+
+    /// Bytes allocated, without alignment and granularity padding.
+    pub(crate) fn allocated_size(&self) -> u64 {
+        self.allocated
+    }
+
+    /// Number of live allocations.
+    pub(crate) fn allocation_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Size and kind of the range at `offset`.
+    pub(crate) fn get(&self, offset: u64) -> Option<(u64, SuballocationType)> {
+        self.ranges
+            .get(&offset)
+            .map(|range| (range.size, range.kind))
+    }
+
+    /// Offsets and sizes of the allocations, in offset order.
+    pub(crate) fn allocations(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.ranges
+            .iter()
+            .map(|(&offset, range)| (offset, range.size))
+    }
+
+    /// Offsets and sizes of the free ranges between allocations, in offset order.
+    pub(crate) fn unused_ranges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let ends = std::iter::once(0).chain(self.allocations().map(|(offset, size)| offset + size));
+        let starts = self
+            .allocations()
+            .map(|(offset, _)| offset)
+            .chain([self.size()]);
+        ends.zip(starts)
+            .filter(|(end, start)| start > end)
+            .map(|(end, start)| (end, start - end))
+    }
+
+    fn reserve(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        upper: bool,
+    ) -> Option<(u64, Option<TlsfAllocation>)> {
+        match &mut self.algorithm {
+            Algorithm::Tlsf(block) if !upper => {
+                let range = block.allocate(size, alignment)?;
+                Some((range.offset, Some(range)))
+            }
+            Algorithm::Tlsf(_) => None,
+            Algorithm::Linear(block) => Some((block.allocate(size, alignment, upper)?, None)),
+        }
+    }
+
+    fn release(&mut self, offset: u64, reserved: Option<TlsfAllocation>, upper: bool) {
+        match &mut self.algorithm {
+            Algorithm::Tlsf(block) => {
+                if let Some(range) = reserved {
+                    unsafe { block.free(range) };
+                }
+            }
+            Algorithm::Linear(block) => block.free(offset, upper),
+        }
+    }
+
+    /// Returns `true` if the range at `offset` shares a page with a conflicting neighbor.
+    /// Neighbors further away can't, as they would share it with the nearest one too.
+    fn conflicts(&self, offset: u64, size: u64, kind: SuballocationType, granularity: u64) -> bool {
+        if granularity <= 1 {
+            return false;
+        }
+        let page = |offset: u64| offset / granularity;
+        let end = offset + size;
+        let prev = self.ranges.range(..offset).next_back();
+        let next = self.ranges.range(end..).next();
+        prev.is_some_and(|(&other, range)| {
+            kind.conflicts(range.kind) && page(other + range.size - 1) == page(offset)
+        }) || next.is_some_and(|(&other, range)| {
+            kind.conflicts(range.kind) && page(other) == page(end - 1)
+        })
+    }
+}
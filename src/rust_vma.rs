@@ -0,0 +1,3673 @@
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{mem, ptr, slice};
+
+use crate::assertions::assertion_failed;
+use crate::ffi::{self, VmaAllocation, VmaAllocationCreateInfo, VmaAllocator, VmaPool};
+use crate::rust_backend::{BlockRanges, SuballocationType};
+use crate::{Allocator, VmaAssertion};
+use ash::vk;
+
+use ffi::VmaAllocationCreateFlagBits as AllocationFlag;
+use ffi::VmaAllocatorCreateFlagBits as AllocatorFlag;
+use ffi::VmaMemoryUsage as Usage;
+
+const KHR_DEDICATED_ALLOCATION: u32 =
+    AllocatorFlag::VMA_ALLOCATOR_CREATE_KHR_DEDICATED_ALLOCATION_BIT as u32;
+const KHR_BIND_MEMORY2: u32 = AllocatorFlag::VMA_ALLOCATOR_CREATE_KHR_BIND_MEMORY2_BIT as u32;
+const EXT_MEMORY_BUDGET: u32 = AllocatorFlag::VMA_ALLOCATOR_CREATE_EXT_MEMORY_BUDGET_BIT as u32;
+const AMD_DEVICE_COHERENT_MEMORY: u32 =
+    AllocatorFlag::VMA_ALLOCATOR_CREATE_AMD_DEVICE_COHERENT_MEMORY_BIT as u32;
+const BUFFER_DEVICE_ADDRESS: u32 =
+    AllocatorFlag::VMA_ALLOCATOR_CREATE_BUFFER_DEVICE_ADDRESS_BIT as u32;
+const EXT_MEMORY_PRIORITY: u32 = AllocatorFlag::VMA_ALLOCATOR_CREATE_EXT_MEMORY_PRIORITY_BIT as u32;
+const KHR_MAINTENANCE4: u32 = AllocatorFlag::VMA_ALLOCATOR_CREATE_KHR_MAINTENANCE4_BIT as u32;
+const KHR_MAINTENANCE5: u32 = AllocatorFlag::VMA_ALLOCATOR_CREATE_KHR_MAINTENANCE5_BIT as u32;
+
+const DEDICATED_MEMORY: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_DEDICATED_MEMORY_BIT as u32;
+const NEVER_ALLOCATE: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_NEVER_ALLOCATE_BIT as u32;
+const MAPPED: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_MAPPED_BIT as u32;
+const USER_DATA_COPY_STRING: u32 =
+    AllocationFlag::VMA_ALLOCATION_CREATE_USER_DATA_COPY_STRING_BIT as u32;
+const UPPER_ADDRESS: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_UPPER_ADDRESS_BIT as u32;
+const DONT_BIND: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_DONT_BIND_BIT as u32;
+const WITHIN_BUDGET: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_WITHIN_BUDGET_BIT as u32;
+const CAN_ALIAS: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_CAN_ALIAS_BIT as u32;
+const HOST_ACCESS_SEQUENTIAL_WRITE: u32 =
+    AllocationFlag::VMA_ALLOCATION_CREATE_HOST_ACCESS_SEQUENTIAL_WRITE_BIT as u32;
+const HOST_ACCESS_RANDOM: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_HOST_ACCESS_RANDOM_BIT as u32;
+const HOST_ACCESS_ALLOW_TRANSFER_INSTEAD: u32 =
+    AllocationFlag::VMA_ALLOCATION_CREATE_HOST_ACCESS_ALLOW_TRANSFER_INSTEAD_BIT as u32;
+const STRATEGY_MIN_TIME: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_STRATEGY_MIN_TIME_BIT as u32;
+const STRATEGY_MASK: u32 = AllocationFlag::VMA_ALLOCATION_CREATE_STRATEGY_MASK as u32;
+
+const IGNORE_BUFFER_IMAGE_GRANULARITY: u32 =
+    ffi::VmaPoolCreateFlagBits::VMA_POOL_CREATE_IGNORE_BUFFER_IMAGE_GRANULARITY_BIT as u32;
+const POOL_LINEAR_ALGORITHM: u32 =
+    ffi::VmaPoolCreateFlagBits::VMA_POOL_CREATE_LINEAR_ALGORITHM_BIT as u32;
+const VIRTUAL_BLOCK_LINEAR_ALGORITHM: u32 =
+    ffi::VmaVirtualBlockCreateFlagBits::VMA_VIRTUAL_BLOCK_CREATE_LINEAR_ALGORITHM_BIT as u32;
+const VIRTUAL_UPPER_ADDRESS: u32 =
+    ffi::VmaVirtualAllocationCreateFlagBits::VMA_VIRTUAL_ALLOCATION_CREATE_UPPER_ADDRESS_BIT as u32;
+
+/// Number of allocations and frees after which the budget of `VK_EXT_memory_budget` is queried
+/// again, like in VMA.
+const BUDGET_FETCH_OPERATIONS: u32 = 30;
+
+/// `VMA_ASSERT`, checked in debug builds and reported to the handler of `set_assert_handler`.
+macro_rules! vma_assert {
+    ($condition:expr, $message:expr) => {
+        if cfg!(debug_assertions) && !$condition {
+            assertion_failed(&VmaAssertion {
+                expression: $message,
+                file: file!(),
+                line: line!(),
+            });
+        }
+    };
+}
+
+/// Returns the error of a `vk::Result` other than `SUCCESS`.
+macro_rules! check {
+    ($result:expr) => {
+        match $result {
+            vk::Result::SUCCESS => {}
+            error => return Err(error),
+        }
+    };
+}
+
+/// Converts a function of `vkGet*ProcAddr` to its actual type.
+unsafe fn cast<F: Copy>(function: vk::PFN_vkVoidFunction) -> Option<F> {
+    function.map(|function| mem::transmute_copy(&function))
+}
+
+/// Vulkan functions of an allocator, from `VmaVulkanFunctions` or fetched through its
+/// `vkGetInstanceProcAddr` and `vkGetDeviceProcAddr`.
+struct Functions {
+    get_physical_device_properties: vk::PFN_vkGetPhysicalDeviceProperties,
+    get_physical_device_memory_properties: vk::PFN_vkGetPhysicalDeviceMemoryProperties,
+    allocate_memory: vk::PFN_vkAllocateMemory,
+    free_memory: vk::PFN_vkFreeMemory,
+    map_memory: vk::PFN_vkMapMemory,
+    unmap_memory: vk::PFN_vkUnmapMemory,
+    flush_mapped_memory_ranges: vk::PFN_vkFlushMappedMemoryRanges,
+    invalidate_mapped_memory_ranges: vk::PFN_vkInvalidateMappedMemoryRanges,
+    bind_buffer_memory: vk::PFN_vkBindBufferMemory,
+    bind_image_memory: vk::PFN_vkBindImageMemory,
+    get_buffer_memory_requirements: vk::PFN_vkGetBufferMemoryRequirements,
+    get_image_memory_requirements: vk::PFN_vkGetImageMemoryRequirements,
+    create_buffer: vk::PFN_vkCreateBuffer,
+    destroy_buffer: vk::PFN_vkDestroyBuffer,
+    create_image: vk::PFN_vkCreateImage,
+    destroy_image: vk::PFN_vkDestroyImage,
+    get_buffer_memory_requirements2: Option<vk::PFN_vkGetBufferMemoryRequirements2>,
+    get_image_memory_requirements2: Option<vk::PFN_vkGetImageMemoryRequirements2>,
+    bind_buffer_memory2: Option<vk::PFN_vkBindBufferMemory2>,
+    bind_image_memory2: Option<vk::PFN_vkBindImageMemory2>,
+    get_physical_device_memory_properties2: Option<vk::PFN_vkGetPhysicalDeviceMemoryProperties2>,
+    get_device_buffer_memory_requirements: Option<vk::PFN_vkGetDeviceBufferMemoryRequirements>,
+    get_device_image_memory_requirements: Option<vk::PFN_vkGetDeviceImageMemoryRequirements>,
+}
+
+impl Functions {
+    /// Loads the functions like `VmaAllocator_T::ImportVulkanFunctions`, returning `None` if a
+    /// function needed for the API version and the allocator flags is missing.
+    unsafe fn load(create_info: &ffi::VmaAllocatorCreateInfo, flags: u32) -> Option<Self> {
+        // The entries of `VmaVulkanFunctions` in order, any of them may be null.
+        let table: [vk::PFN_vkVoidFunction; 26] = if create_info.pVulkanFunctions.is_null() {
+            [None; 26]
+        } else {
+            ptr::read(create_info.pVulkanFunctions.cast())
+        };
+        let get_instance_proc_addr = cast::<vk::PFN_vkGetInstanceProcAddr>(table[0]);
+        let get_device_proc_addr = cast::<vk::PFN_vkGetDeviceProcAddr>(table[1]);
+        let instance_fn = |index: usize, name: &CStr| {
+            table[index].or_else(|| {
+                get_instance_proc_addr.and_then(|get| get(create_info.instance, name.as_ptr()))
+            })
+        };
+        let device_fn = |index: usize, name: &CStr| {
+            table[index].or_else(|| {
+                get_device_proc_addr.and_then(|get| get(create_info.device, name.as_ptr()))
+            })
+        };
+        let vulkan_1_1 = create_info.vulkanApiVersion >= vk::API_VERSION_1_1;
+        let vulkan_1_3 = create_info.vulkanApiVersion >= vk::API_VERSION_1_3;
+        // Functions promoted to Vulkan 1.1 are fetched by their core name, or their KHR name with
+        // the allocator flag of the extension.
+        let promoted = |index: usize, core: &CStr, khr: &CStr, flag: u32| {
+            if vulkan_1_1 {
+                device_fn(index, core)
+            } else if flags & flag != 0 {
+                device_fn(index, khr)
+            } else {
+                table[index]
+            }
+        };
+        let maintenance4 = |index: usize, core: &CStr, khr: &CStr| {
+            if vulkan_1_3 {
+                device_fn(index, core)
+            } else if flags & KHR_MAINTENANCE4 != 0 {
+                device_fn(index, khr)
+            } else {
+                None
+            }
+        };
+        let functions = Functions {
+            get_physical_device_properties: cast(instance_fn(2, c"vkGetPhysicalDeviceProperties"))?,
+            get_physical_device_memory_properties: cast(instance_fn(
+                3,
+                c"vkGetPhysicalDeviceMemoryProperties",
+            ))?,
+            allocate_memory: cast(device_fn(4, c"vkAllocateMemory"))?,
+            free_memory: cast(device_fn(5, c"vkFreeMemory"))?,
+            map_memory: cast(device_fn(6, c"vkMapMemory"))?,
+            unmap_memory: cast(device_fn(7, c"vkUnmapMemory"))?,
+            flush_mapped_memory_ranges: cast(device_fn(8, c"vkFlushMappedMemoryRanges"))?,
+            invalidate_mapped_memory_ranges: cast(device_fn(9, c"vkInvalidateMappedMemoryRanges"))?,
+            bind_buffer_memory: cast(device_fn(10, c"vkBindBufferMemory"))?,
+            bind_image_memory: cast(device_fn(11, c"vkBindImageMemory"))?,
+            get_buffer_memory_requirements: cast(device_fn(12, c"vkGetBufferMemoryRequirements"))?,
+            get_image_memory_requirements: cast(device_fn(13, c"vkGetImageMemoryRequirements"))?,
+            create_buffer: cast(device_fn(14, c"vkCreateBuffer"))?,
+            destroy_buffer: cast(device_fn(15, c"vkDestroyBuffer"))?,
+            create_image: cast(device_fn(16, c"vkCreateImage"))?,
+            destroy_image: cast(device_fn(17, c"vkDestroyImage"))?,
+            get_buffer_memory_requirements2: cast(promoted(
+                19,
+                c"vkGetBufferMemoryRequirements2",
+                c"vkGetBufferMemoryRequirements2KHR",
+                KHR_DEDICATED_ALLOCATION,
+            )),
+            get_image_memory_requirements2: cast(promoted(
+                20,
+                c"vkGetImageMemoryRequirements2",
+                c"vkGetImageMemoryRequirements2KHR",
+                KHR_DEDICATED_ALLOCATION,
+            )),
+            bind_buffer_memory2: cast(promoted(
+                21,
+                c"vkBindBufferMemory2",
+                c"vkBindBufferMemory2KHR",
+                KHR_BIND_MEMORY2,
+            )),
+            bind_image_memory2: cast(promoted(
+                22,
+                c"vkBindImageMemory2",
+                c"vkBindImageMemory2KHR",
+                KHR_BIND_MEMORY2,
+            )),
+            get_physical_device_memory_properties2: cast(if vulkan_1_1 {
+                instance_fn(23, c"vkGetPhysicalDeviceMemoryProperties2")
+            } else if flags & EXT_MEMORY_BUDGET != 0 {
+                instance_fn(23, c"vkGetPhysicalDeviceMemoryProperties2KHR")
+            } else {
+                table[23]
+            }),
+            get_device_buffer_memory_requirements: cast(maintenance4(
+                24,
+                c"vkGetDeviceBufferMemoryRequirements",
+                c"vkGetDeviceBufferMemoryRequirementsKHR",
+            )),
+            get_device_image_memory_requirements: cast(maintenance4(
+                25,
+                c"vkGetDeviceImageMemoryRequirements",
+                c"vkGetDeviceImageMemoryRequirementsKHR",
+            )),
+        };
+        let dedicated = vulkan_1_1 || flags & KHR_DEDICATED_ALLOCATION != 0;
+        let bind_memory2 = vulkan_1_1 || flags & KHR_BIND_MEMORY2 != 0;
+        let budget = vulkan_1_1 || flags & EXT_MEMORY_BUDGET != 0;
+        let complete = (!dedicated
+            || functions.get_buffer_memory_requirements2.is_some()
+                && functions.get_image_memory_requirements2.is_some())
+            && (!bind_memory2
+                || functions.bind_buffer_memory2.is_some()
+                    && functions.bind_image_memory2.is_some())
+            && (!budget || functions.get_physical_device_memory_properties2.is_some());
+        complete.then_some(functions)
+    }
+}
+
+/// Pushes `next` to the front of the pNext chain of `info`, like `VmaPnextChainPushFront`.
+unsafe fn push_front<T>(info: &mut vk::MemoryAllocateInfo<'_>, next: &mut T) {
+    let next = (next as *mut T).cast::<vk::BaseOutStructure<'_>>();
+    (*next).p_next = info.p_next as *mut _;
+    info.p_next = next.cast();
+}
+
+/// Bytes and counts of a memory heap, VMA's `VmaCurrentBudgetData`.
+#[derive(Clone, Copy, Default)]
+struct HeapUsage {
+    block_count: u32,
+    allocation_count: u32,
+    block_bytes: u64,
+    allocation_bytes: u64,
+    /// Usage and budget of the last `VK_EXT_memory_budget` query
+    vulkan_usage: u64,
+    vulkan_budget: u64,
+    /// `block_bytes` at the last query
+    block_bytes_at_fetch: u64,
+}
+
+/// Device memory bookkeeping of an allocator.
+struct Heaps {
+    usage: [HeapUsage; vk::MAX_MEMORY_HEAPS],
+    operations_since_budget_fetch: u32,
+    device_memory_count: u32,
+}
+
+/// Block list of an allocator, see `State::list`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ListId {
+    /// Default pool of a memory type
+    Default(u32),
+    /// Custom pool, by id
+    Pool(u32),
+}
+
+/// `vk::DeviceMemory` block suballocated by a `BlockList`.
+struct Block {
+    id: u32,
+    memory: vk::DeviceMemory,
+    ranges: BlockRanges,
+    /// Allocations by offset
+    allocations: BTreeMap<u64, *mut AllocationImpl>,
+    /// Number of mappings of the whole block
+    map_count: u32,
+    mapped: *mut c_void,
+}
+
+/// Blocks and dedicated allocations of a default or custom pool, VMA's `VmaBlockVector` and
+/// `VmaDedicatedAllocationList`.
+struct BlockList {
+    memory_type: u32,
+    /// Handle of the custom pool, or null
+    #[cfg_attr(not(feature = "stats-string"), allow(dead_code))]
+    pool: *mut PoolImpl,
+    preferred_block_size: u64,
+    explicit_block_size: bool,
+    min_block_count: usize,
+    max_block_count: usize,
+    granularity: u64,
+    linear: bool,
+    priority: f32,
+    min_alignment: u64,
+    memory_allocate_next: *const c_void,
+    blocks: Vec<Block>,
+    next_block_id: u32,
+    dedicated: Vec<*mut AllocationImpl>,
+}
+
+impl BlockList {
+    fn block_mut(&mut self, id: u32) -> &mut Block {
+        self.blocks
+            .iter_mut()
+            .find(|block| block.id == id)
+            .expect("Allocation of a destroyed block")
+    }
+
+    /// Moves a block with less free space ahead of the previous one, a step towards blocks
+    /// sorted by free space like VMA's `IncrementallySortBlocks`.
+    fn sort_incrementally(&mut self) {
+        if self.linear {
+            return;
+        }
+        let free = |block: &Block| block.ranges.size() - block.ranges.allocated_size();
+        for index in 1..self.blocks.len() {
+            if free(&self.blocks[index - 1]) > free(&self.blocks[index]) {
+                self.blocks.swap(index - 1, index);
+                return;
+            }
+        }
+    }
+}
+
+struct Lists {
+    /// Default pools by memory type
+    default: Vec<BlockList>,
+    /// Custom pools by id
+    pools: BTreeMap<u32, BlockList>,
+    next_pool_id: u32,
+}
+
+impl Lists {
+    fn get_mut(&mut self, id: ListId) -> &mut BlockList {
+        match id {
+            ListId::Default(memory_type) => &mut self.default[memory_type as usize],
+            ListId::Pool(pool) => self.pools.get_mut(&pool).expect("Pool destroyed"),
+        }
+    }
+}
+
+struct State {
+    lists: Lists,
+    heaps: Heaps,
+}
+
+/// `VmaAllocator` of the `pure-rust` feature, VMA's `VmaAllocator_T`.
+struct AllocatorImpl {
+    instance: vk::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: vk::Device,
+    flags: u32,
+    api_version: u32,
+    allocation_callbacks: Option<vk::AllocationCallbacks<'static>>,
+    device_memory_callbacks: Option<ffi::VmaDeviceMemoryCallbacks>,
+    functions: Functions,
+    properties: vk::PhysicalDeviceProperties,
+    /// Memory properties with the heap sizes limited by `pHeapSizeLimit`
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    heap_size_limit_mask: u32,
+    external_memory_handle_types: [vk::ExternalMemoryHandleTypeFlags; vk::MAX_MEMORY_TYPES],
+    /// Memory types that can be used, without the AMD device coherent ones unless enabled
+    global_memory_type_bits: u32,
+    /// `preferredLargeHeapBlockSize`, or the default of the build
+    large_heap_block_size: u64,
+    granularity: u64,
+    state: Mutex<State>,
+}
+
+/// `VmaPool` of the `pure-rust` feature. Its blocks are in `Lists::pools`.
+struct PoolImpl {
+    id: u32,
+    /// Locked on its own, so that device memory callbacks can read it during allocations.
+    name: Mutex<Option<CString>>,
+}
+
+/// `VmaAllocation` of the `pure-rust` feature.
+struct AllocationImpl {
+    memory_type: u32,
+    list: ListId,
+    /// Id of the block in its list, or `None` for dedicated memory
+    block: Option<u32>,
+    memory: vk::DeviceMemory,
+    offset: u64,
+    size: u64,
+    /// Size of the block, or of the dedicated memory
+    block_size: u64,
+    #[cfg_attr(not(feature = "stats-string"), allow(dead_code))]
+    kind: SuballocationType,
+    /// Usage flags of the buffer or image, 0 if unknown
+    #[cfg_attr(not(feature = "stats-string"), allow(dead_code))]
+    usage: u64,
+    user_data: *mut c_void,
+    name: Option<CString>,
+    /// Number of `vmaMapMemory` not yet undone
+    map_count: u32,
+    /// Mapped for its whole lifetime with `VMA_ALLOCATION_CREATE_MAPPED_BIT`
+    persistent: bool,
+    /// Mapping of dedicated memory
+    mapped: *mut c_void,
+}
+
+/// Buffer or image an allocation is made for, see `AllocatorImpl::allocate`.
+struct Resource {
+    requirements: vk::MemoryRequirements,
+    requires_dedicated: bool,
+    prefers_dedicated: bool,
+    buffer: vk::Buffer,
+    image: vk::Image,
+    /// Usage flags of the buffer or image, if known
+    usage: Option<u64>,
+    kind: SuballocationType,
+}
+
+impl Resource {
+    fn memory(requirements: vk::MemoryRequirements) -> Self {
+        Resource {
+            requirements,
+            requires_dedicated: false,
+            prefers_dedicated: false,
+            buffer: vk::Buffer::null(),
+            image: vk::Image::null(),
+            usage: None,
+            kind: SuballocationType::Unknown,
+        }
+    }
+}
+
+/// `VmaAllocationCreateInfo` after `VmaAllocator_T::CalcAllocationParams`.
+#[derive(Clone, Copy)]
+struct AllocationParams {
+    flags: u32,
+    priority: f32,
+    user_data: *mut c_void,
+}
+
+/// Usage flags of a buffer, from `vk::BufferUsageFlags2CreateInfoKHR` with
+/// `VK_KHR_maintenance5`, like `VmaBufferImageUsage`.
+unsafe fn buffer_usage(create_info: &vk::BufferCreateInfo<'_>, maintenance5: bool) -> u64 {
+    if maintenance5 {
+        let mut next = create_info.p_next.cast::<vk::BaseInStructure<'_>>();
+        while !next.is_null() {
+            if (*next).s_type == vk::StructureType::BUFFER_USAGE_FLAGS_2_CREATE_INFO_KHR {
+                return (*next.cast::<vk::BufferUsageFlags2CreateInfoKHR<'_>>())
+                    .usage
+                    .as_raw();
+            }
+            next = (*next).p_next;
+        }
+    }
+    create_info.usage.as_raw() as u64
+}
+
+/// Returns `true` if a buffer or image with these usage flags is accessed by the device, and
+/// not only copied.
+fn device_access(usage: u64) -> bool {
+    let transfer = vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+    usage & !(transfer.as_raw() as u64) != 0
+}
+
+/// Required, preferred and not preferred flags of a memory type for an allocation, VMA's
+/// `FindMemoryPreferences`, or `None` if the usage needs a known buffer or image.
+fn memory_preferences(
+    integrated_gpu: bool,
+    create_info: &VmaAllocationCreateInfo,
+    usage: Option<u64>,
+) -> Option<(
+    vk::MemoryPropertyFlags,
+    vk::MemoryPropertyFlags,
+    vk::MemoryPropertyFlags,
+)> {
+    use vk::MemoryPropertyFlags as Flags;
+    let mut required = create_info.requiredFlags;
+    let mut preferred = create_info.preferredFlags;
+    let mut not_preferred = Flags::empty();
+    match create_info.usage {
+        Usage::VMA_MEMORY_USAGE_GPU_ONLY
+            if !integrated_gpu || !preferred.contains(Flags::HOST_VISIBLE) =>
+        {
+            preferred |= Flags::DEVICE_LOCAL;
+        }
+        Usage::VMA_MEMORY_USAGE_CPU_ONLY => {
+            required |= Flags::HOST_VISIBLE | Flags::HOST_COHERENT;
+        }
+        Usage::VMA_MEMORY_USAGE_CPU_TO_GPU => {
+            required |= Flags::HOST_VISIBLE;
+            if !integrated_gpu || !preferred.contains(Flags::HOST_VISIBLE) {
+                preferred |= Flags::DEVICE_LOCAL;
+            }
+        }
+        Usage::VMA_MEMORY_USAGE_GPU_TO_CPU => {
+            required |= Flags::HOST_VISIBLE;
+            preferred |= Flags::HOST_CACHED;
+        }
+        Usage::VMA_MEMORY_USAGE_CPU_COPY => not_preferred |= Flags::DEVICE_LOCAL,
+        Usage::VMA_MEMORY_USAGE_GPU_LAZILY_ALLOCATED => required |= Flags::LAZILY_ALLOCATED,
+        Usage::VMA_MEMORY_USAGE_AUTO
+        | Usage::VMA_MEMORY_USAGE_AUTO_PREFER_DEVICE
+        | Usage::VMA_MEMORY_USAGE_AUTO_PREFER_HOST => {
+            let Some(usage) = usage else {
+                vma_assert!(
+                    false,
+                    "VMA_MEMORY_USAGE_AUTO* values can only be used with functions like \
+                     vmaCreateBuffer, vmaCreateImage so that the details of the created \
+                     resource are known."
+                );
+                return None;
+            };
+            let device_access = device_access(usage);
+            let sequential_write = create_info.flags & HOST_ACCESS_SEQUENTIAL_WRITE != 0;
+            let random = create_info.flags & HOST_ACCESS_RANDOM != 0;
+            let transfer_instead = create_info.flags & HOST_ACCESS_ALLOW_TRANSFER_INSTEAD != 0;
+            let prefer_device = create_info.usage == Usage::VMA_MEMORY_USAGE_AUTO_PREFER_DEVICE;
+            let prefer_host = create_info.usage == Usage::VMA_MEMORY_USAGE_AUTO_PREFER_HOST;
+            if random {
+                // Cached is only preferred, some platforms don't have it.
+                preferred |= Flags::HOST_CACHED;
+                if !integrated_gpu && device_access && transfer_instead && !prefer_host {
+                    preferred |= Flags::DEVICE_LOCAL;
+                } else {
+                    required |= Flags::HOST_VISIBLE;
+                }
+            } else if sequential_write {
+                // Uncached and write-combined.
+                not_preferred |= Flags::HOST_CACHED;
+                if !integrated_gpu && device_access && transfer_instead && !prefer_host {
+                    preferred |= Flags::DEVICE_LOCAL | Flags::HOST_VISIBLE;
+                } else {
+                    required |= Flags::HOST_VISIBLE;
+                    if device_access != prefer_host && (device_access || prefer_device) {
+                        preferred |= Flags::DEVICE_LOCAL;
+                    } else {
+                        not_preferred |= Flags::DEVICE_LOCAL;
+                    }
+                }
+            } else if prefer_host {
+                not_preferred |= Flags::DEVICE_LOCAL;
+            } else {
+                preferred |= Flags::DEVICE_LOCAL;
+            }
+        }
+        _ => {}
+    }
+    // Device coherent memory is avoided unless requested.
+    let amd = Flags::DEVICE_COHERENT_AMD | Flags::DEVICE_UNCACHED_AMD;
+    if !(create_info.requiredFlags | create_info.preferredFlags).intersects(amd) {
+        not_preferred |= Flags::DEVICE_UNCACHED_AMD;
+    }
+    Some((required, preferred, not_preferred))
+}
+
+/// Statistics with no block nor allocation.
+fn empty_statistics() -> ffi::VmaDetailedStatistics {
+    ffi::VmaDetailedStatistics {
+        statistics: ffi::VmaStatistics {
+            blockCount: 0,
+            allocationCount: 0,
+            blockBytes: 0,
+            allocationBytes: 0,
+        },
+        unusedRangeCount: 0,
+        allocationSizeMin: vk::WHOLE_SIZE,
+        allocationSizeMax: 0,
+        unusedRangeSizeMin: vk::WHOLE_SIZE,
+        unusedRangeSizeMax: 0,
+    }
+}
+
+fn add_allocation(stats: &mut ffi::VmaDetailedStatistics, size: u64) {
+    stats.statistics.allocationCount += 1;
+    stats.statistics.allocationBytes += size;
+    stats.allocationSizeMin = stats.allocationSizeMin.min(size);
+    stats.allocationSizeMax = stats.allocationSizeMax.max(size);
+}
+
+fn add_unused_range(stats: &mut ffi::VmaDetailedStatistics, size: u64) {
+    stats.unusedRangeCount += 1;
+    stats.unusedRangeSizeMin = stats.unusedRangeSizeMin.min(size);
+    stats.unusedRangeSizeMax = stats.unusedRangeSizeMax.max(size);
+}
+
+fn add_statistics(stats: &mut ffi::VmaDetailedStatistics, other: &ffi::VmaDetailedStatistics) {
+    stats.statistics.blockCount += other.statistics.blockCount;
+    stats.statistics.allocationCount += other.statistics.allocationCount;
+    stats.statistics.blockBytes += other.statistics.blockBytes;
+    stats.statistics.allocationBytes += other.statistics.allocationBytes;
+    stats.unusedRangeCount += other.unusedRangeCount;
+    stats.allocationSizeMin = stats.allocationSizeMin.min(other.allocationSizeMin);
+    stats.allocationSizeMax = stats.allocationSizeMax.max(other.allocationSizeMax);
+    stats.unusedRangeSizeMin = stats.unusedRangeSizeMin.min(other.unusedRangeSizeMin);
+    stats.unusedRangeSizeMax = stats.unusedRangeSizeMax.max(other.unusedRangeSizeMax);
+}
+
+/// Adds the blocks, allocations and free ranges of a block.
+fn add_block(stats: &mut ffi::VmaDetailedStatistics, ranges: &BlockRanges) {
+    stats.statistics.blockCount += 1;
+    stats.statistics.blockBytes += ranges.size();
+    for (_, size) in ranges.allocations() {
+        add_allocation(stats, size);
+    }
+    for (_, size) in ranges.unused_ranges() {
+        add_unused_range(stats, size);
+    }
+}
+
+/// Adds the blocks and allocations of a block list.
+unsafe fn add_list(stats: &mut ffi::VmaDetailedStatistics, list: &BlockList) {
+    for block in &list.blocks {
+        add_block(stats, &block.ranges);
+    }
+    for &allocation in &list.dedicated {
+        let size = (*allocation).size;
+        add_allocation(stats, size);
+        stats.statistics.blockCount += 1;
+        stats.statistics.blockBytes += size;
+    }
+}
+
+/// Logs an allocation still alive when its block is destroyed, like VMA's
+/// `VMA_LEAK_LOG_FORMAT`.
+#[cfg_attr(not(feature = "log"), allow(unused_variables))]
+fn log_leak(offset: u64, size: u64, user_data: *mut c_void, name: Option<&CStr>) {
+    #[cfg(feature = "log")]
+    log::warn!(
+        target: "vk_mem",
+        "UNFREED ALLOCATION; Offset: {}; Size: {}; UserData: {:p}; Name: {}",
+        offset,
+        size,
+        user_data,
+        name.map_or("".into(), CStr::to_string_lossy),
+    );
+}
+
+impl AllocatorImpl {
+    /// Creates an allocator like `VmaAllocator_T::VmaAllocator_T` and `Init`.
+    unsafe fn new(create_info: &ffi::VmaAllocatorCreateInfo) -> Result<Box<Self>, vk::Result> {
+        let api_version = create_info.vulkanApiVersion.max(vk::API_VERSION_1_0);
+        let mut flags = create_info.flags;
+        if api_version >= vk::API_VERSION_1_1 {
+            // Promoted to core
+            flags &= !(KHR_DEDICATED_ALLOCATION | KHR_BIND_MEMORY2);
+        }
+        let functions =
+            Functions::load(create_info, flags).ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+        let mut properties = mem::zeroed();
+        (functions.get_physical_device_properties)(create_info.physicalDevice, &mut properties);
+        let mut memory_properties = mem::zeroed();
+        (functions.get_physical_device_memory_properties)(
+            create_info.physicalDevice,
+            &mut memory_properties,
+        );
+        let config = Allocator::build_config();
+
+        let mut heap_size_limit_mask = 0;
+        if !create_info.pHeapSizeLimit.is_null() {
+            let limits = slice::from_raw_parts(
+                create_info.pHeapSizeLimit,
+                memory_properties.memory_heap_count as usize,
+            );
+            for (heap, &limit) in limits.iter().enumerate() {
+                if limit != vk::WHOLE_SIZE {
+                    heap_size_limit_mask |= 1 << heap;
+                    let size = &mut memory_properties.memory_heaps[heap].size;
+                    *size = (*size).min(limit);
+                }
+            }
+        }
+        let mut external_memory_handle_types =
+            [vk::ExternalMemoryHandleTypeFlags::empty(); vk::MAX_MEMORY_TYPES];
+        if !create_info.pTypeExternalMemoryHandleTypes.is_null() {
+            let count = memory_properties.memory_type_count as usize;
+            external_memory_handle_types[..count].copy_from_slice(slice::from_raw_parts(
+                create_info.pTypeExternalMemoryHandleTypes,
+                count,
+            ));
+        }
+        let mut global_memory_type_bits = u32::MAX;
+        if flags & AMD_DEVICE_COHERENT_MEMORY == 0 {
+            for index in 0..memory_properties.memory_type_count as usize {
+                if memory_properties.memory_types[index]
+                    .property_flags
+                    .contains(vk::MemoryPropertyFlags::DEVICE_COHERENT_AMD)
+                {
+                    global_memory_type_bits &= !(1 << index);
+                }
+            }
+        }
+        let large_heap_block_size = match create_info.preferredLargeHeapBlockSize {
+            0 => config.large_heap_block_size,
+            size => size,
+        };
+
+        let mut allocator = Box::new(AllocatorImpl {
+            instance: create_info.instance,
+            physical_device: create_info.physicalDevice,
+            device: create_info.device,
+            flags,
+            api_version,
+            allocation_callbacks: create_info.pAllocationCallbacks.as_ref().copied(),
+            device_memory_callbacks: create_info.pDeviceMemoryCallbacks.as_ref().copied(),
+            functions,
+            properties,
+            memory_properties,
+            heap_size_limit_mask,
+            external_memory_handle_types,
+            global_memory_type_bits,
+            large_heap_block_size,
+            granularity: properties
+                .limits
+                .buffer_image_granularity
+                .max(config.min_buffer_image_granularity),
+            state: Mutex::new(State {
+                lists: Lists {
+                    default: Vec::new(),
+                    pools: BTreeMap::new(),
+                    next_pool_id: 1,
+                },
+                heaps: Heaps {
+                    usage: [HeapUsage::default(); vk::MAX_MEMORY_HEAPS],
+                    operations_since_budget_fetch: 0,
+                    device_memory_count: 0,
+                },
+            }),
+        });
+        let default = (0..memory_properties.memory_type_count)
+            .map(|memory_type| BlockList {
+                memory_type,
+                pool: ptr::null_mut(),
+                preferred_block_size: allocator.preferred_block_size(memory_type),
+                explicit_block_size: false,
+                min_block_count: 0,
+                max_block_count: usize::MAX,
+                granularity: allocator.granularity,
+                linear: false,
+                priority: 0.5,
+                min_alignment: allocator.min_alignment(memory_type),
+                memory_allocate_next: ptr::null(),
+                blocks: Vec::new(),
+                next_block_id: 0,
+                dedicated: Vec::new(),
+            })
+            .collect();
+        let state = allocator.state.get_mut().unwrap();
+        state.lists.default = default;
+        if allocator.uses_memory_budget() {
+            let mut state = allocator.lock();
+            allocator.update_budget(&mut state.heaps);
+        }
+        Ok(allocator)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn handle(&self) -> VmaAllocator {
+        self as *const AllocatorImpl as VmaAllocator
+    }
+
+    fn callbacks(&self) -> *const vk::AllocationCallbacks<'static> {
+        self.allocation_callbacks
+            .as_ref()
+            .map_or(ptr::null(), |callbacks| callbacks)
+    }
+
+    fn dedicated_requirements(&self) -> bool {
+        self.api_version >= vk::API_VERSION_1_1 || self.flags & KHR_DEDICATED_ALLOCATION != 0
+    }
+
+    fn bind_memory2(&self) -> bool {
+        self.api_version >= vk::API_VERSION_1_1 || self.flags & KHR_BIND_MEMORY2 != 0
+    }
+
+    fn uses_memory_budget(&self) -> bool {
+        self.flags & EXT_MEMORY_BUDGET != 0
+            && self
+                .functions
+                .get_physical_device_memory_properties2
+                .is_some()
+    }
+
+    fn memory_type_flags(&self, memory_type: u32) -> vk::MemoryPropertyFlags {
+        self.memory_properties.memory_types[memory_type as usize].property_flags
+    }
+
+    fn heap_index(&self, memory_type: u32) -> usize {
+        self.memory_properties.memory_types[memory_type as usize].heap_index as usize
+    }
+
+    fn non_coherent(&self, memory_type: u32) -> bool {
+        let flags = self.memory_type_flags(memory_type)
+            & (vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT);
+        flags == vk::MemoryPropertyFlags::HOST_VISIBLE
+    }
+
+    /// `VmaAllocator_T::GetMemoryTypeMinAlignment`.
+    fn min_alignment(&self, memory_type: u32) -> u64 {
+        let min_alignment = Allocator::build_config().min_alignment;
+        if self.non_coherent(memory_type) {
+            min_alignment.max(self.properties.limits.non_coherent_atom_size)
+        } else {
+            min_alignment
+        }
+    }
+
+    /// `VmaAllocator_T::CalcPreferredBlockSize`, an eighth of small heaps.
+    fn preferred_block_size(&self, memory_type: u32) -> u64 {
+        let heap_size = self.memory_properties.memory_heaps[self.heap_index(memory_type)].size;
+        let size = if heap_size <= Allocator::build_config().small_heap_max_size {
+            heap_size / 8
+        } else {
+            self.large_heap_block_size
+        };
+        size.next_multiple_of(32)
+    }
+
+    /// `VmaAllocator_T::FindMemoryTypeIndex`, the memory type with the required flags and the
+    /// fewest preferences missed.
+    fn find_memory_type_index(
+        &self,
+        mut memory_type_bits: u32,
+        create_info: &VmaAllocationCreateInfo,
+        usage: Option<u64>,
+    ) -> Result<u32, vk::Result> {
+        memory_type_bits &= self.global_memory_type_bits;
+        if create_info.memoryTypeBits != 0 {
+            memory_type_bits &= create_info.memoryTypeBits;
+        }
+        let integrated_gpu = self.properties.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU;
+        let (required, preferred, not_preferred) =
+            memory_preferences(integrated_gpu, create_info, usage)
+                .ok_or(vk::Result::ERROR_FEATURE_NOT_PRESENT)?;
+        let mut best = None;
+        for memory_type in 0..self.memory_properties.memory_type_count {
+            let flags = self.memory_type_flags(memory_type);
+            if memory_type_bits & (1 << memory_type) == 0 || !flags.contains(required) {
+                continue;
+            }
+            let cost = (preferred & !flags).as_raw().count_ones()
+                + (flags & not_preferred).as_raw().count_ones();
+            if best.is_none_or(|(_, best_cost)| cost < best_cost) {
+                if cost == 0 {
+                    return Ok(memory_type);
+                }
+                best = Some((memory_type, cost));
+            }
+        }
+        best.map(|(memory_type, _)| memory_type)
+            .ok_or(vk::Result::ERROR_FEATURE_NOT_PRESENT)
+    }
+
+    /// Queries the budget of `VK_EXT_memory_budget`, like `VmaAllocator_T::UpdateVulkanBudget`.
+    unsafe fn update_budget(&self, heaps: &mut Heaps) {
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget);
+        let Some(get_properties) = self.functions.get_physical_device_memory_properties2 else {
+            return;
+        };
+        get_properties(self.physical_device, &mut properties);
+        for heap in 0..self.memory_properties.memory_heap_count as usize {
+            let heap_size = self.memory_properties.memory_heaps[heap].size;
+            let usage = &mut heaps.usage[heap];
+            usage.vulkan_usage = budget.heap_usage[heap];
+            usage.vulkan_budget = budget.heap_budget[heap];
+            usage.block_bytes_at_fetch = usage.block_bytes;
+            // Some drivers report a budget of 0 or beyond the heap size.
+            if usage.vulkan_budget == 0 {
+                usage.vulkan_budget = heap_size * 8 / 10;
+            } else if usage.vulkan_budget > heap_size {
+                usage.vulkan_budget = heap_size;
+            }
+            if usage.vulkan_usage == 0 && usage.block_bytes_at_fetch > 0 {
+                usage.vulkan_usage = usage.block_bytes_at_fetch;
+            }
+        }
+        heaps.operations_since_budget_fetch = 0;
+    }
+
+    /// Usage and budget of a heap, like `VmaAllocator_T::GetHeapBudgets`. Without
+    /// `VK_EXT_memory_budget`, the usage is the memory of this allocator and the budget 80% of
+    /// the heap.
+    unsafe fn heap_budget(&self, heaps: &mut Heaps, heap: usize) -> ffi::VmaBudget {
+        let heap_size = self.memory_properties.memory_heaps[heap].size;
+        let (usage, budget) = if self.uses_memory_budget() {
+            if heaps.operations_since_budget_fetch >= BUDGET_FETCH_OPERATIONS {
+                self.update_budget(heaps);
+            }
+            let usage = &heaps.usage[heap];
+            (
+                (usage.vulkan_usage + usage.block_bytes).saturating_sub(usage.block_bytes_at_fetch),
+                usage.vulkan_budget.min(heap_size),
+            )
+        } else {
+            (heaps.usage[heap].block_bytes, heap_size * 8 / 10)
+        };
+        let counters = &heaps.usage[heap];
+        ffi::VmaBudget {
+            statistics: ffi::VmaStatistics {
+                blockCount: counters.block_count,
+                allocationCount: counters.allocation_count,
+                blockBytes: counters.block_bytes,
+                allocationBytes: counters.allocation_bytes,
+            },
+            usage,
+            budget,
+        }
+    }
+
+    /// `vkAllocateMemory` within the heap size limit, reported to the device memory callbacks.
+    unsafe fn allocate_device_memory(
+        &self,
+        heaps: &mut Heaps,
+        info: &vk::MemoryAllocateInfo<'_>,
+    ) -> Result<vk::DeviceMemory, vk::Result> {
+        let heap = self.heap_index(info.memory_type_index);
+        if self.heap_size_limit_mask & (1 << heap) != 0
+            && heaps.usage[heap].block_bytes + info.allocation_size
+                > self.memory_properties.memory_heaps[heap].size
+        {
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        let mut memory = vk::DeviceMemory::null();
+        check!((self.functions.allocate_memory)(
+            self.device,
+            info,
+            self.callbacks(),
+            &mut memory
+        ));
+        heaps.usage[heap].block_count += 1;
+        heaps.usage[heap].block_bytes += info.allocation_size;
+        heaps.operations_since_budget_fetch += 1;
+        heaps.device_memory_count += 1;
+        if let Some(callbacks) = &self.device_memory_callbacks {
+            if let Some(allocate) = callbacks.pfnAllocate {
+                allocate(
+                    self.handle(),
+                    info.memory_type_index,
+                    memory,
+                    info.allocation_size,
+                    callbacks.pUserData,
+                );
+            }
+        }
+        Ok(memory)
+    }
+
+    unsafe fn free_device_memory(
+        &self,
+        heaps: &mut Heaps,
+        memory_type: u32,
+        size: u64,
+        memory: vk::DeviceMemory,
+    ) {
+        if let Some(callbacks) = &self.device_memory_callbacks {
+            if let Some(free) = callbacks.pfnFree {
+                free(
+                    self.handle(),
+                    memory_type,
+                    memory,
+                    size,
+                    callbacks.pUserData,
+                );
+            }
+        }
+        (self.functions.free_memory)(self.device, memory, self.callbacks());
+        let heap = self.heap_index(memory_type);
+        heaps.usage[heap].block_count -= 1;
+        heaps.usage[heap].block_bytes -= size;
+        heaps.device_memory_count -= 1;
+    }
+
+    unsafe fn map_block(&self, block: &mut Block, count: u32) -> Result<*mut c_void, vk::Result> {
+        if block.map_count == 0 {
+            check!((self.functions.map_memory)(
+                self.device,
+                block.memory,
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty(),
+                &mut block.mapped
+            ));
+        }
+        block.map_count += count;
+        Ok(block.mapped)
+    }
+
+    unsafe fn unmap_block(&self, block: &mut Block, count: u32) {
+        if block.map_count < count {
+            vma_assert!(
+                false,
+                "VkDeviceMemory block is being unmapped while it was not previously mapped."
+            );
+            return;
+        }
+        block.map_count -= count;
+        if block.map_count == 0 {
+            block.mapped = ptr::null_mut();
+            (self.functions.unmap_memory)(self.device, block.memory);
+        }
+    }
+
+    /// Returns the memory requirements of a buffer and whether it needs or prefers dedicated
+    /// memory.
+    unsafe fn buffer_requirements(
+        &self,
+        buffer: vk::Buffer,
+    ) -> (vk::MemoryRequirements, bool, bool) {
+        match self.functions.get_buffer_memory_requirements2 {
+            Some(get_requirements) if self.dedicated_requirements() => {
+                let info = vk::BufferMemoryRequirementsInfo2::default().buffer(buffer);
+                let mut dedicated = vk::MemoryDedicatedRequirements::default();
+                let mut requirements = vk::MemoryRequirements2::default().push_next(&mut dedicated);
+                get_requirements(self.device, &info, &mut requirements);
+                let requirements = requirements.memory_requirements;
+                (
+                    requirements,
+                    dedicated.requires_dedicated_allocation != vk::FALSE,
+                    dedicated.prefers_dedicated_allocation != vk::FALSE,
+                )
+            }
+            _ => {
+                let mut requirements = mem::zeroed();
+                (self.functions.get_buffer_memory_requirements)(
+                    self.device,
+                    buffer,
+                    &mut requirements,
+                );
+                (requirements, false, false)
+            }
+        }
+    }
+
+    unsafe fn image_requirements(&self, image: vk::Image) -> (vk::MemoryRequirements, bool, bool) {
+        match self.functions.get_image_memory_requirements2 {
+            Some(get_requirements) if self.dedicated_requirements() => {
+                let info = vk::ImageMemoryRequirementsInfo2::default().image(image);
+                let mut dedicated = vk::MemoryDedicatedRequirements::default();
+                let mut requirements = vk::MemoryRequirements2::default().push_next(&mut dedicated);
+                get_requirements(self.device, &info, &mut requirements);
+                let requirements = requirements.memory_requirements;
+                (
+                    requirements,
+                    dedicated.requires_dedicated_allocation != vk::FALSE,
+                    dedicated.prefers_dedicated_allocation != vk::FALSE,
+                )
+            }
+            _ => {
+                let mut requirements = mem::zeroed();
+                (self.functions.get_image_memory_requirements)(
+                    self.device,
+                    image,
+                    &mut requirements,
+                );
+                (requirements, false, false)
+            }
+        }
+    }
+
+    /// `VmaAllocator_T::CalcAllocationParams`, checking the flags of an allocation.
+    unsafe fn allocation_params(
+        &self,
+        state: &mut State,
+        create_info: &VmaAllocationCreateInfo,
+        requires_dedicated: bool,
+    ) -> Result<AllocationParams, vk::Result> {
+        let mut flags = create_info.flags;
+        let mut priority = create_info.priority;
+        vma_assert!(
+            flags & (DEDICATED_MEMORY | NEVER_ALLOCATE) != DEDICATED_MEMORY | NEVER_ALLOCATE,
+            "Specifying both flags is contradictory."
+        );
+        vma_assert!(
+            flags & (HOST_ACCESS_SEQUENTIAL_WRITE | HOST_ACCESS_RANDOM)
+                != HOST_ACCESS_SEQUENTIAL_WRITE | HOST_ACCESS_RANDOM,
+            "Specifying both flags is contradictory."
+        );
+        let auto = matches!(
+            create_info.usage,
+            Usage::VMA_MEMORY_USAGE_AUTO
+                | Usage::VMA_MEMORY_USAGE_AUTO_PREFER_DEVICE
+                | Usage::VMA_MEMORY_USAGE_AUTO_PREFER_HOST
+        );
+        if auto && flags & MAPPED != 0 {
+            vma_assert!(
+                flags & (HOST_ACCESS_SEQUENTIAL_WRITE | HOST_ACCESS_RANDOM) != 0,
+                "When using VMA_ALLOCATION_CREATE_MAPPED_BIT and usage = VMA_MEMORY_USAGE_AUTO*, \
+                 you must also specify VMA_ALLOCATION_CREATE_HOST_ACCESS_SEQUENTIAL_WRITE_BIT or \
+                 VMA_ALLOCATION_CREATE_HOST_ACCESS_RANDOM_BIT."
+            );
+        }
+        // Lazily allocated memory is always dedicated.
+        if requires_dedicated || create_info.usage == Usage::VMA_MEMORY_USAGE_GPU_LAZILY_ALLOCATED {
+            flags |= DEDICATED_MEMORY;
+        }
+        if !create_info.pool.is_null() {
+            let pool = &*(create_info.pool as *const PoolImpl);
+            let list = state.lists.get_mut(ListId::Pool(pool.id));
+            if list.explicit_block_size && flags & DEDICATED_MEMORY != 0 {
+                vma_assert!(
+                    false,
+                    "Specifying VMA_ALLOCATION_CREATE_DEDICATED_MEMORY_BIT while current custom \
+                     pool doesn't support dedicated allocations."
+                );
+                return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+            }
+            priority = list.priority;
+        }
+        if flags & DEDICATED_MEMORY != 0 && flags & NEVER_ALLOCATE != 0 {
+            vma_assert!(
+                false,
+                "Specifying VMA_ALLOCATION_CREATE_DEDICATED_MEMORY_BIT together with \
+                 VMA_ALLOCATION_CREATE_NEVER_ALLOCATE_BIT makes no sense."
+            );
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        if Allocator::build_config().always_dedicated_memory && flags & NEVER_ALLOCATE == 0 {
+            flags |= DEDICATED_MEMORY;
+        }
+        // Non-auto usages allow any host access.
+        if !auto && flags & (HOST_ACCESS_SEQUENTIAL_WRITE | HOST_ACCESS_RANDOM) == 0 {
+            flags |= HOST_ACCESS_RANDOM;
+        }
+        Ok(AllocationParams {
+            flags,
+            priority,
+            user_data: create_info.pUserData,
+        })
+    }
+
+    /// `VmaAllocator_T::AllocateMemory`, filling `allocations` or failing without allocating.
+    unsafe fn allocate(
+        &self,
+        resource: &Resource,
+        create_info: &VmaAllocationCreateInfo,
+        allocations: &mut [VmaAllocation],
+    ) -> vk::Result {
+        allocations.fill(ptr::null_mut());
+        vma_assert!(
+            resource.requirements.alignment.is_power_of_two(),
+            "VmaIsPow2(vkMemReq.alignment)"
+        );
+        if resource.requirements.size == 0 {
+            return vk::Result::ERROR_INITIALIZATION_FAILED;
+        }
+        let mut state = self.lock();
+        let params =
+            match self.allocation_params(&mut state, create_info, resource.requires_dedicated) {
+                Ok(params) => params,
+                Err(error) => return error,
+            };
+        if !create_info.pool.is_null() {
+            let pool = &*(create_info.pool as *const PoolImpl);
+            return self.allocate_of_type(
+                &mut state,
+                ListId::Pool(pool.id),
+                resource,
+                params,
+                allocations,
+            );
+        }
+        let mut memory_type_bits = resource.requirements.memory_type_bits;
+        let mut memory_type =
+            match self.find_memory_type_index(memory_type_bits, create_info, resource.usage) {
+                Ok(memory_type) => memory_type,
+                Err(error) => return error,
+            };
+        loop {
+            let result = self.allocate_of_type(
+                &mut state,
+                ListId::Default(memory_type),
+                resource,
+                params,
+                allocations,
+            );
+            if result == vk::Result::SUCCESS {
+                return result;
+            }
+            // Falls back to the next best memory type.
+            memory_type_bits &= !(1 << memory_type);
+            match self.find_memory_type_index(memory_type_bits, create_info, resource.usage) {
+                Ok(next) => memory_type = next,
+                Err(_) => return vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+            }
+        }
+    }
+
+    /// `VmaAllocator_T::AllocateMemoryOfType`, from the blocks of a list or dedicated memory.
+    unsafe fn allocate_of_type(
+        &self,
+        state: &mut State,
+        list_id: ListId,
+        resource: &Resource,
+        mut params: AllocationParams,
+        allocations: &mut [VmaAllocation],
+    ) -> vk::Result {
+        let State { lists, heaps } = state;
+        let list = lists.get_mut(list_id);
+        let memory_type = list.memory_type;
+        let size = resource.requirements.size;
+        if params.flags & MAPPED != 0
+            && !self
+                .memory_type_flags(memory_type)
+                .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            params.flags &= !MAPPED;
+        }
+        if params.flags & DEDICATED_MEMORY != 0 && params.flags & WITHIN_BUDGET != 0 {
+            let budget = self.heap_budget(heaps, self.heap_index(memory_type));
+            if budget.statistics.blockBytes.max(budget.usage) + size * allocations.len() as u64
+                > budget.budget
+            {
+                return vk::Result::ERROR_OUT_OF_DEVICE_MEMORY;
+            }
+        }
+        if params.flags & DEDICATED_MEMORY != 0 {
+            return self.allocate_dedicated(heaps, list, list_id, resource, params, allocations);
+        }
+
+        let can_allocate_dedicated = params.flags & NEVER_ALLOCATE == 0
+            && (matches!(list_id, ListId::Default(_)) || !list.explicit_block_size);
+        let mut prefers_dedicated = resource.prefers_dedicated;
+        if can_allocate_dedicated {
+            if size > list.preferred_block_size / 2 {
+                prefers_dedicated = true;
+            }
+            // Dedicated memory isn't preferred close to the limit of allocations, which it
+            // would quickly deplete.
+            let max_count = self.properties.limits.max_memory_allocation_count;
+            if max_count < u32::MAX / 4 && heaps.device_memory_count > max_count * 3 / 4 {
+                prefers_dedicated = false;
+            }
+            if prefers_dedicated
+                && self.allocate_dedicated(heaps, list, list_id, resource, params, allocations)
+                    == vk::Result::SUCCESS
+            {
+                return vk::Result::SUCCESS;
+            }
+        }
+        let result = self.allocate_from_blocks(heaps, list, list_id, resource, params, allocations);
+        if result == vk::Result::SUCCESS || !can_allocate_dedicated || prefers_dedicated {
+            return result;
+        }
+        self.allocate_dedicated(heaps, list, list_id, resource, params, allocations)
+    }
+
+    /// `VmaAllocator_T::AllocateDedicatedMemory`, one `vk::DeviceMemory` per allocation.
+    unsafe fn allocate_dedicated(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        list_id: ListId,
+        resource: &Resource,
+        params: AllocationParams,
+        allocations: &mut [VmaAllocation],
+    ) -> vk::Result {
+        for index in 0..allocations.len() {
+            match self.allocate_dedicated_page(heaps, list, list_id, resource, params) {
+                Ok(allocation) => allocations[index] = allocation.cast(),
+                Err(error) => {
+                    for allocation in &mut allocations[..index] {
+                        self.free_dedicated(heaps, list, allocation.cast());
+                        *allocation = ptr::null_mut();
+                    }
+                    return error;
+                }
+            }
+        }
+        vk::Result::SUCCESS
+    }
+
+    unsafe fn allocate_dedicated_page(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        list_id: ListId,
+        resource: &Resource,
+        params: AllocationParams,
+    ) -> Result<*mut AllocationImpl, vk::Result> {
+        let memory_type = list.memory_type;
+        let size = resource.requirements.size;
+        let mut info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type);
+        info.p_next = list.memory_allocate_next;
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default();
+        if params.flags & CAN_ALIAS == 0 && self.dedicated_requirements() {
+            if resource.buffer != vk::Buffer::null() {
+                dedicated_info.buffer = resource.buffer;
+                push_front(&mut info, &mut dedicated_info);
+            } else if resource.image != vk::Image::null() {
+                dedicated_info.image = resource.image;
+                push_front(&mut info, &mut dedicated_info);
+            }
+        }
+        let mut flags_info = vk::MemoryAllocateFlagsInfo::default();
+        if self.flags & BUFFER_DEVICE_ADDRESS != 0 {
+            let can_contain_address = if resource.buffer != vk::Buffer::null() {
+                resource.usage.is_none_or(|usage| {
+                    usage & vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS.as_raw() as u64 != 0
+                })
+            } else {
+                resource.image == vk::Image::null()
+            };
+            if can_contain_address {
+                flags_info.flags = vk::MemoryAllocateFlags::DEVICE_ADDRESS;
+                push_front(&mut info, &mut flags_info);
+            }
+        }
+        let mut priority_info = vk::MemoryPriorityAllocateInfoEXT::default();
+        if self.flags & EXT_MEMORY_PRIORITY != 0 {
+            vma_assert!(
+                (0.0..=1.0).contains(&params.priority),
+                "priority >= 0.f && priority <= 1.f"
+            );
+            priority_info.priority = params.priority;
+            push_front(&mut info, &mut priority_info);
+        }
+        let mut export_info = vk::ExportMemoryAllocateInfo::default()
+            .handle_types(self.external_memory_handle_types[memory_type as usize]);
+        if !export_info.handle_types.is_empty() {
+            push_front(&mut info, &mut export_info);
+        }
+
+        let memory = self.allocate_device_memory(heaps, &info)?;
+        let persistent = params.flags & MAPPED != 0;
+        let mut mapped = ptr::null_mut();
+        if persistent {
+            let result = (self.functions.map_memory)(
+                self.device,
+                memory,
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty(),
+                &mut mapped,
+            );
+            if result != vk::Result::SUCCESS {
+                self.free_device_memory(heaps, memory_type, size, memory);
+                return Err(result);
+            }
+        }
+        let allocation = self.new_allocation(
+            AllocationImpl {
+                memory_type,
+                list: list_id,
+                block: None,
+                memory,
+                offset: 0,
+                size,
+                block_size: size,
+                kind: resource.kind,
+                usage: resource.usage.unwrap_or(0),
+                user_data: ptr::null_mut(),
+                name: None,
+                map_count: 0,
+                persistent,
+                mapped,
+            },
+            params,
+        );
+        let heap = self.heap_index(memory_type);
+        heaps.usage[heap].allocation_count += 1;
+        heaps.usage[heap].allocation_bytes += size;
+        heaps.operations_since_budget_fetch += 1;
+        list.dedicated.push(allocation);
+        Ok(allocation)
+    }
+
+    /// Boxes an allocation, with the user data or name of its creation.
+    unsafe fn new_allocation(
+        &self,
+        mut allocation: AllocationImpl,
+        params: AllocationParams,
+    ) -> *mut AllocationImpl {
+        if params.flags & USER_DATA_COPY_STRING != 0 {
+            if !params.user_data.is_null() {
+                allocation.name = Some(CStr::from_ptr(params.user_data.cast()).to_owned());
+            }
+        } else {
+            allocation.user_data = params.user_data;
+        }
+        Box::into_raw(Box::new(allocation))
+    }
+
+    /// `VmaBlockVector::Allocate`, all allocations or none.
+    unsafe fn allocate_from_blocks(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        list_id: ListId,
+        resource: &Resource,
+        params: AllocationParams,
+        allocations: &mut [VmaAllocation],
+    ) -> vk::Result {
+        for index in 0..allocations.len() {
+            match self.allocate_page(heaps, list, list_id, resource, params) {
+                Ok(allocation) => allocations[index] = allocation.cast(),
+                Err(error) => {
+                    for allocation in allocations[..index].iter_mut().rev() {
+                        self.free_from_block(heaps, list, allocation.cast());
+                        *allocation = ptr::null_mut();
+                    }
+                    return error;
+                }
+            }
+        }
+        vk::Result::SUCCESS
+    }
+
+    /// `VmaBlockVector::AllocatePage`, from an existing block or a new one.
+    unsafe fn allocate_page(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        list_id: ListId,
+        resource: &Resource,
+        params: AllocationParams,
+    ) -> Result<*mut AllocationImpl, vk::Result> {
+        let size = resource.requirements.size;
+        let upper = params.flags & UPPER_ADDRESS != 0;
+        let budget = self.heap_budget(heaps, self.heap_index(list.memory_type));
+        let free_memory = budget.budget.saturating_sub(budget.usage);
+        let can_fallback_to_dedicated =
+            !list.explicit_block_size && params.flags & NEVER_ALLOCATE == 0;
+        let can_create_block = params.flags & NEVER_ALLOCATE == 0
+            && list.blocks.len() < list.max_block_count
+            && (free_memory >= size || !can_fallback_to_dedicated);
+
+        // The upper address is only supported by the linear algorithm within a single block.
+        if upper && (!list.linear || list.max_block_count > 1) {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        if size > list.preferred_block_size {
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+
+        let order: Vec<usize> = if list.linear {
+            // Only the last block of the linear algorithm is used.
+            list.blocks.len().checked_sub(1).into_iter().collect()
+        } else if params.flags & STRATEGY_MASK == STRATEGY_MIN_TIME {
+            // The most free space first
+            (0..list.blocks.len()).rev().collect()
+        } else if self
+            .memory_type_flags(list.memory_type)
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            // Mappable allocations go to mapped blocks first, others to unmapped blocks, so
+            // that fewer blocks are mapped.
+            let mappable = params.flags & (HOST_ACCESS_SEQUENTIAL_WRITE | HOST_ACCESS_RANDOM) != 0;
+            let (first, second): (Vec<usize>, Vec<usize>) = (0..list.blocks.len())
+                .partition(|&index| mappable != list.blocks[index].mapped.is_null());
+            first.into_iter().chain(second).collect()
+        } else {
+            (0..list.blocks.len()).collect()
+        };
+        for index in order {
+            if let Some(allocation) =
+                self.allocate_from_block(heaps, list, list_id, index, resource, params)
+            {
+                return allocation;
+            }
+        }
+
+        if !can_create_block {
+            return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        // The first blocks are 1/8, 1/4 and 1/2 of the preferred size, and failing blocks are
+        // retried smaller, unless the size is explicit.
+        const NEW_BLOCK_SIZE_SHIFT_MAX: u32 = 3;
+        let mut block_size = list.preferred_block_size;
+        let mut shift = 0;
+        if !list.explicit_block_size {
+            let max_existing = list
+                .blocks
+                .iter()
+                .map(|block| block.ranges.size())
+                .max()
+                .unwrap_or(0);
+            while shift < NEW_BLOCK_SIZE_SHIFT_MAX {
+                let smaller = block_size / 2;
+                if smaller > max_existing && smaller >= size * 2 {
+                    block_size = smaller;
+                    shift += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        let within_budget = params.flags & WITHIN_BUDGET != 0;
+        let mut create_block = |list: &mut BlockList, block_size: u64| {
+            if block_size > free_memory && (can_fallback_to_dedicated || within_budget) {
+                Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+            } else {
+                self.create_block(heaps, list, block_size)
+            }
+        };
+        let mut result = create_block(list, block_size);
+        if !list.explicit_block_size {
+            while result.is_err() && shift < NEW_BLOCK_SIZE_SHIFT_MAX {
+                let smaller = block_size / 2;
+                if smaller < size {
+                    break;
+                }
+                block_size = smaller;
+                shift += 1;
+                result = create_block(list, block_size);
+            }
+        }
+        let index = result?;
+        let allocation = self
+            .allocate_from_block(heaps, list, list_id, index, resource, params)
+            .unwrap_or(Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY))?;
+        list.sort_incrementally();
+        Ok(allocation)
+    }
+
+    /// Suballocates a block, or returns `None` if it has no room.
+    unsafe fn allocate_from_block(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        list_id: ListId,
+        index: usize,
+        resource: &Resource,
+        params: AllocationParams,
+    ) -> Option<Result<*mut AllocationImpl, vk::Result>> {
+        let size = resource.requirements.size;
+        let alignment = resource.requirements.alignment.max(list.min_alignment);
+        let granularity = list.granularity;
+        let memory_type = list.memory_type;
+        let block = &mut list.blocks[index];
+        let offset = block.ranges.allocate(
+            size,
+            alignment,
+            resource.kind,
+            granularity,
+            params.flags & UPPER_ADDRESS != 0,
+        )?;
+        let persistent = params.flags & MAPPED != 0;
+        if persistent {
+            if let Err(error) = self.map_block(block, 1) {
+                block.ranges.free(offset);
+                return Some(Err(error));
+            }
+        }
+        let allocation = self.new_allocation(
+            AllocationImpl {
+                memory_type,
+                list: list_id,
+                block: Some(block.id),
+                memory: block.memory,
+                offset,
+                size,
+                block_size: block.ranges.size(),
+                kind: resource.kind,
+                usage: resource.usage.unwrap_or(0),
+                user_data: ptr::null_mut(),
+                name: None,
+                map_count: 0,
+                persistent,
+                mapped: ptr::null_mut(),
+            },
+            params,
+        );
+        block.allocations.insert(offset, allocation);
+        let heap = self.heap_index(memory_type);
+        heaps.usage[heap].allocation_count += 1;
+        heaps.usage[heap].allocation_bytes += size;
+        heaps.operations_since_budget_fetch += 1;
+        Some(Ok(allocation))
+    }
+
+    /// `VmaBlockVector::CreateBlock`, returning the index of the new block.
+    unsafe fn create_block(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        size: u64,
+    ) -> Result<usize, vk::Result> {
+        let mut info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(list.memory_type);
+        info.p_next = list.memory_allocate_next;
+        // Any block may hold a buffer with a device address.
+        let mut flags_info = vk::MemoryAllocateFlagsInfo::default();
+        if self.flags & BUFFER_DEVICE_ADDRESS != 0 {
+            flags_info.flags = vk::MemoryAllocateFlags::DEVICE_ADDRESS;
+            push_front(&mut info, &mut flags_info);
+        }
+        let mut priority_info = vk::MemoryPriorityAllocateInfoEXT::default();
+        if self.flags & EXT_MEMORY_PRIORITY != 0 {
+            priority_info.priority = list.priority;
+            push_front(&mut info, &mut priority_info);
+        }
+        let mut export_info = vk::ExportMemoryAllocateInfo::default()
+            .handle_types(self.external_memory_handle_types[list.memory_type as usize]);
+        if !export_info.handle_types.is_empty() {
+            push_front(&mut info, &mut export_info);
+        }
+        let memory = self.allocate_device_memory(heaps, &info)?;
+        list.blocks.push(Block {
+            id: list.next_block_id,
+            memory,
+            ranges: BlockRanges::new(size, list.linear),
+            allocations: BTreeMap::new(),
+            map_count: 0,
+            mapped: ptr::null_mut(),
+        });
+        list.next_block_id += 1;
+        Ok(list.blocks.len() - 1)
+    }
+
+    /// `VmaAllocator_T::FreeMemory` of one allocation.
+    unsafe fn free(&self, state: &mut State, allocation: *mut AllocationImpl) {
+        let list = state.lists.get_mut((*allocation).list);
+        if (*allocation).block.is_some() {
+            self.free_from_block(&mut state.heaps, list, allocation);
+        } else {
+            self.free_dedicated(&mut state.heaps, list, allocation);
+        }
+    }
+
+    unsafe fn free_dedicated(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        allocation: *mut AllocationImpl,
+    ) {
+        list.dedicated.retain(|&other| other != allocation);
+        let allocation = Box::from_raw(allocation);
+        // Freeing the memory unmaps it.
+        self.free_device_memory(
+            heaps,
+            allocation.memory_type,
+            allocation.size,
+            allocation.memory,
+        );
+        let heap = self.heap_index(allocation.memory_type);
+        heaps.usage[heap].allocation_count -= 1;
+        heaps.usage[heap].allocation_bytes -= allocation.size;
+        heaps.operations_since_budget_fetch += 1;
+    }
+
+    /// `VmaBlockVector::Free`, keeping a single empty block unless over budget.
+    unsafe fn free_from_block(
+        &self,
+        heaps: &mut Heaps,
+        list: &mut BlockList,
+        allocation: *mut AllocationImpl,
+    ) {
+        let allocation = Box::from_raw(allocation);
+        let heap = self.heap_index(list.memory_type);
+        let budget = self.heap_budget(heaps, heap);
+        let budget_exceeded = budget.usage >= budget.budget;
+        let had_empty_block = list.blocks.iter().any(|block| block.ranges.is_empty());
+        let block_id = allocation.block.expect("Dedicated allocation");
+        let index = list
+            .blocks
+            .iter()
+            .position(|block| block.id == block_id)
+            .expect("Allocation of a destroyed block");
+        let block = &mut list.blocks[index];
+        if allocation.persistent {
+            self.unmap_block(block, 1);
+        }
+        block.ranges.free(allocation.offset);
+        block.allocations.remove(&allocation.offset);
+
+        let can_delete = list.blocks.len() > list.min_block_count;
+        let delete = if list.blocks[index].ranges.is_empty() {
+            // Another empty block is kept instead.
+            ((had_empty_block || budget_exceeded) && can_delete).then_some(index)
+        } else if had_empty_block && can_delete {
+            let last = list.blocks.len() - 1;
+            list.blocks[last].ranges.is_empty().then_some(last)
+        } else {
+            None
+        };
+        if let Some(index) = delete {
+            let block = list.blocks.remove(index);
+            self.free_device_memory(heaps, list.memory_type, block.ranges.size(), block.memory);
+        }
+        list.sort_incrementally();
+        heaps.usage[heap].allocation_count -= 1;
+        heaps.usage[heap].allocation_bytes -= allocation.size;
+        heaps.operations_since_budget_fetch += 1;
+    }
+
+    /// Frees the blocks of a list destroyed with its pool or the allocator, asserting that
+    /// nothing is left in them.
+    unsafe fn destroy_list(&self, heaps: &mut Heaps, list: BlockList) {
+        for block in list.blocks {
+            for (&offset, &allocation) in &block.allocations {
+                let allocation = &*allocation;
+                log_leak(
+                    offset,
+                    allocation.size,
+                    allocation.user_data,
+                    allocation.name.as_deref(),
+                );
+            }
+            vma_assert!(
+                block.ranges.is_empty(),
+                "Some allocations were not freed before destruction of this memory block!"
+            );
+            self.free_device_memory(heaps, list.memory_type, block.ranges.size(), block.memory);
+        }
+        vma_assert!(
+            list.dedicated.is_empty(),
+            "Unfreed dedicated allocations found!"
+        );
+    }
+
+    /// Block and mapping of an allocation, `None` for dedicated memory.
+    fn block_of<'a>(
+        &self,
+        state: &'a mut State,
+        allocation: &AllocationImpl,
+    ) -> Option<&'a mut Block> {
+        let block = allocation.block?;
+        Some(state.lists.get_mut(allocation.list).block_mut(block))
+    }
+
+    /// `VmaAllocator_T::Map`, counting the mappings of the allocation.
+    unsafe fn map(&self, allocation: *mut AllocationImpl) -> Result<*mut c_void, vk::Result> {
+        let mut state = self.lock();
+        let allocation = &mut *allocation;
+        let data = match self.block_of(&mut state, allocation) {
+            Some(block) => self
+                .map_block(block, 1)?
+                .cast::<u8>()
+                .add(allocation.offset as usize)
+                .cast(),
+            None if allocation.map_count != 0 || allocation.persistent => allocation.mapped,
+            None => {
+                check!((self.functions.map_memory)(
+                    self.device,
+                    allocation.memory,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                    &mut allocation.mapped
+                ));
+                allocation.mapped
+            }
+        };
+        if allocation.map_count < 0xFF {
+            allocation.map_count += 1;
+        } else {
+            vma_assert!(false, "Allocation mapped too many times simultaneously.");
+        }
+        Ok(data)
+    }
+
+    /// `VmaAllocator_T::Unmap`.
+    unsafe fn unmap(&self, allocation: *mut AllocationImpl) {
+        let mut state = self.lock();
+        let allocation = &mut *allocation;
+        if allocation.map_count == 0 {
+            vma_assert!(false, "Unmapping allocation not previously mapped.");
+            return;
+        }
+        allocation.map_count -= 1;
+        match self.block_of(&mut state, allocation) {
+            Some(block) => self.unmap_block(block, 1),
+            None => {
+                if allocation.map_count == 0 && !allocation.persistent {
+                    allocation.mapped = ptr::null_mut();
+                    (self.functions.unmap_memory)(self.device, allocation.memory);
+                }
+            }
+        }
+    }
+
+    /// Mapped pointer of an allocation, or null if it isn't mapped.
+    unsafe fn mapped_data(&self, state: &mut State, allocation: &AllocationImpl) -> *mut c_void {
+        if allocation.map_count == 0 && !allocation.persistent {
+            return ptr::null_mut();
+        }
+        match self.block_of(state, allocation) {
+            Some(block) => block
+                .mapped
+                .cast::<u8>()
+                .add(allocation.offset as usize)
+                .cast(),
+            None => allocation.mapped,
+        }
+    }
+
+    unsafe fn allocation_info(&self, allocation: VmaAllocation) -> ffi::VmaAllocationInfo {
+        let mut state = self.lock();
+        let allocation = &*allocation.cast::<AllocationImpl>();
+        ffi::VmaAllocationInfo {
+            memoryType: allocation.memory_type,
+            deviceMemory: allocation.memory,
+            offset: allocation.offset,
+            size: allocation.size,
+            pMappedData: self.mapped_data(&mut state, allocation),
+            pUserData: allocation.user_data,
+            pName: allocation
+                .name
+                .as_ref()
+                .map_or(ptr::null(), |name| name.as_ptr()),
+        }
+    }
+
+    /// `VmaAllocator_T::GetFlushOrInvalidateRange`, the range of a non-coherent allocation
+    /// aligned to `nonCoherentAtomSize`.
+    unsafe fn flush_range(
+        &self,
+        allocation: VmaAllocation,
+        offset: u64,
+        size: u64,
+    ) -> Option<vk::MappedMemoryRange<'static>> {
+        let _state = self.lock();
+        let allocation = &*allocation.cast::<AllocationImpl>();
+        if size == 0 || !self.non_coherent(allocation.memory_type) {
+            return None;
+        }
+        let atom = self.properties.limits.non_coherent_atom_size;
+        vma_assert!(offset <= allocation.size, "offset <= allocationSize");
+        let start = offset / atom * atom;
+        let size = if allocation.block.is_some() {
+            let size = if size == vk::WHOLE_SIZE {
+                allocation.size - offset
+            } else {
+                size
+            };
+            let start = start + allocation.offset;
+            (size + (offset - start + allocation.offset))
+                .next_multiple_of(atom)
+                .min(allocation.block_size - start)
+        } else if size == vk::WHOLE_SIZE {
+            allocation.size - start
+        } else {
+            (size + (offset - start))
+                .next_multiple_of(atom)
+                .min(allocation.size - start)
+        };
+        Some(
+            vk::MappedMemoryRange::default()
+                .memory(allocation.memory)
+                .offset(start + allocation.offset)
+                .size(size),
+        )
+    }
+
+    /// Flushes or invalidates the non-coherent ones of `ranges`.
+    unsafe fn flush_or_invalidate(
+        &self,
+        ranges: &[vk::MappedMemoryRange<'_>],
+        flush: bool,
+    ) -> vk::Result {
+        if ranges.is_empty() {
+            return vk::Result::SUCCESS;
+        }
+        let function = if flush {
+            self.functions.flush_mapped_memory_ranges
+        } else {
+            self.functions.invalidate_mapped_memory_ranges
+        };
+        function(self.device, ranges.len() as u32, ranges.as_ptr())
+    }
+
+    /// `VmaAllocator_T::BindBufferMemory`, at `local_offset` in the allocation.
+    unsafe fn bind_buffer(
+        &self,
+        allocation: VmaAllocation,
+        local_offset: u64,
+        buffer: vk::Buffer,
+        next: *const c_void,
+    ) -> vk::Result {
+        let (memory, offset) = {
+            let _state = self.lock();
+            let allocation = &*allocation.cast::<AllocationImpl>();
+            (allocation.memory, allocation.offset + local_offset)
+        };
+        if next.is_null() {
+            return (self.functions.bind_buffer_memory)(self.device, buffer, memory, offset);
+        }
+        match self.functions.bind_buffer_memory2 {
+            Some(bind) if self.bind_memory2() => {
+                let mut info = vk::BindBufferMemoryInfo::default()
+                    .buffer(buffer)
+                    .memory(memory)
+                    .memory_offset(offset);
+                info.p_next = next;
+                bind(self.device, 1, &info)
+            }
+            _ => vk::Result::ERROR_EXTENSION_NOT_PRESENT,
+        }
+    }
+
+    unsafe fn bind_image(
+        &self,
+        allocation: VmaAllocation,
+        local_offset: u64,
+        image: vk::Image,
+        next: *const c_void,
+    ) -> vk::Result {
+        let (memory, offset) = {
+            let _state = self.lock();
+            let allocation = &*allocation.cast::<AllocationImpl>();
+            (allocation.memory, allocation.offset + local_offset)
+        };
+        if next.is_null() {
+            return (self.functions.bind_image_memory)(self.device, image, memory, offset);
+        }
+        match self.functions.bind_image_memory2 {
+            Some(bind) if self.bind_memory2() => {
+                let mut info = vk::BindImageMemoryInfo::default()
+                    .image(image)
+                    .memory(memory)
+                    .memory_offset(offset);
+                info.p_next = next;
+                bind(self.device, 1, &info)
+            }
+            _ => vk::Result::ERROR_EXTENSION_NOT_PRESENT,
+        }
+    }
+
+    /// Statistics of the memory types, heaps and total, like `VmaAllocator_T::CalculateStatistics`.
+    unsafe fn calculate_statistics(&self, state: &State) -> ffi::VmaTotalStatistics {
+        let mut total = ffi::VmaTotalStatistics {
+            memoryType: std::array::from_fn(|_| empty_statistics()),
+            memoryHeap: std::array::from_fn(|_| empty_statistics()),
+            total: empty_statistics(),
+        };
+        let lists = state.lists.default.iter().chain(state.lists.pools.values());
+        for list in lists {
+            add_list(&mut total.memoryType[list.memory_type as usize], list);
+        }
+        for memory_type in 0..self.memory_properties.memory_type_count {
+            let stats = &total.memoryType[memory_type as usize];
+            let heap = &mut total.memoryHeap[self.heap_index(memory_type)];
+            add_statistics(heap, stats);
+        }
+        for heap in 0..self.memory_properties.memory_heap_count as usize {
+            let stats = &total.memoryHeap[heap];
+            add_statistics(&mut total.total, stats);
+        }
+        total
+    }
+}
+
+/// Destroys the blocks of the default pools, asserting that nothing is left in them.
+impl Drop for AllocatorImpl {
+    fn drop(&mut self) {
+        let State { lists, heaps } = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        vma_assert!(lists.pools.is_empty(), "Unfreed pools found!");
+        let lists = mem::take(&mut lists.default);
+        let mut heaps = Heaps {
+            usage: heaps.usage,
+            operations_since_budget_fetch: 0,
+            device_memory_count: heaps.device_memory_count,
+        };
+        for list in lists {
+            unsafe { self.destroy_list(&mut heaps, list) };
+        }
+    }
+}
+
+unsafe fn allocator<'a>(allocator: VmaAllocator) -> &'a AllocatorImpl {
+    &*allocator.cast::<AllocatorImpl>()
+}
+
+/// Writes the info of fresh allocations, if requested.
+unsafe fn write_infos(
+    allocator: &AllocatorImpl,
+    allocations: &[VmaAllocation],
+    infos: *mut ffi::VmaAllocationInfo,
+) {
+    if infos.is_null() {
+        return;
+    }
+    for (index, &allocation) in allocations.iter().enumerate() {
+        infos
+            .add(index)
+            .write(allocator.allocation_info(allocation));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateAllocator(
+    create_info: *const ffi::VmaAllocatorCreateInfo,
+    allocator: *mut VmaAllocator,
+) -> vk::Result {
+    match AllocatorImpl::new(&*create_info) {
+        Ok(created) => {
+            *allocator = Box::into_raw(created).cast();
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaDestroyAllocator(allocator: VmaAllocator) {
+    if !allocator.is_null() {
+        drop(Box::from_raw(allocator.cast::<AllocatorImpl>()));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetAllocatorInfo(
+    allocator: VmaAllocator,
+    info: *mut ffi::VmaAllocatorInfo,
+) {
+    let allocator = self::allocator(allocator);
+    info.write(ffi::VmaAllocatorInfo {
+        instance: allocator.instance,
+        physicalDevice: allocator.physical_device,
+        device: allocator.device,
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetPhysicalDeviceProperties(
+    allocator: VmaAllocator,
+    properties: *mut *const vk::PhysicalDeviceProperties,
+) {
+    *properties = &self::allocator(allocator).properties;
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetMemoryProperties(
+    allocator: VmaAllocator,
+    properties: *mut *const vk::PhysicalDeviceMemoryProperties,
+) {
+    *properties = &self::allocator(allocator).memory_properties;
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetMemoryTypeProperties(
+    allocator: VmaAllocator,
+    memory_type: u32,
+    flags: *mut vk::MemoryPropertyFlags,
+) {
+    *flags = self::allocator(allocator).memory_type_flags(memory_type);
+}
+
+/// Queries the budget again with `VK_EXT_memory_budget`, frame indices aren't used otherwise.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaSetCurrentFrameIndex(
+    allocator: VmaAllocator,
+    _frame_index: u32,
+) {
+    let allocator = self::allocator(allocator);
+    if allocator.uses_memory_budget() {
+        let mut state = allocator.lock();
+        allocator.update_budget(&mut state.heaps);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCalculateStatistics(
+    allocator: VmaAllocator,
+    stats: *mut ffi::VmaTotalStatistics,
+) {
+    let allocator = self::allocator(allocator);
+    let state = allocator.lock();
+    stats.write(allocator.calculate_statistics(&state));
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetHeapBudgets(
+    allocator: VmaAllocator,
+    budgets: *mut ffi::VmaBudget,
+) {
+    let allocator = self::allocator(allocator);
+    let mut state = allocator.lock();
+    for heap in 0..allocator.memory_properties.memory_heap_count as usize {
+        budgets
+            .add(heap)
+            .write(allocator.heap_budget(&mut state.heaps, heap));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFindMemoryTypeIndex(
+    allocator: VmaAllocator,
+    memory_type_bits: u32,
+    create_info: *const VmaAllocationCreateInfo,
+    memory_type: *mut u32,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    match allocator.find_memory_type_index(memory_type_bits, &*create_info, None) {
+        Ok(index) => {
+            *memory_type = index;
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFindMemoryTypeIndexForBufferInfo(
+    allocator: VmaAllocator,
+    buffer_info: *const vk::BufferCreateInfo<'_>,
+    create_info: *const VmaAllocationCreateInfo,
+    memory_type: *mut u32,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let usage = buffer_usage(&*buffer_info, allocator.flags & KHR_MAINTENANCE5 != 0);
+    let memory_type_bits = match allocator.functions.get_device_buffer_memory_requirements {
+        // Queried straight from the create info
+        Some(get_requirements) => {
+            let info = vk::DeviceBufferMemoryRequirements::default().create_info(&*buffer_info);
+            let mut requirements = vk::MemoryRequirements2::default();
+            get_requirements(allocator.device, &info, &mut requirements);
+            requirements.memory_requirements.memory_type_bits
+        }
+        // Or from a temporary buffer
+        None => {
+            let mut buffer = vk::Buffer::null();
+            let result = (allocator.functions.create_buffer)(
+                allocator.device,
+                buffer_info,
+                allocator.callbacks(),
+                &mut buffer,
+            );
+            if result != vk::Result::SUCCESS {
+                return result;
+            }
+            let mut requirements = mem::zeroed();
+            (allocator.functions.get_buffer_memory_requirements)(
+                allocator.device,
+                buffer,
+                &mut requirements,
+            );
+            (allocator.functions.destroy_buffer)(allocator.device, buffer, allocator.callbacks());
+            requirements.memory_type_bits
+        }
+    };
+    match allocator.find_memory_type_index(memory_type_bits, &*create_info, Some(usage)) {
+        Ok(index) => {
+            *memory_type = index;
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFindMemoryTypeIndexForImageInfo(
+    allocator: VmaAllocator,
+    image_info: *const vk::ImageCreateInfo<'_>,
+    create_info: *const VmaAllocationCreateInfo,
+    memory_type: *mut u32,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let usage = (*image_info).usage.as_raw() as u64;
+    let memory_type_bits = match allocator.functions.get_device_image_memory_requirements {
+        Some(get_requirements) => {
+            let info = vk::DeviceImageMemoryRequirements::default().create_info(&*image_info);
+            let mut requirements = vk::MemoryRequirements2::default();
+            get_requirements(allocator.device, &info, &mut requirements);
+            requirements.memory_requirements.memory_type_bits
+        }
+        None => {
+            let mut image = vk::Image::null();
+            let result = (allocator.functions.create_image)(
+                allocator.device,
+                image_info,
+                allocator.callbacks(),
+                &mut image,
+            );
+            if result != vk::Result::SUCCESS {
+                return result;
+            }
+            let mut requirements = mem::zeroed();
+            (allocator.functions.get_image_memory_requirements)(
+                allocator.device,
+                image,
+                &mut requirements,
+            );
+            (allocator.functions.destroy_image)(allocator.device, image, allocator.callbacks());
+            requirements.memory_type_bits
+        }
+    };
+    match allocator.find_memory_type_index(memory_type_bits, &*create_info, Some(usage)) {
+        Ok(index) => {
+            *memory_type = index;
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreatePool(
+    allocator: VmaAllocator,
+    create_info: *const ffi::VmaPoolCreateInfo,
+    pool: *mut VmaPool,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let create_info = &*create_info;
+    let max_block_count = match create_info.maxBlockCount {
+        0 => usize::MAX,
+        count => count,
+    };
+    if create_info.minBlockCount > max_block_count {
+        return vk::Result::ERROR_INITIALIZATION_FAILED;
+    }
+    let memory_type = create_info.memoryTypeIndex;
+    if memory_type >= allocator.memory_properties.memory_type_count
+        || allocator.global_memory_type_bits & (1 << memory_type) == 0
+    {
+        return vk::Result::ERROR_FEATURE_NOT_PRESENT;
+    }
+    vma_assert!(
+        create_info.minAllocationAlignment == 0
+            || create_info.minAllocationAlignment.is_power_of_two(),
+        "VmaIsPow2(newCreateInfo.minAllocationAlignment)"
+    );
+
+    let mut state = allocator.lock();
+    let id = state.lists.next_pool_id;
+    let handle = Box::into_raw(Box::new(PoolImpl {
+        id,
+        name: Mutex::new(None),
+    }));
+    let mut list = BlockList {
+        memory_type,
+        pool: handle,
+        preferred_block_size: match create_info.blockSize {
+            0 => allocator.preferred_block_size(memory_type),
+            size => size,
+        },
+        explicit_block_size: create_info.blockSize != 0,
+        min_block_count: create_info.minBlockCount,
+        max_block_count,
+        granularity: if create_info.flags & IGNORE_BUFFER_IMAGE_GRANULARITY != 0 {
+            1
+        } else {
+            allocator.granularity
+        },
+        linear: create_info.flags & POOL_LINEAR_ALGORITHM != 0,
+        priority: create_info.priority,
+        min_alignment: allocator
+            .min_alignment(memory_type)
+            .max(create_info.minAllocationAlignment),
+        memory_allocate_next: create_info.pMemoryAllocateNext,
+        blocks: Vec::new(),
+        next_block_id: 0,
+        dedicated: Vec::new(),
+    };
+    let block_size = list.preferred_block_size;
+    for _ in 0..create_info.minBlockCount {
+        if let Err(error) = allocator.create_block(&mut state.heaps, &mut list, block_size) {
+            for block in mem::take(&mut list.blocks) {
+                allocator.free_device_memory(
+                    &mut state.heaps,
+                    memory_type,
+                    block.ranges.size(),
+                    block.memory,
+                );
+            }
+            drop(Box::from_raw(handle));
+            return error;
+        }
+    }
+    state.lists.next_pool_id += 1;
+    state.lists.pools.insert(id, list);
+    *pool = handle.cast();
+    vk::Result::SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaDestroyPool(allocator: VmaAllocator, pool: VmaPool) {
+    if pool.is_null() {
+        return;
+    }
+    let allocator = self::allocator(allocator);
+    let pool = Box::from_raw(pool.cast::<PoolImpl>());
+    let mut state = allocator.lock();
+    if let Some(list) = state.lists.pools.remove(&pool.id) {
+        allocator.destroy_list(&mut state.heaps, list);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetPoolStatistics(
+    allocator: VmaAllocator,
+    pool: VmaPool,
+    stats: *mut ffi::VmaStatistics,
+) {
+    let allocator = self::allocator(allocator);
+    let mut state = allocator.lock();
+    let list = state
+        .lists
+        .get_mut(ListId::Pool((*pool.cast::<PoolImpl>()).id));
+    let mut detailed = empty_statistics();
+    add_list(&mut detailed, list);
+    stats.write(detailed.statistics);
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCalculatePoolStatistics(
+    allocator: VmaAllocator,
+    pool: VmaPool,
+    stats: *mut ffi::VmaDetailedStatistics,
+) {
+    let allocator = self::allocator(allocator);
+    let mut state = allocator.lock();
+    let list = state
+        .lists
+        .get_mut(ListId::Pool((*pool.cast::<PoolImpl>()).id));
+    let mut detailed = empty_statistics();
+    add_list(&mut detailed, list);
+    stats.write(detailed);
+}
+
+/// Corruption detection needs the margins of the `detect-corruption` feature, which the Rust
+/// implementation doesn't have.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCheckPoolCorruption(
+    _allocator: VmaAllocator,
+    _pool: VmaPool,
+) -> vk::Result {
+    vk::Result::ERROR_FEATURE_NOT_PRESENT
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetPoolName(
+    _allocator: VmaAllocator,
+    pool: VmaPool,
+    name: *mut *const c_char,
+) {
+    let pool = &*pool.cast::<PoolImpl>();
+    *name = pool
+        .name
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .map_or(ptr::null(), |name| name.as_ptr());
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaSetPoolName(
+    _allocator: VmaAllocator,
+    pool: VmaPool,
+    name: *const c_char,
+) {
+    let pool = &*pool.cast::<PoolImpl>();
+    *pool.name.lock().unwrap_or_else(PoisonError::into_inner) =
+        (!name.is_null()).then(|| CStr::from_ptr(name).to_owned());
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaAllocateMemory(
+    allocator: VmaAllocator,
+    requirements: *const vk::MemoryRequirements,
+    create_info: *const VmaAllocationCreateInfo,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    vmaAllocateMemoryPages(allocator, requirements, create_info, 1, allocation, info)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaAllocateMemoryPages(
+    allocator: VmaAllocator,
+    requirements: *const vk::MemoryRequirements,
+    create_info: *const VmaAllocationCreateInfo,
+    count: usize,
+    allocations: *mut VmaAllocation,
+    infos: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    if count == 0 {
+        return vk::Result::SUCCESS;
+    }
+    let allocator = self::allocator(allocator);
+    let allocations = slice::from_raw_parts_mut(allocations, count);
+    let result = allocator.allocate(&Resource::memory(*requirements), &*create_info, allocations);
+    if result == vk::Result::SUCCESS {
+        write_infos(allocator, allocations, infos);
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaAllocateMemoryForBuffer(
+    allocator: VmaAllocator,
+    buffer: vk::Buffer,
+    create_info: *const VmaAllocationCreateInfo,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let (requirements, requires_dedicated, prefers_dedicated) =
+        allocator.buffer_requirements(buffer);
+    let resource = Resource {
+        requirements,
+        requires_dedicated,
+        prefers_dedicated,
+        buffer,
+        kind: SuballocationType::Buffer,
+        ..Resource::memory(requirements)
+    };
+    let allocations = slice::from_raw_parts_mut(allocation, 1);
+    let result = allocator.allocate(&resource, &*create_info, allocations);
+    if result == vk::Result::SUCCESS {
+        write_infos(allocator, allocations, info);
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaAllocateMemoryForImage(
+    allocator: VmaAllocator,
+    image: vk::Image,
+    create_info: *const VmaAllocationCreateInfo,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let (requirements, requires_dedicated, prefers_dedicated) = allocator.image_requirements(image);
+    let resource = Resource {
+        requirements,
+        requires_dedicated,
+        prefers_dedicated,
+        image,
+        kind: SuballocationType::ImageUnknown,
+        ..Resource::memory(requirements)
+    };
+    let allocations = slice::from_raw_parts_mut(allocation, 1);
+    let result = allocator.allocate(&resource, &*create_info, allocations);
+    if result == vk::Result::SUCCESS {
+        write_infos(allocator, allocations, info);
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFreeMemory(allocator: VmaAllocator, allocation: VmaAllocation) {
+    vmaFreeMemoryPages(allocator, 1, &allocation);
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFreeMemoryPages(
+    allocator: VmaAllocator,
+    count: usize,
+    allocations: *const VmaAllocation,
+) {
+    let allocator = self::allocator(allocator);
+    let mut state = allocator.lock();
+    for &allocation in slice::from_raw_parts(allocations, count).iter().rev() {
+        if !allocation.is_null() {
+            allocator.free(&mut state, allocation.cast());
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetAllocationInfo(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) {
+    info.write(self::allocator(allocator).allocation_info(allocation));
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetAllocationInfo2(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo2,
+) {
+    let allocator = self::allocator(allocator);
+    let allocation_info = allocator.allocation_info(allocation);
+    let _state = allocator.lock();
+    let allocation = &*allocation.cast::<AllocationImpl>();
+    info.write(ffi::VmaAllocationInfo2 {
+        allocationInfo: allocation_info,
+        blockSize: allocation.block_size,
+        dedicatedMemory: allocation.block.is_none() as vk::Bool32,
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaSetAllocationUserData(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    user_data: *mut c_void,
+) {
+    let _state = self::allocator(allocator).lock();
+    (*allocation.cast::<AllocationImpl>()).user_data = user_data;
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaSetAllocationName(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    name: *const c_char,
+) {
+    let _state = self::allocator(allocator).lock();
+    (*allocation.cast::<AllocationImpl>()).name =
+        (!name.is_null()).then(|| CStr::from_ptr(name).to_owned());
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetAllocationMemoryProperties(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    flags: *mut vk::MemoryPropertyFlags,
+) {
+    let allocator = self::allocator(allocator);
+    let _state = allocator.lock();
+    *flags = allocator.memory_type_flags((*allocation.cast::<AllocationImpl>()).memory_type);
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaMapMemory(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    data: *mut *mut c_void,
+) -> vk::Result {
+    match self::allocator(allocator).map(allocation.cast()) {
+        Ok(mapped) => {
+            *data = mapped;
+            vk::Result::SUCCESS
+        }
+        Err(error) => error,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaUnmapMemory(allocator: VmaAllocator, allocation: VmaAllocation) {
+    self::allocator(allocator).unmap(allocation.cast());
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFlushAllocation(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let ranges: Vec<_> = allocator
+        .flush_range(allocation, offset, size)
+        .into_iter()
+        .collect();
+    allocator.flush_or_invalidate(&ranges, true)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaInvalidateAllocation(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let ranges: Vec<_> = allocator
+        .flush_range(allocation, offset, size)
+        .into_iter()
+        .collect();
+    allocator.flush_or_invalidate(&ranges, false)
+}
+
+/// Ranges of several allocations, with optional offsets and sizes.
+unsafe fn flush_ranges(
+    allocator: &AllocatorImpl,
+    count: u32,
+    allocations: *const VmaAllocation,
+    offsets: *const vk::DeviceSize,
+    sizes: *const vk::DeviceSize,
+) -> Vec<vk::MappedMemoryRange<'static>> {
+    (0..count as usize)
+        .filter_map(|index| {
+            let offset = if offsets.is_null() {
+                0
+            } else {
+                *offsets.add(index)
+            };
+            let size = if sizes.is_null() {
+                vk::WHOLE_SIZE
+            } else {
+                *sizes.add(index)
+            };
+            allocator.flush_range(*allocations.add(index), offset, size)
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFlushAllocations(
+    allocator: VmaAllocator,
+    count: u32,
+    allocations: *const VmaAllocation,
+    offsets: *const vk::DeviceSize,
+    sizes: *const vk::DeviceSize,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let ranges = flush_ranges(allocator, count, allocations, offsets, sizes);
+    allocator.flush_or_invalidate(&ranges, true)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaInvalidateAllocations(
+    allocator: VmaAllocator,
+    count: u32,
+    allocations: *const VmaAllocation,
+    offsets: *const vk::DeviceSize,
+    sizes: *const vk::DeviceSize,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let ranges = flush_ranges(allocator, count, allocations, offsets, sizes);
+    allocator.flush_or_invalidate(&ranges, false)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCopyMemoryToAllocation(
+    allocator: VmaAllocator,
+    source: *const c_void,
+    allocation: VmaAllocation,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> vk::Result {
+    if size == 0 {
+        return vk::Result::SUCCESS;
+    }
+    let allocator = self::allocator(allocator);
+    let data = match allocator.map(allocation.cast()) {
+        Ok(data) => data,
+        Err(error) => return error,
+    };
+    ptr::copy_nonoverlapping(
+        source.cast::<u8>(),
+        data.cast::<u8>().add(offset as usize),
+        size as usize,
+    );
+    allocator.unmap(allocation.cast());
+    let ranges: Vec<_> = allocator
+        .flush_range(allocation, offset, size)
+        .into_iter()
+        .collect();
+    allocator.flush_or_invalidate(&ranges, true)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCopyAllocationToMemory(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    offset: vk::DeviceSize,
+    destination: *mut c_void,
+    size: vk::DeviceSize,
+) -> vk::Result {
+    if size == 0 {
+        return vk::Result::SUCCESS;
+    }
+    let allocator = self::allocator(allocator);
+    let data = match allocator.map(allocation.cast()) {
+        Ok(data) => data,
+        Err(error) => return error,
+    };
+    let ranges: Vec<_> = allocator
+        .flush_range(allocation, offset, size)
+        .into_iter()
+        .collect();
+    let result = allocator.flush_or_invalidate(&ranges, false);
+    if result == vk::Result::SUCCESS {
+        ptr::copy_nonoverlapping(
+            data.cast::<u8>().add(offset as usize),
+            destination.cast::<u8>(),
+            size as usize,
+        );
+    }
+    allocator.unmap(allocation.cast());
+    result
+}
+
+/// See `vmaCheckPoolCorruption`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCheckCorruption(
+    _allocator: VmaAllocator,
+    _memory_type_bits: u32,
+) -> vk::Result {
+    vk::Result::ERROR_FEATURE_NOT_PRESENT
+}
+
+/// Defragmentation of the `pure-rust` feature, which doesn't move anything yet.
+struct DefragmentationImpl;
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBeginDefragmentation(
+    allocator: VmaAllocator,
+    info: *const ffi::VmaDefragmentationInfo,
+    context: *mut ffi::VmaDefragmentationContext,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let pool = (*info).pool;
+    if !pool.is_null() {
+        let mut state = allocator.lock();
+        if state
+            .lists
+            .get_mut(ListId::Pool((*pool.cast::<PoolImpl>()).id))
+            .linear
+        {
+            return vk::Result::ERROR_FEATURE_NOT_PRESENT;
+        }
+    }
+    *context = Box::into_raw(Box::new(DefragmentationImpl)).cast();
+    vk::Result::SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaEndDefragmentation(
+    _allocator: VmaAllocator,
+    context: ffi::VmaDefragmentationContext,
+    stats: *mut ffi::VmaDefragmentationStats,
+) {
+    drop(Box::from_raw(context.cast::<DefragmentationImpl>()));
+    if !stats.is_null() {
+        stats.write(ffi::VmaDefragmentationStats {
+            bytesMoved: 0,
+            bytesFreed: 0,
+            allocationsMoved: 0,
+            deviceMemoryBlocksFreed: 0,
+        });
+    }
+}
+
+/// Returns `VK_SUCCESS` without moves, which ends the defragmentation.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBeginDefragmentationPass(
+    _allocator: VmaAllocator,
+    _context: ffi::VmaDefragmentationContext,
+    pass_info: *mut ffi::VmaDefragmentationPassMoveInfo,
+) -> vk::Result {
+    (*pass_info).moveCount = 0;
+    (*pass_info).pMoves = ptr::null_mut();
+    vk::Result::SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaEndDefragmentationPass(
+    _allocator: VmaAllocator,
+    _context: ffi::VmaDefragmentationContext,
+    _pass_info: *mut ffi::VmaDefragmentationPassMoveInfo,
+) -> vk::Result {
+    vk::Result::SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBindBufferMemory(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    buffer: vk::Buffer,
+) -> vk::Result {
+    self::allocator(allocator).bind_buffer(allocation, 0, buffer, ptr::null())
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBindBufferMemory2(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    local_offset: vk::DeviceSize,
+    buffer: vk::Buffer,
+    next: *const c_void,
+) -> vk::Result {
+    self::allocator(allocator).bind_buffer(allocation, local_offset, buffer, next)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBindImageMemory(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    image: vk::Image,
+) -> vk::Result {
+    self::allocator(allocator).bind_image(allocation, 0, image, ptr::null())
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBindImageMemory2(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    local_offset: vk::DeviceSize,
+    image: vk::Image,
+    next: *const c_void,
+) -> vk::Result {
+    self::allocator(allocator).bind_image(allocation, local_offset, image, next)
+}
+
+/// Checks the create info of a buffer like `vmaCreateBuffer`.
+unsafe fn check_buffer_info(
+    allocator: &AllocatorImpl,
+    buffer_info: &vk::BufferCreateInfo<'_>,
+) -> vk::Result {
+    if buffer_info.size == 0 {
+        return vk::Result::ERROR_INITIALIZATION_FAILED;
+    }
+    if buffer_info
+        .usage
+        .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+        && allocator.flags & BUFFER_DEVICE_ADDRESS == 0
+    {
+        vma_assert!(
+            false,
+            "Creating a buffer with VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT is not valid if \
+             VMA_ALLOCATOR_CREATE_BUFFER_DEVICE_ADDRESS_BIT was not used."
+        );
+        return vk::Result::ERROR_INITIALIZATION_FAILED;
+    }
+    vk::Result::SUCCESS
+}
+
+fn check_image_info(image_info: &vk::ImageCreateInfo<'_>) -> vk::Result {
+    let extent = image_info.extent;
+    if extent.width == 0
+        || extent.height == 0
+        || extent.depth == 0
+        || image_info.mip_levels == 0
+        || image_info.array_layers == 0
+    {
+        vk::Result::ERROR_INITIALIZATION_FAILED
+    } else {
+        vk::Result::SUCCESS
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateBuffer(
+    allocator: VmaAllocator,
+    buffer_info: *const vk::BufferCreateInfo<'_>,
+    create_info: *const VmaAllocationCreateInfo,
+    buffer: *mut vk::Buffer,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    vmaCreateBufferWithAlignment(
+        allocator,
+        buffer_info,
+        create_info,
+        1,
+        buffer,
+        allocation,
+        info,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateBufferWithAlignment(
+    allocator: VmaAllocator,
+    buffer_info: *const vk::BufferCreateInfo<'_>,
+    create_info: *const VmaAllocationCreateInfo,
+    min_alignment: vk::DeviceSize,
+    buffer: *mut vk::Buffer,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let result = check_buffer_info(allocator, &*buffer_info);
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    *buffer = vk::Buffer::null();
+    *allocation = ptr::null_mut();
+    let result = (allocator.functions.create_buffer)(
+        allocator.device,
+        buffer_info,
+        allocator.callbacks(),
+        buffer,
+    );
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    let (mut requirements, requires_dedicated, prefers_dedicated) =
+        allocator.buffer_requirements(*buffer);
+    requirements.alignment = requirements.alignment.max(min_alignment);
+    let resource = Resource {
+        requirements,
+        requires_dedicated,
+        prefers_dedicated,
+        buffer: *buffer,
+        image: vk::Image::null(),
+        usage: Some(buffer_usage(
+            &*buffer_info,
+            allocator.flags & KHR_MAINTENANCE5 != 0,
+        )),
+        kind: SuballocationType::Buffer,
+    };
+    let allocations = slice::from_raw_parts_mut(allocation, 1);
+    let mut result = allocator.allocate(&resource, &*create_info, allocations);
+    if result == vk::Result::SUCCESS {
+        if (*create_info).flags & DONT_BIND == 0 {
+            result = allocator.bind_buffer(*allocation, 0, *buffer, ptr::null());
+        }
+        if result == vk::Result::SUCCESS {
+            write_infos(allocator, allocations, info);
+            return result;
+        }
+        vmaFreeMemory(allocator.handle(), *allocation);
+        *allocation = ptr::null_mut();
+    }
+    (allocator.functions.destroy_buffer)(allocator.device, *buffer, allocator.callbacks());
+    *buffer = vk::Buffer::null();
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateAliasingBuffer(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    buffer_info: *const vk::BufferCreateInfo<'_>,
+    buffer: *mut vk::Buffer,
+) -> vk::Result {
+    vmaCreateAliasingBuffer2(allocator, allocation, 0, buffer_info, buffer)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateAliasingBuffer2(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    local_offset: vk::DeviceSize,
+    buffer_info: *const vk::BufferCreateInfo<'_>,
+    buffer: *mut vk::Buffer,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let result = check_buffer_info(allocator, &*buffer_info);
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    *buffer = vk::Buffer::null();
+    let result = (allocator.functions.create_buffer)(
+        allocator.device,
+        buffer_info,
+        allocator.callbacks(),
+        buffer,
+    );
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    let result = allocator.bind_buffer(allocation, local_offset, *buffer, ptr::null());
+    if result != vk::Result::SUCCESS {
+        (allocator.functions.destroy_buffer)(allocator.device, *buffer, allocator.callbacks());
+        *buffer = vk::Buffer::null();
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaDestroyBuffer(
+    allocator: VmaAllocator,
+    buffer: vk::Buffer,
+    allocation: VmaAllocation,
+) {
+    let impl_ = self::allocator(allocator);
+    if buffer != vk::Buffer::null() {
+        (impl_.functions.destroy_buffer)(impl_.device, buffer, impl_.callbacks());
+    }
+    if !allocation.is_null() {
+        vmaFreeMemory(allocator, allocation);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateImage(
+    allocator: VmaAllocator,
+    image_info: *const vk::ImageCreateInfo<'_>,
+    create_info: *const VmaAllocationCreateInfo,
+    image: *mut vk::Image,
+    allocation: *mut VmaAllocation,
+    info: *mut ffi::VmaAllocationInfo,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let result = check_image_info(&*image_info);
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    *image = vk::Image::null();
+    *allocation = ptr::null_mut();
+    let result = (allocator.functions.create_image)(
+        allocator.device,
+        image_info,
+        allocator.callbacks(),
+        image,
+    );
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    let (requirements, requires_dedicated, prefers_dedicated) =
+        allocator.image_requirements(*image);
+    let resource = Resource {
+        requirements,
+        requires_dedicated,
+        prefers_dedicated,
+        buffer: vk::Buffer::null(),
+        image: *image,
+        usage: Some((*image_info).usage.as_raw() as u64),
+        kind: if (*image_info).tiling == vk::ImageTiling::OPTIMAL {
+            SuballocationType::ImageOptimal
+        } else {
+            SuballocationType::ImageLinear
+        },
+    };
+    let allocations = slice::from_raw_parts_mut(allocation, 1);
+    let mut result = allocator.allocate(&resource, &*create_info, allocations);
+    if result == vk::Result::SUCCESS {
+        if (*create_info).flags & DONT_BIND == 0 {
+            result = allocator.bind_image(*allocation, 0, *image, ptr::null());
+        }
+        if result == vk::Result::SUCCESS {
+            write_infos(allocator, allocations, info);
+            return result;
+        }
+        vmaFreeMemory(allocator.handle(), *allocation);
+        *allocation = ptr::null_mut();
+    }
+    (allocator.functions.destroy_image)(allocator.device, *image, allocator.callbacks());
+    *image = vk::Image::null();
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateAliasingImage(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    image_info: *const vk::ImageCreateInfo<'_>,
+    image: *mut vk::Image,
+) -> vk::Result {
+    vmaCreateAliasingImage2(allocator, allocation, 0, image_info, image)
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateAliasingImage2(
+    allocator: VmaAllocator,
+    allocation: VmaAllocation,
+    local_offset: vk::DeviceSize,
+    image_info: *const vk::ImageCreateInfo<'_>,
+    image: *mut vk::Image,
+) -> vk::Result {
+    let allocator = self::allocator(allocator);
+    let result = check_image_info(&*image_info);
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    *image = vk::Image::null();
+    let result = (allocator.functions.create_image)(
+        allocator.device,
+        image_info,
+        allocator.callbacks(),
+        image,
+    );
+    if result != vk::Result::SUCCESS {
+        return result;
+    }
+    let result = allocator.bind_image(allocation, local_offset, *image, ptr::null());
+    if result != vk::Result::SUCCESS {
+        (allocator.functions.destroy_image)(allocator.device, *image, allocator.callbacks());
+        *image = vk::Image::null();
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaDestroyImage(
+    allocator: VmaAllocator,
+    image: vk::Image,
+    allocation: VmaAllocation,
+) {
+    let impl_ = self::allocator(allocator);
+    if image != vk::Image::null() {
+        (impl_.functions.destroy_image)(impl_.device, image, impl_.callbacks());
+    }
+    if !allocation.is_null() {
+        vmaFreeMemory(allocator, allocation);
+    }
+}
+
+/// `VmaVirtualBlock` of the `pure-rust` feature. Allocation handles are offsets plus one, so
+/// that none is null.
+struct VirtualBlockImpl {
+    ranges: BlockRanges,
+    user_data: BTreeMap<u64, *mut c_void>,
+}
+
+unsafe fn virtual_block<'a>(block: ffi::VmaVirtualBlock) -> &'a mut VirtualBlockImpl {
+    &mut *block.cast::<VirtualBlockImpl>()
+}
+
+fn virtual_offset(allocation: ffi::VmaVirtualAllocation) -> u64 {
+    allocation as u64 - 1
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCreateVirtualBlock(
+    create_info: *const ffi::VmaVirtualBlockCreateInfo,
+    block: *mut ffi::VmaVirtualBlock,
+) -> vk::Result {
+    let create_info = &*create_info;
+    vma_assert!(create_info.size > 0, "pCreateInfo->size > 0");
+    *block = Box::into_raw(Box::new(VirtualBlockImpl {
+        ranges: BlockRanges::new(
+            create_info.size,
+            create_info.flags & VIRTUAL_BLOCK_LINEAR_ALGORITHM != 0,
+        ),
+        user_data: BTreeMap::new(),
+    }))
+    .cast();
+    vk::Result::SUCCESS
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaDestroyVirtualBlock(block: ffi::VmaVirtualBlock) {
+    if block.is_null() {
+        return;
+    }
+    let block = Box::from_raw(block.cast::<VirtualBlockImpl>());
+    for (offset, size) in block.ranges.allocations() {
+        let user_data = block
+            .user_data
+            .get(&offset)
+            .copied()
+            .unwrap_or(ptr::null_mut());
+        log_leak(offset, size, user_data, None);
+    }
+    vma_assert!(
+        block.ranges.is_empty(),
+        "Some virtual allocations were not freed before destruction of this virtual block!"
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaIsVirtualBlockEmpty(block: ffi::VmaVirtualBlock) -> vk::Bool32 {
+    virtual_block(block).ranges.is_empty() as vk::Bool32
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetVirtualAllocationInfo(
+    block: ffi::VmaVirtualBlock,
+    allocation: ffi::VmaVirtualAllocation,
+    info: *mut ffi::VmaVirtualAllocationInfo,
+) {
+    let block = virtual_block(block);
+    let offset = virtual_offset(allocation);
+    let (size, _) = block
+        .ranges
+        .get(offset)
+        .unwrap_or((0, SuballocationType::Unknown));
+    info.write(ffi::VmaVirtualAllocationInfo {
+        offset,
+        size,
+        pUserData: block
+            .user_data
+            .get(&offset)
+            .copied()
+            .unwrap_or(ptr::null_mut()),
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaVirtualAllocate(
+    block: ffi::VmaVirtualBlock,
+    create_info: *const ffi::VmaVirtualAllocationCreateInfo,
+    allocation: *mut ffi::VmaVirtualAllocation,
+    offset: *mut vk::DeviceSize,
+) -> vk::Result {
+    let block = virtual_block(block);
+    let create_info = &*create_info;
+    vma_assert!(create_info.size > 0, "allocSize > 0");
+    let upper = create_info.flags & VIRTUAL_UPPER_ADDRESS != 0;
+    let allocated = block.ranges.allocate(
+        create_info.size,
+        create_info.alignment.max(1),
+        SuballocationType::Unknown,
+        1,
+        upper,
+    );
+    match allocated {
+        Some(allocated) => {
+            if !create_info.pUserData.is_null() {
+                block.user_data.insert(allocated, create_info.pUserData);
+            }
+            *allocation = (allocated + 1) as ffi::VmaVirtualAllocation;
+            if !offset.is_null() {
+                *offset = allocated;
+            }
+            vk::Result::SUCCESS
+        }
+        None => {
+            *allocation = ptr::null_mut();
+            if !offset.is_null() {
+                *offset = u64::MAX;
+            }
+            vk::Result::ERROR_OUT_OF_DEVICE_MEMORY
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaVirtualFree(
+    block: ffi::VmaVirtualBlock,
+    allocation: ffi::VmaVirtualAllocation,
+) {
+    if allocation.is_null() {
+        return;
+    }
+    let block = virtual_block(block);
+    let offset = virtual_offset(allocation);
+    block.user_data.remove(&offset);
+    let freed = block.ranges.free(offset);
+    vma_assert!(freed, "Not found!");
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaClearVirtualBlock(block: ffi::VmaVirtualBlock) {
+    let block = virtual_block(block);
+    block.ranges.clear();
+    block.user_data.clear();
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaSetVirtualAllocationUserData(
+    block: ffi::VmaVirtualBlock,
+    allocation: ffi::VmaVirtualAllocation,
+    user_data: *mut c_void,
+) {
+    let block = virtual_block(block);
+    let offset = virtual_offset(allocation);
+    if user_data.is_null() {
+        block.user_data.remove(&offset);
+    } else {
+        block.user_data.insert(offset, user_data);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaGetVirtualBlockStatistics(
+    block: ffi::VmaVirtualBlock,
+    stats: *mut ffi::VmaStatistics,
+) {
+    let ranges = &virtual_block(block).ranges;
+    stats.write(ffi::VmaStatistics {
+        blockCount: 1,
+        allocationCount: ranges.allocation_count() as u32,
+        blockBytes: ranges.size(),
+        allocationBytes: ranges.allocated_size(),
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaCalculateVirtualBlockStatistics(
+    block: ffi::VmaVirtualBlock,
+    stats: *mut ffi::VmaDetailedStatistics,
+) {
+    let mut detailed = empty_statistics();
+    add_block(&mut detailed, &virtual_block(block).ranges);
+    stats.write(detailed);
+}
+
+/// JSON writer of the statistics strings, like VMA's `VmaJsonWriter` without indentation.
+#[cfg(feature = "stats-string")]
+#[derive(Default)]
+struct JsonWriter {
+    out: String,
+    /// Whether the next value follows another one in its object or array
+    separate: bool,
+}
+
+#[cfg(feature = "stats-string")]
+impl JsonWriter {
+    fn begin(&mut self, bracket: char) {
+        self.separator();
+        self.out.push(bracket);
+        self.separate = false;
+    }
+
+    fn end(&mut self, bracket: char) {
+        self.out.push(bracket);
+        self.separate = true;
+    }
+
+    fn key(&mut self, key: &str) {
+        self.string(key);
+        self.out.push(':');
+        self.separate = false;
+    }
+
+    fn string(&mut self, value: &str) {
+        use std::fmt::Write;
+        self.separator();
+        self.out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if (c as u32) < 0x20 => {
+                    let _ = write!(self.out, "\\u{:04X}", c as u32);
+                }
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+        self.separate = true;
+    }
+
+    fn number(&mut self, value: u64) {
+        self.separator();
+        self.out.push_str(&value.to_string());
+        self.separate = true;
+    }
+
+    fn field(&mut self, key: &str, value: u64) {
+        self.key(key);
+        self.number(value);
+    }
+
+    fn separator(&mut self) {
+        if self.separate {
+            self.out.push(',');
+        }
+    }
+
+    /// `VmaPrintDetailedStatistics`
+    fn statistics(&mut self, stats: &ffi::VmaDetailedStatistics) {
+        self.begin('{');
+        self.field("BlockCount", stats.statistics.blockCount as u64);
+        self.field("BlockBytes", stats.statistics.blockBytes);
+        self.field("AllocationCount", stats.statistics.allocationCount as u64);
+        self.field("AllocationBytes", stats.statistics.allocationBytes);
+        self.field("UnusedRangeCount", stats.unusedRangeCount as u64);
+        if stats.statistics.allocationCount > 1 {
+            self.field("AllocationSizeMin", stats.allocationSizeMin);
+            self.field("AllocationSizeMax", stats.allocationSizeMax);
+        }
+        if stats.unusedRangeCount > 1 {
+            self.field("UnusedRangeSizeMin", stats.unusedRangeSizeMin);
+            self.field("UnusedRangeSizeMax", stats.unusedRangeSizeMax);
+        }
+        self.end('}');
+    }
+
+    /// Names of the flags, then the unknown ones as a number.
+    fn flags(&mut self, mut flags: u32, names: &[(u32, &str)]) {
+        self.begin('[');
+        for &(flag, name) in names {
+            if flags & flag != 0 {
+                self.string(name);
+                flags &= !flag;
+            }
+        }
+        if flags != 0 {
+            self.number(flags as u64);
+        }
+        self.end(']');
+    }
+
+    /// The fields of `VmaBlockMetadata::PrintDetailedMap`, with `allocation` writing the
+    /// parameters of the allocation at an offset.
+    fn block_map(&mut self, ranges: &BlockRanges, mut allocation: impl FnMut(&mut Self, u64, u64)) {
+        self.field("TotalBytes", ranges.size());
+        self.field("UnusedBytes", ranges.size() - ranges.allocated_size());
+        self.field("Allocations", ranges.allocation_count() as u64);
+        self.field("UnusedRanges", ranges.unused_ranges().count() as u64);
+        self.key("Suballocations");
+        self.begin('[');
+        let mut allocations = ranges.allocations().peekable();
+        let mut unused = ranges.unused_ranges().peekable();
+        loop {
+            let next_allocation = allocations.peek().map(|&(offset, _)| offset);
+            let next_unused = unused.peek().map(|&(offset, _)| offset);
+            match (next_allocation, next_unused) {
+                (Some(offset), other) if other.is_none_or(|other| offset < other) => {
+                    let (offset, size) = allocations.next().unwrap();
+                    self.begin('{');
+                    self.field("Offset", offset);
+                    allocation(self, offset, size);
+                    self.end('}');
+                }
+                (_, Some(_)) => {
+                    let (offset, size) = unused.next().unwrap();
+                    self.begin('{');
+                    self.field("Offset", offset);
+                    self.key("Type");
+                    self.string("FREE");
+                    self.field("Size", size);
+                    self.end('}');
+                }
+                _ => break,
+            }
+        }
+        self.end(']');
+    }
+
+    fn custom_data(&mut self, user_data: *mut c_void) {
+        if !user_data.is_null() {
+            self.key("CustomData");
+            self.string(&format!("{:p}", user_data));
+        }
+    }
+
+    /// `VmaAllocation_T::PrintParameters`
+    unsafe fn allocation(&mut self, allocation: &AllocationImpl) {
+        self.key("Type");
+        self.string(allocation.kind.name());
+        self.field("Size", allocation.size);
+        self.field("Usage", allocation.usage);
+        self.custom_data(allocation.user_data);
+        if let Some(name) = &allocation.name {
+            self.key("Name");
+            self.string(&name.to_string_lossy());
+        }
+    }
+
+    /// `VmaBlockVector::PrintDetailedMap` and the dedicated allocations of a list.
+    unsafe fn list(&mut self, list: &BlockList) {
+        self.field("PreferredBlockSize", list.preferred_block_size);
+        self.key("Blocks");
+        self.begin('{');
+        for block in &list.blocks {
+            self.key(&block.id.to_string());
+            self.begin('{');
+            self.field("MapRefs", block.map_count as u64);
+            self.block_map(&block.ranges, |json, offset, _| {
+                json.allocation(&*block.allocations[&offset]);
+            });
+            self.end('}');
+        }
+        self.end('}');
+        self.key("DedicatedAllocations");
+        self.begin('[');
+        for &allocation in &list.dedicated {
+            self.begin('{');
+            self.allocation(&*allocation);
+            self.end('}');
+        }
+        self.end(']');
+    }
+}
+
+/// `vmaBuildStatsString`
+#[cfg(feature = "stats-string")]
+unsafe fn stats_string(allocator: &AllocatorImpl, detailed_map: bool) -> String {
+    use vk::MemoryHeapFlags as HeapFlags;
+    use vk::MemoryPropertyFlags as Flags;
+    let state = allocator.lock();
+    let stats = allocator.calculate_statistics(&state);
+    let properties = &allocator.properties;
+    let memory_properties = &allocator.memory_properties;
+    let mut json = JsonWriter::default();
+    json.begin('{');
+    json.key("General");
+    json.begin('{');
+    json.key("API");
+    json.string("Vulkan");
+    json.key("apiVersion");
+    json.string(&format!(
+        "{}.{}.{}",
+        vk::api_version_major(properties.api_version),
+        vk::api_version_minor(properties.api_version),
+        vk::api_version_patch(properties.api_version)
+    ));
+    json.key("GPU");
+    json.string(
+        &properties
+            .device_name_as_c_str()
+            .unwrap_or_default()
+            .to_string_lossy(),
+    );
+    json.field("deviceType", properties.device_type.as_raw() as u64);
+    json.field(
+        "maxMemoryAllocationCount",
+        properties.limits.max_memory_allocation_count as u64,
+    );
+    json.field(
+        "bufferImageGranularity",
+        properties.limits.buffer_image_granularity,
+    );
+    json.field(
+        "nonCoherentAtomSize",
+        properties.limits.non_coherent_atom_size,
+    );
+    json.field(
+        "memoryHeapCount",
+        memory_properties.memory_heap_count as u64,
+    );
+    json.field(
+        "memoryTypeCount",
+        memory_properties.memory_type_count as u64,
+    );
+    json.end('}');
+    json.key("Total");
+    json.statistics(&stats.total);
+
+    let type_flags = [
+        (Flags::DEVICE_LOCAL, "DEVICE_LOCAL"),
+        (Flags::HOST_VISIBLE, "HOST_VISIBLE"),
+        (Flags::HOST_COHERENT, "HOST_COHERENT"),
+        (Flags::HOST_CACHED, "HOST_CACHED"),
+        (Flags::LAZILY_ALLOCATED, "LAZILY_ALLOCATED"),
+        (Flags::PROTECTED, "PROTECTED"),
+        (Flags::DEVICE_COHERENT_AMD, "DEVICE_COHERENT_AMD"),
+        (Flags::DEVICE_UNCACHED_AMD, "DEVICE_UNCACHED_AMD"),
+    ]
+    .map(|(flag, name)| (flag.as_raw(), name));
+    let heap_flags = [
+        (HeapFlags::DEVICE_LOCAL, "DEVICE_LOCAL"),
+        (HeapFlags::MULTI_INSTANCE, "MULTI_INSTANCE"),
+    ]
+    .map(|(flag, name)| (flag.as_raw(), name));
+    json.key("MemoryInfo");
+    json.begin('{');
+    let mut heaps = Heaps {
+        usage: state.heaps.usage,
+        operations_since_budget_fetch: 0,
+        device_memory_count: state.heaps.device_memory_count,
+    };
+    for heap in 0..memory_properties.memory_heap_count as usize {
+        let info = memory_properties.memory_heaps[heap];
+        json.key(&format!("Heap {}", heap));
+        json.begin('{');
+        json.key("Flags");
+        json.flags(info.flags.as_raw(), &heap_flags);
+        json.field("Size", info.size);
+        let budget = allocator.heap_budget(&mut heaps, heap);
+        json.key("Budget");
+        json.begin('{');
+        json.field("BudgetBytes", budget.budget);
+        json.field("UsageBytes", budget.usage);
+        json.end('}');
+        json.key("Stats");
+        json.statistics(&stats.memoryHeap[heap]);
+        json.key("MemoryPools");
+        json.begin('{');
+        for memory_type in 0..memory_properties.memory_type_count {
+            if allocator.heap_index(memory_type) != heap {
+                continue;
+            }
+            json.key(&format!("Type {}", memory_type));
+            json.begin('{');
+            json.key("Flags");
+            json.flags(
+                allocator.memory_type_flags(memory_type).as_raw(),
+                &type_flags,
+            );
+            json.key("Stats");
+            json.statistics(&stats.memoryType[memory_type as usize]);
+            json.end('}');
+        }
+        json.end('}');
+        json.end('}');
+    }
+    json.end('}');
+
+    if detailed_map {
+        json.key("DefaultPools");
+        json.begin('{');
+        for list in &state.lists.default {
+            json.key(&format!("Type {}", list.memory_type));
+            json.begin('{');
+            json.list(list);
+            json.end('}');
+        }
+        json.end('}');
+        json.key("CustomPools");
+        json.begin('{');
+        for memory_type in 0..memory_properties.memory_type_count {
+            let pools: Vec<_> = state
+                .lists
+                .pools
+                .values()
+                .filter(|list| list.memory_type == memory_type)
+                .collect();
+            if pools.is_empty() {
+                continue;
+            }
+            json.key(&format!("Type {}", memory_type));
+            json.begin('[');
+            for (index, list) in pools.into_iter().enumerate() {
+                json.begin('{');
+                let name = (*list.pool)
+                    .name
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .as_ref()
+                    .map(|name| format!("{} - {}", index, name.to_string_lossy()));
+                json.key("Name");
+                json.string(&name.unwrap_or_else(|| index.to_string()));
+                json.list(list);
+                json.end('}');
+            }
+            json.end(']');
+        }
+        json.end('}');
+    }
+    json.end('}');
+    json.out
+}
+
+/// Hands a statistics string over to C, freed with `free_string`.
+#[cfg(feature = "stats-string")]
+fn into_raw_string(string: String) -> *mut c_char {
+    CString::new(string)
+        .expect("JSON strings are escaped")
+        .into_raw()
+}
+
+#[cfg(feature = "stats-string")]
+unsafe fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(feature = "stats-string")]
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBuildStatsString(
+    allocator: VmaAllocator,
+    string: *mut *mut c_char,
+    detailed_map: vk::Bool32,
+) {
+    *string = into_raw_string(stats_string(
+        self::allocator(allocator),
+        detailed_map != vk::FALSE,
+    ));
+}
+
+#[cfg(feature = "stats-string")]
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFreeStatsString(_allocator: VmaAllocator, string: *mut c_char) {
+    free_string(string);
+}
+
+#[cfg(feature = "stats-string")]
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaBuildVirtualBlockStatsString(
+    block: ffi::VmaVirtualBlock,
+    string: *mut *mut c_char,
+    detailed_map: vk::Bool32,
+) {
+    let block = virtual_block(block);
+    let mut stats = empty_statistics();
+    add_block(&mut stats, &block.ranges);
+    let mut json = JsonWriter::default();
+    json.begin('{');
+    json.key("Stats");
+    json.statistics(&stats);
+    if detailed_map != vk::FALSE {
+        json.key("Details");
+        json.begin('{');
+        json.block_map(&block.ranges, |json, offset, size| {
+            json.field("Size", size);
+            json.custom_data(
+                block
+                    .user_data
+                    .get(&offset)
+                    .copied()
+                    .unwrap_or(ptr::null_mut()),
+            );
+        });
+        json.end('}');
+    }
+    json.end('}');
+    *string = into_raw_string(json.out);
+}
+
+#[cfg(feature = "stats-string")]
+#[no_mangle]
+pub unsafe extern "C-unwind" fn vmaFreeVirtualBlockStatsString(
+    _block: ffi::VmaVirtualBlock,
+    string: *mut c_char,
+) {
+    free_string(string);
+}
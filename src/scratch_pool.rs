@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorCreateFlags;
+use crate::MemoryUsage;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Device local buffer of a `ScratchPool`.
+struct ScratchBuffer {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    address: vk::DeviceAddress,
+    size: vk::DeviceSize,
+    /// Offset of the free part of the buffer
+    cursor: vk::DeviceSize,
+}
+
+impl ScratchBuffer {
+    /// Offset the next scratch range of `size` bytes would be at, if it fits.
+    fn fit(&self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let offset = (self.address + self.cursor).next_multiple_of(alignment) - self.address;
+        (offset + size <= self.size).then_some(offset)
+    }
+}
+
+/// Scratch memory for acceleration structure builds, reused from one build to the next.
+///
+/// `ScratchPool::get_scratch` hands out ranges of device local buffers created with
+/// `vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS`, whose device addresses are aligned to
+/// `minAccelerationStructureScratchOffsetAlignment`. Ranges handed out since the last
+/// `ScratchPool::reset` don't overlap, so builds recorded with them can run concurrently.
+///
+/// Buffers are `block_size` bytes, or larger for bigger builds, and are only destroyed when the
+/// pool is dropped.
+pub struct ScratchPool {
+    allocator: Arc<Allocator>,
+    device: ash::Device,
+    alignment: vk::DeviceSize,
+    block_size: vk::DeviceSize,
+    buffers: Vec<ScratchBuffer>,
+}
+unsafe impl Send for ScratchPool {}
+
+impl Allocator {
+    /// Creates a `ScratchPool` allocating buffers of `block_size` bytes.
+    ///
+    /// `properties` are the acceleration structure properties of the physical device, queried
+    /// with `vkGetPhysicalDeviceProperties2`. Fails with `VmaError::InvalidParameter` unless the
+    /// allocator was created with `AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS`.
+    ///
+    /// # Safety
+    /// `device` must be the allocator's device.
+    pub unsafe fn create_scratch_pool(
+        self: &Arc<Self>,
+        device: &ash::Device,
+        properties: &vk::PhysicalDeviceAccelerationStructurePropertiesKHR<'_>,
+        block_size: vk::DeviceSize,
+    ) -> VmaResult<ScratchPool> {
+        if !self
+            .flags
+            .contains(AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS)
+        {
+            return Err(VmaError::InvalidParameter(
+                "Scratch buffers need an allocator created with BUFFER_DEVICE_ADDRESS",
+            ));
+        }
+        Ok(ScratchPool {
+            allocator: self.clone(),
+            device: device.clone(),
+            alignment: (properties.min_acceleration_structure_scratch_offset_alignment as u64)
+                .max(1),
+            block_size,
+            buffers: Vec::new(),
+        })
+    }
+}
+
+impl ScratchPool {
+    /// Reserves `size` bytes of scratch memory until the next `ScratchPool::reset`.
+    ///
+    /// Returns the buffer holding the range and the device address of its start, to use as
+    /// `vk::AccelerationStructureBuildGeometryInfoKHR::scratch_data`.
+    #[track_caller]
+    pub fn get_scratch(
+        &mut self,
+        size: vk::DeviceSize,
+    ) -> VmaResult<(vk::Buffer, vk::DeviceAddress)> {
+        let index = match self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.fit(size, self.alignment).is_some())
+        {
+            Some(index) => index,
+            None => {
+                // Leaves room to align the start, in case the buffer's address isn't aligned
+                let buffer =
+                    self.create_buffer((size + self.alignment - 1).max(self.block_size))?;
+                self.buffers.push(buffer);
+                self.buffers.len() - 1
+            }
+        };
+        let buffer = &mut self.buffers[index];
+        let offset = buffer.fit(size, self.alignment).unwrap();
+        buffer.cursor = offset + size;
+        Ok((buffer.buffer, buffer.address + offset))
+    }
+
+    /// Makes all scratch memory available again.
+    ///
+    /// # Safety
+    /// The builds using ranges handed out so far must be complete.
+    pub unsafe fn reset(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.cursor = 0;
+        }
+    }
+
+    /// Alignment of scratch addresses, the device's
+    /// `minAccelerationStructureScratchOffsetAlignment`.
+    pub fn alignment(&self) -> vk::DeviceSize {
+        self.alignment
+    }
+
+    /// Number of scratch buffers.
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Destroys all scratch buffers, reporting errors that `Drop` would ignore.
+    ///
+    /// The builds using them must be complete.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.destroy_buffers()
+    }
+
+    #[track_caller]
+    fn create_buffer(&self, size: vk::DeviceSize) -> VmaResult<ScratchBuffer> {
+        let buffer_info = vk::BufferCreateInfo::default().size(size).usage(
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        );
+        let create_info = AllocationCreateInfo {
+            usage: MemoryUsage::AutoPreferDevice,
+            ..Default::default()
+        };
+        unsafe {
+            let (buffer, allocation) = self.allocator.create_buffer_with_alignment(
+                &buffer_info,
+                &create_info,
+                self.alignment,
+            )?;
+            let address = self
+                .device
+                .get_buffer_device_address(&vk::BufferDeviceAddressInfo::default().buffer(buffer));
+            Ok(ScratchBuffer {
+                buffer,
+                allocation,
+                address,
+                size,
+                cursor: 0,
+            })
+        }
+    }
+
+    fn destroy_buffers(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        for buffer in self.buffers.drain(..) {
+            result = result.and(unsafe {
+                self.allocator
+                    .destroy_buffer(buffer.buffer, buffer.allocation)
+                    .map_err(VmaError::from)
+            });
+        }
+        result
+    }
+}
+
+/// Destroys all scratch buffers, the device must not use them anymore.
+impl Drop for ScratchPool {
+    fn drop(&mut self) {
+        let _ = self.destroy_buffers();
+    }
+}
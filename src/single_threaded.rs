@@ -0,0 +1,281 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::AllocationInfo;
+use crate::Allocator;
+use crate::AllocatorCreateFlags;
+use crate::AllocatorCreateInfo;
+use crate::HeapBudget;
+use crate::MappedMemory;
+use crate::TotalStatistics;
+use crate::{FreeResult, VmaResult};
+use ash::vk;
+
+/// Allocator created with `AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED`.
+///
+/// VMA doesn't lock any mutex in this mode, so the allocator can be moved to another thread but
+/// not shared between threads: unlike `Allocator`, this type is `Send` but not `Sync`.
+///
+/// The inner `Allocator` is never handed out, since a `&Allocator` could be sent to another
+/// thread. The most common functions are forwarded instead; custom pools, sub-allocators and
+/// managed resources need an `Arc<Allocator>` and aren't available.
+pub struct SingleThreadedAllocator {
+    allocator: Allocator,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl SingleThreadedAllocator {
+    /// Construct a new allocator, `AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED` is added to
+    /// `create_info.flags`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::new`.
+    pub unsafe fn new(mut create_info: AllocatorCreateInfo) -> VmaResult<Self> {
+        create_info.flags |= AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED;
+        crate::check_vma_version()?;
+        Ok(Self {
+            allocator: Allocator::create(create_info)?,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// See `Allocator::get_memory_properties`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::get_memory_properties`.
+    pub unsafe fn get_memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        self.allocator.get_memory_properties()
+    }
+
+    /// See `Allocator::set_current_frame_index`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::set_current_frame_index`.
+    pub unsafe fn set_current_frame_index(&self, frame_index: u32) {
+        self.allocator.set_current_frame_index(frame_index)
+    }
+
+    /// See `Allocator::calculate_statistics`.
+    pub fn calculate_statistics(&self) -> VmaResult<TotalStatistics> {
+        self.allocator.calculate_statistics()
+    }
+
+    /// See `Allocator::get_heap_budgets`.
+    pub fn get_heap_budgets(&self) -> VmaResult<Vec<HeapBudget>> {
+        self.allocator.get_heap_budgets()
+    }
+
+    /// See `Alloc::find_memory_type_index`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::find_memory_type_index`.
+    pub unsafe fn find_memory_type_index(
+        &self,
+        memory_type_bits: u32,
+        allocation_info: &AllocationCreateInfo,
+    ) -> VmaResult<u32> {
+        self.allocator
+            .find_memory_type_index(memory_type_bits, allocation_info)
+    }
+
+    /// See `Alloc::find_memory_type_index_for_buffer_info`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::find_memory_type_index_for_buffer_info`.
+    pub unsafe fn find_memory_type_index_for_buffer_info(
+        &self,
+        buffer_info: &vk::BufferCreateInfo,
+        allocation_info: &AllocationCreateInfo,
+    ) -> VmaResult<u32> {
+        self.allocator
+            .find_memory_type_index_for_buffer_info(buffer_info, allocation_info)
+    }
+
+    /// See `Alloc::find_memory_type_index_for_image_info`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::find_memory_type_index_for_image_info`.
+    pub unsafe fn find_memory_type_index_for_image_info(
+        &self,
+        image_info: vk::ImageCreateInfo,
+        allocation_info: &AllocationCreateInfo,
+    ) -> VmaResult<u32> {
+        self.allocator
+            .find_memory_type_index_for_image_info(image_info, allocation_info)
+    }
+
+    /// See `Alloc::allocate_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::allocate_memory`.
+    #[track_caller]
+    pub unsafe fn allocate_memory(
+        &self,
+        memory_requirements: &vk::MemoryRequirements,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        self.allocator
+            .allocate_memory(memory_requirements, create_info)
+    }
+
+    /// See `Alloc::allocate_memory_for_buffer`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::allocate_memory_for_buffer`.
+    #[track_caller]
+    pub unsafe fn allocate_memory_for_buffer(
+        &self,
+        buffer: vk::Buffer,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        self.allocator
+            .allocate_memory_for_buffer(buffer, create_info)
+    }
+
+    /// See `Alloc::allocate_memory_for_image`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::allocate_memory_for_image`.
+    #[track_caller]
+    pub unsafe fn allocate_memory_for_image(
+        &self,
+        image: vk::Image,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        self.allocator.allocate_memory_for_image(image, create_info)
+    }
+
+    /// See `Alloc::create_buffer`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::create_buffer`.
+    #[track_caller]
+    pub unsafe fn create_buffer(
+        &self,
+        buffer_info: &vk::BufferCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<(vk::Buffer, Allocation)> {
+        self.allocator.create_buffer(buffer_info, create_info)
+    }
+
+    /// See `Alloc::create_image`.
+    ///
+    /// # Safety
+    /// Same requirements as `Alloc::create_image`.
+    #[track_caller]
+    pub unsafe fn create_image(
+        &self,
+        image_info: &vk::ImageCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<(vk::Image, Allocation)> {
+        self.allocator.create_image(image_info, create_info)
+    }
+
+    /// See `Allocator::free_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::free_memory`.
+    pub unsafe fn free_memory(&self, allocation: Allocation) -> FreeResult {
+        self.allocator.free_memory(allocation)
+    }
+
+    /// See `Allocator::destroy_buffer`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::destroy_buffer`.
+    pub unsafe fn destroy_buffer(&self, buffer: vk::Buffer, allocation: Allocation) -> FreeResult {
+        self.allocator.destroy_buffer(buffer, allocation)
+    }
+
+    /// See `Allocator::destroy_image`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::destroy_image`.
+    pub unsafe fn destroy_image(&self, image: vk::Image, allocation: Allocation) -> FreeResult {
+        self.allocator.destroy_image(image, allocation)
+    }
+
+    /// See `Allocator::get_allocation_info`.
+    pub fn get_allocation_info(&self, allocation: &Allocation) -> VmaResult<AllocationInfo> {
+        self.allocator.get_allocation_info(allocation)
+    }
+
+    /// See `Allocator::map_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map_memory`.
+    #[track_caller]
+    pub unsafe fn map_memory(&self, allocation: &mut Allocation) -> VmaResult<*mut u8> {
+        self.allocator.map_memory(allocation)
+    }
+
+    /// See `Allocator::unmap_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::unmap_memory`.
+    pub unsafe fn unmap_memory(&self, allocation: &mut Allocation) {
+        self.allocator.unmap_memory(allocation)
+    }
+
+    /// See `Allocator::map`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map`.
+    #[track_caller]
+    pub unsafe fn map<'a>(&'a self, allocation: &'a mut Allocation) -> VmaResult<MappedMemory<'a>> {
+        self.allocator.map(allocation)
+    }
+
+    /// See `Allocator::flush_allocation`.
+    pub fn flush_allocation(
+        &self,
+        allocation: &Allocation,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> VmaResult<()> {
+        self.allocator.flush_allocation(allocation, offset, size)
+    }
+
+    /// See `Allocator::invalidate_allocation`.
+    pub fn invalidate_allocation(
+        &self,
+        allocation: &Allocation,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> VmaResult<()> {
+        self.allocator
+            .invalidate_allocation(allocation, offset, size)
+    }
+
+    /// See `Allocator::bind_buffer_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::bind_buffer_memory`.
+    pub unsafe fn bind_buffer_memory(
+        &self,
+        allocation: &Allocation,
+        buffer: vk::Buffer,
+    ) -> VmaResult<()> {
+        self.allocator.bind_buffer_memory(allocation, buffer)
+    }
+
+    /// See `Allocator::bind_image_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::bind_image_memory`.
+    pub unsafe fn bind_image_memory(
+        &self,
+        allocation: &Allocation,
+        image: vk::Image,
+    ) -> VmaResult<()> {
+        self.allocator.bind_image_memory(allocation, image)
+    }
+
+    /// See `Allocator::destroy`.
+    pub fn destroy(self) -> VmaResult<()> {
+        self.allocator.destroy()
+    }
+}
@@ -0,0 +1,209 @@
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+
+use crate::ffi;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::AllocatorPoolCreateFlags;
+use crate::PoolCreateInfo;
+use crate::{FreeError, FreeResult, VmaError, VmaResult};
+use ash::vk;
+
+/// Abstract model of the state of an `Allocator`, see `Allocator::snapshot`.
+///
+/// Only the layout is captured: custom pools and the size, memory type and name of every live
+/// allocation, not the memory contents. It can be recreated on another allocator with
+/// `Allocator::restore_snapshot`, e.g. to reproduce the memory layout of a fragmentation report
+/// locally.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocatorSnapshot {
+    /// Custom pools, in creation order.
+    pub pools: Vec<PoolSnapshot>,
+    /// Live allocations, in creation order.
+    pub allocations: Vec<AllocationSnapshot>,
+}
+
+/// Custom pool captured in an `AllocatorSnapshot`, fields mirror `PoolCreateInfo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSnapshot {
+    pub name: Option<String>,
+    pub memory_type_index: u32,
+    pub flags: AllocatorPoolCreateFlags,
+    pub block_size: vk::DeviceSize,
+    pub min_block_count: usize,
+    pub max_block_count: usize,
+    pub priority: f32,
+    pub min_allocation_alignment: vk::DeviceSize,
+}
+
+/// Allocation captured in an `AllocatorSnapshot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationSnapshot {
+    pub name: Option<String>,
+    pub size: vk::DeviceSize,
+    pub memory_type_index: u32,
+    /// Index into `AllocatorSnapshot::pools`, or `None` for the default pools.
+    ///
+    /// Allocations adopted with `Allocation::adopt` are always attributed to the default pools.
+    pub pool: Option<usize>,
+}
+
+/// Objects recreated by `Allocator::restore_snapshot`.
+///
+/// The allocations must be freed before the pools are dropped, see `RestoredSnapshot::free`.
+pub struct RestoredSnapshot {
+    /// Recreated pools, in the order of `AllocatorSnapshot::pools`.
+    pub pools: Vec<AllocatorPool>,
+    /// Recreated allocations, in the order of `AllocatorSnapshot::allocations`.
+    pub allocations: Vec<Allocation>,
+}
+
+impl RestoredSnapshot {
+    /// Frees all recreated allocations, then destroys the pools.
+    ///
+    /// The snapshot is handed back in the `FreeError` if the allocations couldn't be freed.
+    pub fn free(mut self, allocator: &Allocator) -> FreeResult<Self> {
+        let allocations = std::mem::take(&mut self.allocations);
+        unsafe { allocator.free_memory_pages(allocations) }.map_err(|err| {
+            self.allocations = err.allocation;
+            FreeError {
+                error: err.error,
+                allocation: self,
+            }
+        })
+    }
+}
+
+impl Allocator {
+    /// Captures the current layout of the allocator, see `AllocatorSnapshot`.
+    pub fn snapshot(&self) -> AllocatorSnapshot {
+        let pools = self.tracker.pools();
+        let mut live: Vec<_> = self
+            .tracker
+            .live()
+            .iter()
+            .map(|(&id, allocation)| (id, allocation.raw, allocation.pool))
+            .collect();
+        live.sort_by_key(|&(id, _, _)| id);
+
+        let pool_snapshots = pools
+            .iter()
+            .map(|pool| unsafe {
+                let mut name: *const ::std::os::raw::c_char = std::ptr::null();
+                ffi::vmaGetPoolName(self.internal, pool.handle.0, &mut name);
+                let info = &pool.create_info;
+                PoolSnapshot {
+                    name: to_string(name),
+                    memory_type_index: info.memory_type_index,
+                    flags: info.flags,
+                    block_size: info.block_size,
+                    min_block_count: info.min_block_count,
+                    max_block_count: info.max_block_count,
+                    priority: info.priority,
+                    min_allocation_alignment: info.min_allocation_alignment,
+                }
+            })
+            .collect();
+        let allocations = live
+            .into_iter()
+            .map(|(_, raw, pool)| unsafe {
+                let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+                ffi::vmaGetAllocationInfo(self.internal, raw, &mut info);
+                AllocationSnapshot {
+                    name: to_string(info.pName),
+                    size: info.size,
+                    memory_type_index: info.memoryType,
+                    pool: pools.iter().position(|p| p.handle.0 == pool.0),
+                }
+            })
+            .collect();
+        AllocatorSnapshot {
+            pools: pool_snapshots,
+            allocations,
+        }
+    }
+
+    /// Recreates the pools and allocations of `snapshot` on this allocator.
+    ///
+    /// Allocations are made in their original order, with the same size and memory type, but
+    /// without alignment requirements since those aren't captured. Memory type indices are used
+    /// as is, so the snapshot should come from a similar device. On failure, everything that was
+    /// already recreated is freed again.
+    #[track_caller]
+    pub fn restore_snapshot(
+        self: &Arc<Self>,
+        snapshot: &AllocatorSnapshot,
+    ) -> VmaResult<RestoredSnapshot> {
+        let mut restored = RestoredSnapshot {
+            pools: Vec::with_capacity(snapshot.pools.len()),
+            allocations: Vec::with_capacity(snapshot.allocations.len()),
+        };
+        match self.restore_into(snapshot, &mut restored) {
+            Ok(()) => Ok(restored),
+            Err(err) => {
+                let _ = restored.free(self);
+                Err(err)
+            }
+        }
+    }
+
+    #[track_caller]
+    fn restore_into(
+        self: &Arc<Self>,
+        snapshot: &AllocatorSnapshot,
+        restored: &mut RestoredSnapshot,
+    ) -> VmaResult<()> {
+        for pool in &snapshot.pools {
+            let created = self.create_pool(&PoolCreateInfo {
+                memory_type_index: pool.memory_type_index,
+                flags: pool.flags,
+                block_size: pool.block_size,
+                min_block_count: pool.min_block_count,
+                max_block_count: pool.max_block_count,
+                priority: pool.priority,
+                min_allocation_alignment: pool.min_allocation_alignment,
+                ..Default::default()
+            })?;
+            if let Some(name) = pool.name.as_deref().and_then(|n| CString::new(n).ok()) {
+                created.set_name(Some(&name));
+            }
+            restored.pools.push(created);
+        }
+
+        let default_pool = self.default_pool();
+        for allocation in &snapshot.allocations {
+            let pool = match allocation.pool {
+                Some(index) => restored.pools.get(index).ok_or(VmaError::InvalidParameter(
+                    "Snapshot allocation refers to a missing pool",
+                ))?,
+                None => &default_pool,
+            };
+            let memory_type_bits = 1u32.checked_shl(allocation.memory_type_index).ok_or(
+                VmaError::InvalidParameter("Snapshot allocation has an invalid memory type index"),
+            )?;
+            let requirements = vk::MemoryRequirements {
+                size: allocation.size,
+                alignment: 1,
+                memory_type_bits,
+            };
+            let created =
+                unsafe { pool.allocate_memory(&requirements, &AllocationCreateInfo::default())? };
+            if let Some(name) = allocation
+                .name
+                .as_deref()
+                .and_then(|n| CString::new(n).ok())
+            {
+                unsafe { ffi::vmaSetAllocationName(self.internal, created.raw, name.as_ptr()) };
+            }
+            restored.allocations.push(created);
+        }
+        Ok(())
+    }
+}
+
+unsafe fn to_string(name: *const ::std::os::raw::c_char) -> Option<String> {
+    (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned())
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Stack of allocations in a linear pool with a single block, freed back to a marker at once.
+///
+/// Allocations are owned by the stack. `StackAllocator::marker` records the current top, and
+/// `StackAllocator::free_to_marker` frees everything allocated after it, most recent first, which
+/// is the "free at once" pattern of linear pools.
+///
+/// An allocation that doesn't fit on top of the stack fails with
+/// `VmaError::OutOfDeviceMemory`.
+pub struct StackAllocator {
+    pool: AllocatorPool,
+    create_info: AllocationCreateInfo,
+    memory_type_bits: u32,
+    stack: Vec<Allocation>,
+}
+
+/// Position in a `StackAllocator`, see `StackAllocator::marker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StackMarker(usize);
+
+impl Allocator {
+    /// Creates a `StackAllocator` of `size` bytes in the given memory type.
+    ///
+    /// # Safety
+    /// `create_info` is used for every allocation, see `Alloc::allocate_memory`.
+    pub unsafe fn create_stack_allocator(
+        self: &Arc<Self>,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<StackAllocator> {
+        Ok(StackAllocator {
+            pool: self.create_linear_pool(memory_type_index, size)?,
+            create_info: create_info.clone(),
+            memory_type_bits: 1 << memory_type_index,
+            stack: Vec::new(),
+        })
+    }
+}
+
+impl StackAllocator {
+    /// Pool backing the stack.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.pool
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` on top of the stack.
+    ///
+    /// Returns the allocation, owned by the stack until it is freed by
+    /// `StackAllocator::free_to_marker`, and its offset in the block.
+    #[track_caller]
+    pub fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&Allocation, vk::DeviceSize)> {
+        let (allocation, offset) = allocate_linear(
+            &self.pool,
+            self.memory_type_bits,
+            size,
+            alignment,
+            &self.create_info,
+        )?;
+        self.stack.push(allocation);
+        Ok((self.stack.last().unwrap(), offset))
+    }
+
+    /// Returns a marker for the current top of the stack.
+    pub fn marker(&self) -> StackMarker {
+        StackMarker(self.stack.len())
+    }
+
+    /// Frees all allocations made after `marker`, most recent first.
+    ///
+    /// Fails with `VmaError::InvalidParameter` if the stack was already freed below `marker`, and
+    /// like `Allocator::free_memory_pages` otherwise, in which case nothing is freed.
+    pub fn free_to_marker(&mut self, marker: StackMarker) -> VmaResult<()> {
+        if marker.0 > self.stack.len() {
+            return Err(VmaError::InvalidParameter(
+                "Stack marker is above the top of the stack",
+            ));
+        }
+        free_stack(&self.pool, &mut self.stack, marker.0)
+    }
+
+    /// Frees all allocations of the stack.
+    pub fn reset(&mut self) -> VmaResult<()> {
+        free_stack(&self.pool, &mut self.stack, 0)
+    }
+
+    /// Frees all allocations, reporting errors that `Drop` would ignore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.reset()
+    }
+
+    /// Number of allocations on the stack.
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns `true` if the stack has no allocations.
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+/// Frees all allocations of the stack, the device must not use them anymore.
+impl Drop for StackAllocator {
+    fn drop(&mut self) {
+        let _ = self.reset();
+    }
+}
+
+/// Allocates from a single block linear pool, returning the offset of the allocation in the block.
+#[track_caller]
+pub(crate) fn allocate_linear(
+    pool: &AllocatorPool,
+    memory_type_bits: u32,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    create_info: &AllocationCreateInfo,
+) -> VmaResult<(Allocation, vk::DeviceSize)> {
+    let requirements = vk::MemoryRequirements {
+        size,
+        alignment,
+        memory_type_bits,
+    };
+    let allocation = unsafe { pool.allocate_memory(&requirements, create_info)? };
+    match pool.allocator().get_allocation_info(&allocation) {
+        Ok(info) => Ok((allocation, info.offset)),
+        Err(err) => {
+            let _ = unsafe { pool.allocator().free_memory(allocation) };
+            Err(err)
+        }
+    }
+}
+
+/// Frees the allocations of `stack` from `start` up, most recent first. The stack is left
+/// untouched on failure.
+pub(crate) fn free_stack(
+    pool: &AllocatorPool,
+    stack: &mut Vec<Allocation>,
+    start: usize,
+) -> VmaResult<()> {
+    let allocator = pool.allocator();
+    for allocation in &stack[start..] {
+        allocator.check_freeable(allocation)?;
+    }
+    unsafe { Ok(allocator.free_memory_pages(stack.drain(start..).rev())?) }
+}
@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::MemoryUsage;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Persistently mapped, host visible buffer of a `StagingBelt`.
+struct StagingChunk {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    mapped: *mut u8,
+    size: vk::DeviceSize,
+    /// Offset of the free part of the chunk
+    cursor: vk::DeviceSize,
+}
+
+impl StagingChunk {
+    /// Offset the next write of `size` bytes aligned to `alignment` would be at, if it fits.
+    fn fit(&self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let offset = self.cursor.next_multiple_of(alignment.max(1));
+        (offset + size <= self.size).then_some(offset)
+    }
+}
+
+/// Uploads through a set of persistently mapped host visible buffers, for per-frame data.
+///
+/// `StagingBelt::write` hands out chunks of staging memory, and `StagingBelt::copy_to_buffer`
+/// also records a copy from them into a device local buffer, submitted with
+/// `StagingBelt::record_copies`. `StagingBelt::finish` tags the staging buffers written so far
+/// with the fence or timeline semaphore value signaled once the device is done with them, and
+/// `StagingBelt::recall` recycles them once that value is reached.
+///
+/// Staging buffers are `chunk_size` bytes, or larger for bigger writes, and are only freed when
+/// the belt is dropped.
+pub struct StagingBelt {
+    allocator: Arc<Allocator>,
+    chunk_size: vk::DeviceSize,
+    /// Chunks written since the last `StagingBelt::finish`
+    active: Vec<StagingChunk>,
+    /// Finished chunks that weren't recalled yet, oldest first
+    in_flight: VecDeque<(u64, Vec<StagingChunk>)>,
+    /// Recalled chunks, ready to be written again
+    free: Vec<StagingChunk>,
+    /// Copies recorded by `StagingBelt::copy_to_buffer` in order, grouped by consecutive source
+    /// and destination buffers
+    copies: Vec<(vk::Buffer, vk::Buffer, Vec<vk::BufferCopy>)>,
+}
+unsafe impl Send for StagingBelt {}
+
+impl Allocator {
+    /// Creates a `StagingBelt` allocating staging buffers of `chunk_size` bytes.
+    pub fn create_staging_belt(self: &Arc<Self>, chunk_size: vk::DeviceSize) -> StagingBelt {
+        StagingBelt {
+            allocator: self.clone(),
+            chunk_size,
+            active: Vec::new(),
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            copies: Vec::new(),
+        }
+    }
+}
+
+impl StagingBelt {
+    /// Reserves `size` bytes of staging memory aligned to `alignment`.
+    ///
+    /// Returns the mapped memory to write, and the staging buffer and offset to copy it from.
+    /// A new staging buffer is created if neither the active nor the recalled ones have room.
+    #[track_caller]
+    pub fn write(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> VmaResult<(&mut [u8], vk::Buffer, vk::DeviceSize)> {
+        let index = match self
+            .active
+            .iter()
+            .position(|chunk| chunk.fit(size, alignment).is_some())
+        {
+            Some(index) => index,
+            None => {
+                let chunk = match self
+                    .free
+                    .iter()
+                    .position(|chunk| chunk.fit(size, alignment).is_some())
+                {
+                    Some(index) => self.free.swap_remove(index),
+                    None => self.create_chunk(size.max(self.chunk_size))?,
+                };
+                self.active.push(chunk);
+                self.active.len() - 1
+            }
+        };
+        let chunk = &mut self.active[index];
+        let offset = chunk.fit(size, alignment).unwrap();
+        chunk.cursor = offset + size;
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(chunk.mapped.add(offset as usize), size as usize)
+        };
+        Ok((data, chunk.buffer, offset))
+    }
+
+    /// Writes `data` to staging memory, and records a copy of it to `dst_offset` in `dst`.
+    #[track_caller]
+    pub fn copy_to_buffer(
+        &mut self,
+        data: &[u8],
+        dst: vk::Buffer,
+        dst_offset: vk::DeviceSize,
+    ) -> VmaResult<()> {
+        let (staging, src, src_offset) = self.write(data.len() as vk::DeviceSize, 4)?;
+        staging.copy_from_slice(data);
+        let region = vk::BufferCopy {
+            src_offset,
+            dst_offset,
+            size: data.len() as vk::DeviceSize,
+        };
+        match self.copies.last_mut() {
+            Some((last_src, last_dst, regions)) if (*last_src, *last_dst) == (src, dst) => {
+                regions.push(region)
+            }
+            _ => self.copies.push((src, dst, vec![region])),
+        }
+        Ok(())
+    }
+
+    /// Flushes the active staging buffers, and records the copies of
+    /// `StagingBelt::copy_to_buffer` into `command_buffer`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be in the recording state, and be submitted before the value passed
+    /// to the next `StagingBelt::finish` is signaled.
+    pub unsafe fn record_copies(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) -> VmaResult<()> {
+        self.allocator.flush_allocations(
+            self.active.iter().map(|chunk| &chunk.allocation),
+            None,
+            None,
+        )?;
+        for (src, dst, regions) in self.copies.drain(..) {
+            device.cmd_copy_buffer(command_buffer, src, dst, &regions);
+        }
+        Ok(())
+    }
+
+    /// Ends the use of the staging buffers written so far, they are recycled once
+    /// `fence_value` is recalled.
+    ///
+    /// Values must increase from one call to the next.
+    pub fn finish(&mut self, fence_value: u64) {
+        debug_assert!(self
+            .in_flight
+            .back()
+            .is_none_or(|&(last, _)| last <= fence_value));
+        if !self.active.is_empty() {
+            self.in_flight
+                .push_back((fence_value, std::mem::take(&mut self.active)));
+        }
+    }
+
+    /// Recycles the staging buffers of all finished writes whose fence value is at most
+    /// `completed_value`.
+    ///
+    /// Returns the number of staging buffers that were recycled.
+    pub fn recall(&mut self, completed_value: u64) -> usize {
+        let mut recalled = 0;
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|&(fence_value, _)| fence_value <= completed_value)
+        {
+            let (_, chunks) = self.in_flight.pop_front().unwrap();
+            recalled += chunks.len();
+            self.free.extend(
+                chunks
+                    .into_iter()
+                    .map(|chunk| StagingChunk { cursor: 0, ..chunk }),
+            );
+        }
+        recalled
+    }
+
+    /// Number of staging buffers, whether active, in flight or recalled.
+    pub fn chunk_count(&self) -> usize {
+        self.active.len()
+            + self
+                .in_flight
+                .iter()
+                .map(|(_, chunks)| chunks.len())
+                .sum::<usize>()
+            + self.free.len()
+    }
+
+    /// Destroys all staging buffers, reporting errors that `Drop` would ignore.
+    ///
+    /// The device must not use them anymore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.destroy_chunks()
+    }
+
+    #[track_caller]
+    fn create_chunk(&self, size: vk::DeviceSize) -> VmaResult<StagingChunk> {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC);
+        let create_info = AllocationCreateInfo {
+            usage: MemoryUsage::AutoPreferHost,
+            flags: AllocationCreateFlags::MAPPED
+                | AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        unsafe {
+            let (buffer, allocation) = self.allocator.create_buffer(&buffer_info, &create_info)?;
+            let mapped = self.allocator.get_allocation_info(&allocation)?.mapped_data;
+            Ok(StagingChunk {
+                buffer,
+                allocation,
+                mapped: mapped.cast(),
+                size,
+                cursor: 0,
+            })
+        }
+    }
+
+    fn destroy_chunks(&mut self) -> VmaResult<()> {
+        let in_flight = self.in_flight.drain(..).flat_map(|(_, chunks)| chunks);
+        let chunks: Vec<_> = self.active.drain(..).chain(in_flight).collect();
+        let mut result = Ok(());
+        for chunk in chunks.into_iter().chain(self.free.drain(..)) {
+            result = result.and(unsafe {
+                self.allocator
+                    .destroy_buffer(chunk.buffer, chunk.allocation)
+                    .map_err(VmaError::from)
+            });
+        }
+        result
+    }
+}
+
+/// Destroys all staging buffers, the device must not use them anymore.
+impl Drop for StagingBelt {
+    fn drop(&mut self) {
+        let _ = self.destroy_chunks();
+    }
+}
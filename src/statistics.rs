@@ -0,0 +1,443 @@
+use std::fmt::{self, Write};
+
+use crate::ffi;
+use crate::pool::{pool_detailed_statistics, pool_name};
+use crate::Allocator;
+use ash::vk;
+
+/// Statistics of memory usage, e.g. of a memory heap or a custom pool.
+///
+/// These are fast to calculate, see `AllocatorPool::get_statistics` and `HeapBudget`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statistics {
+    /// Number of `vk::DeviceMemory` objects - Vulkan memory blocks allocated.
+    pub block_count: u32,
+    /// Number of allocations.
+    ///
+    /// Dedicated allocations have their own blocks, so each one adds 1 to `allocation_count` as
+    /// well as `block_count`.
+    pub allocation_count: u32,
+    /// Number of bytes allocated in `vk::DeviceMemory` blocks.
+    pub block_bytes: vk::DeviceSize,
+    /// Total number of bytes occupied by all allocations, always less or equal than
+    /// `block_bytes`.
+    pub allocation_bytes: vk::DeviceSize,
+}
+
+impl Statistics {
+    /// Converts back to the statistics of the C API.
+    pub fn as_raw(&self) -> ffi::VmaStatistics {
+        ffi::VmaStatistics {
+            blockCount: self.block_count,
+            allocationCount: self.allocation_count,
+            blockBytes: self.block_bytes,
+            allocationBytes: self.allocation_bytes,
+        }
+    }
+}
+
+impl From<&ffi::VmaStatistics> for Statistics {
+    fn from(raw: &ffi::VmaStatistics) -> Self {
+        Statistics {
+            block_count: raw.blockCount,
+            allocation_count: raw.allocationCount,
+            block_bytes: raw.blockBytes,
+            allocation_bytes: raw.allocationBytes,
+        }
+    }
+}
+
+/// More detailed statistics than `Statistics`.
+///
+/// These are slower to calculate, see `Allocator::calculate_statistics` and
+/// `AllocatorPool::calculate_statistics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailedStatistics {
+    /// Basic statistics.
+    pub statistics: Statistics,
+    /// Number of free ranges of memory between allocations.
+    pub unused_range_count: u32,
+    /// Smallest allocation size. `vk::WHOLE_SIZE` if there are 0 allocations.
+    pub allocation_size_min: vk::DeviceSize,
+    /// Largest allocation size. 0 if there are 0 allocations.
+    pub allocation_size_max: vk::DeviceSize,
+    /// Smallest empty range size. `vk::WHOLE_SIZE` if there are 0 empty ranges.
+    pub unused_range_size_min: vk::DeviceSize,
+    /// Largest empty range size. 0 if there are 0 empty ranges.
+    pub unused_range_size_max: vk::DeviceSize,
+}
+
+impl DetailedStatistics {
+    /// Converts back to the statistics of the C API.
+    pub fn as_raw(&self) -> ffi::VmaDetailedStatistics {
+        ffi::VmaDetailedStatistics {
+            statistics: self.statistics.as_raw(),
+            unusedRangeCount: self.unused_range_count,
+            allocationSizeMin: self.allocation_size_min,
+            allocationSizeMax: self.allocation_size_max,
+            unusedRangeSizeMin: self.unused_range_size_min,
+            unusedRangeSizeMax: self.unused_range_size_max,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.statistics.block_count == 0 && self.statistics.allocation_count == 0
+    }
+}
+
+impl From<&ffi::VmaDetailedStatistics> for DetailedStatistics {
+    fn from(raw: &ffi::VmaDetailedStatistics) -> Self {
+        DetailedStatistics {
+            statistics: (&raw.statistics).into(),
+            unused_range_count: raw.unusedRangeCount,
+            allocation_size_min: raw.allocationSizeMin,
+            allocation_size_max: raw.allocationSizeMax,
+            unused_range_size_min: raw.unusedRangeSizeMin,
+            unused_range_size_max: raw.unusedRangeSizeMax,
+        }
+    }
+}
+
+/// Statistics of all memory types and heaps of the allocator, and their total, see
+/// `Allocator::calculate_statistics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TotalStatistics {
+    /// Statistics by memory type index.
+    pub memory_type: [DetailedStatistics; 32],
+    /// Statistics by memory heap index.
+    pub memory_heap: [DetailedStatistics; 16],
+    /// Statistics over all memory types.
+    pub total: DetailedStatistics,
+}
+
+impl TotalStatistics {
+    /// Converts back to the statistics of the C API.
+    pub fn as_raw(&self) -> ffi::VmaTotalStatistics {
+        ffi::VmaTotalStatistics {
+            memoryType: self.memory_type.map(|statistics| statistics.as_raw()),
+            memoryHeap: self.memory_heap.map(|statistics| statistics.as_raw()),
+            total: self.total.as_raw(),
+        }
+    }
+}
+
+impl From<&ffi::VmaTotalStatistics> for TotalStatistics {
+    fn from(raw: &ffi::VmaTotalStatistics) -> Self {
+        TotalStatistics {
+            memory_type: std::array::from_fn(|index| (&raw.memoryType[index]).into()),
+            memory_heap: std::array::from_fn(|index| (&raw.memoryHeap[index]).into()),
+            total: (&raw.total).into(),
+        }
+    }
+}
+
+/// Memory usage and available budget of a memory heap, as returned by VMA.
+///
+/// `Allocator::get_heap_budgets` returns them as `HeapBudget`, along with the heap they belong
+/// to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Budget {
+    /// Statistics of the allocations of the library in the heap.
+    pub statistics: Statistics,
+    /// Estimated current memory usage of the program, in bytes.
+    ///
+    /// Fetched from the system using `VK_EXT_memory_budget` if enabled. It might be higher than
+    /// `statistics.block_bytes` due to other objects occupying the memory, like swapchains,
+    /// pipelines, or `vk::DeviceMemory` blocks allocated outside of this library.
+    pub usage: vk::DeviceSize,
+    /// Estimated amount of memory available to the program, in bytes.
+    ///
+    /// Fetched from the system using `VK_EXT_memory_budget` if enabled. It might be smaller
+    /// than the size of the heap. Exceeding the budget may result in various problems.
+    pub budget: vk::DeviceSize,
+}
+
+impl Budget {
+    /// Converts back to the budget of the C API.
+    pub fn as_raw(&self) -> ffi::VmaBudget {
+        ffi::VmaBudget {
+            statistics: self.statistics.as_raw(),
+            usage: self.usage,
+            budget: self.budget,
+        }
+    }
+}
+
+impl From<&ffi::VmaBudget> for Budget {
+    fn from(raw: &ffi::VmaBudget) -> Self {
+        Budget {
+            statistics: (&raw.statistics).into(),
+            usage: raw.usage,
+            budget: raw.budget,
+        }
+    }
+}
+
+/// Budget of a memory heap, with the properties of the heap, see `Allocator::get_heap_budgets`.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeapBudget {
+    /// Index of the heap in `vk::PhysicalDeviceMemoryProperties::memory_heaps`.
+    pub heap_index: u32,
+    /// Flags of the heap.
+    #[cfg_attr(feature = "serde", serde(with = "serde_heap_flags"))]
+    pub flags: vk::MemoryHeapFlags,
+    /// Size of the heap, in bytes.
+    pub size: vk::DeviceSize,
+    /// Statistics of the allocations of the library in the heap.
+    pub statistics: Statistics,
+    /// Estimated current memory usage of the program, in bytes, see `Budget::usage`.
+    pub usage: vk::DeviceSize,
+    /// Estimated amount of memory available to the program, in bytes, see `Budget::budget`.
+    pub budget: vk::DeviceSize,
+}
+
+impl HeapBudget {
+    pub(crate) fn new(heap_index: u32, heap: &vk::MemoryHeap, budget: &Budget) -> Self {
+        HeapBudget {
+            heap_index,
+            flags: heap.flags,
+            size: heap.size,
+            statistics: budget.statistics,
+            usage: budget.usage,
+            budget: budget.budget,
+        }
+    }
+
+    /// Returns `true` if the heap is device local.
+    pub fn is_device_local(&self) -> bool {
+        self.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+    }
+
+    /// Budget numbers without the heap properties.
+    pub fn to_budget(&self) -> Budget {
+        Budget {
+            statistics: self.statistics,
+            usage: self.usage,
+            budget: self.budget,
+        }
+    }
+
+    /// Converts back to the budget of the C API.
+    pub fn as_raw(&self) -> ffi::VmaBudget {
+        self.to_budget().as_raw()
+    }
+}
+
+// No derive, ash only implements `Debug` for flags with its `debug` feature.
+impl fmt::Debug for HeapBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeapBudget")
+            .field("heap_index", &self.heap_index)
+            .field("flags", &self.flags.as_raw())
+            .field("size", &self.size)
+            .field("statistics", &self.statistics)
+            .field("usage", &self.usage)
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+/// Serializes heap flags as their raw bits.
+#[cfg(feature = "serde")]
+mod serde_heap_flags {
+    use ash::vk;
+
+    pub fn serialize<S: serde::Serializer>(
+        flags: &vk::MemoryHeapFlags,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(flags.as_raw())
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<vk::MemoryHeapFlags, D::Error> {
+        <u32 as serde::Deserialize>::deserialize(deserializer).map(vk::MemoryHeapFlags::from_raw)
+    }
+}
+
+/// Byte count formatted with binary units, e.g. `1.5 MiB`.
+///
+/// Sizes below 1 KiB are printed in bytes. Width and alignment apply to the whole text, so it
+/// lines up in tables, e.g. `format!("{:>10}", ByteSize(size))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        if self.0 < 1024 {
+            return f.pad(&format!("{} B", self.0));
+        }
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        f.pad(&format!("{:.1} {}", value, UNITS[unit]))
+    }
+}
+
+/// `part` as a percentage of `whole`, e.g. `12.5%`, or `-` if `whole` is 0.
+fn percentage(part: vk::DeviceSize, whole: vk::DeviceSize) -> String {
+    if whole == 0 {
+        return "-".to_owned();
+    }
+    format!("{:.1}%", part as f64 * 100.0 / whole as f64)
+}
+
+/// Rows of a statistics table, with a column for the share of the heap budget if budgets are
+/// known.
+struct Table<'a> {
+    budgets: Option<(&'a [HeapBudget], &'a vk::PhysicalDeviceMemoryProperties)>,
+}
+
+impl Table<'_> {
+    fn write_header(&self, f: &mut impl Write) -> fmt::Result {
+        write!(
+            f,
+            "{:<24} {:>8} {:>12} {:>12} {:>12} {:>12}",
+            "", "blocks", "allocations", "used", "unused", "largest free"
+        )?;
+        if self.budgets.is_some() {
+            write!(f, " {:>8}", "budget")?;
+        }
+        writeln!(f)
+    }
+
+    /// Writes a row, with the share of the budget of heap `heap` if any.
+    fn write_row(
+        &self,
+        f: &mut impl Write,
+        label: &str,
+        statistics: &DetailedStatistics,
+        heap: Option<u32>,
+    ) -> fmt::Result {
+        let basic = &statistics.statistics;
+        write!(
+            f,
+            "{:<24} {:>8} {:>12} {:>12} {:>12} {:>12}",
+            label,
+            basic.block_count,
+            basic.allocation_count,
+            ByteSize(basic.allocation_bytes),
+            ByteSize(basic.block_bytes.saturating_sub(basic.allocation_bytes)),
+            ByteSize(statistics.unused_range_size_max)
+        )?;
+        if let Some((budgets, _)) = self.budgets {
+            let budget = match heap {
+                Some(heap) => budgets.get(heap as usize).map_or(0, |budget| budget.budget),
+                None => budgets.iter().map(|budget| budget.budget).sum(),
+            };
+            write!(f, " {:>8}", percentage(basic.block_bytes, budget))?;
+        }
+        writeln!(f)
+    }
+
+    /// Heap of memory type `memory_type`.
+    fn heap_of(&self, memory_type: u32) -> Option<u32> {
+        let (_, properties) = self.budgets?;
+        properties
+            .memory_types
+            .get(memory_type as usize)
+            .map(|memory_type| memory_type.heap_index)
+    }
+
+    fn write_total(&self, f: &mut impl Write, total: &TotalStatistics) -> fmt::Result {
+        self.write_header(f)?;
+        for (index, statistics) in total.memory_type.iter().enumerate() {
+            if !statistics.is_empty() {
+                let label = format!("memory type {}", index);
+                self.write_row(f, &label, statistics, self.heap_of(index as u32))?;
+            }
+        }
+        for (index, statistics) in total.memory_heap.iter().enumerate() {
+            if !statistics.is_empty() {
+                let label = format!("memory heap {}", index);
+                self.write_row(f, &label, statistics, Some(index as u32))?;
+            }
+        }
+        self.write_row(f, "total", &total.total, None)
+    }
+}
+
+/// Single line summary: block and allocation counts, used and unused bytes, largest free range.
+impl fmt::Display for DetailedStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let basic = &self.statistics;
+        write!(
+            f,
+            "{} block(s), {} allocation(s), {} used, {} unused, largest free range {}",
+            basic.block_count,
+            basic.allocation_count,
+            ByteSize(basic.allocation_bytes),
+            ByteSize(basic.block_bytes.saturating_sub(basic.allocation_bytes)),
+            ByteSize(self.unused_range_size_max)
+        )
+    }
+}
+
+/// Table with a row per memory type and heap in use, and the total.
+impl fmt::Display for TotalStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Table { budgets: None }.write_total(f, self)
+    }
+}
+
+/// Single line summary: heap, bytes allocated by the library, and usage against the budget.
+impl fmt::Display for HeapBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heap {}{} ({}): {} in {} block(s), usage {} of {} budget ({})",
+            self.heap_index,
+            if self.is_device_local() {
+                ", device local"
+            } else {
+                ""
+            },
+            ByteSize(self.size),
+            ByteSize(self.statistics.block_bytes),
+            self.statistics.block_count,
+            ByteSize(self.usage),
+            ByteSize(self.budget),
+            percentage(self.usage, self.budget)
+        )
+    }
+}
+
+impl Allocator {
+    /// Formats the statistics of the allocator as a human-readable table.
+    ///
+    /// Memory types and heaps in use are listed like the `Display` implementation of
+    /// `TotalStatistics`, followed by a row per custom pool. The last column is the share of
+    /// the heap budget taken by the blocks of each row.
+    pub fn to_table(&self) -> String {
+        let budgets = self.get_heap_budgets().unwrap_or_default();
+        let properties = unsafe { self.get_memory_properties() };
+        let table = Table {
+            budgets: Some((&budgets, properties)),
+        };
+        let mut text = String::new();
+        // Writing to a String can't fail.
+        if let Ok(total) = self.calculate_statistics() {
+            let _ = table.write_total(&mut text, &total);
+        }
+        for (index, pool) in self.tracker.pools().iter().enumerate() {
+            let name = unsafe { pool_name(self.internal, pool.handle) }
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| format!("pool {}", index));
+            let memory_type = pool.create_info.memory_type_index;
+            let label = format!("{} (type {})", name, memory_type);
+            let statistics = unsafe { pool_detailed_statistics(self.internal, pool.handle) };
+            let _ = table.write_row(&mut text, &label, &statistics, table.heap_of(memory_type));
+        }
+        text
+    }
+}
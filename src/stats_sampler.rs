@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::Allocator;
+use crate::HeapBudget;
+use ash::vk;
+
+/// History of heap budgets, sampled from a background thread or ticked manually.
+///
+/// The history keeps the last `capacity` samples, older ones are dropped. Use
+/// `StatsSampler::summary` to get the minimum, maximum and average of a value over a window,
+/// e.g. to detect slow leaks or to draw memory graphs.
+pub struct StatsSampler {
+    allocator: Arc<Allocator>,
+    history: Arc<Mutex<VecDeque<StatsSample>>>,
+    capacity: usize,
+    thread: Option<(JoinHandle<()>, mpsc::Sender<()>)>,
+}
+
+/// Heap budgets at a point in time, see `StatsSampler`.
+#[derive(Debug, Clone)]
+pub struct StatsSample {
+    /// When the sample was taken.
+    pub time: Instant,
+    /// Budgets of all heaps, see `Allocator::get_heap_budgets`.
+    pub heaps: Vec<HeapBudget>,
+}
+
+/// Minimum, maximum and average of a value over the samples of a window, see
+/// `StatsSampler::summary`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSummary {
+    /// Number of samples in the window.
+    pub samples: usize,
+    /// Smallest value in the window.
+    pub min: vk::DeviceSize,
+    /// Largest value in the window.
+    pub max: vk::DeviceSize,
+    /// Average value over the window, rounded down.
+    pub average: vk::DeviceSize,
+}
+
+impl Allocator {
+    /// Creates a `StatsSampler` keeping the last `capacity` samples, at least one.
+    ///
+    /// The sampler records nothing until `StatsSampler::sample` is called or
+    /// `StatsSampler::start` spawns its thread.
+    pub fn create_stats_sampler(self: &Arc<Self>, capacity: usize) -> StatsSampler {
+        let capacity = capacity.max(1);
+        StatsSampler {
+            allocator: self.clone(),
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            thread: None,
+        }
+    }
+}
+
+impl StatsSampler {
+    /// Records the current heap budgets.
+    pub fn sample(&self) {
+        record(&self.allocator, &self.history, self.capacity);
+    }
+
+    /// Spawns a thread recording the heap budgets every `interval`, until `StatsSampler::stop`
+    /// is called or the sampler is dropped.
+    ///
+    /// A thread that is already running is stopped first.
+    pub fn start(&mut self, interval: Duration) {
+        self.stop();
+        let allocator = self.allocator.clone();
+        let history = self.history.clone();
+        let capacity = self.capacity;
+        let (stop, stopped) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                record(&allocator, &history, capacity);
+            }
+        });
+        self.thread = Some((thread, stop));
+    }
+
+    /// Stops the thread spawned by `StatsSampler::start`, if any. The history is kept.
+    pub fn stop(&mut self) {
+        if let Some((thread, stop)) = self.thread.take() {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+
+    /// Returns `true` if the thread spawned by `StatsSampler::start` is running.
+    pub fn is_running(&self) -> bool {
+        self.thread.is_some()
+    }
+
+    /// Recorded samples, oldest first.
+    pub fn samples(&self) -> Vec<StatsSample> {
+        self.history().iter().cloned().collect()
+    }
+
+    /// Summarizes `metric` of heap `heap_index` over the samples taken in the last `window`,
+    /// e.g. `|heap| heap.usage`.
+    ///
+    /// Returns `None` if there are no such samples.
+    pub fn summary(
+        &self,
+        heap_index: u32,
+        window: Duration,
+        metric: impl Fn(&HeapBudget) -> vk::DeviceSize,
+    ) -> Option<SampleSummary> {
+        let since = Instant::now().checked_sub(window);
+        let history = self.history();
+        let values = history
+            .iter()
+            .filter(|sample| since.is_none_or(|since| sample.time >= since))
+            .filter_map(|sample| sample.heaps.get(heap_index as usize))
+            .map(metric);
+        let (samples, min, max, sum) = values.fold(
+            (0, vk::DeviceSize::MAX, 0, 0u128),
+            |(samples, min, max, sum), value| {
+                (
+                    samples + 1,
+                    min.min(value),
+                    max.max(value),
+                    sum + value as u128,
+                )
+            },
+        );
+        (samples > 0).then(|| SampleSummary {
+            samples,
+            min,
+            max,
+            average: (sum / samples as u128) as vk::DeviceSize,
+        })
+    }
+
+    fn history(&self) -> std::sync::MutexGuard<'_, VecDeque<StatsSample>> {
+        self.history.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Stops the sampling thread.
+impl Drop for StatsSampler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn record(allocator: &Allocator, history: &Mutex<VecDeque<StatsSample>>, capacity: usize) {
+    let Ok(heaps) = allocator.get_heap_budgets() else {
+        return;
+    };
+    let sample = StatsSample {
+        time: Instant::now(),
+        heaps,
+    };
+    let mut history = history.lock().unwrap_or_else(PoisonError::into_inner);
+    if history.len() == capacity {
+        history.pop_front();
+    }
+    history.push_back(sample);
+}
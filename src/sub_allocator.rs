@@ -0,0 +1,254 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateInfo;
+use crate::AllocationInfo;
+use crate::Allocator;
+use crate::MappedMemory;
+use crate::{FreeError, FreeResult, VmaError, VmaResult};
+use ash::vk;
+
+/// Budgeted façade over an `Allocator`.
+///
+/// A `SubAllocator` can be handed to plugins or middleware to let them allocate from the shared
+/// allocator without exceeding their share of memory. The budget is enforced by the wrapper
+/// before calling into VMA: an allocation that would push the usage above the budget fails with
+/// `VmaError::OutOfDeviceMemory`, just like `AllocationCreateFlags::WITHIN_BUDGET`.
+///
+/// Usage is accounted using the real allocation sizes, so it includes alignment padding.
+/// Allocations made through a `SubAllocator` must be freed through it as well.
+pub struct SubAllocator {
+    allocator: Arc<Allocator>,
+    category: String,
+    budget: vk::DeviceSize,
+    allocation_bytes: AtomicU64,
+    allocation_count: AtomicUsize,
+}
+
+/// Statistics of a `SubAllocator`, see `SubAllocator::statistics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubAllocatorStatistics {
+    /// Number of live allocations made through the sub-allocator.
+    pub allocation_count: usize,
+    /// Total size of live allocations made through the sub-allocator, in bytes.
+    pub allocation_bytes: vk::DeviceSize,
+    /// Budget of the sub-allocator, in bytes.
+    pub budget: vk::DeviceSize,
+}
+
+impl Allocator {
+    /// Creates a `SubAllocator` limited to `budget` bytes, labeled with `category`.
+    pub fn create_sub_allocator(
+        self: &Arc<Self>,
+        category: impl Into<String>,
+        budget: vk::DeviceSize,
+    ) -> SubAllocator {
+        SubAllocator {
+            allocator: self.clone(),
+            category: category.into(),
+            budget,
+            allocation_bytes: AtomicU64::new(0),
+            allocation_count: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SubAllocator {
+    /// Category this sub-allocator was created with.
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    /// Budget of this sub-allocator, in bytes.
+    pub fn budget(&self) -> vk::DeviceSize {
+        self.budget
+    }
+
+    /// Returns current usage of this sub-allocator.
+    pub fn statistics(&self) -> SubAllocatorStatistics {
+        SubAllocatorStatistics {
+            allocation_count: self.allocation_count.load(Ordering::Relaxed),
+            allocation_bytes: self.allocation_bytes.load(Ordering::Relaxed),
+            budget: self.budget,
+        }
+    }
+
+    fn reserve(&self, size: vk::DeviceSize) -> VmaResult<()> {
+        self.allocation_bytes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(size).filter(|&used| used <= self.budget)
+            })
+            .map(|_| ())
+            .map_err(|_| VmaError::OutOfDeviceMemory(None))
+    }
+
+    fn release(&self, size: vk::DeviceSize) {
+        self.allocation_bytes.fetch_sub(size, Ordering::AcqRel);
+    }
+
+    /// Settles a reservation of `reserved` bytes once the real allocation size is known.
+    fn settle(&self, reserved: vk::DeviceSize, allocation: &Allocation) -> VmaResult<()> {
+        let size = self.allocator.get_allocation_info(allocation)?.size;
+        if size > reserved {
+            self.reserve(size - reserved)
+                .inspect_err(|_| self.release(reserved))?;
+        } else {
+            self.release(reserved - size);
+        }
+        self.allocation_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn forget(&self, allocation: &Allocation) {
+        // A stale allocation was already forgotten, freeing it reports the error.
+        if let Ok(info) = self.allocator.get_allocation_info(allocation) {
+            self.release(info.size);
+            self.allocation_count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Checks that the allocation can be freed, then forgets it. The allocation is handed back
+    /// either way.
+    fn forget_for_free(&self, allocation: Allocation) -> Result<Allocation, FreeError> {
+        if let Err(error) = self.allocator.check_freeable(&allocation) {
+            return Err(FreeError { error, allocation });
+        }
+        self.forget(&allocation);
+        Ok(allocation)
+    }
+
+    /// Budgeted version of `Allocator::allocate_memory`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::allocate_memory`.
+    #[track_caller]
+    pub unsafe fn allocate_memory(
+        &self,
+        memory_requirements: &vk::MemoryRequirements,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<Allocation> {
+        self.reserve(memory_requirements.size)?;
+        let allocation = self
+            .allocator
+            .allocate_memory(memory_requirements, create_info)
+            .inspect_err(|_| self.release(memory_requirements.size))?;
+        if let Err(err) = self.settle(memory_requirements.size, &allocation) {
+            let _ = self.allocator.free_memory(allocation);
+            return Err(err);
+        }
+        Ok(allocation)
+    }
+
+    /// Budgeted version of `Allocator::create_buffer`.
+    ///
+    /// `buffer_info.size` is checked against the budget before creating the buffer, the real
+    /// allocation size is checked once it is known.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::create_buffer`.
+    #[track_caller]
+    pub unsafe fn create_buffer(
+        &self,
+        buffer_info: &vk::BufferCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<(vk::Buffer, Allocation)> {
+        self.reserve(buffer_info.size)?;
+        let (buffer, allocation) = self
+            .allocator
+            .create_buffer(buffer_info, create_info)
+            .inspect_err(|_| self.release(buffer_info.size))?;
+        if let Err(err) = self.settle(buffer_info.size, &allocation) {
+            let _ = self.allocator.destroy_buffer(buffer, allocation);
+            return Err(err);
+        }
+        Ok((buffer, allocation))
+    }
+
+    /// Budgeted version of `Allocator::create_image`.
+    ///
+    /// The image is created with `device` first, and its memory requirements are checked against
+    /// the budget before any memory is allocated for it. The memory is then allocated with
+    /// `Allocator::allocate_memory_for_image` and bound to the image.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::create_image`, and `device` must be the device of the
+    /// allocator.
+    #[track_caller]
+    pub unsafe fn create_image(
+        &self,
+        device: &ash::Device,
+        image_info: &vk::ImageCreateInfo,
+        create_info: &AllocationCreateInfo,
+    ) -> VmaResult<(vk::Image, Allocation)> {
+        let image = device.create_image(image_info, None)?;
+        let size = device.get_image_memory_requirements(image).size;
+        if let Err(err) = self.reserve(size) {
+            device.destroy_image(image, None);
+            return Err(err);
+        }
+        let allocation = match self.allocator.allocate_memory_for_image(image, create_info) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                self.release(size);
+                device.destroy_image(image, None);
+                return Err(err);
+            }
+        };
+        if let Err(err) = self.allocator.bind_image_memory(&allocation, image) {
+            self.release(size);
+            let _ = self.allocator.free_memory(allocation);
+            device.destroy_image(image, None);
+            return Err(err);
+        }
+        self.allocator.track_resource(&allocation, image);
+        if let Err(err) = self.settle(size, &allocation) {
+            let _ = self.allocator.destroy_image(image, allocation);
+            return Err(err);
+        }
+        Ok((image, allocation))
+    }
+
+    /// Frees memory allocated through this sub-allocator.
+    ///
+    /// # Safety
+    /// `allocation` must have been made by this sub-allocator.
+    pub unsafe fn free_memory(&self, allocation: Allocation) -> FreeResult {
+        let allocation = self.forget_for_free(allocation)?;
+        self.allocator.free_memory(allocation)
+    }
+
+    /// Destroys a buffer created through this sub-allocator and frees its memory.
+    ///
+    /// # Safety
+    /// `allocation` must have been made by this sub-allocator.
+    pub unsafe fn destroy_buffer(&self, buffer: vk::Buffer, allocation: Allocation) -> FreeResult {
+        let allocation = self.forget_for_free(allocation)?;
+        self.allocator.destroy_buffer(buffer, allocation)
+    }
+
+    /// Destroys an image created through this sub-allocator and frees its memory.
+    ///
+    /// # Safety
+    /// `allocation` must have been made by this sub-allocator.
+    pub unsafe fn destroy_image(&self, image: vk::Image, allocation: Allocation) -> FreeResult {
+        let allocation = self.forget_for_free(allocation)?;
+        self.allocator.destroy_image(image, allocation)
+    }
+
+    /// See `Allocator::get_allocation_info`.
+    pub fn get_allocation_info(&self, allocation: &Allocation) -> VmaResult<AllocationInfo> {
+        self.allocator.get_allocation_info(allocation)
+    }
+
+    /// See `Allocator::map`.
+    ///
+    /// # Safety
+    /// Same requirements as `Allocator::map`.
+    #[track_caller]
+    pub unsafe fn map<'a>(&'a self, allocation: &'a mut Allocation) -> VmaResult<MappedMemory<'a>> {
+        self.allocator.map(allocation)
+    }
+}
@@ -0,0 +1,554 @@
+use std::any::Any;
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::ffi;
+use crate::Allocation;
+use crate::AllocationEventKind;
+use crate::AllocationInfo;
+use crate::AllocationRecord;
+use crate::Allocator;
+use crate::PoolCreateInfo;
+use crate::PoolHandle;
+#[cfg(feature = "recording")]
+use crate::RecordedCall;
+use crate::{FreeError, VmaError, VmaResult};
+use ash::vk;
+
+/// Identifier of an `Allocation`, unique for the lifetime of its `Allocator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AllocationId(u64);
+
+impl AllocationId {
+    /// Returns the id as an integer.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for AllocationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Identifies the allocation an error is about, see `VmaError::InvalidAllocation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationIdentity {
+    /// Id of the allocation.
+    pub id: AllocationId,
+    /// Name set with `Allocator::set_allocation_name`, if any.
+    pub name: Option<String>,
+    /// Where the allocation was made, or `None` if it isn't alive anymore.
+    pub location: Option<&'static Location<'static>>,
+}
+
+impl fmt::Display for AllocationIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        if let Some(location) = self.location {
+            write!(f, " allocated at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// Allocations that were still alive when their allocator was destroyed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakReport {
+    /// Leaked allocations, ordered by id.
+    pub allocations: Vec<LeakedAllocation>,
+}
+
+/// Call stack captured when an allocation was made, with the `backtrace` feature.
+///
+/// Frames are resolved when the backtrace is first printed.
+#[cfg(feature = "backtrace")]
+#[derive(Debug, Clone)]
+pub struct AllocationBacktrace(Arc<Backtrace>);
+
+#[cfg(feature = "backtrace")]
+impl AllocationBacktrace {
+    fn capture() -> Self {
+        AllocationBacktrace(Arc::new(Backtrace::force_capture()))
+    }
+
+    /// Returns the captured backtrace.
+    pub fn get(&self) -> &Backtrace {
+        &self.0
+    }
+}
+
+/// Backtraces are equal if they were captured for the same allocation.
+#[cfg(feature = "backtrace")]
+impl PartialEq for AllocationBacktrace {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "backtrace")]
+impl Eq for AllocationBacktrace {}
+
+#[cfg(feature = "backtrace")]
+impl fmt::Display for AllocationBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Allocation listed in a `LeakReport`.
+pub type LeakedAllocation = AllocationRecord;
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} allocation(s) leaked", self.allocations.len())?;
+        for allocation in &self.allocations {
+            write!(
+                f,
+                "\n  {} ({} bytes, {}) allocated at {}",
+                allocation.id,
+                allocation.size,
+                allocation.name.as_deref().unwrap_or("unnamed"),
+                allocation.location
+            )?;
+            #[cfg(feature = "backtrace")]
+            write!(f, "\n{}", allocation.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct LiveAllocation {
+    pub(crate) raw: ffi::VmaAllocation,
+    pub(crate) pool: PoolHandle,
+    pub(crate) location: &'static Location<'static>,
+    #[cfg(feature = "backtrace")]
+    pub(crate) backtrace: AllocationBacktrace,
+    /// Set with `Allocator::set_allocation_pinned`
+    pub(crate) pinned: bool,
+    /// Buffer or image bound to the allocation, only tracked for debug names
+    pub(crate) resource: Option<(vk::ObjectType, u64)>,
+}
+
+/// Outstanding `Allocator::map_memory` calls of an allocation.
+struct MappedAllocation {
+    /// Number of maps not unmapped yet
+    count: u32,
+    /// Where the oldest outstanding map was made
+    location: &'static Location<'static>,
+}
+
+pub(crate) struct LivePool {
+    pub(crate) handle: PoolHandle,
+    pub(crate) create_info: PoolCreateInfo<'static>,
+    /// Placeholder allocations spanning the blocks reserved by `AllocatorPool::reserve`
+    reserved_blocks: Vec<ffi::VmaAllocation>,
+    /// Byte quota set with `AllocatorPool::set_quota`
+    pub(crate) quota: Option<vk::DeviceSize>,
+    /// Serializes allocations from the pool while it has a quota, see
+    /// `Allocator::pool_quota_lock`
+    pub(crate) quota_lock: Arc<Mutex<()>>,
+    /// Structures of the `memory_allocate_next` chain owned by the pool, see
+    /// `Allocator::keep_pool_next`
+    memory_allocate_next: Vec<Box<dyn Any>>,
+}
+
+impl LivePool {
+    /// Bytes held by placeholder allocations, which don't count as usage of the pool.
+    pub(crate) fn reserved_bytes(&self) -> vk::DeviceSize {
+        self.reserved_blocks.len() as vk::DeviceSize * self.create_info.block_size
+    }
+}
+
+/// Whether live allocations and outstanding maps are tracked, i.e. the `minimal-checks` feature
+/// is disabled.
+///
+/// Pools are tracked either way, since they own parts of their create info.
+pub(crate) const BOOKKEEPING: bool = !cfg!(feature = "minimal-checks");
+
+/// Bookkeeping of the live allocations and pools of an `Allocator`.
+pub(crate) struct AllocationTracker {
+    next_allocation_id: AtomicU64,
+    live: Mutex<HashMap<AllocationId, LiveAllocation>>,
+    /// Custom pools in creation order, with their `pNext` chain stripped
+    pools: Mutex<Vec<LivePool>>,
+    /// Allocations with outstanding maps
+    mapped_allocations: Mutex<HashMap<AllocationId, MappedAllocation>>,
+    /// Pools with a running defragmentation, null for the default pools
+    defragmented_pools: Mutex<Vec<PoolHandle>>,
+}
+
+impl AllocationTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_allocation_id: AtomicU64::new(1),
+            live: Mutex::new(HashMap::new()),
+            pools: Mutex::new(Vec::new()),
+            mapped_allocations: Mutex::new(HashMap::new()),
+            defragmented_pools: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn live(&self) -> MutexGuard<'_, HashMap<AllocationId, LiveAllocation>> {
+        self.live.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn pools(&self) -> MutexGuard<'_, Vec<LivePool>> {
+        self.pools.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn mapped_allocations(&self) -> MutexGuard<'_, HashMap<AllocationId, MappedAllocation>> {
+        self.mapped_allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Allocator {
+    /// Wraps a freshly made `VmaAllocation`, recording it as live.
+    pub(crate) fn track(
+        &self,
+        raw: ffi::VmaAllocation,
+        pool: PoolHandle,
+        location: &'static Location<'static>,
+    ) -> Allocation {
+        self.fill_allocation(raw, |patterns| patterns.allocated);
+        self.track_existing(raw, pool, location)
+    }
+
+    /// Wraps a `VmaAllocation` that may already hold data, recording it as live.
+    pub(crate) fn track_existing(
+        &self,
+        raw: ffi::VmaAllocation,
+        pool: PoolHandle,
+        location: &'static Location<'static>,
+    ) -> Allocation {
+        let id = AllocationId(
+            self.tracker
+                .next_allocation_id
+                .fetch_add(1, Ordering::Relaxed),
+        );
+        if BOOKKEEPING {
+            self.tracker.live().insert(
+                id,
+                LiveAllocation {
+                    raw,
+                    pool,
+                    location,
+                    #[cfg(feature = "backtrace")]
+                    backtrace: AllocationBacktrace::capture(),
+                    pinned: false,
+                    resource: None,
+                },
+            );
+        }
+        if !self.hooks.is_empty() {
+            let info = raw_allocation_info(self, raw);
+            self.notify_hooks(AllocationEventKind::Allocated, id, Some(pool), &info);
+        }
+        Allocation { raw, id }
+    }
+
+    /// Records the allocation as freed, right before it's passed to VMA.
+    pub(crate) fn untrack(&self, allocation: &Allocation) {
+        self.fill_allocation(allocation.raw, |patterns| patterns.freed);
+        // The pool isn't known without the live allocations
+        let live = BOOKKEEPING.then(|| self.tracker.live().remove(&allocation.id));
+        #[cfg(feature = "recording")]
+        self.record(|_| RecordedCall::Free {
+            id: allocation.id.0,
+        });
+        let pool = match live {
+            Some(Some(live)) => Some(live.pool),
+            Some(None) => return,
+            None => None,
+        };
+        if !self.hooks.is_empty() {
+            let info = raw_allocation_info(self, allocation.raw);
+            self.notify_hooks(AllocationEventKind::Freed, allocation.id, pool, &info);
+        }
+    }
+
+    /// Ids of the live allocations by handle, for looking up allocations handed out by VMA,
+    /// along with whether they are pinned.
+    pub(crate) fn ids_by_raw(&self) -> HashMap<ffi::VmaAllocation, (AllocationId, bool)> {
+        self.tracker
+            .live()
+            .iter()
+            .map(|(&id, live)| (live.raw, (id, live.pinned)))
+            .collect()
+    }
+
+    /// Records an allocation as freed by VMA itself, e.g. by a defragmentation pass, `info`
+    /// being its last allocation info.
+    pub(crate) fn untrack_id(&self, id: AllocationId, info: &AllocationInfo) {
+        let live = self.tracker.live().remove(&id);
+        self.tracker.mapped_allocations().remove(&id);
+        #[cfg(feature = "recording")]
+        self.record(|_| RecordedCall::Free { id: id.0 });
+        if let Some(live) = live {
+            self.notify_hooks(AllocationEventKind::Freed, id, Some(live.pool), info);
+        }
+    }
+
+    /// Fails with `VmaError::StaleAllocation` if `allocation` was already freed.
+    ///
+    /// Ids are never reused, so they double as generation stamps: a handle duplicated with
+    /// `Allocation::clone_raw` is stale once any copy is freed, even if VMA reuses the
+    /// `VmaAllocation` pointer. The check is only performed with debug assertions enabled, and
+    /// without the `minimal-checks` feature.
+    #[inline]
+    pub(crate) fn check_allocation(&self, allocation: &Allocation) -> VmaResult<()> {
+        if cfg!(debug_assertions)
+            && BOOKKEEPING
+            && self
+                .tracker
+                .live()
+                .get(&allocation.id)
+                .is_none_or(|live| live.raw != allocation.raw)
+        {
+            return Err(VmaError::StaleAllocation(allocation.id));
+        }
+        Ok(())
+    }
+
+    /// Looks up the name and creation location of `allocation` for error messages.
+    pub(crate) fn allocation_identity(&self, allocation: &Allocation) -> AllocationIdentity {
+        let location = self
+            .tracker
+            .live()
+            .get(&allocation.id)
+            .filter(|live| live.raw == allocation.raw)
+            .map(|live| live.location);
+        let name = location.and_then(|_| unsafe {
+            let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+            ffi::vmaGetAllocationInfo(self.internal, allocation.raw, &mut info);
+            (!info.pName.is_null())
+                .then(|| CStr::from_ptr(info.pName).to_string_lossy().into_owned())
+        });
+        AllocationIdentity {
+            id: allocation.id,
+            name,
+            location,
+        }
+    }
+
+    /// Fails with `VmaError::InvalidAllocation`, naming `allocation`.
+    pub(crate) fn invalid_allocation<T>(
+        &self,
+        allocation: &Allocation,
+        reason: &'static str,
+    ) -> VmaResult<T> {
+        Err(VmaError::InvalidAllocation {
+            allocation: self.allocation_identity(allocation),
+            reason,
+        })
+    }
+
+    /// Panics if `allocation` was already freed, for functions that can't report errors.
+    #[inline]
+    pub(crate) fn assert_live(&self, allocation: &Allocation) {
+        if let Err(err) = self.check_allocation(allocation) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Records a successful `vmaMapMemory` of the allocation.
+    pub(crate) fn track_map(&self, allocation: &Allocation, location: &'static Location<'static>) {
+        if !BOOKKEEPING {
+            return;
+        }
+        self.tracker
+            .mapped_allocations()
+            .entry(allocation.id)
+            .or_insert(MappedAllocation { count: 0, location })
+            .count += 1;
+    }
+
+    /// Records a `vmaUnmapMemory` of the allocation.
+    pub(crate) fn untrack_map(&self, allocation: &Allocation) {
+        if !BOOKKEEPING {
+            return;
+        }
+        let mut mapped = self.tracker.mapped_allocations();
+        if let Some(entry) = mapped.get_mut(&allocation.id) {
+            entry.count -= 1;
+            if entry.count == 0 {
+                mapped.remove(&allocation.id);
+            }
+        }
+    }
+
+    /// Fails with `VmaError::StillMapped` if the allocation has outstanding maps.
+    pub(crate) fn check_unmapped(&self, allocation: &Allocation) -> VmaResult<()> {
+        if !BOOKKEEPING {
+            return Ok(());
+        }
+        let mapped_at = self
+            .tracker
+            .mapped_allocations()
+            .get(&allocation.id)
+            .map(|mapped| mapped.location);
+        match mapped_at {
+            Some(location) => Err(VmaError::StillMapped {
+                allocation: self.allocation_identity(allocation),
+                location,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the allocation can be freed.
+    pub(crate) fn check_freeable(&self, allocation: &Allocation) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        self.check_unmapped(allocation)
+    }
+
+    /// Checks that the allocation can be freed, then records it as freed. The allocation is
+    /// handed back either way.
+    pub(crate) fn untrack_for_free(&self, allocation: Allocation) -> Result<Allocation, FreeError> {
+        if let Err(error) = self.check_freeable(&allocation) {
+            return Err(FreeError { error, allocation });
+        }
+        self.untrack(&allocation);
+        Ok(allocation)
+    }
+
+    /// Records a freshly created custom pool.
+    pub(crate) fn track_pool(&self, handle: PoolHandle, create_info: &PoolCreateInfo) {
+        let create_info = PoolCreateInfo {
+            memory_allocate_next: std::ptr::null(),
+            _marker: std::marker::PhantomData,
+            ..*create_info
+        };
+        #[cfg(feature = "recording")]
+        self.record_pool(handle, &create_info);
+        self.tracker.pools().push(LivePool {
+            handle,
+            create_info,
+            reserved_blocks: Vec::new(),
+            quota: None,
+            quota_lock: Arc::default(),
+            memory_allocate_next: Vec::new(),
+        });
+    }
+
+    /// Keeps a structure of the `memory_allocate_next` chain of the pool alive until the pool
+    /// is destroyed.
+    pub(crate) fn keep_pool_next(&self, handle: PoolHandle, next: Box<dyn Any>) {
+        if let Some(pool) = self
+            .tracker
+            .pools()
+            .iter_mut()
+            .find(|pool| pool.handle.0 == handle.0)
+        {
+            pool.memory_allocate_next.push(next);
+        }
+    }
+
+    /// Records a placeholder allocation keeping a block of the pool alive.
+    pub(crate) fn track_reserved_block(&self, handle: PoolHandle, raw: ffi::VmaAllocation) {
+        if let Some(pool) = self
+            .tracker
+            .pools()
+            .iter_mut()
+            .find(|pool| pool.handle.0 == handle.0)
+        {
+            pool.reserved_blocks.push(raw);
+        }
+    }
+
+    pub(crate) fn has_reserved_blocks(&self, handle: PoolHandle) -> bool {
+        !handle.0.is_null()
+            && self
+                .tracker
+                .pools()
+                .iter()
+                .any(|pool| pool.handle.0 == handle.0 && !pool.reserved_blocks.is_empty())
+    }
+
+    /// Frees up to `count` placeholder allocations of the pool, most recent first, so their
+    /// blocks can be used by regular allocations.
+    pub(crate) fn release_reserved_blocks(&self, handle: PoolHandle, count: usize) {
+        let released: Vec<ffi::VmaAllocation> = match self
+            .tracker
+            .pools()
+            .iter_mut()
+            .find(|pool| pool.handle.0 == handle.0)
+        {
+            Some(pool) => {
+                let keep = pool.reserved_blocks.len().saturating_sub(count);
+                pool.reserved_blocks.drain(keep..).rev().collect()
+            }
+            None => return,
+        };
+        for raw in released {
+            unsafe { ffi::vmaFreeMemory(self.internal, raw) };
+        }
+    }
+
+    /// Records the pool as destroyed, right before it's passed to VMA.
+    pub(crate) fn untrack_pool(&self, handle: PoolHandle) {
+        #[cfg(feature = "recording")]
+        self.record_pool_destruction(handle);
+        self.tracker
+            .pools()
+            .retain(|pool| pool.handle.0 != handle.0);
+    }
+
+    /// Records a defragmentation of the pool, failing with `VmaError::InvalidParameter` if one
+    /// is already running.
+    pub(crate) fn track_defragmentation(&self, handle: PoolHandle) -> VmaResult<()> {
+        let mut pools = self
+            .tracker
+            .defragmented_pools
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if pools.iter().any(|pool| pool.0 == handle.0) {
+            return Err(VmaError::InvalidParameter(
+                "The pool is already being defragmented",
+            ));
+        }
+        pools.push(handle);
+        Ok(())
+    }
+
+    /// Records the end of a defragmentation of the pool.
+    pub(crate) fn untrack_defragmentation(&self, handle: PoolHandle) {
+        self.tracker
+            .defragmented_pools
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|pool| pool.0 != handle.0);
+    }
+
+    /// Lists allocations that are still alive, if any.
+    pub(crate) fn leak_report(&self) -> Option<LeakReport> {
+        let allocations = self.live_allocations();
+        if allocations.is_empty() {
+            return None;
+        }
+        Some(LeakReport { allocations })
+    }
+}
+
+pub(crate) fn raw_allocation_info(
+    allocator: &Allocator,
+    allocation: ffi::VmaAllocation,
+) -> AllocationInfo {
+    unsafe {
+        let mut info: ffi::VmaAllocationInfo = std::mem::zeroed();
+        ffi::vmaGetAllocationInfo(allocator.internal, allocation, &mut info);
+        info.into()
+    }
+}
@@ -0,0 +1,249 @@
+use crate::ffi;
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::MemoryUsage;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+use bytemuck::Pod;
+
+/// Records commands with `record` into a one time command buffer allocated from
+/// `command_pool`, submits it to `queue` and waits for it to complete.
+unsafe fn submit_and_wait(
+    device: &ash::Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    record: impl FnOnce(vk::CommandBuffer),
+) -> VmaResult<()> {
+    let command_buffer = device.allocate_command_buffers(
+        &vk::CommandBufferAllocateInfo::default()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1),
+    )?[0];
+    let result = (|| {
+        device.begin_command_buffer(
+            command_buffer,
+            &vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        )?;
+        record(command_buffer);
+        device.end_command_buffer(command_buffer)?;
+        let fence = device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+        let command_buffers = [command_buffer];
+        let submit = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        let result = device
+            .queue_submit(queue, &[submit], fence)
+            .and_then(|()| device.wait_for_fences(&[fence], true, u64::MAX));
+        device.destroy_fence(fence, None);
+        result
+    })();
+    device.free_command_buffers(command_pool, &[command_buffer]);
+    Ok(result?)
+}
+
+impl Allocator {
+    /// Creates a device local buffer initialized with `data`, e.g. for immutable geometry.
+    ///
+    /// The buffer is created with `usage` and `vk::BufferUsageFlags::TRANSFER_DST`. If its memory
+    /// ends up host visible, e.g. on integrated GPUs or with resizable BAR, `data` is written
+    /// directly. Otherwise it's copied through a temporary staging buffer, with a command buffer
+    /// allocated from `command_pool` and submitted to `queue`. The function waits for the copy to
+    /// complete and destroys the staging buffer before returning.
+    ///
+    /// # Safety
+    /// `device` must be the allocator's device, `command_pool` must be a pool of the queue family
+    /// of `queue`, and both must be externally synchronized.
+    #[track_caller]
+    pub unsafe fn create_device_buffer_init(
+        &self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        usage: vk::BufferUsageFlags,
+        data: &[u8],
+    ) -> VmaResult<(vk::Buffer, Allocation)> {
+        if data.is_empty() {
+            return Err(VmaError::InvalidParameter("Buffers can't be empty"));
+        }
+        let size = data.len() as vk::DeviceSize;
+        let (buffer, allocation) = self.create_buffer(
+            &vk::BufferCreateInfo::default()
+                .size(size)
+                .usage(usage | vk::BufferUsageFlags::TRANSFER_DST),
+            &AllocationCreateInfo {
+                usage: MemoryUsage::AutoPreferDevice,
+                flags: AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+                    | AllocationCreateFlags::HOST_ACCESS_ALLOW_TRANSFER_INSTEAD
+                    | AllocationCreateFlags::MAPPED,
+                ..Default::default()
+            },
+        )?;
+        match self.init_device_buffer(device, queue, command_pool, buffer, &allocation, data) {
+            Ok(()) => Ok((buffer, allocation)),
+            Err(err) => {
+                let _ = self.destroy_buffer(buffer, allocation);
+                Err(err)
+            }
+        }
+    }
+
+    #[track_caller]
+    unsafe fn init_device_buffer(
+        &self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        buffer: vk::Buffer,
+        allocation: &Allocation,
+        data: &[u8],
+    ) -> VmaResult<()> {
+        let size = data.len() as vk::DeviceSize;
+        let info = self.get_allocation_info(allocation)?;
+        if !info.mapped_data.is_null() {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), info.mapped_data.cast(), data.len());
+            return self.flush_allocation(allocation, 0, size);
+        }
+
+        let (staging, staging_allocation) = self.create_buffer(
+            &vk::BufferCreateInfo::default()
+                .size(size)
+                .usage(vk::BufferUsageFlags::TRANSFER_SRC),
+            &AllocationCreateInfo {
+                usage: MemoryUsage::AutoPreferHost,
+                flags: AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+                    | AllocationCreateFlags::MAPPED,
+                ..Default::default()
+            },
+        )?;
+        let result = (|| {
+            let mapped = self.get_allocation_info(&staging_allocation)?.mapped_data;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.cast(), data.len());
+            self.flush_allocation(&staging_allocation, 0, size)?;
+            submit_and_wait(device, queue, command_pool, |command_buffer| {
+                let region = vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size,
+                };
+                device.cmd_copy_buffer(command_buffer, staging, buffer, &[region]);
+            })
+        })();
+        let _ = self.destroy_buffer(staging, staging_allocation);
+        result
+    }
+
+    /// Copies `count` elements of `T` at `offset` in a host visible allocation into a `Vec`.
+    ///
+    /// The memory is mapped, invalidated and unmapped again, with
+    /// `vmaCopyAllocationToMemory`. Fails if `T` is zero-sized, if the range is outside of the
+    /// allocation, or if the allocation isn't host visible, see
+    /// `Allocator::read_device_buffer` for device local memory.
+    ///
+    /// # Safety
+    /// The device must not write the read range concurrently, and its previous writes must have
+    /// been made available to the host.
+    #[track_caller]
+    pub unsafe fn read_allocation<T: Pod>(
+        &self,
+        allocation: &Allocation,
+        offset: vk::DeviceSize,
+        count: usize,
+    ) -> VmaResult<Vec<T>> {
+        if std::mem::size_of::<T>() == 0 {
+            return Err(VmaError::InvalidParameter(
+                "Cannot read an allocation as zero-sized types",
+            ));
+        }
+        let info = self.get_allocation_info(allocation)?;
+        let size = count
+            .checked_mul(std::mem::size_of::<T>())
+            .map(|size| size as vk::DeviceSize);
+        let in_bounds = size
+            .and_then(|size| offset.checked_add(size))
+            .is_some_and(|end| end <= info.size);
+        if !in_bounds {
+            return self.invalid_allocation(allocation, "Range is outside of the allocation");
+        }
+        let mut property_flags = vk::MemoryPropertyFlags::empty();
+        ffi::vmaGetMemoryTypeProperties(self.internal, info.memory_type, &mut property_flags);
+        if !property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return self.invalid_allocation(allocation, "Allocation is not host visible");
+        }
+        let mut data = Vec::<T>::with_capacity(count);
+        ffi::vmaCopyAllocationToMemory(
+            self.internal,
+            allocation.raw,
+            offset,
+            data.as_mut_ptr().cast(),
+            size.unwrap(),
+        )
+        .result()?;
+        data.set_len(count);
+        Ok(data)
+    }
+
+    /// Copies `count` elements of `T` at `offset` in `buffer` into a `Vec`, e.g. to read back
+    /// a compute result or a screenshot from device local memory.
+    ///
+    /// The range is copied to a temporary host visible buffer, with a command buffer allocated
+    /// from `command_pool` and submitted to `queue`, and the function waits for the copy to
+    /// complete before reading it with `Allocator::read_allocation`.
+    ///
+    /// # Safety
+    /// `device` must be the allocator's device, `command_pool` must be a pool of the queue family
+    /// of `queue`, and both must be externally synchronized. `buffer` must have been created with
+    /// `vk::BufferUsageFlags::TRANSFER_SRC`, and the range must be in bounds and not written by
+    /// commands that are still pending.
+    #[track_caller]
+    pub unsafe fn read_device_buffer<T: Pod>(
+        &self,
+        device: &ash::Device,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count: usize,
+    ) -> VmaResult<Vec<T>> {
+        let size = std::mem::size_of::<T>()
+            .checked_mul(count)
+            .filter(|&size| size != 0)
+            .ok_or(VmaError::InvalidParameter("Range is empty or too large"))?
+            as vk::DeviceSize;
+        let (staging, staging_allocation) = self.create_buffer(
+            &vk::BufferCreateInfo::default()
+                .size(size)
+                .usage(vk::BufferUsageFlags::TRANSFER_DST),
+            &AllocationCreateInfo {
+                usage: MemoryUsage::AutoPreferHost,
+                flags: AllocationCreateFlags::HOST_ACCESS_RANDOM,
+                ..Default::default()
+            },
+        )?;
+        let result = submit_and_wait(device, queue, command_pool, |command_buffer| {
+            let region = vk::BufferCopy {
+                src_offset: offset,
+                dst_offset: 0,
+                size,
+            };
+            device.cmd_copy_buffer(command_buffer, buffer, staging, &[region]);
+            let barrier = vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        })
+        .and_then(|()| self.read_allocation(&staging_allocation, 0, count));
+        let _ = self.destroy_buffer(staging, staging_allocation);
+        result
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::ManagedBuffer;
+use crate::MemoryUsage;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+use bytemuck::Pod;
+
+/// Bump allocator of uniform data in a persistently mapped buffer, for dynamic uniform buffer
+/// descriptors.
+///
+/// Every allocation starts at a multiple of `minUniformBufferOffsetAlignment`, so its offset can
+/// be used as is as a dynamic offset of a `vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC`
+/// descriptor of the arena's buffer. `UniformArena::reset` frees everything at once, typically
+/// with one arena per frame in flight.
+///
+/// An allocation that doesn't fit in the rest of the buffer fails with
+/// `VmaError::OutOfDeviceMemory`.
+pub struct UniformArena {
+    allocator: Arc<Allocator>,
+    buffer: ManagedBuffer,
+    mapped: *mut u8,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    /// Offset of the free part of the buffer
+    cursor: vk::DeviceSize,
+}
+unsafe impl Send for UniformArena {}
+
+impl Allocator {
+    /// Creates a `UniformArena` of `size` bytes.
+    ///
+    /// The buffer prefers device local memory that is host visible, and falls back to host
+    /// memory otherwise.
+    pub fn create_uniform_arena(self: &Arc<Self>, size: vk::DeviceSize) -> VmaResult<UniformArena> {
+        if size > u32::MAX as vk::DeviceSize {
+            return Err(VmaError::InvalidParameter(
+                "Dynamic offsets can't address more than 4 GiB",
+            ));
+        }
+        let alignment = unsafe { self.get_physical_device_properties()? }
+            .limits
+            .min_uniform_buffer_offset_alignment
+            .max(1);
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER);
+        let create_info = AllocationCreateInfo {
+            usage: MemoryUsage::AutoPreferDevice,
+            flags: AllocationCreateFlags::MAPPED
+                | AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let buffer = unsafe { self.create_managed_buffer(&buffer_info, &create_info)? };
+        Ok(UniformArena {
+            allocator: self.clone(),
+            mapped: buffer.info().mapped_data.cast(),
+            buffer,
+            size,
+            alignment,
+            cursor: 0,
+        })
+    }
+}
+
+impl UniformArena {
+    /// Reserves `size` bytes, rounded up to the offset alignment.
+    ///
+    /// Returns the dynamic offset of the allocation in `UniformArena::buffer`, and its mapped
+    /// memory.
+    pub fn alloc(&mut self, size: vk::DeviceSize) -> VmaResult<(u32, &mut [u8])> {
+        let aligned_size = size
+            .checked_next_multiple_of(self.alignment)
+            .filter(|&aligned_size| aligned_size <= self.size - self.cursor)
+            .ok_or(VmaError::OutOfDeviceMemory(None))?;
+        let offset = self.cursor;
+        self.cursor += aligned_size;
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(self.mapped.add(offset as usize), size as usize)
+        };
+        Ok((offset as u32, data))
+    }
+
+    /// Copies `value` to a new allocation and returns its dynamic offset.
+    pub fn push<T: Pod>(&mut self, value: &T) -> VmaResult<u32> {
+        let bytes = bytemuck::bytes_of(value);
+        let (offset, data) = self.alloc(bytes.len() as vk::DeviceSize)?;
+        data.copy_from_slice(bytes);
+        Ok(offset)
+    }
+
+    /// Flushes the allocations made since the last `UniformArena::reset`, for memory that isn't
+    /// host coherent.
+    pub fn flush(&self) -> VmaResult<()> {
+        self.allocator
+            .flush_allocation(self.buffer.allocation(), 0, self.cursor)
+    }
+
+    /// Frees all allocations at once.
+    ///
+    /// # Safety
+    /// The device must be done reading them.
+    pub unsafe fn reset(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Buffer the offsets returned by `UniformArena::alloc` are relative to.
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer.buffer()
+    }
+
+    /// Alignment of the allocations, the device's `minUniformBufferOffsetAlignment`.
+    pub fn alignment(&self) -> vk::DeviceSize {
+        self.alignment
+    }
+
+    /// Number of bytes allocated since the last `UniformArena::reset`, including padding.
+    pub fn used(&self) -> vk::DeviceSize {
+        self.cursor
+    }
+}
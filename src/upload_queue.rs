@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::Alloc;
+use crate::Allocation;
+use crate::AllocationCreateFlags;
+use crate::AllocationCreateInfo;
+use crate::Allocator;
+use crate::AllocatorPool;
+use crate::MemoryUsage;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Copy from a staging buffer recorded by an `UploadQueue`.
+enum UploadCopy {
+    Buffer {
+        dst: vk::Buffer,
+        region: vk::BufferCopy,
+    },
+    Image {
+        dst: vk::Image,
+        layout: vk::ImageLayout,
+        region: vk::BufferImageCopy,
+    },
+}
+
+/// Uploads of an `UploadQueue` submitted together.
+#[derive(Default)]
+struct UploadBatch {
+    staging: Vec<(vk::Buffer, Allocation)>,
+    copies: Vec<(vk::Buffer, UploadCopy)>,
+    callbacks: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl UploadBatch {
+    fn is_empty(&self) -> bool {
+        self.copies.is_empty() && self.callbacks.is_empty()
+    }
+}
+
+/// Asynchronous uploads to buffers and images through a dedicated transfer queue.
+///
+/// Uploads are staged in a linear pool of the allocator, used as a ring buffer, and batched
+/// until `UploadQueue::submit`, typically once per frame. Every batch signals the next value of
+/// a timeline semaphore, returned by the upload functions, which other queues can wait on with
+/// `UploadQueue::semaphore`. `UploadQueue::poll` frees the staging memory of completed batches
+/// and runs their `UploadQueue::on_complete` callbacks.
+///
+/// Images must be in `layout` when the copies execute, and resources shared with other queue
+/// families need ownership transfers, both are left to the caller.
+pub struct UploadQueue {
+    device: ash::Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    semaphore: vk::Semaphore,
+    /// Value signaled by the next `UploadQueue::submit`
+    next_value: u64,
+    /// Uploads since the last `UploadQueue::submit`
+    pending: UploadBatch,
+    /// Submitted batches that weren't polled yet, oldest first
+    in_flight: VecDeque<(u64, UploadBatch, vk::CommandBuffer)>,
+    /// Command buffers of polled batches, ready to be recorded again
+    free_command_buffers: Vec<vk::CommandBuffer>,
+    pool: AllocatorPool,
+}
+unsafe impl Send for UploadQueue {}
+
+impl Allocator {
+    /// Creates an `UploadQueue` submitting to `queue` of `queue_family_index`, with a staging
+    /// ring of `staging_size` bytes.
+    ///
+    /// The `timelineSemaphore` feature of the device must be enabled.
+    ///
+    /// # Safety
+    /// `device` must be the allocator's device, and `queue` must only be used by the upload
+    /// queue until it is dropped.
+    pub unsafe fn create_upload_queue(
+        self: &Arc<Self>,
+        device: &ash::Device,
+        queue: vk::Queue,
+        queue_family_index: u32,
+        staging_size: vk::DeviceSize,
+    ) -> VmaResult<UploadQueue> {
+        let memory_type_index = self.find_memory_type_index_for_buffer_info(
+            &vk::BufferCreateInfo::default()
+                .size(staging_size)
+                .usage(vk::BufferUsageFlags::TRANSFER_SRC),
+            &AllocationCreateInfo {
+                usage: MemoryUsage::AutoPreferHost,
+                flags: AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        )?;
+        let pool = self.create_linear_pool(memory_type_index, staging_size)?;
+        let command_pool = device.create_command_pool(
+            &vk::CommandPoolCreateInfo::default()
+                .flags(
+                    vk::CommandPoolCreateFlags::TRANSIENT
+                        | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+                )
+                .queue_family_index(queue_family_index),
+            None,
+        )?;
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore = match device.create_semaphore(
+            &vk::SemaphoreCreateInfo::default().push_next(&mut type_info),
+            None,
+        ) {
+            Ok(semaphore) => semaphore,
+            Err(err) => {
+                device.destroy_command_pool(command_pool, None);
+                return Err(err.into());
+            }
+        };
+        Ok(UploadQueue {
+            device: device.clone(),
+            queue,
+            command_pool,
+            semaphore,
+            next_value: 1,
+            pending: UploadBatch::default(),
+            in_flight: VecDeque::new(),
+            free_command_buffers: Vec::new(),
+            pool,
+        })
+    }
+}
+
+impl UploadQueue {
+    /// Timeline semaphore signaled with the value of each batch once its uploads are done.
+    pub fn semaphore(&self) -> vk::Semaphore {
+        self.semaphore
+    }
+
+    /// Pool the staging memory is allocated from.
+    pub fn pool(&self) -> &AllocatorPool {
+        &self.pool
+    }
+
+    /// Stages `data` to be copied to `dst_offset` in `dst` by the next `UploadQueue::submit`.
+    ///
+    /// Returns the semaphore value signaled once the copy is done. `dst` must have been created
+    /// with `vk::BufferUsageFlags::TRANSFER_DST`.
+    #[track_caller]
+    pub fn upload_buffer(
+        &mut self,
+        data: &[u8],
+        dst: vk::Buffer,
+        dst_offset: vk::DeviceSize,
+    ) -> VmaResult<u64> {
+        let src = self.stage(data)?;
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset,
+            size: data.len() as vk::DeviceSize,
+        };
+        self.pending
+            .copies
+            .push((src, UploadCopy::Buffer { dst, region }));
+        Ok(self.next_value)
+    }
+
+    /// Stages `data` to be copied to `region` of `dst` by the next `UploadQueue::submit`.
+    ///
+    /// `region.buffer_offset` is ignored, `region.buffer_row_length` and
+    /// `region.buffer_image_height` describe the layout of `data`.
+    ///
+    /// Returns the semaphore value signaled once the copy is done. `dst` must have been created
+    /// with `vk::ImageUsageFlags::TRANSFER_DST`, and be in `layout` when the copy executes.
+    #[track_caller]
+    pub fn upload_image(
+        &mut self,
+        data: &[u8],
+        dst: vk::Image,
+        layout: vk::ImageLayout,
+        region: vk::BufferImageCopy,
+    ) -> VmaResult<u64> {
+        let src = self.stage(data)?;
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            ..region
+        };
+        self.pending.copies.push((
+            src,
+            UploadCopy::Image {
+                dst,
+                layout,
+                region,
+            },
+        ));
+        Ok(self.next_value)
+    }
+
+    /// Runs `callback` from `UploadQueue::poll` once the uploads made so far are done.
+    ///
+    /// Returns the semaphore value the callback waits for.
+    pub fn on_complete(&mut self, callback: impl FnOnce() + Send + 'static) -> u64 {
+        self.pending.callbacks.push(Box::new(callback));
+        self.next_value
+    }
+
+    /// Submits the uploads made since the last call.
+    ///
+    /// Returns the semaphore value signaled once they are done, or the value of the previous
+    /// batch if there was nothing to submit.
+    pub fn submit(&mut self) -> VmaResult<u64> {
+        if self.pending.is_empty() {
+            return Ok(self.next_value - 1);
+        }
+        let command_buffer = match self.free_command_buffers.pop() {
+            Some(command_buffer) => command_buffer,
+            None => unsafe {
+                self.device.allocate_command_buffers(
+                    &vk::CommandBufferAllocateInfo::default()
+                        .command_pool(self.command_pool)
+                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .command_buffer_count(1),
+                )?[0]
+            },
+        };
+        if let Err(err) = unsafe { self.record_and_submit(command_buffer) } {
+            self.free_command_buffers.push(command_buffer);
+            return Err(err);
+        }
+        let value = self.next_value;
+        self.in_flight
+            .push_back((value, std::mem::take(&mut self.pending), command_buffer));
+        self.next_value += 1;
+        Ok(value)
+    }
+
+    /// Last semaphore value signaled by the device.
+    pub fn completed_value(&self) -> VmaResult<u64> {
+        Ok(unsafe { self.device.get_semaphore_counter_value(self.semaphore)? })
+    }
+
+    /// Frees the staging memory of completed batches and runs their callbacks, oldest first.
+    ///
+    /// Returns the last completed semaphore value.
+    pub fn poll(&mut self) -> VmaResult<u64> {
+        let completed_value = self.completed_value()?;
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|&(value, _, _)| value <= completed_value)
+        {
+            let (_, batch, command_buffer) = self.in_flight.pop_front().unwrap();
+            self.free_command_buffers.push(command_buffer);
+            let _ = self.free_staging(batch.staging);
+            for callback in batch.callbacks {
+                callback();
+            }
+        }
+        Ok(completed_value)
+    }
+
+    /// Waits until the semaphore reaches `value` or `timeout` nanoseconds have passed, then
+    /// polls the completed batches.
+    ///
+    /// Fails with `vk::Result::TIMEOUT` if the value wasn't reached in time.
+    pub fn wait(&mut self, value: u64, timeout: u64) -> VmaResult<u64> {
+        let semaphores = [self.semaphore];
+        let values = [value];
+        unsafe {
+            self.device.wait_semaphores(
+                &vk::SemaphoreWaitInfo::default()
+                    .semaphores(&semaphores)
+                    .values(&values),
+                timeout,
+            )?;
+        }
+        self.poll()
+    }
+
+    /// Creates a staging buffer holding `data`, waiting for in flight batches to complete if
+    /// the ring is full.
+    #[track_caller]
+    fn stage(&mut self, data: &[u8]) -> VmaResult<vk::Buffer> {
+        if data.is_empty() {
+            return Err(VmaError::InvalidParameter("Uploads can't be empty"));
+        }
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(data.len() as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC);
+        let create_info = AllocationCreateInfo {
+            flags: AllocationCreateFlags::MAPPED
+                | AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let (buffer, allocation) = loop {
+            match unsafe { self.pool.create_buffer(&buffer_info, &create_info) } {
+                Err(err) if err.is_out_of_device_memory() && !self.in_flight.is_empty() => {
+                    let oldest = self.in_flight.front().unwrap().0;
+                    self.wait(oldest, u64::MAX)?;
+                }
+                result => break result?,
+            }
+        };
+        let allocator = self.pool.allocator();
+        let mapped = match allocator.get_allocation_info(&allocation) {
+            Ok(info) => info.mapped_data,
+            Err(err) => {
+                let _ = unsafe { allocator.destroy_buffer(buffer, allocation) };
+                return Err(err);
+            }
+        };
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.cast(), data.len()) };
+        self.pending.staging.push((buffer, allocation));
+        Ok(buffer)
+    }
+
+    unsafe fn record_and_submit(&mut self, command_buffer: vk::CommandBuffer) -> VmaResult<()> {
+        self.pool.allocator().flush_allocations(
+            self.pending
+                .staging
+                .iter()
+                .map(|(_, allocation)| allocation),
+            None,
+            None,
+        )?;
+        self.device
+            .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+        self.device.begin_command_buffer(
+            command_buffer,
+            &vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        )?;
+        for (src, copy) in &self.pending.copies {
+            match copy {
+                UploadCopy::Buffer { dst, region } => {
+                    self.device
+                        .cmd_copy_buffer(command_buffer, *src, *dst, &[*region])
+                }
+                UploadCopy::Image {
+                    dst,
+                    layout,
+                    region,
+                } => self.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    *src,
+                    *dst,
+                    *layout,
+                    &[*region],
+                ),
+            }
+        }
+        self.device.end_command_buffer(command_buffer)?;
+        let command_buffers = [command_buffer];
+        let semaphores = [self.semaphore];
+        let values = [self.next_value];
+        let mut timeline_info =
+            vk::TimelineSemaphoreSubmitInfo::default().signal_semaphore_values(&values);
+        let submit = vk::SubmitInfo::default()
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&semaphores)
+            .push_next(&mut timeline_info);
+        self.device
+            .queue_submit(self.queue, &[submit], vk::Fence::null())?;
+        Ok(())
+    }
+
+    /// Waits for the submitted uploads and runs their callbacks, discards the pending uploads
+    /// and frees all staging memory, reporting errors that `Drop` would ignore.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> VmaResult<()> {
+        let mut result = Ok(());
+        if let Some(&(last, _, _)) = self.in_flight.back() {
+            result = self.wait(last, u64::MAX).map(|_| ());
+        }
+        for (_, batch, _) in std::mem::take(&mut self.in_flight) {
+            result = result.and(self.free_staging(batch.staging));
+        }
+        let pending = std::mem::take(&mut self.pending.staging);
+        result.and(self.free_staging(pending))
+    }
+
+    fn free_staging(&self, staging: Vec<(vk::Buffer, Allocation)>) -> VmaResult<()> {
+        let allocator = self.pool.allocator();
+        let mut result = Ok(());
+        for (buffer, allocation) in staging {
+            result = result.and(
+                unsafe { allocator.destroy_buffer(buffer, allocation) }.map_err(VmaError::from),
+            );
+        }
+        result
+    }
+}
+
+/// Waits for the submitted uploads and runs their callbacks, pending uploads are discarded.
+impl Drop for UploadQueue {
+    fn drop(&mut self) {
+        let _ = self.finish();
+        unsafe {
+            self.device.destroy_semaphore(self.semaphore, None);
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
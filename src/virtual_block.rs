@@ -1,6 +1,10 @@
 use crate::ffi;
-use ash::prelude::VkResult;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+use std::any::Any;
+use std::collections::HashMap;
 use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::definitions::*;
 
@@ -9,30 +13,81 @@ use crate::definitions::*;
 /// For more info: <https://gpuopen-librariesandsdks.github.io/VulkanMemoryAllocator/html/virtual_allocator.html>
 pub struct VirtualBlock {
     internal: ffi::VmaVirtualBlock,
+    /// Stamped on allocations, renewed by `VirtualBlock::clear`
+    id: u64,
+    /// Data attached with `VirtualBlock::allocate_with_data`, dropped when its allocation is freed
+    data: HashMap<ffi::VmaVirtualAllocation, Box<dyn Any>>,
+    /// Offset and size of the live allocations, reported if the block is destroyed before
+    /// they are freed
+    live: HashMap<ffi::VmaVirtualAllocation, (vk::DeviceSize, vk::DeviceSize)>,
+}
+
+/// Allocation that was still alive when its `VirtualBlock` was destroyed, see
+/// `VmaError::VirtualBlockNotEmpty`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeakedVirtualAllocation {
+    /// Offset of the allocation in the block, in bytes.
+    pub offset: vk::DeviceSize,
+    /// Requested size of the allocation, in bytes.
+    pub size: vk::DeviceSize,
 }
 
 /// Represents single memory allocation done inside VirtualBlock.
+///
+/// The allocation remembers the block it was made from, so using it with another block, or with
+/// its block after `VirtualBlock::clear`, fails with `VmaError::WrongVirtualBlock`.
 #[derive(Debug)]
-pub struct VirtualAllocation(ffi::VmaVirtualAllocation);
+pub struct VirtualAllocation {
+    raw: ffi::VmaVirtualAllocation,
+    block: u64,
+}
 unsafe impl Send for VirtualAllocation {}
 unsafe impl Sync for VirtualAllocation {}
 
+/// Offset of a `VirtualAllocation` in its `VirtualBlock`, in bytes.
+///
+/// In debug builds it also remembers the block it was allocated from, so that helpers taking
+/// offsets can catch offsets from another block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VirtualOffset {
+    offset: vk::DeviceSize,
+    #[cfg(debug_assertions)]
+    block: u64,
+}
+
+impl VirtualOffset {
+    /// Returns the offset in bytes.
+    pub fn get(self) -> vk::DeviceSize {
+        self.offset
+    }
+}
+
+impl From<VirtualOffset> for vk::DeviceSize {
+    fn from(offset: VirtualOffset) -> Self {
+        offset.offset
+    }
+}
+
+static NEXT_BLOCK_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_block_id() -> u64 {
+    NEXT_BLOCK_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 impl VirtualBlock {
     /// Creates new VirtualBlock object.
-    pub fn new(create_info: VirtualBlockCreateInfo) -> VkResult<Self> {
+    pub fn new(create_info: VirtualBlockCreateInfo) -> VmaResult<Self> {
         unsafe {
             let mut internal: ffi::VmaVirtualBlock = mem::zeroed();
-            let raw_info = ffi::VmaVirtualBlockCreateInfo {
-                size: create_info.size,
-                flags: create_info.flags.bits(),
-                pAllocationCallbacks: create_info
-                    .allocation_callbacks
-                    .map(|a| std::mem::transmute(a))
-                    .unwrap_or(std::ptr::null()),
-            };
+            let raw_info: ffi::VmaVirtualBlockCreateInfo = (&create_info).into();
             ffi::vmaCreateVirtualBlock(&raw_info, &mut internal).result()?;
 
-            Ok(VirtualBlock { internal })
+            Ok(VirtualBlock {
+                internal,
+                id: next_block_id(),
+                data: HashMap::new(),
+                live: HashMap::new(),
+            })
         }
     }
 
@@ -40,63 +95,172 @@ impl VirtualBlock {
     ///
     /// Possible error values:
     ///
-    /// - `ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY` - Allocation failed due to not enough free space in the virtual block.
+    /// - `VmaError::OutOfDeviceMemory` - Allocation failed due to not enough free space in the virtual block.
     ///     (despite the function doesn't ever allocate actual GPU memory)
     pub unsafe fn allocate(
         &mut self,
         allocation_info: VirtualAllocationCreateInfo,
-    ) -> VkResult<(VirtualAllocation, u64)> {
+    ) -> VmaResult<(VirtualAllocation, VirtualOffset)> {
         let create_info: ffi::VmaVirtualAllocationCreateInfo = allocation_info.into();
         let mut allocation: ffi::VmaVirtualAllocation = std::mem::zeroed();
         let mut offset = 0;
         ffi::vmaVirtualAllocate(self.internal, &create_info, &mut allocation, &mut offset)
             .result()?;
-        Ok((VirtualAllocation(allocation), offset))
+        self.live.insert(allocation, (offset, create_info.size));
+        Ok((
+            VirtualAllocation {
+                raw: allocation,
+                block: self.id,
+            },
+            VirtualOffset {
+                offset,
+                #[cfg(debug_assertions)]
+                block: self.id,
+            },
+        ))
+    }
+
+    /// Allocates like `VirtualBlock::allocate` and attaches `data` to the allocation.
+    ///
+    /// The block owns `data` until the allocation is freed by `VirtualBlock::free` or
+    /// `VirtualBlock::clear`, which drop it. Use `VirtualBlock::get_data` to access it.
+    pub fn allocate_with_data<T: Any>(
+        &mut self,
+        allocation_info: VirtualAllocationCreateInfo,
+        data: T,
+    ) -> VmaResult<(VirtualAllocation, VirtualOffset)> {
+        let (allocation, offset) = unsafe { self.allocate(allocation_info)? };
+        self.data.insert(allocation.raw, Box::new(data));
+        Ok((allocation, offset))
+    }
+
+    /// Returns the data attached to `allocation` by `VirtualBlock::allocate_with_data`.
+    ///
+    /// Returns `None` if the allocation has no data, the data isn't a `T` or the allocation
+    /// wasn't made from this block.
+    pub fn get_data<T: Any>(&self, allocation: &VirtualAllocation) -> Option<&T> {
+        self.check_allocation(allocation).ok()?;
+        self.data.get(&allocation.raw)?.downcast_ref()
+    }
+
+    /// Mutable version of `VirtualBlock::get_data`.
+    pub fn get_data_mut<T: Any>(&mut self, allocation: &VirtualAllocation) -> Option<&mut T> {
+        self.check_allocation(allocation).ok()?;
+        self.data.get_mut(&allocation.raw)?.downcast_mut()
+    }
+
+    fn check_allocation(&self, allocation: &VirtualAllocation) -> VmaResult<()> {
+        if allocation.block == self.id {
+            Ok(())
+        } else {
+            Err(VmaError::WrongVirtualBlock)
+        }
     }
 
     /// Frees virtual allocation inside given VirtualBlock.
     ///
     /// It is correct to call this function with `allocation == VK_NULL_HANDLE` - it does nothing.
-    pub unsafe fn free(&mut self, allocation: &mut VirtualAllocation) {
-        ffi::vmaVirtualFree(self.internal, allocation.0);
+    /// Data attached with `VirtualBlock::allocate_with_data` is dropped.
+    ///
+    /// Fails with `VmaError::WrongVirtualBlock` if the allocation wasn't made from this block.
+    ///
+    /// # Safety
+    /// The allocation must not have been freed already.
+    pub unsafe fn free(&mut self, allocation: &mut VirtualAllocation) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        ffi::vmaVirtualFree(self.internal, allocation.raw);
+        self.data.remove(&allocation.raw);
+        self.live.remove(&allocation.raw);
+        Ok(())
     }
 
     /// Frees all virtual allocations inside given VirtualBlock.
     ///
-    /// You must either call this function or free each virtual allocation individually with vmaVirtualFree()
-    /// before destroying a virtual block. Otherwise, an assert is called.
+    /// Blocks destroyed with live allocations are cleared first, see `VirtualBlock::destroy`.
     ///
     /// If you keep pointer to some additional metadata associated with your virtual allocation in its `user_data`,
-    /// don't forget to free it as well.
+    /// don't forget to free it as well. Data attached with `VirtualBlock::allocate_with_data` is
+    /// dropped.
     ///
     /// Any VirtualAllocations created previously in the VirtualBlock will no longer be valid!
     pub unsafe fn clear(&mut self) {
         ffi::vmaClearVirtualBlock(self.internal);
+        self.data.clear();
+        self.live.clear();
+        self.id = next_block_id();
+    }
+
+    /// Destroys the block.
+    ///
+    /// Destroying a block with live allocations would trigger an assertion inside VMA. Instead,
+    /// they are freed like with `VirtualBlock::clear`, and `VmaError::VirtualBlockNotEmpty` is
+    /// returned with their offsets and sizes.
+    pub fn destroy(mut self) -> VmaResult<()> {
+        self.clear_leaked()
+    }
+
+    /// Frees the allocations that are still alive, failing with
+    /// `VmaError::VirtualBlockNotEmpty` if there were any.
+    fn clear_leaked(&mut self) -> VmaResult<()> {
+        if self.live.is_empty() {
+            return Ok(());
+        }
+        let mut leaked: Vec<LeakedVirtualAllocation> = self
+            .live
+            .values()
+            .map(|&(offset, size)| LeakedVirtualAllocation { offset, size })
+            .collect();
+        leaked.sort_by_key(|allocation| allocation.offset);
+        unsafe { self.clear() };
+        Err(VmaError::VirtualBlockNotEmpty { leaked })
     }
 
     /// Returns information about a specific virtual allocation within a virtual block, like its size and user_data pointer.
+    ///
+    /// Fails with `VmaError::WrongVirtualBlock` if the allocation wasn't made from this block.
+    ///
+    /// # Safety
+    /// The allocation must not have been freed.
     pub unsafe fn get_allocation_info(
         &self,
         allocation: &VirtualAllocation,
-    ) -> VkResult<VirtualAllocationInfo> {
+    ) -> VmaResult<VirtualAllocationInfo> {
+        self.check_allocation(allocation)?;
         let mut allocation_info: ffi::VmaVirtualAllocationInfo = mem::zeroed();
-        ffi::vmaGetVirtualAllocationInfo(self.internal, allocation.0, &mut allocation_info);
+        ffi::vmaGetVirtualAllocationInfo(self.internal, allocation.raw, &mut allocation_info);
         Ok(allocation_info.into())
     }
 
     /// Changes custom pointer associated with given virtual allocation.
+    ///
+    /// Fails with `VmaError::WrongVirtualBlock` if the allocation wasn't made from this block.
+    ///
+    /// # Safety
+    /// The allocation must not have been freed.
     pub unsafe fn set_allocation_user_data(
         &self,
         allocation: &mut VirtualAllocation,
         user_data: *mut ::std::os::raw::c_void,
-    ) {
-        ffi::vmaSetVirtualAllocationUserData(self.internal, allocation.0, user_data);
+    ) -> VmaResult<()> {
+        self.check_allocation(allocation)?;
+        ffi::vmaSetVirtualAllocationUserData(self.internal, allocation.raw, user_data);
+        Ok(())
     }
 }
 
 /// Custom `Drop` implementation to clean up internal VirtualBlock instance
+///
+/// Allocations that are still alive are freed first, and logged as a warning with the `log`
+/// feature, see `VirtualBlock::destroy`.
 impl Drop for VirtualBlock {
     fn drop(&mut self) {
+        let result = self.clear_leaked();
+        #[cfg(feature = "log")]
+        if let Err(err) = result {
+            log::warn!(target: "vk_mem", "{}", err);
+        }
+        #[cfg(not(feature = "log"))]
+        let _ = result;
         unsafe {
             ffi::vmaDestroyVirtualBlock(self.internal);
             self.internal = std::ptr::null_mut();
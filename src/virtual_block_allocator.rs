@@ -0,0 +1,103 @@
+use std::alloc::Layout;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr::NonNull;
+
+use crate::definitions::*;
+use crate::VirtualAllocation;
+use crate::VirtualBlock;
+use crate::VmaResult;
+
+/// CPU memory arena managed by a `VirtualBlock`, with an `alloc`/`dealloc` pair shaped like
+/// `std::alloc::GlobalAlloc`.
+///
+/// The arena hands out pointers into a caller provided range of memory, e.g. a persistently
+/// mapped staging buffer, using the allocation algorithm of the block. Offsets in the block are
+/// offsets from `base`.
+pub struct VirtualBlockAllocator {
+    base: NonNull<u8>,
+    block: RefCell<VirtualBlock>,
+    /// Live allocations by offset
+    allocations: RefCell<HashMap<usize, VirtualAllocation>>,
+}
+
+impl VirtualBlockAllocator {
+    /// Creates an arena over the `create_info.size` bytes starting at `base`.
+    ///
+    /// # Safety
+    /// The memory range must be valid for reads and writes, and not be used otherwise, for the
+    /// whole lifetime of the arena and of the pointers it returns.
+    pub unsafe fn new(base: NonNull<u8>, create_info: VirtualBlockCreateInfo) -> VmaResult<Self> {
+        Ok(VirtualBlockAllocator {
+            base,
+            block: RefCell::new(VirtualBlock::new(create_info)?),
+            allocations: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Allocates memory as described by `layout`, or returns `None` if the arena has no room
+    /// for it.
+    ///
+    /// Alignments above the alignment of `base` aren't supported and also return `None`.
+    /// Zero-sized layouts get a dangling, well aligned pointer.
+    pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if self.base.as_ptr() as usize & (layout.align() - 1) != 0 {
+            return None;
+        }
+        if layout.size() == 0 {
+            return NonNull::new(layout.align() as *mut u8);
+        }
+        let allocation_info = VirtualAllocationCreateInfo {
+            size: layout.size() as u64,
+            alignment: layout.align() as u64,
+            user_data: 0,
+            flags: VirtualAllocationCreateFlags::empty(),
+        };
+        let (allocation, offset) =
+            unsafe { self.block.borrow_mut().allocate(allocation_info) }.ok()?;
+        let offset = offset.get() as usize;
+        self.allocations.borrow_mut().insert(offset, allocation);
+        Some(unsafe { NonNull::new_unchecked(self.base.as_ptr().add(offset)) })
+    }
+
+    /// Frees memory returned by `VirtualBlockAllocator::alloc`.
+    ///
+    /// # Safety
+    /// `ptr` must have been returned by `alloc` of this arena with the same `layout`, and must
+    /// not have been freed already.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let offset = ptr.as_ptr() as usize - self.base.as_ptr() as usize;
+        let allocation = self.allocations.borrow_mut().remove(&offset);
+        debug_assert!(
+            allocation.is_some(),
+            "Attempted to free a pointer that wasn't allocated from this arena"
+        );
+        if let Some(mut allocation) = allocation {
+            let _ = self.block.borrow_mut().free(&mut allocation);
+        }
+    }
+
+    /// Frees all allocations of the arena at once.
+    ///
+    /// # Safety
+    /// None of the pointers returned by `alloc` may be used anymore.
+    pub unsafe fn reset(&mut self) {
+        self.allocations.get_mut().clear();
+        self.block.get_mut().clear();
+    }
+
+    /// Number of live allocations, not counting zero-sized ones.
+    pub fn allocation_count(&self) -> usize {
+        self.allocations.borrow().len()
+    }
+}
+
+/// Frees the remaining allocations, so that the block can be destroyed.
+impl Drop for VirtualBlockAllocator {
+    fn drop(&mut self) {
+        unsafe { self.reset() };
+    }
+}
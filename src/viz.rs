@@ -0,0 +1,449 @@
+use std::fmt::Write;
+
+#[cfg(feature = "stats-string")]
+use crate::Allocator;
+use crate::ByteSize;
+use crate::{VmaError, VmaResult};
+use ash::vk;
+
+/// Blocks of device memory with their allocations and free ranges, parsed from the detailed
+/// map of `Allocator::build_stats_string`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MemoryMap {
+    /// Blocks of the default pools, then of the custom pools, then dedicated allocations.
+    pub blocks: Vec<Block>,
+}
+
+/// Block of device memory in a `MemoryMap`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Block {
+    /// Memory type index of the block.
+    pub memory_type: u32,
+    /// Name of the custom pool of the block, `pool N` for unnamed ones, or `None` for the
+    /// default pools.
+    pub pool: Option<String>,
+    /// Size of the block, in bytes.
+    pub size: vk::DeviceSize,
+    /// Whether the block is a dedicated allocation.
+    pub dedicated: bool,
+    /// Allocations and free ranges of the block, in offset order.
+    pub suballocations: Vec<Suballocation>,
+}
+
+/// Allocation or free range of a `Block`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Suballocation {
+    /// Offset in the block, in bytes.
+    pub offset: vk::DeviceSize,
+    /// Size of the range, in bytes.
+    pub size: vk::DeviceSize,
+    /// Kind of the range as reported by VMA, e.g. `FREE`, `BUFFER` or `IMAGE_OPTIMAL`.
+    pub kind: String,
+    /// Name of the allocation, see `Allocator::set_allocation_name`.
+    pub name: Option<String>,
+}
+
+impl Suballocation {
+    /// Returns `true` if the range is free.
+    pub fn is_free(&self) -> bool {
+        self.kind == "FREE"
+    }
+}
+
+impl MemoryMap {
+    /// Takes a memory map of the current state of `allocator`.
+    #[cfg(feature = "stats-string")]
+    pub fn capture(allocator: &Allocator) -> VmaResult<Self> {
+        Self::from_json(&allocator.build_stats_string(true))
+    }
+
+    /// Parses the output of `Allocator::build_stats_string` with the detailed map, e.g. saved
+    /// to a file on another machine.
+    ///
+    /// Fails with `VmaError::InvalidParameter` if `json` isn't valid JSON.
+    pub fn from_json(json: &str) -> VmaResult<Self> {
+        let root = Parser::new(json)
+            .parse_document()
+            .ok_or(VmaError::InvalidParameter(
+                "The statistics aren't valid JSON",
+            ))?;
+        let mut map = MemoryMap::default();
+        let mut dedicated = Vec::new();
+        for (type_key, pool) in root.get("DefaultPools").map_or(&[][..], Json::entries) {
+            let memory_type = parse_type(type_key);
+            map.read_pool(pool, memory_type, None, &mut dedicated);
+        }
+        let mut pool_index = 0;
+        for (type_key, pools) in root.get("CustomPools").map_or(&[][..], Json::entries) {
+            let memory_type = parse_type(type_key);
+            for pool in pools.items() {
+                let name = pool
+                    .get("Name")
+                    .and_then(Json::as_str)
+                    .map_or_else(|| format!("pool {}", pool_index), str::to_owned);
+                pool_index += 1;
+                map.read_pool(pool, memory_type, Some(name), &mut dedicated);
+            }
+        }
+        map.blocks.append(&mut dedicated);
+        Ok(map)
+    }
+
+    fn read_pool(
+        &mut self,
+        pool: &Json,
+        memory_type: u32,
+        name: Option<String>,
+        dedicated: &mut Vec<Block>,
+    ) {
+        for (_, block) in pool.get("Blocks").map_or(&[][..], Json::entries) {
+            let suballocations = block
+                .get("Suballocations")
+                .map_or(&[][..], Json::items)
+                .iter()
+                .map(|suballocation| Suballocation {
+                    offset: suballocation.number("Offset"),
+                    ..parse_suballocation(suballocation)
+                })
+                .collect();
+            self.blocks.push(Block {
+                memory_type,
+                pool: name.clone(),
+                size: block.number("TotalBytes"),
+                dedicated: false,
+                suballocations,
+            });
+        }
+        for allocation in pool
+            .get("DedicatedAllocations")
+            .map_or(&[][..], Json::items)
+        {
+            let suballocation = parse_suballocation(allocation);
+            dedicated.push(Block {
+                memory_type,
+                pool: name.clone(),
+                size: suballocation.size,
+                dedicated: true,
+                suballocations: vec![suballocation],
+            });
+        }
+    }
+
+    /// Draws the map as an SVG image, a row per block with its ranges scaled to the largest
+    /// block. Allocations are colored by pool and name, free ranges are gray.
+    pub fn to_svg(&self) -> String {
+        const LABEL_WIDTH: u64 = 320;
+        const BAR_WIDTH: u64 = 1024;
+        const ROW_HEIGHT: u64 = 24;
+        let scale = self
+            .blocks
+            .iter()
+            .map(|block| block.size)
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        let mut svg = String::new();
+        // Writing to a String can't fail.
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="12">"#,
+            LABEL_WIDTH + BAR_WIDTH,
+            ROW_HEIGHT * self.blocks.len().max(1) as u64
+        );
+        for (row, block) in self.blocks.iter().enumerate() {
+            let y = row as u64 * ROW_HEIGHT;
+            let label = format!(
+                "type {}{} {}{}",
+                block.memory_type,
+                block
+                    .pool
+                    .as_deref()
+                    .map_or(String::new(), |pool| format!(" {}", pool)),
+                ByteSize(block.size),
+                if block.dedicated { " dedicated" } else { "" }
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="4" y="{}">{}</text>"#,
+                y + ROW_HEIGHT * 2 / 3,
+                escape(&label)
+            );
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#eeeeee" stroke="#000000"/>"##,
+                LABEL_WIDTH,
+                y + 2,
+                (block.size * BAR_WIDTH / scale).max(1),
+                ROW_HEIGHT - 4
+            );
+            for suballocation in &block.suballocations {
+                let color = if suballocation.is_free() {
+                    "#bbbbbb".to_owned()
+                } else {
+                    color(block.pool.as_deref(), suballocation.name.as_deref())
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"><title>{}</title></rect>"#,
+                    LABEL_WIDTH + suballocation.offset * BAR_WIDTH / scale,
+                    y + 3,
+                    (suballocation.size * BAR_WIDTH / scale).max(1),
+                    ROW_HEIGHT - 6,
+                    color,
+                    escape(&format!(
+                        "{} {} at {}{}",
+                        suballocation.kind,
+                        ByteSize(suballocation.size),
+                        suballocation.offset,
+                        suballocation
+                            .name
+                            .as_deref()
+                            .map_or(String::new(), |name| format!(" ({})", name))
+                    ))
+                );
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Wraps `MemoryMap::to_svg` into a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let allocated: vk::DeviceSize = self
+            .blocks
+            .iter()
+            .flat_map(|block| &block.suballocations)
+            .filter(|suballocation| !suballocation.is_free())
+            .map(|suballocation| suballocation.size)
+            .sum();
+        let total: vk::DeviceSize = self.blocks.iter().map(|block| block.size).sum();
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Memory map</title></head>\n<body>\n<p>{} block(s), {} allocated of {}</p>\n{}</body>\n</html>\n",
+            self.blocks.len(),
+            ByteSize(allocated),
+            ByteSize(total),
+            self.to_svg()
+        )
+    }
+}
+
+/// Memory type index of a `Type N` key.
+fn parse_type(key: &str) -> u32 {
+    key.trim_start_matches("Type ").parse().unwrap_or(0)
+}
+
+fn parse_suballocation(json: &Json) -> Suballocation {
+    Suballocation {
+        offset: 0,
+        size: json.number("Size"),
+        kind: json
+            .get("Type")
+            .and_then(Json::as_str)
+            .unwrap_or("UNKNOWN")
+            .to_owned(),
+        name: json.get("Name").and_then(Json::as_str).map(str::to_owned),
+    }
+}
+
+/// Stable color for allocations of a pool and name.
+fn color(pool: Option<&str>, name: Option<&str>) -> String {
+    // FNV-1a, so that colors don't change between runs.
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in pool
+        .unwrap_or("")
+        .bytes()
+        .chain([0])
+        .chain(name.unwrap_or("").bytes())
+    {
+        hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+    }
+    format!("hsl({}, 65%, 55%)", hash % 360)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// JSON value, only as much as needed to read the statistics string.
+enum Json {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        self.entries()
+            .iter()
+            .find(|(entry, _)| entry == key)
+            .map(|(_, value)| value)
+    }
+
+    fn entries(&self) -> &[(String, Json)] {
+        match self {
+            Json::Object(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Integer member `key`, 0 if it's missing.
+    fn number(&self, key: &str) -> u64 {
+        match self.get(key) {
+            Some(Json::Number(number)) => *number as u64,
+            _ => 0,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Parser { text, position: 0 }
+    }
+
+    fn parse_document(mut self) -> Option<Json> {
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        (self.position == self.text.len()).then_some(value)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.rest().chars().next()? {
+            '{' => self.parse_object(),
+            '[' => self.parse_array(),
+            '"' => self.parse_string().map(Json::String),
+            't' | 'f' if self.eat("true") || self.eat("false") => Some(Json::Bool),
+            'n' if self.eat("null") => Some(Json::Null),
+            _ => self.parse_number(),
+        }
+    }
+
+    fn parse_object(&mut self) -> Option<Json> {
+        self.eat("{");
+        let mut entries = Vec::new();
+        if self.eat("}") {
+            return Some(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            if !self.eat(":") {
+                return None;
+            }
+            entries.push((key, self.parse_value()?));
+            if self.eat("}") {
+                return Some(Json::Object(entries));
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Option<Json> {
+        self.eat("[");
+        let mut items = Vec::new();
+        if self.eat("]") {
+            return Some(Json::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            if self.eat("]") {
+                return Some(Json::Array(items));
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Option<String> {
+        let mut chars = self.rest().strip_prefix('"')?.char_indices();
+        let mut string = String::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += index + 2;
+                    return Some(string);
+                }
+                '\\' => match chars.next()?.1 {
+                    'n' => string.push('\n'),
+                    't' => string.push('\t'),
+                    'r' => string.push('\r'),
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'u' => {
+                        let code: String =
+                            (0..4).filter_map(|_| chars.next()).map(|c| c.1).collect();
+                        let code = u32::from_str_radix(&code, 16).ok()?;
+                        string.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    c => string.push(c),
+                },
+                c => string.push(c),
+            }
+        }
+        None
+    }
+
+    fn parse_number(&mut self) -> Option<Json> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(rest.len());
+        let number = rest[..len].parse().ok()?;
+        self.position += len;
+        Some(Json::Number(number))
+    }
+}
@@ -117,8 +117,11 @@ impl TestHarness {
             .queue_family_index(0)
             .queue_priorities(&priorities)];
 
-        let device_create_info =
-            ash::vk::DeviceCreateInfo::default().queue_create_infos(&queue_info);
+        let mut vulkan_12_features =
+            ash::vk::PhysicalDeviceVulkan12Features::default().timeline_semaphore(true);
+        let device_create_info = ash::vk::DeviceCreateInfo::default()
+            .queue_create_infos(&queue_info)
+            .push_next(&mut vulkan_12_features);
 
         let device: ash::Device = unsafe {
             instance
@@ -139,7 +142,7 @@ impl TestHarness {
     pub fn create_allocator(&self) -> vk_mem::Allocator {
         let create_info =
             vk_mem::AllocatorCreateInfo::new(&self.instance, &self.device, self.physical_device);
-        vk_mem::Allocator::new(create_info).unwrap()
+        unsafe { vk_mem::Allocator::new(create_info).unwrap() }
     }
 }
 
@@ -154,6 +157,58 @@ fn create_allocator() {
     let _ = harness.create_allocator();
 }
 
+#[test]
+fn single_threaded_allocator() {
+    let harness = TestHarness::new();
+    let create_info = || {
+        let mut create_info = vk_mem::AllocatorCreateInfo::new(
+            &harness.instance,
+            &harness.device,
+            harness.physical_device,
+        );
+        create_info.flags = vk_mem::AllocatorCreateFlags::EXTERNALLY_SYNCHRONIZED;
+        create_info
+    };
+    unsafe {
+        assert!(matches!(
+            vk_mem::Allocator::new(create_info()),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+        let allocator = vk_mem::SingleThreadedAllocator::new(create_info()).unwrap();
+        let allocator = std::thread::spawn(move || {
+            let (buffer, allocation) = allocator
+                .create_buffer(
+                    &ash::vk::BufferCreateInfo::default()
+                        .size(16 * 1024)
+                        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC),
+                    &vk_mem::AllocationCreateInfo::default(),
+                )
+                .unwrap();
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+            allocator
+        })
+        .join()
+        .unwrap();
+        allocator.destroy().unwrap();
+    }
+}
+
+#[test]
+fn memory_types() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let properties = unsafe { allocator.get_memory_properties() };
+    let memory_types: Vec<_> = allocator.memory_types().collect();
+    assert_eq!(memory_types.len(), properties.memory_type_count as usize);
+    for (index, memory_type, heap_index, heap_size) in memory_types {
+        assert!(
+            memory_type.property_flags == properties.memory_types[index as usize].property_flags
+        );
+        assert!(heap_index < properties.memory_heap_count);
+        assert_eq!(heap_size, properties.memory_heaps[heap_index as usize].size);
+    }
+}
+
 #[test]
 fn create_gpu_buffer() {
     let harness = TestHarness::new();
@@ -164,7 +219,7 @@ fn create_gpu_buffer() {
     };
 
     unsafe {
-        let (buffer, mut allocation) = allocator
+        let (buffer, allocation) = allocator
             .create_buffer(
                 &ash::vk::BufferCreateInfo::default().size(16 * 1024).usage(
                     ash::vk::BufferUsageFlags::VERTEX_BUFFER
@@ -173,216 +228,3246 @@ fn create_gpu_buffer() {
                 &allocation_info,
             )
             .unwrap();
-        let allocation_info = allocator.get_allocation_info(&allocation);
+        let allocation_info = allocator.get_allocation_info(&allocation).unwrap();
         assert_eq!(allocation_info.mapped_data, std::ptr::null_mut());
-        allocator.destroy_buffer(buffer, &mut allocation);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
     }
 }
 
 #[test]
-fn create_cpu_buffer_preferred() {
+fn leak_report() {
     let harness = TestHarness::new();
     let allocator = harness.create_allocator();
     let allocation_info = vk_mem::AllocationCreateInfo {
-        required_flags: ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
-        preferred_flags: ash::vk::MemoryPropertyFlags::HOST_COHERENT
-            | ash::vk::MemoryPropertyFlags::HOST_CACHED,
-        flags: vk_mem::AllocationCreateFlags::MAPPED,
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER);
+
+    let (_leaked_buffer, leaked) = unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap()
+    };
+    match allocator.destroy() {
+        Err(vk_mem::VmaError::Leaked(report)) => {
+            assert_eq!(report.allocations.len(), 1);
+            assert_eq!(report.allocations[0].id, leaked.id());
+            assert!(report.allocations[0].size >= 16 * 1024);
+            assert_eq!(report.allocations[0].location.file(), file!());
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+#[cfg(not(feature = "panic-on-leak"))]
+fn drop_leaking_allocator() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    let _leaked = unsafe {
+        allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(1024)
+                    .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER),
+                &allocation_info,
+            )
+            .unwrap()
+    };
+    // The report is only logged, and the VMA allocator is leaked rather than destroyed.
+    drop(allocator);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn stale_allocation() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
         ..Default::default()
     };
     unsafe {
-        let (buffer, mut allocation) = allocator
+        let (buffer, allocation) = allocator
             .create_buffer(
-                &ash::vk::BufferCreateInfo::default().size(16 * 1024).usage(
-                    ash::vk::BufferUsageFlags::VERTEX_BUFFER
-                        | ash::vk::BufferUsageFlags::TRANSFER_DST,
-                ),
+                &ash::vk::BufferCreateInfo::default()
+                    .size(16 * 1024)
+                    .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER),
                 &allocation_info,
             )
             .unwrap();
-        let allocation_info = allocator.get_allocation_info(&allocation);
-        assert_ne!(allocation_info.mapped_data, std::ptr::null_mut());
-        allocator.destroy_buffer(buffer, &mut allocation);
+        let copy = allocation.clone_raw();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        match allocator.get_allocation_info(&copy) {
+            Err(vk_mem::VmaError::StaleAllocation(id)) => assert_eq!(id, copy.id()),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
     }
 }
 
 #[test]
-fn create_gpu_buffer_pool() {
+fn create_gpu_buffer_with_next_chain() {
     let harness = TestHarness::new();
     let allocator = harness.create_allocator();
-    let allocator = Arc::new(allocator);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
 
+    let mut external_info = ash::vk::ExternalMemoryBufferCreateInfo::default();
     let buffer_info = ash::vk::BufferCreateInfo::default()
         .size(16 * 1024)
-        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST);
+        .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST)
+        .push_next(&mut external_info);
+
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
 
+#[test]
+fn create_cpu_buffer_preferred() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
     let allocation_info = vk_mem::AllocationCreateInfo {
         required_flags: ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
         preferred_flags: ash::vk::MemoryPropertyFlags::HOST_COHERENT
             | ash::vk::MemoryPropertyFlags::HOST_CACHED,
         flags: vk_mem::AllocationCreateFlags::MAPPED,
-
         ..Default::default()
     };
     unsafe {
-        let memory_type_index = allocator
-            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+        let (buffer, allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default().size(16 * 1024).usage(
+                    ash::vk::BufferUsageFlags::VERTEX_BUFFER
+                        | ash::vk::BufferUsageFlags::TRANSFER_DST,
+                ),
+                &allocation_info,
+            )
             .unwrap();
-
-        // Create a pool that can have at most 2 blocks, 128 MiB each.
-        let pool_info = vk_mem::PoolCreateInfo {
-            memory_type_index,
-            block_size: 128 * 1024 * 1024,
-            max_block_count: 2,
-            ..Default::default()
-        };
-
-        let pool = allocator.create_pool(&pool_info).unwrap();
-
-        let (buffer, mut allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
-        let allocation_info = allocator.get_allocation_info(&allocation);
+        let allocation_info = allocator.get_allocation_info(&allocation).unwrap();
         assert_ne!(allocation_info.mapped_data, std::ptr::null_mut());
-        allocator.destroy_buffer(buffer, &mut allocation);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
     }
 }
 
 #[test]
-fn test_gpu_stats() {
+fn map_guard() {
     let harness = TestHarness::new();
     let allocator = harness.create_allocator();
     let allocation_info = vk_mem::AllocationCreateInfo {
         usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
         ..Default::default()
     };
-
     unsafe {
-        let stats_1 = allocator.calculate_statistics().unwrap();
-        assert_eq!(stats_1.total.statistics.blockCount, 0);
-        assert_eq!(stats_1.total.statistics.allocationCount, 0);
-        assert_eq!(stats_1.total.statistics.allocationBytes, 0);
-
         let (buffer, mut allocation) = allocator
             .create_buffer(
-                &ash::vk::BufferCreateInfo::default().size(16 * 1024).usage(
-                    ash::vk::BufferUsageFlags::VERTEX_BUFFER
-                        | ash::vk::BufferUsageFlags::TRANSFER_DST,
-                ),
+                &ash::vk::BufferCreateInfo::default()
+                    .size(16 * 1024)
+                    .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC),
                 &allocation_info,
             )
             .unwrap();
+        {
+            let mut mapped = allocator.map(&mut allocation).unwrap().flush_on_drop();
+            assert!(mapped.len() >= 16 * 1024);
+            mapped[..4].copy_from_slice(&[1, 2, 3, 4]);
+        }
+        {
+            let words = allocator
+                .map_memory_as_slice::<u32>(&mut allocation)
+                .unwrap();
+            assert!(words.len() >= 4 * 1024);
+            assert_eq!(words[0], u32::from_ne_bytes([1, 2, 3, 4]));
+        }
+        let allocation_info = allocator.get_allocation_info(&allocation).unwrap();
+        assert_eq!(allocation_info.mapped_data, std::ptr::null_mut());
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
 
-        let stats_2 = allocator.calculate_statistics().unwrap();
-        assert_eq!(stats_2.total.statistics.blockCount, 1);
-        assert_eq!(stats_2.total.statistics.allocationCount, 1);
-        assert_eq!(stats_2.total.statistics.allocationBytes, 16 * 1024);
-
-        allocator.destroy_buffer(buffer, &mut allocation);
+#[test]
+fn managed_buffer() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC);
+    unsafe {
+        let mut buffer = allocator
+            .create_managed_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        assert!(buffer.size() >= 16 * 1024);
+        buffer.map().unwrap()[..4].copy_from_slice(&[1, 2, 3, 4]);
+        buffer.destroy().unwrap();
 
-        let stats_3 = allocator.calculate_statistics().unwrap();
-        assert_eq!(stats_3.total.statistics.blockCount, 1);
-        assert_eq!(stats_3.total.statistics.allocationCount, 0);
-        assert_eq!(stats_3.total.statistics.allocationBytes, 0);
+        // Dropping frees the buffer as well.
+        let _buffer = allocator
+            .create_managed_buffer(&buffer_info, &allocation_info)
+            .unwrap();
     }
+    assert!(allocator.snapshot().allocations.is_empty());
 }
 
 #[test]
-fn create_virtual_block() {
-    let create_info = vk_mem::VirtualBlockCreateInfo {
-        size: 16 * 1024 * 1024,
-        flags: vk_mem::VirtualBlockCreateFlags::VMA_VIRTUAL_BLOCK_CREATE_LINEAR_ALGORITHM_BIT,
-        allocation_callbacks: None,
-    }; // 16MB block
-    let _virtual_block =
-        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+fn allocation_info_pod_access() {
+    let mut backing = vec![0u64; 4];
+    let info = vk_mem::AllocationInfo {
+        memory_type: 0,
+        device_memory: vk::DeviceMemory::null(),
+        offset: 0,
+        size: 32,
+        mapped_data: backing.as_mut_ptr() as *mut c_void,
+        user_data: 0,
+    };
+    unsafe {
+        info.write_pod_at(8, &0xdead_beef_u32).unwrap();
+        assert_eq!(info.read_pod_at::<u32>(8).unwrap(), 0xdead_beef);
+        info.write_pod_slice_at(16, &[1u64, 2]).unwrap();
+        assert!(info.write_pod_at(28, &0u64).is_err());
+        assert!(info.read_pod_at::<u32>(2).is_err());
+    }
+    assert!(info.ptr_at(32).is_err());
+    assert_eq!(backing[2..], [1, 2]);
 }
 
 #[test]
-fn virtual_allocate_and_free() {
-    let create_info = vk_mem::VirtualBlockCreateInfo {
-        size: 16 * 1024 * 1024,
-        flags: vk_mem::VirtualBlockCreateFlags::VMA_VIRTUAL_BLOCK_CREATE_LINEAR_ALGORITHM_BIT,
-        allocation_callbacks: None,
-    }; // 16MB block
-    let mut virtual_block =
-        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+fn map_device_local_allocation() {
+    // The handle is just the VmaAllocation and its id
+    assert_eq!(std::mem::size_of::<vk_mem::Allocation>(), 16);
 
-    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
-        size: 8 * 1024 * 1024,
-        alignment: 0,
-        user_data: 0,
-        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let Some((memory_type_index, ..)) = allocator.memory_types().find(|(_, memory_type, ..)| {
+        !memory_type
+            .property_flags
+            .contains(ash::vk::MemoryPropertyFlags::HOST_VISIBLE)
+    }) else {
+        return;
+    };
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: 1 << memory_type_index,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
     };
-
-    // Fully allocate the VirtualBlock and then free both allocations
     unsafe {
-        let (mut virtual_alloc_0, offset_0) = virtual_block.allocate(allocation_info).unwrap();
-        let (mut virtual_alloc_1, offset_1) = virtual_block.allocate(allocation_info).unwrap();
-        assert_ne!(offset_0, offset_1);
-        virtual_block.free(&mut virtual_alloc_0);
-        virtual_block.free(&mut virtual_alloc_1);
+        let mut allocation = allocator
+            .allocate_memory(&requirements, &allocation_info)
+            .unwrap();
+        match allocator.map_memory(&mut allocation) {
+            Err(vk_mem::VmaError::InvalidAllocation { reason, .. }) => {
+                assert_eq!(reason, "Allocation is not host visible")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        allocator.free_memory(allocation).unwrap();
     }
+}
 
-    // Fully allocate it again and then clear it
+#[test]
+fn free_mapped_allocation() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
     unsafe {
-        let (_virtual_alloc_0, offset_0) = virtual_block.allocate(allocation_info).unwrap();
-        let (_virtual_alloc_1, offset_1) = virtual_block.allocate(allocation_info).unwrap();
-        assert_ne!(offset_0, offset_1);
-        virtual_block.clear();
+        let (buffer, mut allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(16 * 1024)
+                    .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC),
+                &allocation_info,
+            )
+            .unwrap();
+        let line = line!() + 1;
+        allocator.map_memory(&mut allocation).unwrap();
+        allocator.map_memory(&mut allocation).unwrap();
+        let id = allocation.id();
+        let mut allocation = match allocator.destroy_buffer(buffer, allocation) {
+            Err(vk_mem::FreeError {
+                error:
+                    vk_mem::VmaError::StillMapped {
+                        allocation: identity,
+                        location,
+                    },
+                allocation,
+            }) => {
+                assert_eq!(identity.id, id);
+                assert_eq!(identity.location.unwrap().file(), file!());
+                assert_eq!((location.file(), location.line()), (file!(), line));
+                allocation
+            }
+            other => panic!("unexpected result: {:?}", other),
+        };
+        // Each map has to be undone before the allocation can be freed
+        allocator.unmap_memory(&mut allocation);
+        let mut allocation = match allocator.destroy_buffer(buffer, allocation) {
+            Err(vk_mem::FreeError {
+                error: vk_mem::VmaError::StillMapped { .. },
+                allocation,
+            }) => allocation,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        allocator.unmap_memory(&mut allocation);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
     }
-
-    // VMA should trigger an assert when the VirtualBlock is dropped, if any
-    // allocations have not been freed, or the block not cleared instead
 }
 
 #[test]
-fn virtual_allocation_user_data() {
-    let create_info = vk_mem::VirtualBlockCreateInfo {
-        size: 16 * 1024 * 1024,
+fn free_rejected_allocations_after_unmapping() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: u32::MAX,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferHost,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
         ..Default::default()
-    }; // 16MB block
-    let mut virtual_block =
-        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
-
-    let user_data = Box::new(vec![12, 34, 56, 78, 90]);
-    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
-        size: 8 * 1024 * 1024,
-        alignment: 0,
-        user_data: user_data.as_ptr() as usize,
-        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
     };
-
     unsafe {
-        let (mut virtual_alloc_0, _) = virtual_block.allocate(allocation_info).unwrap();
-        let queried_info = virtual_block
-            .get_allocation_info(&virtual_alloc_0)
-            .expect("Couldn't get VirtualAllocationInfo from VirtualBlock");
-        let queried_user_data = std::slice::from_raw_parts(queried_info.user_data as *const i32, 5);
-        assert_eq!(queried_user_data, &*user_data);
-        virtual_block.free(&mut virtual_alloc_0);
+        let mut allocation = allocator
+            .allocate_memory(&requirements, &allocation_info)
+            .unwrap();
+        allocator.map_memory(&mut allocation).unwrap();
+        let err = allocator.free_memory(allocation).unwrap_err();
+        assert!(matches!(err.error, vk_mem::VmaError::StillMapped { .. }));
+        let mut allocation = err.allocation;
+        allocator.unmap_memory(&mut allocation);
+        allocator.free_memory(allocation).unwrap();
+
+        let mut allocations = allocator
+            .allocate_memory_pages(&requirements, &allocation_info, 2)
+            .unwrap();
+        allocator.map_memory(&mut allocations[1]).unwrap();
+        let err = allocator.free_memory_pages(allocations).unwrap_err();
+        assert!(matches!(err.error, vk_mem::VmaError::StillMapped { .. }));
+        let mut allocations = err.allocation;
+        assert_eq!(allocations.len(), 2);
+        allocator.unmap_memory(&mut allocations[1]);
+        allocator.free_memory_pages(allocations).unwrap();
     }
+    // Nothing is left behind for the leak check
+    allocator.destroy().unwrap();
 }
 
 #[test]
-fn virtual_block_out_of_space() {
-    let create_info = vk_mem::VirtualBlockCreateInfo {
-        size: 16 * 1024 * 1024,
+fn allocate_memory2_prefers_dedicated() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_DST);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    let dedicated = ash::vk::MemoryDedicatedRequirements {
+        prefers_dedicated_allocation: ash::vk::TRUE,
         ..Default::default()
-    }; // 16MB block
-    let mut virtual_block =
-        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
-
-    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
-        size: 16 * 1024 * 1024 + 1,
-        alignment: 0,
-        user_data: 0,
-        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
     };
-
     unsafe {
-        match virtual_block.allocate(allocation_info) {
-            Ok(_) => panic!("Created VirtualAllocation larger than VirtualBlock"),
-            Err(ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY) => {}
-            Err(_) => panic!("Unexpected VirtualBlock error"),
+        let resources = [(); 2].map(|_| {
+            let buffer = harness.device.create_buffer(&buffer_info, None).unwrap();
+            let requirements = ash::vk::MemoryRequirements2 {
+                memory_requirements: harness.device.get_buffer_memory_requirements(buffer),
+                ..Default::default()
+            };
+            let allocation = allocator
+                .allocate_memory2(
+                    &requirements,
+                    &dedicated,
+                    Some(vk_mem::MemoryResource::Buffer(buffer)),
+                    &allocation_info,
+                )
+                .unwrap();
+            (buffer, allocation)
+        });
+        // Small buffers would share a block without the preference
+        let [a, b] = [&resources[0].1, &resources[1].1]
+            .map(|allocation| allocator.get_allocation_info(allocation).unwrap());
+        assert_eq!((a.offset, b.offset), (0, 0));
+        assert_ne!(a.device_memory, b.device_memory);
+        for (buffer, allocation) in resources {
+            allocator.destroy_buffer(buffer, allocation).unwrap();
         }
     }
 }
+
+#[test]
+fn allocate_memory2_never_allocate() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: u32::MAX,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    unsafe {
+        let first = allocator
+            .allocate_memory(&requirements, &allocation_info)
+            .unwrap();
+        let first_info = allocator.get_allocation_info(&first).unwrap();
+        let requirements = ash::vk::MemoryRequirements2 {
+            memory_requirements: ash::vk::MemoryRequirements {
+                memory_type_bits: 1 << first_info.memory_type,
+                ..requirements
+            },
+            ..Default::default()
+        };
+        let never_allocate = vk_mem::AllocationCreateInfo {
+            flags: vk_mem::AllocationCreateFlags::NEVER_ALLOCATE,
+            ..allocation_info
+        };
+
+        // A required dedicated allocation can't be made without allocating
+        let requires = ash::vk::MemoryDedicatedRequirements {
+            requires_dedicated_allocation: ash::vk::TRUE,
+            ..Default::default()
+        };
+        assert!(matches!(
+            allocator.allocate_memory2(&requirements, &requires, None, &never_allocate),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            allocator.allocate_memory2(&requirements, &requires, None, &allocation_info),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+
+        // A preferred one is dropped, the allocation goes in the existing block
+        let prefers = ash::vk::MemoryDedicatedRequirements {
+            prefers_dedicated_allocation: ash::vk::TRUE,
+            ..Default::default()
+        };
+        let second = allocator
+            .allocate_memory2(&requirements, &prefers, None, &never_allocate)
+            .unwrap();
+        let second_info = allocator.get_allocation_info(&second).unwrap();
+        assert_eq!(second_info.device_memory, first_info.device_memory);
+
+        allocator.free_memory(first).unwrap();
+        allocator.free_memory(second).unwrap();
+    }
+}
+
+#[test]
+fn create_gpu_buffer_pool() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let allocator = Arc::new(allocator);
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST);
+
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        required_flags: ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
+        preferred_flags: ash::vk::MemoryPropertyFlags::HOST_COHERENT
+            | ash::vk::MemoryPropertyFlags::HOST_CACHED,
+        flags: vk_mem::AllocationCreateFlags::MAPPED,
+
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+
+        // Create a pool that can have at most 2 blocks, 128 MiB each.
+        let pool_info = vk_mem::PoolCreateInfo {
+            memory_type_index,
+            block_size: 128 * 1024 * 1024,
+            max_block_count: 2,
+            ..Default::default()
+        };
+
+        let pool = allocator.create_pool(&pool_info).unwrap();
+
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        let allocation_info = allocator.get_allocation_info(&allocation).unwrap();
+        assert_ne!(allocation_info.mapped_data, std::ptr::null_mut());
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn deferred_pool_destruction() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        match pool.destroy() {
+            Err(vk_mem::VmaError::PoolNotEmpty {
+                allocation_count, ..
+            }) => assert_eq!(allocation_count, 1),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(allocator.destroy_deferred_pools(), 1);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        assert_eq!(allocator.destroy_deferred_pools(), 0);
+    }
+}
+
+#[test]
+fn out_of_memory_report() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        flags: vk_mem::AllocationCreateFlags::NEVER_ALLOCATE,
+        ..Default::default()
+    };
+    let err = unsafe { allocator.allocate_memory(&requirements, &allocation_info) }.unwrap_err();
+    assert_eq!(
+        err.result(),
+        Some(ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+    );
+    match err {
+        vk_mem::VmaError::OutOfDeviceMemory(Some(report)) => {
+            assert_eq!(report.size, Some(4096));
+            assert!(!report.custom_pool);
+            assert!(!report.memory_type_candidates.is_empty());
+            assert_eq!(
+                report.budgets.len(),
+                unsafe { allocator.get_memory_properties() }.memory_heap_count as usize
+            );
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn out_of_memory_report_overflowing_pages() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let requirements = ash::vk::MemoryRequirements {
+        size: 1 << 62,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        flags: vk_mem::AllocationCreateFlags::NEVER_ALLOCATE,
+        ..Default::default()
+    };
+    let err =
+        unsafe { allocator.allocate_memory_pages(&requirements, &allocation_info, 4) }.unwrap_err();
+    match err {
+        vk_mem::VmaError::OutOfDeviceMemory(Some(report)) => assert_eq!(report.size, None),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn allocate_memory_within_budget() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let budgets = allocator.get_heap_budgets().unwrap();
+    let size = budgets.iter().map(|budget| budget.budget).max().unwrap() + 1;
+    let requirements = ash::vk::MemoryRequirements {
+        size,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    let err = unsafe { allocator.allocate_memory_within_budget(&requirements, &allocation_info) }
+        .unwrap_err();
+    assert!(err.is_out_of_device_memory());
+    match err {
+        vk_mem::VmaError::BudgetExceeded(denial) => {
+            assert_eq!(denial.size, size);
+            assert!(!denial.heaps.is_empty());
+            for heap in &denial.heaps {
+                assert_eq!(heap.usage + size - heap.budget, heap.missing);
+            }
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn structured_vulkan_errors() {
+    for result in [
+        ash::vk::Result::ERROR_OUT_OF_HOST_MEMORY,
+        ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+        ash::vk::Result::ERROR_FEATURE_NOT_PRESENT,
+        ash::vk::Result::ERROR_FRAGMENTED_POOL,
+        ash::vk::Result::ERROR_INVALID_EXTERNAL_HANDLE,
+        ash::vk::Result::ERROR_UNKNOWN,
+    ] {
+        assert_eq!(vk_mem::VmaError::from(result).result(), Some(result));
+    }
+    assert_eq!(
+        vk_mem::VmaError::from(ash::vk::Result::ERROR_FRAGMENTED_POOL),
+        vk_mem::VmaError::FragmentedPool
+    );
+    assert_eq!(
+        vk_mem::VmaError::from(ash::vk::Result::ERROR_UNKNOWN),
+        vk_mem::VmaError::VulkanError(ash::vk::Result::ERROR_UNKNOWN)
+    );
+    assert!(
+        vk_mem::VmaError::from(ash::vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+            .is_out_of_device_memory()
+    );
+    assert_eq!(vk_mem::VmaError::WrongVirtualBlock.result(), None);
+}
+
+#[test]
+fn pool_algorithm_validation() {
+    let mut create_info = vk_mem::PoolCreateInfo::default();
+    assert_eq!(create_info.algorithm(), vk_mem::PoolAlgorithm::Default);
+    create_info.validate().unwrap();
+
+    create_info.set_algorithm(vk_mem::PoolAlgorithm::Linear);
+    assert!(create_info
+        .flags
+        .contains(vk_mem::AllocatorPoolCreateFlags::LINEAR_ALGORITHM));
+    // Multi-block linear pools are plain linear allocators.
+    create_info.validate().unwrap();
+    create_info.max_block_count = 1;
+    create_info.validate().unwrap();
+    create_info.min_block_count = 2;
+    assert!(matches!(
+        create_info.validate(),
+        Err(vk_mem::VmaError::InvalidParameter(_))
+    ));
+    create_info.min_block_count = 0;
+
+    create_info.set_algorithm(vk_mem::PoolAlgorithm::Default);
+    assert!(create_info.flags.is_empty());
+    create_info.min_allocation_alignment = 3;
+    assert!(create_info.validate().is_err());
+}
+
+#[test]
+fn pool_reserve() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                block_size: 1024 * 1024,
+                ..Default::default()
+            })
+            .unwrap();
+
+        pool.reserve(3 * 1024 * 1024).unwrap();
+        assert_eq!(pool.get_statistics().unwrap().block_count, 3);
+
+        // Allocations take over the reserved blocks instead of allocating new ones.
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        let statistics = pool.get_statistics().unwrap();
+        assert_eq!(statistics.block_count, 3);
+        assert_eq!(statistics.allocation_count, 3);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        pool.destroy().unwrap();
+
+        assert!(matches!(
+            allocator.default_pool().reserve(1024),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+    }
+}
+
+#[test]
+fn pool_quota() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(64 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        pool.set_quota(Some(100 * 1024)).unwrap();
+        assert_eq!(pool.quota(), Some(100 * 1024));
+
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        match pool.create_buffer(&buffer_info, &allocation_info) {
+            Err(vk_mem::VmaError::PoolQuotaExceeded { quota, usage, .. }) => {
+                assert_eq!(quota, 100 * 1024);
+                assert!(usage >= 64 * 1024);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        assert_eq!(pool.get_statistics().unwrap().allocation_count, 1);
+
+        pool.set_quota(None).unwrap();
+        let (buffer2, allocation2) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        allocator.destroy_buffer(buffer2, allocation2).unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn pool_quota_concurrent() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(64 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        pool.set_quota(Some(100 * 1024)).unwrap();
+
+        // Exactly one of two racing buffers fits, neither fails from seeing the other.
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| pool.create_buffer(&buffer_info, &allocation_info)))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        for result in results {
+            match result {
+                Ok((buffer, allocation)) => allocator.destroy_buffer(buffer, allocation).unwrap(),
+                Err(err) => assert!(matches!(err, vk_mem::VmaError::PoolQuotaExceeded { .. })),
+            }
+        }
+    }
+}
+
+#[test]
+fn ring_allocator() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(u32::MAX, &allocation_info)
+            .unwrap();
+        let mut ring = allocator
+            .create_ring_allocator(memory_type_index, 1024 * 1024, &allocation_info)
+            .unwrap();
+
+        // Three frames of 384 KiB only fit once the first one is retired.
+        for frame in 1..=3u64 {
+            if frame == 3 {
+                assert!(ring.push(384 * 1024, 256).is_err());
+                assert_eq!(ring.retire_frame(1).unwrap(), 1);
+            }
+            let (_, offset) = ring.push(384 * 1024, 256).unwrap();
+            assert_eq!(offset % 256, 0);
+            ring.end_frame(frame);
+        }
+        assert_eq!(ring.frames_in_flight(), 2);
+        assert_eq!(ring.retire_frame(3).unwrap(), 2);
+    }
+}
+
+#[test]
+fn frame_ring() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(u32::MAX, &allocation_info)
+            .unwrap();
+        let mut ring = allocator
+            .create_frame_ring::<2>(memory_type_index, 1024 * 1024, &allocation_info)
+            .unwrap();
+
+        // The third frame reuses the slot of the first one, freeing its allocation.
+        for frame in 0..3u64 {
+            assert_eq!(ring.begin_frame(frame).unwrap(), (frame == 2) as usize);
+            let (_, offset) = ring.push(384 * 1024, 256).unwrap();
+            assert_eq!(offset % 256, 0);
+        }
+        ring.destroy().unwrap();
+    }
+}
+
+#[test]
+fn double_stack_allocator() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(u32::MAX, &allocation_info)
+            .unwrap();
+        let mut stacks = allocator
+            .create_double_stack_allocator(memory_type_index, 1024 * 1024, &allocation_info)
+            .unwrap();
+
+        let (_, bottom) = stacks.allocate_bottom(256 * 1024, 256).unwrap();
+        let (_, top) = stacks.allocate_top(256 * 1024, 256).unwrap();
+        assert_eq!(bottom, 0);
+        assert_eq!(top, 768 * 1024);
+        assert!(stacks.allocate_top(768 * 1024, 256).is_err());
+
+        stacks.reset_bottom().unwrap();
+        assert_eq!(stacks.bottom_len(), 0);
+        assert_eq!(stacks.top_len(), 1);
+        stacks.allocate_bottom(768 * 1024, 256).unwrap();
+        stacks.reset_top().unwrap();
+    }
+}
+
+#[test]
+fn stack_allocator() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(u32::MAX, &allocation_info)
+            .unwrap();
+        let mut stack = allocator
+            .create_stack_allocator(memory_type_index, 1024 * 1024, &allocation_info)
+            .unwrap();
+
+        stack.allocate(256 * 1024, 256).unwrap();
+        let marker = stack.marker();
+        let (_, offset) = stack.allocate(256 * 1024, 256).unwrap();
+        assert_eq!(offset, 256 * 1024);
+        stack.allocate(256 * 1024, 256).unwrap();
+        assert_eq!(stack.len(), 3);
+
+        stack.free_to_marker(marker).unwrap();
+        assert_eq!(stack.len(), 1);
+        let (_, offset) = stack.allocate(256 * 1024, 256).unwrap();
+        assert_eq!(offset, 256 * 1024);
+
+        stack.reset().unwrap();
+        assert!(matches!(
+            stack.free_to_marker(marker),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+    }
+}
+
+#[test]
+fn snapshot_restore() {
+    let harness = TestHarness::new();
+    let source = harness.create_allocator();
+    let target = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = source
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(64 * 1024)
+                    .usage(ash::vk::BufferUsageFlags::STORAGE_BUFFER),
+                &allocation_info,
+            )
+            .unwrap();
+        let snapshot = source.snapshot();
+        assert_eq!(snapshot.allocations.len(), 1);
+        assert!(snapshot.allocations[0].pool.is_none());
+
+        let restored = target.restore_snapshot(&snapshot).unwrap();
+        let restored_snapshot = target.snapshot();
+        assert_eq!(
+            restored_snapshot.allocations[0].size,
+            snapshot.allocations[0].size
+        );
+        assert_eq!(
+            restored_snapshot.allocations[0].memory_type_index,
+            snapshot.allocations[0].memory_type_index
+        );
+        restored.free(&target).unwrap();
+        source.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn sub_allocator_budget() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let sub_allocator = allocator.create_sub_allocator("plugin", 64 * 1024);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+    let buffer_info = |size| {
+        ash::vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER)
+    };
+
+    unsafe {
+        let (buffer, allocation) = sub_allocator
+            .create_buffer(&buffer_info(16 * 1024), &allocation_info)
+            .unwrap();
+        let stats = sub_allocator.statistics();
+        assert_eq!(stats.allocation_count, 1);
+        assert!(stats.allocation_bytes >= 16 * 1024);
+
+        match sub_allocator.create_buffer(&buffer_info(64 * 1024), &allocation_info) {
+            Err(vk_mem::VmaError::OutOfDeviceMemory(_)) => {}
+            _ => panic!("SubAllocator exceeded its budget"),
+        }
+
+        // Images are checked against the budget before VMA allocates anything for them.
+        let image_info = |size| {
+            ash::vk::ImageCreateInfo::default()
+                .image_type(ash::vk::ImageType::TYPE_2D)
+                .format(ash::vk::Format::R8G8B8A8_UNORM)
+                .extent(ash::vk::Extent3D {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(ash::vk::SampleCountFlags::TYPE_1)
+                .tiling(ash::vk::ImageTiling::OPTIMAL)
+                .usage(ash::vk::ImageUsageFlags::SAMPLED)
+        };
+        match sub_allocator.create_image(&harness.device, &image_info(1024), &allocation_info) {
+            Err(vk_mem::VmaError::OutOfDeviceMemory(_)) => {}
+            _ => panic!("SubAllocator exceeded its budget"),
+        }
+        assert_eq!(
+            allocator
+                .calculate_statistics()
+                .unwrap()
+                .total
+                .statistics
+                .allocation_count,
+            1
+        );
+        let (image, image_allocation) = sub_allocator
+            .create_image(&harness.device, &image_info(16), &allocation_info)
+            .unwrap();
+        assert_eq!(sub_allocator.statistics().allocation_count, 2);
+        sub_allocator
+            .destroy_image(image, image_allocation)
+            .unwrap();
+
+        sub_allocator.destroy_buffer(buffer, allocation).unwrap();
+        assert_eq!(sub_allocator.statistics().allocation_bytes, 0);
+    }
+}
+
+#[test]
+fn test_gpu_stats() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        ..Default::default()
+    };
+
+    unsafe {
+        let stats_1 = allocator.calculate_statistics().unwrap();
+        assert_eq!(stats_1.total.statistics.block_count, 0);
+        assert_eq!(stats_1.total.statistics.allocation_count, 0);
+        assert_eq!(stats_1.total.statistics.allocation_bytes, 0);
+
+        let (buffer, allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default().size(16 * 1024).usage(
+                    ash::vk::BufferUsageFlags::VERTEX_BUFFER
+                        | ash::vk::BufferUsageFlags::TRANSFER_DST,
+                ),
+                &allocation_info,
+            )
+            .unwrap();
+
+        let stats_2 = allocator.calculate_statistics().unwrap();
+        assert_eq!(stats_2.total.statistics.block_count, 1);
+        assert_eq!(stats_2.total.statistics.allocation_count, 1);
+        assert_eq!(stats_2.total.statistics.allocation_bytes, 16 * 1024);
+        assert_eq!(vk_mem::TotalStatistics::from(&stats_2.as_raw()), stats_2);
+
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+
+        let stats_3 = allocator.calculate_statistics().unwrap();
+        assert_eq!(stats_3.total.statistics.block_count, 1);
+        assert_eq!(stats_3.total.statistics.allocation_count, 0);
+        assert_eq!(stats_3.total.statistics.allocation_bytes, 0);
+    }
+}
+
+#[test]
+fn create_virtual_block() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 16 * 1024 * 1024,
+        flags: vk_mem::VirtualBlockCreateFlags::VMA_VIRTUAL_BLOCK_CREATE_LINEAR_ALGORITHM_BIT,
+        allocation_callbacks: None,
+    }; // 16MB block
+    let _virtual_block =
+        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+}
+
+#[test]
+fn virtual_allocate_and_free() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 16 * 1024 * 1024,
+        flags: vk_mem::VirtualBlockCreateFlags::VMA_VIRTUAL_BLOCK_CREATE_LINEAR_ALGORITHM_BIT,
+        allocation_callbacks: None,
+    }; // 16MB block
+    let mut virtual_block =
+        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 8 * 1024 * 1024,
+        alignment: 0,
+        user_data: 0,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+
+    // Fully allocate the VirtualBlock and then free both allocations
+    unsafe {
+        let (mut virtual_alloc_0, offset_0) = virtual_block.allocate(allocation_info).unwrap();
+        let (mut virtual_alloc_1, offset_1) = virtual_block.allocate(allocation_info).unwrap();
+        assert_ne!(offset_0, offset_1);
+        assert_eq!(
+            offset_1.get(),
+            virtual_block
+                .get_allocation_info(&virtual_alloc_1)
+                .unwrap()
+                .offset
+        );
+        virtual_block.free(&mut virtual_alloc_0).unwrap();
+        virtual_block.free(&mut virtual_alloc_1).unwrap();
+    }
+
+    // Fully allocate it again and then clear it
+    unsafe {
+        let (_virtual_alloc_0, offset_0) = virtual_block.allocate(allocation_info).unwrap();
+        let (_virtual_alloc_1, offset_1) = virtual_block.allocate(allocation_info).unwrap();
+        assert_ne!(offset_0, offset_1);
+        virtual_block.clear();
+    }
+
+    // VMA should trigger an assert when the VirtualBlock is dropped, if any
+    // allocations have not been freed, or the block not cleared instead
+}
+
+#[test]
+fn virtual_allocation_user_data() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 16 * 1024 * 1024,
+        ..Default::default()
+    }; // 16MB block
+    let mut virtual_block =
+        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+
+    let user_data = Box::new(vec![12, 34, 56, 78, 90]);
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 8 * 1024 * 1024,
+        alignment: 0,
+        user_data: user_data.as_ptr() as usize,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+
+    unsafe {
+        let (mut virtual_alloc_0, _) = virtual_block.allocate(allocation_info).unwrap();
+        let queried_info = virtual_block
+            .get_allocation_info(&virtual_alloc_0)
+            .expect("Couldn't get VirtualAllocationInfo from VirtualBlock");
+        let queried_user_data = std::slice::from_raw_parts(queried_info.user_data as *const i32, 5);
+        assert_eq!(queried_user_data, &*user_data);
+        virtual_block.free(&mut virtual_alloc_0).unwrap();
+    }
+}
+
+#[test]
+fn virtual_allocation_typed_data() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 16 * 1024 * 1024,
+        ..Default::default()
+    }; // 16MB block
+    let mut virtual_block =
+        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 1024,
+        alignment: 0,
+        user_data: 0,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+
+    let data = std::rc::Rc::new(42);
+    let (mut virtual_alloc_0, _) = virtual_block
+        .allocate_with_data(allocation_info, data.clone())
+        .unwrap();
+    let (virtual_alloc_1, _) = virtual_block
+        .allocate_with_data(allocation_info, data.clone())
+        .unwrap();
+    assert_eq!(
+        virtual_block
+            .get_data::<std::rc::Rc<i32>>(&virtual_alloc_0)
+            .map(|data| **data),
+        Some(42)
+    );
+    assert!(virtual_block.get_data::<u32>(&virtual_alloc_0).is_none());
+    assert_eq!(std::rc::Rc::strong_count(&data), 3);
+
+    unsafe {
+        virtual_block.free(&mut virtual_alloc_0).unwrap();
+        assert_eq!(std::rc::Rc::strong_count(&data), 2);
+        virtual_block.clear();
+    }
+    assert_eq!(std::rc::Rc::strong_count(&data), 1);
+    assert!(virtual_block
+        .get_data::<std::rc::Rc<i32>>(&virtual_alloc_1)
+        .is_none());
+}
+
+#[test]
+fn virtual_allocation_wrong_block() {
+    let create_info = || vk_mem::VirtualBlockCreateInfo {
+        size: 1024 * 1024,
+        ..Default::default()
+    };
+    let mut block_0 = vk_mem::VirtualBlock::new(create_info()).unwrap();
+    let mut block_1 = vk_mem::VirtualBlock::new(create_info()).unwrap();
+
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 1024,
+        alignment: 0,
+        user_data: 0,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+
+    unsafe {
+        let (mut allocation, _) = block_0.allocate(allocation_info).unwrap();
+        assert_eq!(
+            block_1.get_allocation_info(&allocation).err(),
+            Some(vk_mem::VmaError::WrongVirtualBlock)
+        );
+        assert_eq!(
+            block_1.free(&mut allocation),
+            Err(vk_mem::VmaError::WrongVirtualBlock)
+        );
+        assert_eq!(block_0.get_allocation_info(&allocation).unwrap().size, 1024);
+
+        block_0.clear();
+        assert_eq!(
+            block_0.free(&mut allocation),
+            Err(vk_mem::VmaError::WrongVirtualBlock)
+        );
+    }
+}
+
+#[test]
+fn virtual_block_out_of_space() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 16 * 1024 * 1024,
+        ..Default::default()
+    }; // 16MB block
+    let mut virtual_block =
+        vk_mem::VirtualBlock::new(create_info).expect("Couldn't create VirtualBlock");
+
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 16 * 1024 * 1024 + 1,
+        alignment: 0,
+        user_data: 0,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+
+    unsafe {
+        match virtual_block.allocate(allocation_info) {
+            Ok(_) => panic!("Created VirtualAllocation larger than VirtualBlock"),
+            Err(vk_mem::VmaError::OutOfDeviceMemory(_)) => {}
+            Err(_) => panic!("Unexpected VirtualBlock error"),
+        }
+    }
+}
+
+#[test]
+fn virtual_block_destroy() {
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 1024,
+        ..Default::default()
+    };
+    let allocation_info = vk_mem::VirtualAllocationCreateInfo {
+        size: 256,
+        alignment: 0,
+        user_data: 0,
+        flags: vk_mem::VirtualAllocationCreateFlags::empty(),
+    };
+    let mut virtual_block = vk_mem::VirtualBlock::new(create_info).unwrap();
+    let (mut allocation, _) = unsafe { virtual_block.allocate(allocation_info).unwrap() };
+    unsafe { virtual_block.free(&mut allocation).unwrap() };
+    virtual_block.destroy().unwrap();
+
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 1024,
+        ..Default::default()
+    };
+    let mut virtual_block = vk_mem::VirtualBlock::new(create_info).unwrap();
+    let _ = unsafe { virtual_block.allocate(allocation_info).unwrap() };
+    let (_, offset) = unsafe { virtual_block.allocate(allocation_info).unwrap() };
+    let (mut allocation, _) = unsafe { virtual_block.allocate(allocation_info).unwrap() };
+    unsafe { virtual_block.free(&mut allocation).unwrap() };
+    match virtual_block.destroy() {
+        Err(vk_mem::VmaError::VirtualBlockNotEmpty { leaked }) => {
+            assert_eq!(leaked.len(), 2);
+            assert_eq!(leaked[1].offset, offset.get());
+            assert!(leaked.iter().all(|allocation| allocation.size == 256));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Dropping a block with live allocations frees them instead of asserting inside VMA
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: 1024,
+        ..Default::default()
+    };
+    let mut virtual_block = vk_mem::VirtualBlock::new(create_info).unwrap();
+    let _ = unsafe { virtual_block.allocate(allocation_info).unwrap() };
+    drop(virtual_block);
+}
+
+#[test]
+fn statistics_table() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+
+        let table = allocator.to_table();
+        assert!(table.contains(&format!("memory type {}", memory_type_index)));
+        assert!(table.contains(&format!("pool 0 (type {})", memory_type_index)));
+        assert!(table.lines().any(|line| line.starts_with("total")));
+        assert!(table.lines().next().unwrap().ends_with("budget"));
+        assert!(table.contains("16.0 KiB"));
+
+        let summary = pool.calculate_statistics().unwrap().to_string();
+        assert!(summary.starts_with("1 block(s), 1 allocation(s)"));
+        assert!(summary.contains("16.0 KiB used"));
+        assert_eq!(format!("{:>8}", vk_mem::ByteSize(512)), "   512 B");
+        assert_eq!(vk_mem::ByteSize(3 << 29).to_string(), "1.5 GiB");
+
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn protected_pool_requires_feature() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    match allocator.create_protected_pool(u32::MAX, &vk_mem::PoolCreateInfo::default()) {
+        Err(vk_mem::VmaError::ProtectedMemoryNotEnabled) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn virtual_block_allocator() {
+    let mut memory = vec![0u64; 1024];
+    let base = std::ptr::NonNull::new(memory.as_mut_ptr() as *mut u8).unwrap();
+    let create_info = vk_mem::VirtualBlockCreateInfo {
+        size: (memory.len() * 8) as u64,
+        ..Default::default()
+    };
+    unsafe {
+        let arena = vk_mem::VirtualBlockAllocator::new(base, create_info).unwrap();
+        let layout = std::alloc::Layout::new::<[u32; 16]>();
+        let a = arena.alloc(layout).unwrap();
+        let b = arena.alloc(layout).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.as_ptr() as usize % layout.align(), 0);
+        assert_eq!(arena.allocation_count(), 2);
+        a.as_ptr().write_bytes(0xab, layout.size());
+
+        assert!(arena
+            .alloc(std::alloc::Layout::from_size_align(1 << 20, 8).unwrap())
+            .is_none());
+        assert!(arena
+            .alloc(std::alloc::Layout::from_size_align(8, 4096).unwrap())
+            .is_none());
+
+        arena.dealloc(a, layout);
+        arena.dealloc(b, layout);
+        assert_eq!(arena.allocation_count(), 0);
+    }
+}
+
+#[test]
+fn descriptor_heap_suballocator() {
+    // 48-byte descriptors with 64-byte offset alignment: ranges start at multiples of 4
+    // descriptors (192 bytes).
+    let mut heap = vk_mem::DescriptorHeapSuballocator::new(64, 48, 64).unwrap();
+    let a = heap.allocate_descriptors(3).unwrap();
+    let b = heap.allocate_descriptors(5).unwrap();
+    assert_eq!(a.byte_size(), 3 * 48);
+    assert_eq!(a.byte_offset() % 64, 0);
+    assert_eq!(b.byte_offset() % 64, 0);
+    assert_eq!(b.first_descriptor() % 4, 0);
+    assert!(
+        b.byte_offset() >= a.byte_offset() + a.byte_size()
+            || a.byte_offset() >= b.byte_offset() + b.byte_size()
+    );
+    assert!(heap.allocate_descriptors(65).is_err());
+
+    heap.free(a).unwrap();
+    heap.reset();
+    assert_eq!(heap.free(b), Err(vk_mem::VmaError::WrongVirtualBlock));
+
+    assert!(vk_mem::DescriptorHeapSuballocator::new(64, 48, 48).is_err());
+}
+
+#[test]
+fn defragmentation_pass() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+
+    let requirements = ash::vk::MemoryRequirements {
+        size: 64 * 1024,
+        alignment: 256,
+        memory_type_bits: u32::MAX,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (allocations, free): (Vec<_>, Vec<_>) = (0..16)
+            .map(|_| {
+                allocator
+                    .allocate_memory(&requirements, &allocation_info)
+                    .unwrap()
+            })
+            .enumerate()
+            .partition(|(index, _)| index % 2 == 0);
+        allocator
+            .free_memory_pages(free.into_iter().map(|(_, allocation)| allocation))
+            .unwrap();
+        let allocations: Vec<_> = allocations
+            .into_iter()
+            .map(|(_, allocation)| allocation)
+            .collect();
+
+        let info = vk_mem::ffi::VmaDefragmentationInfo {
+            flags: 0,
+            pool: std::ptr::null_mut(),
+            maxBytesPerPass: 0,
+            maxAllocationsPerPass: 0,
+            pfnBreakCallback: None,
+            pBreakCallbackUserData: std::ptr::null_mut(),
+        };
+        allocator
+            .set_allocation_pinned(&allocations[0], true)
+            .unwrap();
+        assert!(allocator.is_allocation_pinned(&allocations[0]));
+
+        let context = allocator.begin_defragmentation(&info).unwrap();
+        context
+            .begin_pass(|moves| {
+                for defrag_move in moves.iter_mut() {
+                    assert!(allocations
+                        .iter()
+                        .any(|allocation| defrag_move.is_source(allocation)));
+                    assert!(defrag_move.source_id().is_some());
+                    assert_eq!(
+                        defrag_move.is_pinned(),
+                        defrag_move.is_source(&allocations[0])
+                    );
+                    defrag_move.set_operation(vk_mem::DefragMoveOperation::Ignore);
+                    if defrag_move.is_pinned() {
+                        defrag_move.set_operation(vk_mem::DefragMoveOperation::Copy);
+                        assert_eq!(defrag_move.operation(), vk_mem::DefragMoveOperation::Ignore);
+                    }
+                }
+                Ok::<(), vk_mem::VmaError>(())
+            })
+            .unwrap();
+        assert_eq!(context.last_pass_stats().allocations_moved, 0);
+        let stats = context.end();
+        assert_eq!(stats.allocations_moved, 0);
+        assert!(stats.to_string().starts_with("0 allocation(s) moved"));
+
+        allocator.free_memory_pages(allocations).unwrap();
+    }
+}
+
+#[test]
+fn defragment_resources() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let device = &harness.device;
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(64 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC | ash::vk::BufferUsageFlags::TRANSFER_DST);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffers, free): (Vec<_>, Vec<_>) = (0..16)
+            .map(|_| {
+                allocator
+                    .create_buffer(&buffer_info, &allocation_info)
+                    .unwrap()
+            })
+            .enumerate()
+            .partition(|(index, _)| index % 2 == 0);
+        for (_, (buffer, allocation)) in free {
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+        }
+        let mut resources = vk_mem::DefragmentableResources::new(device);
+        let allocations: Vec<vk_mem::Allocation> = buffers
+            .into_iter()
+            .map(|(_, (buffer, allocation))| {
+                resources.register_buffer(&allocation, buffer, &buffer_info);
+                allocation
+            })
+            .collect();
+
+        let pool_info = ash::vk::CommandPoolCreateInfo::default()
+            .flags(ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(0);
+        let command_pool = device.create_command_pool(&pool_info, None).unwrap();
+        let cmd = device
+            .allocate_command_buffers(
+                &ash::vk::CommandBufferAllocateInfo::default()
+                    .command_pool(command_pool)
+                    .command_buffer_count(1),
+            )
+            .unwrap()[0];
+        let queue = device.get_device_queue(0, 0);
+
+        let info = vk_mem::ffi::VmaDefragmentationInfo {
+            flags: 0,
+            pool: std::ptr::null_mut(),
+            maxBytesPerPass: 0,
+            maxAllocationsPerPass: 0,
+            pfnBreakCallback: None,
+            pBreakCallbackUserData: std::ptr::null_mut(),
+        };
+        allocator
+            .defragment(&info, &mut resources, cmd, |cmd| {
+                let command_buffers = [cmd];
+                let submit = ash::vk::SubmitInfo::default().command_buffers(&command_buffers);
+                device.queue_submit(queue, &[submit], ash::vk::Fence::null())?;
+                device.queue_wait_idle(queue)?;
+                Ok(())
+            })
+            .unwrap();
+
+        for allocation in allocations {
+            let buffer = resources.buffer(&allocation).unwrap();
+            resources.unregister(&allocation);
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+        }
+        device.destroy_command_pool(command_pool, None);
+    }
+}
+
+#[test]
+fn async_defragmentation() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let device = &harness.device;
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(64 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC | ash::vk::BufferUsageFlags::TRANSFER_DST);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffers, free): (Vec<_>, Vec<_>) = (0..16)
+            .map(|_| {
+                allocator
+                    .create_buffer(&buffer_info, &allocation_info)
+                    .unwrap()
+            })
+            .enumerate()
+            .partition(|(index, _)| index % 2 == 0);
+        for (_, (buffer, allocation)) in free {
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+        }
+        let mut resources = vk_mem::DefragmentableResources::new(device);
+        let allocations: Vec<vk_mem::Allocation> = buffers
+            .into_iter()
+            .map(|(_, (buffer, allocation))| {
+                resources.register_buffer(&allocation, buffer, &buffer_info);
+                allocation
+            })
+            .collect();
+
+        let pool_info = ash::vk::CommandPoolCreateInfo::default()
+            .flags(ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(0);
+        let command_pool = device.create_command_pool(&pool_info, None).unwrap();
+        let cmd = device
+            .allocate_command_buffers(
+                &ash::vk::CommandBufferAllocateInfo::default()
+                    .command_pool(command_pool)
+                    .command_buffer_count(1),
+            )
+            .unwrap()[0];
+        let mut semaphore_type = ash::vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(ash::vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore = device
+            .create_semaphore(
+                &ash::vk::SemaphoreCreateInfo::default().push_next(&mut semaphore_type),
+                None,
+            )
+            .unwrap();
+        let queue = device.get_device_queue(0, 0);
+
+        let info = vk_mem::ffi::VmaDefragmentationInfo {
+            flags: 0,
+            pool: std::ptr::null_mut(),
+            maxBytesPerPass: 0,
+            maxAllocationsPerPass: 0,
+            pfnBreakCallback: None,
+            pBreakCallbackUserData: std::ptr::null_mut(),
+        };
+        let mut defragmentation = allocator
+            .begin_async_defragmentation(&info, &resources, queue, cmd, semaphore, 0)
+            .unwrap();
+        while !defragmentation.poll(&mut resources).unwrap() {
+            std::thread::yield_now();
+        }
+        assert_eq!(
+            device.get_semaphore_counter_value(semaphore).unwrap(),
+            defragmentation.signal_value()
+        );
+        for moved in defragmentation.last_pass_moves() {
+            let allocation = allocations
+                .iter()
+                .find(|allocation| allocation.id() == moved.id)
+                .unwrap();
+            let info = allocator.get_allocation_info(allocation).unwrap();
+            assert_eq!(
+                (info.device_memory, info.offset),
+                (moved.new_memory, moved.new_offset)
+            );
+            assert_ne!(
+                (moved.old_memory, moved.old_offset),
+                (moved.new_memory, moved.new_offset)
+            );
+        }
+        defragmentation.end();
+
+        for allocation in allocations {
+            let buffer = resources.buffer(&allocation).unwrap();
+            resources.unregister(&allocation);
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+        }
+        device.destroy_semaphore(semaphore, None);
+        device.destroy_command_pool(command_pool, None);
+    }
+}
+
+#[test]
+fn create_buffer_with_defrag_fallback() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let device = &harness.device;
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC | ash::vk::BufferUsageFlags::TRANSFER_DST);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let mut resources = vk_mem::DefragmentableResources::new(device);
+        let mut fallback =
+            vk_mem::DefragFallback::new(&mut resources, ash::vk::CommandBuffer::null(), |_| {
+                panic!("defragmentation shouldn't be needed")
+            })
+            .max_passes(1);
+        let (buffer, allocation) = allocator
+            .create_buffer_with_defrag_fallback(&buffer_info, &allocation_info, &mut fallback)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn concurrent_pool_defragmentation() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC | ash::vk::BufferUsageFlags::TRANSFER_DST);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    let info = vk_mem::ffi::VmaDefragmentationInfo {
+        flags: 0,
+        pool: std::ptr::null_mut(),
+        maxBytesPerPass: 0,
+        maxAllocationsPerPass: 0,
+        pfnBreakCallback: None,
+        pBreakCallbackUserData: std::ptr::null_mut(),
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pools: Vec<_> = (0..2)
+            .map(|_| {
+                allocator
+                    .create_pool(&vk_mem::PoolCreateInfo {
+                        memory_type_index,
+                        ..Default::default()
+                    })
+                    .unwrap()
+            })
+            .collect();
+        let buffers: Vec<_> = pools
+            .iter()
+            .map(|pool| pool.create_buffer(&buffer_info, &allocation_info).unwrap())
+            .collect();
+
+        let contexts: Vec<_> = pools
+            .iter()
+            .map(|pool| pool.begin_defragmentation(&info).unwrap())
+            .collect();
+        assert!(matches!(
+            pools[0].begin_defragmentation(&info),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+        std::thread::scope(|scope| {
+            for context in contexts {
+                scope.spawn(move || {
+                    context
+                        .begin_pass(|moves| {
+                            for defrag_move in moves.iter_mut() {
+                                defrag_move.set_operation(vk_mem::DefragMoveOperation::Ignore);
+                            }
+                            Ok::<(), vk_mem::VmaError>(())
+                        })
+                        .unwrap();
+                    context.end();
+                });
+            }
+        });
+        drop(pools[0].begin_defragmentation(&info).unwrap());
+
+        for (buffer, allocation) in buffers {
+            allocator.destroy_buffer(buffer, allocation).unwrap();
+        }
+    }
+}
+
+#[test]
+fn heap_budgets() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let budgets = allocator.get_heap_budgets().unwrap();
+    let properties = unsafe { allocator.get_memory_properties() };
+    assert_eq!(budgets.len(), properties.memory_heap_count as usize);
+    for (index, budget) in budgets.iter().enumerate() {
+        assert_eq!(budget.heap_index, index as u32);
+        assert_eq!(budget.size, properties.memory_heaps[index].size);
+        assert!(budget.flags == properties.memory_heaps[index].flags);
+    }
+    assert!(budgets.iter().any(|budget| budget.is_device_local()));
+}
+
+#[test]
+fn stats_sampler() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let mut sampler = allocator.create_stats_sampler(2);
+    for _ in 0..3 {
+        sampler.sample();
+    }
+    assert_eq!(sampler.samples().len(), 2);
+    let summary = sampler
+        .summary(0, std::time::Duration::MAX, |heap| heap.usage)
+        .unwrap();
+    assert_eq!(summary.samples, 2);
+    assert!(summary.min <= summary.average && summary.average <= summary.max);
+    assert!(sampler
+        .summary(u32::MAX, std::time::Duration::MAX, |heap| heap.usage)
+        .is_none());
+
+    let before = sampler.samples().last().unwrap().time;
+    sampler.start(std::time::Duration::from_millis(1));
+    assert!(sampler.is_running());
+    while sampler.samples().last().unwrap().time == before {
+        std::thread::yield_now();
+    }
+    sampler.stop();
+    assert!(!sampler.is_running());
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn prometheus_metrics() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+
+        let text = allocator.to_prometheus();
+        assert!(text.contains("# TYPE vk_mem_heap_budget_bytes gauge"));
+        assert!(text.contains("vk_mem_heap_usage_bytes{heap=\"0\"}"));
+        assert!(text.contains("vk_mem_live_allocations 1"));
+        assert!(text.contains(&format!(
+            "vk_mem_pool_allocation_count{{pool=\"pool 0\",memory_type=\"{}\"}} 1",
+            memory_type_index
+        )));
+
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn allocation_hooks() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let hook = allocator.add_allocation_hook({
+        let events = events.clone();
+        move |event: &vk_mem::AllocationEvent| events.lock().unwrap().push(*event)
+    });
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        user_data: 42,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        let id = allocation.id();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+
+        {
+            let events = events.lock().unwrap();
+            assert_eq!(events.len(), 2);
+            assert_eq!(events[0].kind, vk_mem::AllocationEventKind::Allocated);
+            assert_eq!(events[1].kind, vk_mem::AllocationEventKind::Freed);
+            for event in events.iter() {
+                assert_eq!(event.id, id);
+                // Frees don't know their pool without bookkeeping
+                let pool_known = event.kind == vk_mem::AllocationEventKind::Allocated
+                    || vk_mem::Allocator::build_config().bookkeeping;
+                assert_eq!(event.pool, pool_known.then(|| pool.as_raw()));
+                assert_eq!(event.memory_type, memory_type_index);
+                assert_eq!(event.user_data, 42);
+                assert!(event.size >= 16 * 1024);
+            }
+        }
+
+        assert!(allocator.remove_allocation_hook(hook));
+        assert!(!allocator.remove_allocation_hook(hook));
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn allocation_registry() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        user_data: 7,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.set_allocation_name(&allocation, Some(c"uniforms"));
+
+        let live = allocator.live_allocations();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].id, allocation.id());
+        assert_eq!(live[0].name.as_deref(), Some("uniforms"));
+        assert_eq!(live[0].user_data, 7);
+        assert!(live[0].pool.is_null());
+        assert_eq!(live[0].location.file(), file!());
+
+        let report = allocator.report();
+        assert_eq!(report.by_location().len(), 1);
+        let text = report.to_string();
+        assert!(text.starts_with("1 live allocation(s)"));
+        assert!(text.contains("default pools: 1 allocation(s)"));
+
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        assert!(allocator.live_allocations().is_empty());
+    }
+}
+
+#[test]
+fn adopt_raw_allocation() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(requirements.memory_type_bits, &allocation_info)
+            .unwrap();
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut raw_info: vk_mem::ffi::VmaAllocationCreateInfo = (&allocation_info).into();
+        raw_info.pool = pool.as_raw();
+        let mut raw: vk_mem::ffi::VmaAllocation = std::ptr::null_mut();
+        vk_mem::ffi::vmaAllocateMemory(
+            allocator.as_raw(),
+            &requirements,
+            &raw_info,
+            &mut raw,
+            std::ptr::null_mut(),
+        )
+        .result()
+        .unwrap();
+
+        let allocation = vk_mem::Allocation::adopt(raw, &allocator, Some(&pool));
+        assert_eq!(allocation.as_raw(), raw);
+        let live = allocator.live_allocations();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].id, allocation.id());
+        assert_eq!(live[0].pool, pool.as_raw());
+        assert_eq!(live[0].location.file(), file!());
+
+        allocator.free_memory(allocation).unwrap();
+        assert!(allocator.live_allocations().is_empty());
+        assert_eq!(pool.get_statistics().unwrap().allocation_count, 0);
+    }
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn allocation_backtrace() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        let live = allocator.live_allocations();
+        assert_eq!(
+            live[0].backtrace.get().status(),
+            std::backtrace::BacktraceStatus::Captured
+        );
+        assert_eq!(live[0].backtrace, live[0].clone().backtrace);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn build_config() {
+    let config = vk_mem::Allocator::build_config();
+    assert_eq!(config.stats_string, cfg!(feature = "stats-string"));
+    assert_eq!(config.rust_allocator, cfg!(feature = "rust-allocator"));
+    assert_eq!(config.assertions, cfg!(debug_assertions));
+    assert!(config.min_alignment.is_power_of_two());
+    assert!(config.large_heap_block_size > 0);
+}
+
+#[test]
+fn memory_map_svg() {
+    let json = r#"{
+        "DefaultPools": {
+            "Type 1": {
+                "Blocks": {
+                    "0": {
+                        "TotalBytes": 4096,
+                        "Suballocations": [
+                            {"Offset": 0, "Type": "BUFFER", "Size": 1024, "Name": "vertices <a&b>"},
+                            {"Offset": 1024, "Type": "FREE", "Size": 3072}
+                        ]
+                    }
+                },
+                "DedicatedAllocations": [{"Type": "IMAGE_OPTIMAL", "Size": 8192}]
+            }
+        },
+        "CustomPools": {
+            "Type 2": [{"Name": "particles", "Blocks": {}}]
+        }
+    }"#;
+    let map = vk_mem::viz::MemoryMap::from_json(json).unwrap();
+    assert_eq!(map.blocks.len(), 2);
+    assert_eq!(map.blocks[0].memory_type, 1);
+    assert_eq!(map.blocks[0].size, 4096);
+    assert_eq!(
+        map.blocks[0].suballocations[0].name.as_deref(),
+        Some("vertices <a&b>")
+    );
+    assert!(map.blocks[0].suballocations[1].is_free());
+    assert!(map.blocks[1].dedicated);
+    assert_eq!(map.blocks[1].suballocations[0].kind, "IMAGE_OPTIMAL");
+
+    let svg = map.to_svg();
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("vertices &lt;a&amp;b&gt;"));
+    assert!(map.to_html().contains(&svg));
+    assert!(vk_mem::viz::MemoryMap::from_json("{").is_err());
+}
+
+#[cfg(feature = "stats-string")]
+#[test]
+fn memory_map_capture() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        let map = vk_mem::viz::MemoryMap::capture(&allocator).unwrap();
+        assert!(map
+            .blocks
+            .iter()
+            .flat_map(|block| &block.suballocations)
+            .any(|suballocation| suballocation.size >= 16 * 1024 && !suballocation.is_free()));
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[cfg(feature = "recording")]
+#[test]
+fn record_and_replay() {
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+    let recording = SharedBuffer::default();
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.start_recording(recording.clone()).unwrap();
+        assert!(allocator.start_recording(std::io::sink()).is_err());
+        let pool = allocator
+            .create_pool(&vk_mem::PoolCreateInfo {
+                memory_type_index,
+                ..Default::default()
+            })
+            .unwrap();
+        let (buffer, mut allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        allocator.map_memory(&mut allocation).unwrap();
+        allocator.unmap_memory(&mut allocation);
+        let (kept_buffer, kept_allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        drop(pool);
+        allocator.stop_recording().unwrap();
+        assert!(!allocator.is_recording());
+
+        let calls = vk_mem::RecordedCall::read_all(&recording.0.lock().unwrap()[..]).unwrap();
+        assert_eq!(calls.len(), 7);
+        assert!(matches!(
+            calls[0],
+            vk_mem::RecordedCall::CreatePool { pool: 0, .. }
+        ));
+        assert!(matches!(
+            calls[1],
+            vk_mem::RecordedCall::Allocate {
+                pool: Some(0),
+                resource: vk_mem::RecordedResource::Buffer { size: 16384, .. },
+                ..
+            }
+        ));
+        assert!(matches!(calls[2], vk_mem::RecordedCall::Map { .. }));
+        assert!(matches!(calls[3], vk_mem::RecordedCall::Unmap { .. }));
+        assert!(matches!(
+            calls[4],
+            vk_mem::RecordedCall::Allocate { pool: None, .. }
+        ));
+        assert!(matches!(calls[5], vk_mem::RecordedCall::Free { .. }));
+        assert!(matches!(
+            calls[6],
+            vk_mem::RecordedCall::DestroyPool { pool: 0 }
+        ));
+        assert!(vk_mem::RecordedCall::read_all(&b"not a recording"[..]).is_err());
+
+        let replay_allocator = Arc::new(harness.create_allocator());
+        let mut replayer = replay_allocator.create_replayer();
+        replayer.replay_all(&calls).unwrap();
+        assert_eq!(replayer.live_allocation_count(), 1);
+        assert_eq!(replay_allocator.live_allocations().len(), 1);
+        drop(replayer);
+        assert!(replay_allocator.live_allocations().is_empty());
+
+        allocator
+            .destroy_buffer(kept_buffer, kept_allocation)
+            .unwrap();
+    }
+}
+
+#[cfg(feature = "recording")]
+#[test]
+fn record_to() {
+    let harness = TestHarness::new();
+    let path = std::env::temp_dir().join("vk-mem-record-to.vmarec");
+    let create_info = vk_mem::AllocatorCreateInfo::new(
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    )
+    .record_to(&path, vk_mem::RecordFlags::FLUSH_AFTER_CALL);
+    let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+    assert!(allocator.is_recording());
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(1024)
+                    .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER),
+                &vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::AutoPreferDevice,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        // Flushed after the call, before the recording is stopped
+        let calls = vk_mem::RecordedCall::read_all(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(matches!(calls[..], [vk_mem::RecordedCall::Allocate { .. }]));
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+    allocator.stop_recording().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let create_info = vk_mem::AllocatorCreateInfo::new(
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    )
+    .record_to(path.join("missing"), vk_mem::RecordFlags::empty());
+    assert!(matches!(
+        unsafe { vk_mem::Allocator::new(create_info) },
+        Err(vk_mem::VmaError::RecordingFailed(_))
+    ));
+}
+
+#[test]
+fn corruption_detection() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let memory_types = vk_mem::MemoryTypeMask::from_properties(
+        unsafe { allocator.get_memory_properties() },
+        ash::vk::MemoryPropertyFlags::HOST_VISIBLE | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+    );
+    assert_ne!(memory_types, vk_mem::MemoryTypeMask(0));
+    for result in [
+        allocator.check_corruption_in(memory_types),
+        allocator.check_corruption_all(),
+    ] {
+        if cfg!(feature = "detect-corruption") {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(result, Err(vk_mem::VmaError::CorruptionDetectionNotEnabled));
+        }
+    }
+}
+
+#[test]
+fn fill_patterns() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(1024)
+        .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::Auto,
+        flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+        ..Default::default()
+    };
+    assert_eq!(allocator.fill_patterns(), None);
+    allocator.set_fill_patterns(Some(vk_mem::FillPatterns::default()));
+    unsafe {
+        let (buffer, mut allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        let data = allocator.map_memory(&mut allocation).unwrap();
+        assert!(std::slice::from_raw_parts(data, 1024)
+            .iter()
+            .all(|&byte| byte == 0xDC));
+        allocator.unmap_memory(&mut allocation);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+    allocator.set_fill_patterns(None);
+}
+
+#[test]
+fn assert_handler() {
+    let failures = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = failures.clone();
+    vk_mem::set_assert_handler(move |assertion| {
+        recorded
+            .lock()
+            .unwrap()
+            .push(assertion.expression.to_owned());
+    });
+    unsafe {
+        // Destroying a virtual block that still has allocations fails an assertion.
+        let create_info = vk_mem::ffi::VmaVirtualBlockCreateInfo {
+            size: 1024,
+            flags: 0,
+            pAllocationCallbacks: std::ptr::null(),
+        };
+        let mut block = std::ptr::null_mut();
+        vk_mem::ffi::vmaCreateVirtualBlock(&create_info, &mut block)
+            .result()
+            .unwrap();
+        let allocation_info = vk_mem::ffi::VmaVirtualAllocationCreateInfo {
+            size: 64,
+            alignment: 0,
+            flags: 0,
+            pUserData: std::ptr::null_mut(),
+        };
+        let mut allocation = std::mem::zeroed();
+        vk_mem::ffi::vmaVirtualAllocate(
+            block,
+            &allocation_info,
+            &mut allocation,
+            std::ptr::null_mut(),
+        )
+        .result()
+        .unwrap();
+        vk_mem::ffi::vmaDestroyVirtualBlock(block);
+    }
+    vk_mem::reset_assert_handler();
+    // Release builds of VMA have no assertions.
+    assert_eq!(!failures.lock().unwrap().is_empty(), cfg!(debug_assertions));
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn fault_injection() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    allocator.set_fault_injector(Some(vk_mem::FaultInjector {
+        fail_every: 2,
+        ..Default::default()
+    }));
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        assert_eq!(
+            allocator
+                .create_buffer(&buffer_info, &allocation_info)
+                .err(),
+            Some(vk_mem::VmaError::OutOfDeviceMemory(None))
+        );
+        assert_eq!(allocator.injected_fault_count(), 1);
+        assert_eq!(allocator.live_allocations().len(), 1);
+
+        allocator.set_fault_injector(Some(vk_mem::FaultInjector {
+            max_size: Some(1024),
+            ..Default::default()
+        }));
+        assert!(allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .is_err());
+        allocator.set_fault_injector(None);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn fault_injection_before_allocating() {
+    static ALLOCATED_BLOCKS: std::sync::atomic::AtomicUsize =
+        std::sync::atomic::AtomicUsize::new(0);
+    unsafe extern "C" fn allocate(
+        _allocator: vk_mem::ffi::VmaAllocator,
+        _memory_type: u32,
+        _memory: ash::vk::DeviceMemory,
+        _size: ash::vk::DeviceSize,
+        _user_data: *mut std::ffi::c_void,
+    ) {
+        ALLOCATED_BLOCKS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    let harness = TestHarness::new();
+    let callbacks = vk_mem::ffi::VmaDeviceMemoryCallbacks {
+        pfnAllocate: Some(allocate),
+        pfnFree: None,
+        pUserData: std::ptr::null_mut(),
+    };
+    let mut create_info = vk_mem::AllocatorCreateInfo::new(
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    );
+    create_info.device_memory_callbacks = Some(&callbacks);
+    let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        flags: vk_mem::AllocationCreateFlags::DEDICATED_MEMORY,
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    let memory_requirements = ash::vk::MemoryRequirements {
+        size: 16 * 1024,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    allocator.set_fault_injector(Some(vk_mem::FaultInjector {
+        memory_type_bits: !0,
+        ..Default::default()
+    }));
+    unsafe {
+        assert!(allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .is_err());
+        assert!(allocator
+            .allocate_memory(&memory_requirements, &allocation_info)
+            .is_err());
+        allocator.set_fault_injector(Some(vk_mem::FaultInjector {
+            fail_every: 3,
+            ..Default::default()
+        }));
+        assert!(allocator
+            .allocate_memory_pages(&memory_requirements, &allocation_info, 3)
+            .is_err());
+    }
+    assert_eq!(allocator.injected_fault_count(), 1);
+    assert_eq!(
+        ALLOCATED_BLOCKS.load(std::sync::atomic::Ordering::Relaxed),
+        0
+    );
+}
+
+#[test]
+fn debug_utils_names() {
+    static ALLOCATED_BLOCKS: std::sync::atomic::AtomicUsize =
+        std::sync::atomic::AtomicUsize::new(0);
+    unsafe extern "C" fn allocate(
+        _allocator: vk_mem::ffi::VmaAllocator,
+        _memory_type: u32,
+        _memory: ash::vk::DeviceMemory,
+        _size: ash::vk::DeviceSize,
+        _user_data: *mut std::ffi::c_void,
+    ) {
+        ALLOCATED_BLOCKS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    let harness = TestHarness::new();
+    let debug_utils = ash::ext::debug_utils::Device::new(&harness.instance, &harness.device);
+    let callbacks = vk_mem::ffi::VmaDeviceMemoryCallbacks {
+        pfnAllocate: Some(allocate),
+        pfnFree: None,
+        pUserData: std::ptr::null_mut(),
+    };
+    let mut create_info = vk_mem::AllocatorCreateInfo::new(
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    );
+    create_info.debug_utils = Some(&debug_utils);
+    create_info.device_memory_callbacks = Some(&callbacks);
+    let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.set_allocation_name(&allocation, Some(c"uniforms"));
+        allocator.set_allocation_name(&allocation, None);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+    assert!(ALLOCATED_BLOCKS.load(std::sync::atomic::Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn exportable_pool() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let handle_type = ash::vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD;
+    let mut external_info =
+        ash::vk::ExternalMemoryBufferCreateInfo::default().handle_types(handle_type);
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::STORAGE_BUFFER)
+        .push_next(&mut external_info);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(&buffer_info, &allocation_info)
+            .unwrap();
+        assert!(matches!(
+            allocator.create_exportable_pool(
+                memory_type_index,
+                ash::vk::ExternalMemoryHandleTypeFlags::empty()
+            ),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+        let pool = allocator
+            .create_exportable_pool(memory_type_index, handle_type)
+            .unwrap();
+        let (buffer, allocation) = pool.create_buffer(&buffer_info, &allocation_info).unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn host_pointer_pool_alignment() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let external_memory_host =
+        ash::ext::external_memory_host::Device::new(&harness.instance, &harness.device);
+    let properties = ash::vk::PhysicalDeviceExternalMemoryHostPropertiesEXT::default()
+        .min_imported_host_pointer_alignment(4096);
+    let mut memory = vec![0u8; 3 * 4096];
+    let misaligned = memory
+        .as_mut_ptr()
+        .wrapping_add(1 + 4096 - memory.as_ptr() as usize % 4096);
+    unsafe {
+        assert!(matches!(
+            allocator.create_host_pointer_pool(
+                &external_memory_host,
+                &properties,
+                misaligned.cast(),
+                4096,
+                !0
+            ),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+    }
+}
+
+#[test]
+fn raw_allocator_sharing() {
+    let harness = TestHarness::new();
+    let raw = harness.create_allocator().into_raw();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let borrowed = vk_mem::Allocator::from_raw_borrowed(raw);
+        assert!(borrowed.as_raw() == raw);
+        let (buffer, allocation) = borrowed
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        borrowed.destroy_buffer(buffer, allocation).unwrap();
+        drop(borrowed);
+
+        let allocator = vk_mem::Allocator::from_raw(raw);
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn device_group_pool_validation() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    assert!(matches!(
+        allocator.create_device_group_pool(0b1, &vk_mem::PoolCreateInfo::default()),
+        Err(vk_mem::VmaError::DeviceGroupNotEnabled)
+    ));
+}
+
+#[test]
+fn create_allocator_from_raw_parts() {
+    let harness = TestHarness::new();
+    let (instance, device) = (&harness.instance, &harness.device);
+    let functions = vk_mem::VulkanFunctions {
+        vkGetInstanceProcAddr: harness.entry.static_fn().get_instance_proc_addr,
+        vkGetDeviceProcAddr: instance.fp_v1_0().get_device_proc_addr,
+        vkGetPhysicalDeviceProperties: instance.fp_v1_0().get_physical_device_properties,
+        vkGetPhysicalDeviceMemoryProperties: instance
+            .fp_v1_0()
+            .get_physical_device_memory_properties,
+        vkAllocateMemory: device.fp_v1_0().allocate_memory,
+        vkFreeMemory: device.fp_v1_0().free_memory,
+        vkMapMemory: device.fp_v1_0().map_memory,
+        vkUnmapMemory: device.fp_v1_0().unmap_memory,
+        vkFlushMappedMemoryRanges: device.fp_v1_0().flush_mapped_memory_ranges,
+        vkInvalidateMappedMemoryRanges: device.fp_v1_0().invalidate_mapped_memory_ranges,
+        vkBindBufferMemory: device.fp_v1_0().bind_buffer_memory,
+        vkBindImageMemory: device.fp_v1_0().bind_image_memory,
+        vkGetBufferMemoryRequirements: device.fp_v1_0().get_buffer_memory_requirements,
+        vkGetImageMemoryRequirements: device.fp_v1_0().get_image_memory_requirements,
+        vkCreateBuffer: device.fp_v1_0().create_buffer,
+        vkDestroyBuffer: device.fp_v1_0().destroy_buffer,
+        vkCreateImage: device.fp_v1_0().create_image,
+        vkDestroyImage: device.fp_v1_0().destroy_image,
+        vkCmdCopyBuffer: device.fp_v1_0().cmd_copy_buffer,
+        vkGetBufferMemoryRequirements2KHR: device.fp_v1_1().get_buffer_memory_requirements2,
+        vkGetImageMemoryRequirements2KHR: device.fp_v1_1().get_image_memory_requirements2,
+        vkBindBufferMemory2KHR: device.fp_v1_1().bind_buffer_memory2,
+        vkBindImageMemory2KHR: device.fp_v1_1().bind_image_memory2,
+        vkGetPhysicalDeviceMemoryProperties2KHR: instance
+            .fp_v1_1()
+            .get_physical_device_memory_properties2,
+        vkGetDeviceBufferMemoryRequirements: device.fp_v1_3().get_device_buffer_memory_requirements,
+        vkGetDeviceImageMemoryRequirements: device.fp_v1_3().get_device_image_memory_requirements,
+    };
+    let create_info = vk_mem::AllocatorCreateInfo::from_raw_parts(
+        instance.handle(),
+        device.handle(),
+        harness.physical_device,
+        functions,
+    );
+    let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn create_allocator_from_entry() {
+    let harness = TestHarness::new();
+    let mut create_info = vk_mem::AllocatorCreateInfo::from_entry(
+        &harness.entry,
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    );
+    create_info.vulkan_api_version = ash::vk::make_api_version(0, 1, 3, 0);
+    let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(16 * 1024)
+        .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER);
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(&buffer_info, &allocation_info)
+            .unwrap();
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn missing_device_functions() {
+    let harness = TestHarness::new();
+    let mut create_info = vk_mem::AllocatorCreateInfo::from_entry(
+        &harness.entry,
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    );
+    // VK_KHR_maintenance4 isn't enabled on the device, so its functions can't be fetched
+    create_info.flags = vk_mem::AllocatorCreateFlags::KHR_MAINTENANCE4;
+    match unsafe { vk_mem::Allocator::new(create_info) } {
+        Err(vk_mem::VmaError::UnsupportedVulkanFeature { functions }) => assert_eq!(
+            functions,
+            [
+                "vkGetDeviceBufferMemoryRequirementsKHR",
+                "vkGetDeviceImageMemoryRequirementsKHR"
+            ]
+        ),
+        other => panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+#[test]
+fn allocator_flags_need_enabled_extensions() {
+    let harness = TestHarness::new();
+    let mut create_info = vk_mem::AllocatorCreateInfo::new(
+        &harness.instance,
+        &harness.device,
+        harness.physical_device,
+    );
+    create_info.vulkan_api_version = ash::vk::make_api_version(0, 1, 3, 0);
+    create_info.flags = vk_mem::AllocatorCreateFlags::KHR_MAINTENANCE4
+        | vk_mem::AllocatorCreateFlags::EXT_MEMORY_PRIORITY;
+    create_info.enabled_device_extensions = Some(&[]);
+    match unsafe { vk_mem::Allocator::new(create_info) } {
+        Err(vk_mem::VmaError::MissingDeviceExtension { flag, extension }) => {
+            assert_eq!(
+                (flag, extension),
+                ("EXT_MEMORY_PRIORITY", "VK_EXT_memory_priority")
+            )
+        }
+        other => panic!("unexpected result: {:?}", other.err()),
+    }
+}
+
+#[test]
+fn device_memory_allocator_trait() {
+    unsafe fn upload<A: vk_mem::DeviceMemoryAllocator>(
+        allocator: &A,
+        requirements: ash::vk::MemoryRequirements,
+    ) -> Result<ash::vk::DeviceSize, A::Error> {
+        let mut allocation = allocator.allocate(&vk_mem::DeviceMemoryRequest {
+            requirements,
+            location: vk_mem::MemoryLocation::CpuToGpu,
+            linear: true,
+            name: Some("upload"),
+        })?;
+        let range = allocator.memory_range(&allocation)?;
+        let data = allocator.map(&mut allocation)?;
+        data.write_bytes(0, range.size as usize);
+        allocator.unmap(&mut allocation);
+        allocator.set_name(&allocation, "uploaded")?;
+        let allocated = allocator.report()?.allocation_bytes;
+        allocator.free(allocation)?;
+        Ok(allocated)
+    }
+
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocated = unsafe { upload(&allocator, requirements).unwrap() };
+    assert!(allocated >= 4096);
+    assert_eq!(
+        allocator
+            .calculate_statistics()
+            .unwrap()
+            .total
+            .statistics
+            .allocation_count,
+        0
+    );
+}
+
+#[cfg(feature = "minimal-checks")]
+#[test]
+fn minimal_checks() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    assert!(!vk_mem::Allocator::build_config().bookkeeping);
+    let requirements = ash::vk::MemoryRequirements {
+        size: 4096,
+        alignment: 256,
+        memory_type_bits: !0,
+    };
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let allocation = allocator
+            .allocate_memory(&requirements, &allocation_info)
+            .unwrap();
+        assert!(allocator.live_allocations().is_empty());
+        assert!(allocator.set_allocation_pinned(&allocation, true).is_err());
+        allocator.free_memory(allocation).unwrap();
+
+        // Moves can't be matched to resources
+        let info: vk_mem::ffi::VmaDefragmentationInfo = std::mem::zeroed();
+        let mut resources = vk_mem::DefragmentableResources::new(&harness.device);
+        assert!(matches!(
+            allocator.defragment(
+                &info,
+                &mut resources,
+                ash::vk::CommandBuffer::null(),
+                |_| Ok(())
+            ),
+            Err(vk_mem::VmaError::InvalidParameter(_))
+        ));
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+#[test]
+fn tlsf_block() {
+    let mut block = vk_mem::TlsfBlock::new(1024);
+    let a = block.allocate(100, 64).unwrap();
+    let b = block.allocate(1024 - 100, 1).unwrap();
+    assert!(block.allocate(1, 1).is_none());
+    assert_eq!(b.offset(), 100);
+    unsafe {
+        block.free(a);
+        block.free(b);
+    }
+    assert!(block.is_empty());
+    assert_eq!(block.allocate(1024, 1024).unwrap().offset(), 0);
+}
+
+#[test]
+fn staging_belt() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let mut belt = allocator.create_staging_belt(1024);
+
+    let (data, buffer, offset) = belt.write(100, 16).unwrap();
+    data.fill(1);
+    assert_eq!(offset, 0);
+    let (_, same_buffer, offset) = belt.write(100, 256).unwrap();
+    assert!(same_buffer == buffer);
+    assert_eq!(offset, 256);
+    let (data, _, _) = belt.write(2000, 16).unwrap();
+    assert_eq!(data.len(), 2000);
+    assert_eq!(belt.chunk_count(), 2);
+
+    belt.finish(1);
+    assert_eq!(belt.recall(0), 0);
+    assert_eq!(belt.recall(1), 2);
+    let (_, _, offset) = belt.write(512, 16).unwrap();
+    assert_eq!(offset, 0);
+    assert_eq!(belt.chunk_count(), 2);
+}
+
+#[test]
+fn create_device_buffer_init() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    unsafe {
+        let queue = harness.device.get_device_queue(0, 0);
+        let command_pool = harness
+            .device
+            .create_command_pool(
+                &ash::vk::CommandPoolCreateInfo::default().queue_family_index(0),
+                None,
+            )
+            .unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        let (buffer, allocation) = allocator
+            .create_device_buffer_init(
+                &harness.device,
+                queue,
+                command_pool,
+                ash::vk::BufferUsageFlags::VERTEX_BUFFER,
+                &data,
+            )
+            .unwrap();
+        assert!(allocator.get_allocation_info(&allocation).unwrap().size >= 256);
+        assert!(allocator
+            .create_device_buffer_init(
+                &harness.device,
+                queue,
+                command_pool,
+                ash::vk::BufferUsageFlags::VERTEX_BUFFER,
+                &[],
+            )
+            .is_err());
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+        harness.device.destroy_command_pool(command_pool, None);
+    }
+}
+
+#[test]
+fn read_back() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    unsafe {
+        let (buffer, mut allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(64)
+                    .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC),
+                &vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::AutoPreferHost,
+                    flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let mut mapped = allocator
+            .map_memory_as_slice::<u32>(&mut allocation)
+            .unwrap();
+        for (i, value) in mapped.iter_mut().enumerate() {
+            *value = i as u32;
+        }
+        mapped.flush(0, ash::vk::WHOLE_SIZE).unwrap();
+        drop(mapped);
+        let values: Vec<u32> = allocator.read_allocation(&allocation, 8, 4).unwrap();
+        assert_eq!(values, [2, 3, 4, 5]);
+        assert!(allocator
+            .read_allocation::<u32>(&allocation, 8, 15)
+            .is_err());
+
+        let queue = harness.device.get_device_queue(0, 0);
+        let command_pool = harness
+            .device
+            .create_command_pool(
+                &ash::vk::CommandPoolCreateInfo::default().queue_family_index(0),
+                None,
+            )
+            .unwrap();
+        let values: Vec<u32> = allocator
+            .read_device_buffer(&harness.device, queue, command_pool, buffer, 60, 1)
+            .unwrap();
+        assert_eq!(values, [15]);
+        harness.device.destroy_command_pool(command_pool, None);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn invalid_allocation_identity() {
+    let harness = TestHarness::new();
+    let allocator = harness.create_allocator();
+    unsafe {
+        let (buffer, allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(64)
+                    .usage(ash::vk::BufferUsageFlags::TRANSFER_SRC),
+                &vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::AutoPreferHost,
+                    flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let line = line!() - 11;
+        allocator.set_allocation_name(&allocation, Some(c"readback"));
+        match allocator.read_allocation::<u32>(&allocation, 60, 2) {
+            Err(vk_mem::VmaError::InvalidAllocation {
+                allocation: identity,
+                reason,
+            }) => {
+                assert_eq!(identity.id, allocation.id());
+                assert_eq!(identity.name.as_deref(), Some("readback"));
+                let location = identity.location.unwrap();
+                assert_eq!((location.file(), location.line()), (file!(), line));
+                assert_eq!(reason, "Range is outside of the allocation");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn upload_queue() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    unsafe {
+        let queue = harness.device.get_device_queue(0, 0);
+        let mut uploads = allocator
+            .create_upload_queue(&harness.device, queue, 0, 1024 * 1024)
+            .unwrap();
+        let (buffer, allocation) = allocator
+            .create_buffer(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(256)
+                    .usage(ash::vk::BufferUsageFlags::TRANSFER_DST),
+                &vk_mem::AllocationCreateInfo {
+                    usage: vk_mem::MemoryUsage::AutoPreferHost,
+                    flags: vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let data: Vec<u8> = (0..=255).collect();
+        let value = uploads.upload_buffer(&data[..128], buffer, 0).unwrap();
+        assert_eq!(
+            uploads.upload_buffer(&data[128..], buffer, 128).unwrap(),
+            value
+        );
+        let completed = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = completed.clone();
+        uploads.on_complete(move || flag.store(true, std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(uploads.submit().unwrap(), value);
+        assert_eq!(uploads.submit().unwrap(), value);
+        assert!(uploads.upload_buffer(&[], buffer, 0).is_err());
+
+        assert!(uploads.wait(value, u64::MAX).unwrap() >= value);
+        assert!(completed.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(
+            allocator
+                .read_allocation::<u8>(&allocation, 0, 256)
+                .unwrap(),
+            data
+        );
+        drop(uploads);
+        allocator.destroy_buffer(buffer, allocation).unwrap();
+    }
+}
+
+#[test]
+fn deletion_queue() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let mut deletions = allocator.create_deletion_queue();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(1024)
+        .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER);
+    let create_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        for frame_index in [2, 1, 2, 3] {
+            let (buffer, allocation) = allocator.create_buffer(&buffer_info, &create_info).unwrap();
+            deletions.defer_destroy_buffer(buffer, allocation, frame_index);
+        }
+        assert_eq!(deletions.pending_count(), 4);
+        assert_eq!(deletions.collect(0).unwrap(), 0);
+        assert_eq!(deletions.collect(2).unwrap(), 3);
+        assert_eq!(deletions.pending_count(), 1);
+        assert_eq!(
+            allocator
+                .calculate_statistics()
+                .unwrap()
+                .total
+                .statistics
+                .allocation_count,
+            1
+        );
+    }
+    deletions.destroy().unwrap();
+    assert_eq!(
+        allocator
+            .calculate_statistics()
+            .unwrap()
+            .total
+            .statistics
+            .allocation_count,
+        0
+    );
+}
+
+#[test]
+fn frame_deletion_queue() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let mut deletions = allocator.create_frame_deletion_queue::<2>();
+    let buffer_info = ash::vk::BufferCreateInfo::default()
+        .size(1024)
+        .usage(ash::vk::BufferUsageFlags::VERTEX_BUFFER);
+    let create_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        for frame_index in 0..2 {
+            assert_eq!(deletions.begin_frame(frame_index).unwrap(), 0);
+            for _ in 0..=frame_index {
+                let (buffer, allocation) =
+                    allocator.create_buffer(&buffer_info, &create_info).unwrap();
+                deletions.defer_destroy_buffer(buffer, allocation);
+            }
+        }
+        assert_eq!(deletions.pending_count(), 3);
+        assert_eq!(deletions.begin_frame(2).unwrap(), 1);
+        assert_eq!(deletions.begin_frame(3).unwrap(), 2);
+        assert_eq!(deletions.pending_count(), 0);
+
+        let (buffer, allocation) = allocator.create_buffer(&buffer_info, &create_info).unwrap();
+        deletions.defer_destroy_buffer(buffer, allocation);
+    }
+    deletions.destroy().unwrap();
+    assert_eq!(
+        allocator
+            .calculate_statistics()
+            .unwrap()
+            .total
+            .statistics
+            .allocation_count,
+        0
+    );
+}
+
+#[test]
+fn frame_arena() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferHost,
+        flags: vk_mem::AllocationCreateFlags::MAPPED
+            | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index_for_buffer_info(
+                &ash::vk::BufferCreateInfo::default()
+                    .size(1024)
+                    .usage(ash::vk::BufferUsageFlags::UNIFORM_BUFFER),
+                &allocation_info,
+            )
+            .unwrap();
+        let mut arena = allocator
+            .create_frame_arena(memory_type_index, 64 * 1024, 2, &allocation_info)
+            .unwrap();
+        for _ in 0..3 {
+            let (_, allocation) = arena
+                .alloc_buffer(256, ash::vk::BufferUsageFlags::UNIFORM_BUFFER)
+                .unwrap();
+            assert!(!allocator
+                .get_allocation_info(allocation)
+                .unwrap()
+                .mapped_data
+                .is_null());
+        }
+        assert_eq!(arena.len(), 3);
+        assert!(arena
+            .alloc_buffer(128 * 1024, ash::vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .is_err());
+
+        arena.reset(1).unwrap();
+        assert!(arena.is_empty());
+        arena
+            .alloc_buffer(256, ash::vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .unwrap();
+        arena.reset(2).unwrap();
+        assert!(arena.is_empty());
+        assert_eq!(
+            allocator
+                .calculate_statistics()
+                .unwrap()
+                .total
+                .statistics
+                .allocation_count,
+            1
+        );
+    }
+}
+
+#[test]
+fn fixed_frame_arena() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let allocation_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let memory_type_index = allocator
+            .find_memory_type_index(u32::MAX, &allocation_info)
+            .unwrap();
+        let mut arena = allocator
+            .create_fixed_frame_arena::<3>(memory_type_index, 64 * 1024, &allocation_info)
+            .unwrap();
+        for frame_index in 0..3 {
+            arena.reset(frame_index).unwrap();
+            arena
+                .alloc_buffer(256, ash::vk::BufferUsageFlags::UNIFORM_BUFFER)
+                .unwrap();
+        }
+        assert_eq!(
+            allocator
+                .calculate_statistics()
+                .unwrap()
+                .total
+                .statistics
+                .allocation_count,
+            3
+        );
+        arena.reset(3).unwrap();
+        assert!(arena.is_empty());
+        arena.destroy().unwrap();
+    }
+    assert_eq!(
+        allocator
+            .calculate_statistics()
+            .unwrap()
+            .total
+            .statistics
+            .allocation_count,
+        0
+    );
+}
+
+#[test]
+fn uniform_arena() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let limits = unsafe { allocator.get_physical_device_properties().unwrap().limits };
+    let mut arena = allocator.create_uniform_arena(64 * 1024).unwrap();
+    assert_eq!(
+        arena.alignment(),
+        limits.min_uniform_buffer_offset_alignment
+    );
+
+    let (first, data) = arena.alloc(12).unwrap();
+    assert_eq!(data.len(), 12);
+    let second = arena.push(&[1.0f32; 4]).unwrap();
+    assert_eq!(first, 0);
+    assert_eq!(second as u64, arena.alignment());
+    assert!(arena.alloc(128 * 1024).is_err());
+    arena.flush().unwrap();
+
+    unsafe { arena.reset() };
+    assert_eq!(arena.used(), 0);
+    assert_eq!(arena.push(&0u32).unwrap(), 0);
+}
+
+#[test]
+fn scratch_pool_requires_buffer_device_address() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let properties = ash::vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default()
+        .min_acceleration_structure_scratch_offset_alignment(128);
+    let result = unsafe { allocator.create_scratch_pool(&harness.device, &properties, 1 << 20) };
+    assert!(matches!(result, Err(vk_mem::VmaError::InvalidParameter(_))));
+}
+
+#[test]
+fn buffer_suballocator() {
+    let harness = TestHarness::new();
+    let allocator = Arc::new(harness.create_allocator());
+    let create_info = vk_mem::AllocationCreateInfo {
+        usage: vk_mem::MemoryUsage::AutoPreferDevice,
+        ..Default::default()
+    };
+    unsafe {
+        let mut suballocator = allocator.create_buffer_suballocator(
+            ash::vk::BufferUsageFlags::VERTEX_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST,
+            4096,
+            &create_info,
+        );
+        let first = suballocator.allocate(3000, 256).unwrap();
+        let mut second = suballocator.allocate(2000, 256).unwrap();
+        assert_eq!(suballocator.block_count(), 2);
+        assert!(first.buffer() != second.buffer());
+        assert!(second.mapped_data().is_null());
+        assert_eq!(suballocator.used_size(), 5000);
+
+        assert!(suballocator.compact(&mut second).unwrap().is_none());
+        suballocator.free(first).unwrap();
+        let old_buffer = second.buffer();
+        let slice_move = suballocator.compact(&mut second).unwrap().unwrap();
+        assert!(slice_move.src_buffer() == old_buffer);
+        assert!(slice_move.dst_buffer() == second.buffer());
+        assert_eq!(slice_move.region().size, 2000);
+        assert_eq!(suballocator.trim(), 0);
+        suballocator.finish_move(slice_move).unwrap();
+        assert_eq!(suballocator.trim(), 1);
+        assert_eq!(suballocator.block_count(), 1);
+        assert_eq!(suballocator.total_size(), 4096);
+        suballocator.free(second).unwrap();
+    }
+}
@@ -1,2 +1,48 @@
+#ifdef VMA_RUST_LOG
+#include <cstdarg>
+#include <cstdio>
+
+// Implemented in src/logging.rs, level 0 is debug and 1 is warn.
+extern "C" void vma_rust_log(int level, const char* message);
+
+static void vma_rust_log_format(int level, const char* format, ...)
+{
+    char message[1024];
+    va_list args;
+    va_start(args, format);
+    vsnprintf(message, sizeof(message), format, args);
+    va_end(args);
+    vma_rust_log(level, message);
+}
+
+#define VMA_DEBUG_LOG_FORMAT(...) vma_rust_log_format(0, __VA_ARGS__)
+#define VMA_LEAK_LOG_FORMAT(...) vma_rust_log_format(1, __VA_ARGS__)
+#endif
+
+#ifndef NDEBUG
+// Implemented in src/assertions.rs as extern "C-unwind", so a panic may unwind through VMA.
+// noexcept(false) makes that explicit: compilers like MSVC with /EHsc otherwise assume extern "C"
+// functions don't throw. Unwinding out of a destructor, which is implicitly noexcept, still calls
+// std::terminate.
+extern "C" void vma_rust_assert(const char* expression, const char* file, int line) noexcept(false);
+
+#define VMA_ASSERT(expr) \
+    do { \
+        if (!(expr)) \
+            vma_rust_assert(#expr, __FILE__, __LINE__); \
+    } while (false)
+#endif
+
+#ifdef VMA_RUST_ALLOCATOR
+#include <cstddef>
+
+// Implemented in src/host_allocator.rs, with Rust's global allocator.
+extern "C" void* vma_rust_alloc(size_t size, size_t alignment);
+extern "C" void vma_rust_free(void* ptr);
+
+#define VMA_SYSTEM_ALIGNED_MALLOC(size, alignment) vma_rust_alloc((size), (alignment))
+#define VMA_SYSTEM_ALIGNED_FREE(ptr) vma_rust_free(ptr)
+#endif
+
 #define VMA_IMPLEMENTATION
 #include "vk_mem_alloc.h"